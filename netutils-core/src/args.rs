@@ -0,0 +1,603 @@
+//! Command line parsing for the tools. Options are declared along with their help, and parsed
+//! the usual way: short options may be grouped, as in `-vn`, values may be attached, as in `-c4`
+//! and `--count=4`, options and operands may be mixed, and `--` ends the options. Options that
+//! aren't given take their defaults from the configuration file, if it has them.
+
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::{env, process};
+
+use config::{self, Config, Value};
+use error::{EXIT_FAILURE, EXIT_USAGE};
+use log::{Level, Logger};
+use output::Format;
+use ratelimit::{self, Limit};
+use resolve::Family;
+
+/// The column that the help of options starts at
+const HELP_COLUMN: usize = 28;
+
+struct Opt {
+    short: Option<char>,
+    long: Option<&'static str>,
+    /// The name of the value that the option takes, if it takes one
+    value: Option<&'static str>,
+    help: &'static str,
+}
+
+impl Opt {
+    /// How the option is written in messages, by its short name if it has one
+    fn name(&self) -> String {
+        match (self.short, self.long) {
+            (Some(short), _) => format!("-{}", short),
+            (None, Some(long)) => format!("--{}", long),
+            (None, None) => unreachable!()
+        }
+    }
+
+    fn label(&self) -> String {
+        let mut label = match (self.short, self.long) {
+            (Some(short), Some(long)) => format!("-{}, --{}", short, long),
+            _ => self.name()
+        };
+        if let Some(value) = self.value {
+            label.push(' ');
+            label.push_str(value);
+        }
+        label
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// -h or --help was given
+    Help,
+    /// The arguments are invalid, for the reason given
+    Invalid(String),
+}
+
+/// The options that a tool takes
+pub struct Parser {
+    name: &'static str,
+    synopsis: Vec<&'static str>,
+    man: Option<&'static str>,
+    opts: Vec<Opt>,
+    /// Groups of options that can't be used together, by index
+    exclusive: Vec<Vec<usize>>,
+    /// The formats that --output takes, of which the first is the default
+    formats: Vec<Format>,
+}
+
+impl Parser {
+    pub fn new(name: &'static str) -> Parser {
+        Parser {
+            name: name,
+            synopsis: Vec::new(),
+            man: None,
+            opts: Vec::new(),
+            exclusive: Vec::new(),
+            formats: Vec::new(),
+        }
+    }
+
+    /// Add a line to the usage message, which follows the name of the tool, such as
+    /// `[-c count] host`
+    pub fn synopsis(mut self, synopsis: &'static str) -> Parser {
+        self.synopsis.push(synopsis);
+        self
+    }
+
+    /// The manual page, which is printed for --help in place of the generated help
+    pub fn man(mut self, man: &'static str) -> Parser {
+        self.man = Some(man);
+        self
+    }
+
+    fn add(mut self, short: &'static str, long: &'static str, value: Option<&'static str>, help: &'static str) -> Parser {
+        let mut chars = short.chars();
+        let short = chars.next();
+        assert!(chars.next().is_none(), "short option {} is longer than a character", short.unwrap_or(' '));
+        let long = if long.is_empty() { None } else { Some(long) };
+        assert!(short.is_some() || long.is_some(), "option without a name");
+        self.opts.push(Opt {
+            short: short,
+            long: long,
+            value: value,
+            help: help,
+        });
+        self
+    }
+
+    /// An option that takes no value. Either name may be empty.
+    pub fn flag(self, short: &'static str, long: &'static str, help: &'static str) -> Parser {
+        self.add(short, long, None, help)
+    }
+
+    /// An option that takes a value, which is called `value` in the help. Either name may be
+    /// empty.
+    pub fn opt(self, short: &'static str, long: &'static str, value: &'static str, help: &'static str) -> Parser {
+        self.add(short, long, Some(value), help)
+    }
+
+    /// The -4 and -6 options, which choose the address family that names are resolved to
+    pub fn family(self) -> Parser {
+        let first = self.opts.len();
+        let mut parser = self.flag("4", "", "Use IPv4 only")
+            .flag("6", "", "Use IPv6 only");
+        parser.exclusive.push(vec![first, first + 1]);
+        parser
+    }
+
+    /// The --output option, which chooses between text and JSON
+    pub fn output(self) -> Parser {
+        self.output_formats("", "text|json", "Print text, or a JSON object for each result")
+    }
+
+    /// The --output option, with a short name, which may be empty, for the formats that a tool
+    /// prints, such as `tsv|json`. The first is the default.
+    pub fn output_formats(mut self, short: &'static str, formats: &'static str, help: &'static str) -> Parser {
+        self.formats = formats.split('|').map(|name| name.parse().expect("unknown output format")).collect();
+        self.opt(short, "output", formats, help)
+    }
+
+    /// The --log, --log-level and --log-max-size options of the daemons
+    pub fn logging(self) -> Parser {
+        self.opt("", "log", "file|syslog", "Log to file, or to the system log, rather than the terminal")
+            .opt("", "log-level", "level", "Log messages of level and above: error, warn, info or debug")
+            .opt("", "log-max-size", "bytes", "Rotate the log file once it reaches this size")
+    }
+
+    /// The --limit-rate and --limit-burst options, which shape traffic with a token bucket
+    pub fn rate_limit(self) -> Parser {
+        self.opt("", "limit-rate", "rate", "Limit the rate to rate bytes a second, with k, m or g for KiB, MiB or GiB")
+            .opt("", "limit-burst", "bytes", "Let bursts of up to bytes through at once, a second's worth by default")
+    }
+
+    /// -h asks for help, unless the tool has an option of its own called -h
+    fn short_help(&self) -> bool {
+        self.opts.iter().all(|opt| opt.short != Some('h'))
+    }
+
+    /// The usage message, with a line for each line of the synopsis
+    pub fn usage(&self) -> String {
+        let mut usage = String::new();
+        if self.synopsis.is_empty() {
+            usage.push_str(&format!("usage: {}\n", self.name));
+        }
+        for (i, synopsis) in self.synopsis.iter().enumerate() {
+            let prefix = if i == 0 { "usage:" } else { "      " };
+            usage.push_str(&format!("{} {} {}\n", prefix, self.name, synopsis));
+        }
+        usage
+    }
+
+    /// The usage message, followed by the help of each option
+    pub fn help(&self) -> String {
+        let mut help = self.usage();
+        help.push_str("\noptions:\n");
+        let help_opt = Opt {
+            short: if self.short_help() { Some('h') } else { None },
+            long: Some("help"),
+            value: None,
+            help: "Print this help",
+        };
+        for opt in self.opts.iter().chain(Some(&help_opt)) {
+            let label = format!("    {}", opt.label());
+            if label.len() + 2 > HELP_COLUMN {
+                help.push_str(&format!("{}\n{:width$}{}\n", label, "", opt.help, width = HELP_COLUMN));
+            } else {
+                help.push_str(&format!("{:width$}{}\n", label, opt.help, width = HELP_COLUMN));
+            }
+        }
+        help
+    }
+
+    fn find_long(&self, name: &str) -> Option<usize> {
+        self.opts.iter().position(|opt| opt.long == Some(name))
+    }
+
+    fn find_short(&self, name: char) -> Option<usize> {
+        self.opts.iter().position(|opt| opt.short == Some(name))
+    }
+
+    /// The option at `index`, and those that can't be used with it
+    fn group(&self, index: usize) -> Vec<usize> {
+        match self.exclusive.iter().find(|group| group.contains(&index)) {
+            Some(group) => group.clone(),
+            None => vec![index]
+        }
+    }
+
+    /// Parse arguments, which don't include the name of the program
+    pub fn parse<I: IntoIterator<Item = String>>(self, args: I) -> Result<Args, Error> {
+        let mut found = Vec::new();
+        let mut free = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                free.extend(args);
+                break;
+            } else if arg.starts_with("--") {
+                let (name, value) = match arg.find('=') {
+                    Some(equals) => (&arg[..equals], Some(arg[equals + 1..].to_string())),
+                    None => (&arg[..], None)
+                };
+                let index = match self.find_long(&name[2..]) {
+                    Some(index) => index,
+                    None if name == "--help" => return Err(Error::Help),
+                    None => return Err(Error::Invalid(format!("unknown option {}", name)))
+                };
+                let value = match (self.opts[index].value, value) {
+                    (Some(_), Some(value)) => Some(value),
+                    (Some(_), None) => match args.next() {
+                        Some(value) => Some(value),
+                        None => return Err(Error::Invalid(format!("{} needs a value", name)))
+                    },
+                    (None, Some(_)) => return Err(Error::Invalid(format!("{} doesn't take a value", name))),
+                    (None, None) => None
+                };
+                found.push((index, value));
+            } else if arg.starts_with('-') && arg.len() > 1 {
+                // A group of short options, the last of which may take a value
+                for (i, c) in arg.char_indices().skip(1) {
+                    let name = format!("-{}", c);
+                    let index = match self.find_short(c) {
+                        Some(index) => index,
+                        None if c == 'h' && self.short_help() => return Err(Error::Help),
+                        None => return Err(Error::Invalid(format!("unknown option {}", name)))
+                    };
+                    if self.opts[index].value.is_none() {
+                        found.push((index, None));
+                        continue;
+                    }
+                    let rest = &arg[i + c.len_utf8()..];
+                    let value = if !rest.is_empty() {
+                        rest.to_string()
+                    } else {
+                        match args.next() {
+                            Some(value) => value,
+                            None => return Err(Error::Invalid(format!("{} needs a value", name)))
+                        }
+                    };
+                    found.push((index, Some(value)));
+                    break;
+                }
+            } else {
+                free.push(arg);
+            }
+        }
+        Ok(Args {
+            parser: self,
+            found: found,
+            free: free,
+        })
+    }
+
+    /// Parse the arguments of the program, with the defaults of the configuration file. For
+    /// --help, the help is printed and the program exits, and for invalid arguments or an
+    /// invalid configuration file, the usage message is printed and it exits with 2.
+    pub fn parse_env(self) -> Args {
+        let name = self.name;
+        let help = match self.man {
+            Some(man) => man.to_string(),
+            None => self.help()
+        };
+        let usage = self.usage();
+        let config = Config::load().unwrap_or_else(|err| {
+            let _ = writeln!(io::stderr(), "{}: {}", name, err);
+            process::exit(EXIT_USAGE);
+        });
+        match self.parse(env::args().skip(1)).and_then(|mut args| args.defaults(&config).map(|_| args)) {
+            Ok(args) => args,
+            Err(Error::Help) => {
+                print!("{}", help);
+                process::exit(0);
+            },
+            Err(Error::Invalid(message)) => {
+                let _ = write!(io::stderr(), "{}: {}\n{}", name, message, usage);
+                process::exit(EXIT_USAGE);
+            }
+        }
+    }
+}
+
+/// The options and operands that were given
+pub struct Args {
+    parser: Parser,
+    /// Each option that was given, in order, with its value
+    found: Vec<(usize, Option<String>)>,
+    /// The operands, which are the arguments that aren't options or their values
+    pub free: Vec<String>,
+}
+
+impl Args {
+    /// Give each option that wasn't given the value that the configuration has for it. The keys
+    /// for every tool are skipped by tools without such an option, while a key in a tool's own
+    /// table has to be one of its options, and takes precedence over a key for every tool. An
+    /// option that can't be used with one that was given, such as -6 with -4, isn't given a
+    /// value either.
+    fn defaults(&mut self, config: &Config) -> Result<(), Error> {
+        let keys = config.table(self.parser.name).into_iter().map(|key| (key, true))
+            .chain(config.global().into_iter().map(|key| (key, false)));
+        // The options that the command line or the configuration has already given, even as false
+        let mut given: Vec<usize> = self.found.iter().flat_map(|&(i, _)| self.parser.group(i)).collect();
+        for (&(ref key, ref value), own) in keys {
+            let short = if key.chars().count() == 1 { key.chars().next() } else { None };
+            let index = match self.parser.find_long(key).or_else(|| short.and_then(|c| self.parser.find_short(c))) {
+                Some(index) => index,
+                None if own => {
+                    let path = config::path().map(|path| path.display().to_string()).unwrap_or_default();
+                    return Err(Error::Invalid(format!("{}: unknown option '{}' in [{}]", path, key, self.parser.name)));
+                },
+                None => continue
+            };
+            if given.contains(&index) {
+                continue;
+            }
+            given.push(index);
+            let name = self.parser.opts[index].name();
+            match (self.parser.opts[index].value, value) {
+                (Some(_), &Value::Text(ref text)) => self.found.push((index, Some(text.clone()))),
+                (None, &Value::Bool(true)) => {
+                    given.extend(self.parser.group(index));
+                    self.found.push((index, None));
+                },
+                (None, &Value::Bool(false)) => (),
+                (Some(_), &Value::Bool(_)) => return Err(Error::Invalid(format!("{} in the configuration needs a value", name))),
+                (None, &Value::Text(_)) => return Err(Error::Invalid(format!("{} in the configuration is true or false", name)))
+            }
+        }
+        Ok(())
+    }
+
+    fn index(&self, name: &str) -> usize {
+        let mut chars = name.chars();
+        let short = match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None
+        };
+        match self.parser.opts.iter().position(|opt| opt.long == Some(name) || (short.is_some() && opt.short == short)) {
+            Some(index) => index,
+            None => panic!("option {} is not declared", name)
+        }
+    }
+
+    /// The number of times that an option was given, such as 2 for `-vv`. Options are named by
+    /// either of their names.
+    pub fn count(&self, name: &str) -> usize {
+        let index = self.index(name);
+        self.found.iter().filter(|&&(i, _)| i == index).count()
+    }
+
+    /// Whether an option was given
+    pub fn flag(&self, name: &str) -> bool {
+        self.count(name) > 0
+    }
+
+    /// Every value that an option was given, in order
+    pub fn values(&self, name: &str) -> Vec<&str> {
+        let index = self.index(name);
+        self.found.iter()
+            .filter(|&&(i, _)| i == index)
+            .filter_map(|&(_, ref value)| value.as_ref().map(|value| value.as_str()))
+            .collect()
+    }
+
+    /// The value that an option was last given
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values(name).pop()
+    }
+
+    /// The value of an option that must be given, exiting with the usage message if it wasn't
+    pub fn required(&self, name: &str) -> &str {
+        match self.value(name) {
+            Some(value) => value,
+            None => {
+                let opt = &self.parser.opts[self.index(name)];
+                self.invalid(&format!("{} {} is required", opt.name(), opt.value.unwrap_or("")))
+            }
+        }
+    }
+
+    /// Parse the value of an option, exiting with the usage message if it is invalid
+    pub fn parse<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.value(name).map(|value| match value.parse() {
+            Ok(value) => value,
+            Err(_) => {
+                let opt = &self.parser.opts[self.index(name)];
+                self.invalid(&format!("invalid {} '{}' for {}", opt.value.unwrap_or("value"), value, opt.name()))
+            }
+        })
+    }
+
+    /// The address family chosen with the options added by `Parser::family`
+    pub fn family(&self) -> Family {
+        match (self.flag("4"), self.flag("6")) {
+            (true, true) => self.invalid("-4 and -6 can't be used together"),
+            (true, false) => Family::V4,
+            (false, true) => Family::V6,
+            (false, false) => Family::Any
+        }
+    }
+
+    /// The format chosen with the option added by `Parser::output` or `Parser::output_formats`
+    pub fn output(&self) -> Format {
+        let formats = &self.parser.formats;
+        match self.value("output") {
+            None => formats.first().cloned().unwrap_or(Format::Text),
+            Some(value) => match value.parse() {
+                Ok(format) if formats.contains(&format) => format,
+                _ => self.invalid(&format!("unknown output format '{}'", value))
+            }
+        }
+    }
+
+    /// The level chosen with --log-level, or `level`
+    pub fn log_level(&self, level: Level) -> Level {
+        self.parse("log-level").unwrap_or(level)
+    }
+
+    /// The logger chosen with the options added by `Parser::logging`, which logs messages of
+    /// `level` and above unless --log-level says otherwise
+    pub fn logger(&self, level: Level) -> Logger {
+        let mut logger = match self.value("log") {
+            Some(dest) => Logger::open(self.parser.name, dest, self.parse("log-max-size")).unwrap_or_else(|err| {
+                let _ = writeln!(io::stderr(), "{}: failed to open log '{}': {}", self.parser.name, dest, err);
+                process::exit(EXIT_FAILURE);
+            }),
+            None => Logger::console(self.parser.name)
+        };
+        logger.level = self.log_level(level);
+        logger
+    }
+
+    /// A size of bytes given to an option, such as 200k, exiting with the usage message if it is
+    /// invalid
+    pub fn size(&self, name: &str) -> Option<u64> {
+        self.value(name).map(|value| ratelimit::parse_size(value).unwrap_or_else(|| {
+            let opt = &self.parser.opts[self.index(name)];
+            self.invalid(&format!("invalid {} '{}' for {}", opt.value.unwrap_or("size"), value, opt.name()))
+        }))
+    }
+
+    /// The limit chosen with the options added by `Parser::rate_limit`, if any
+    pub fn rate_limit(&self) -> Option<Limit> {
+        let burst = self.size("limit-burst");
+        match self.size("limit-rate") {
+            Some(rate) => Some(Limit::new(rate, burst)),
+            None if burst.is_some() => self.invalid("--limit-burst needs --limit-rate"),
+            None => None
+        }
+    }
+
+    /// Exit after printing a message and the usage message
+    pub fn invalid(&self, message: &str) -> ! {
+        let _ = write!(io::stderr(), "{}: {}\n{}", self.parser.name, message, self.parser.usage());
+        process::exit(EXIT_USAGE);
+    }
+
+    /// Exit after printing the usage message
+    pub fn usage(&self) -> ! {
+        let _ = write!(io::stderr(), "{}", self.parser.usage());
+        process::exit(EXIT_USAGE);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use config::Config;
+    use output::Format;
+    use resolve::Family;
+    use super::{Error, Parser};
+
+    fn parser() -> Parser {
+        Parser::new("tool")
+            .synopsis("[-v] [-c count] host")
+            .flag("v", "verbose", "Print more")
+            .flag("n", "", "Don't resolve names")
+            .opt("c", "count", "count", "Stop after count packets")
+            .opt("", "header", "header", "Add a header")
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_test() {
+        let found = parser().parse(args(&["-vvn", "-c4", "host", "--header=a: b", "--header", "c", "--", "-v"])).unwrap();
+        assert_eq!(found.count("v"), 2);
+        assert!(found.flag("verbose"));
+        assert!(found.flag("n"));
+        assert_eq!(found.parse::<u32>("count"), Some(4));
+        assert_eq!(found.values("header"), vec!["a: b", "c"]);
+        assert_eq!(found.free, args(&["host", "-v"]));
+
+        let found = parser().parse(args(&["-nc", "5", "-"])).unwrap();
+        assert_eq!(found.value("c"), Some("5"));
+        assert_eq!(found.free, args(&["-"]));
+        assert_eq!(found.value("header"), None);
+
+        let found = Parser::new("tool").output().parse(args(&["--output=json"])).unwrap();
+        assert_eq!(found.output(), Format::Json);
+
+        let parser = || Parser::new("tool").output_formats("o", "tsv|json", "The output format");
+        assert_eq!(parser().parse(args(&[])).unwrap().output(), Format::Tsv);
+        assert_eq!(parser().parse(args(&["-ojson"])).unwrap().output(), Format::Json);
+    }
+
+    #[test]
+    fn defaults_test() {
+        let config = Config::parse("output = \"json\"\n\
+                                    [tool]\n\
+                                    count = 3\n\
+                                    v = true\n\
+                                    header = \"a: b\"\n").unwrap();
+        let mut found = parser().parse(args(&["--count", "5"])).unwrap();
+        found.defaults(&config).unwrap();
+        assert_eq!(found.value("count"), Some("5"));
+        assert!(found.flag("v"));
+        assert_eq!(found.values("header"), vec!["a: b"]);
+
+        // A tool's own table beats the keys for every tool, even when they come first
+        let config = Config::parse("count = 1
+                                    v = true
+                                    [tool]
+                                    count = 3
+                                    v = false
+").unwrap();
+        let mut found = parser().parse(args(&[])).unwrap();
+        found.defaults(&config).unwrap();
+        assert_eq!(found.value("count"), Some("3"));
+        assert!(!found.flag("v"));
+        let mut found = parser().parse(args(&[])).unwrap();
+        found.defaults(&Config::parse("count = 1
+[other]
+count = 3
+").unwrap()).unwrap();
+        assert_eq!(found.value("count"), Some("1"));
+
+        // -4 on the command line beats 6 = true in the configuration, rather than clashing with it
+        let config = Config::parse("6 = true\n").unwrap();
+        let mut found = parser().family().parse(args(&["-4"])).unwrap();
+        found.defaults(&config).unwrap();
+        assert_eq!(found.family(), Family::V4);
+        let mut found = parser().family().parse(args(&[])).unwrap();
+        found.defaults(&config).unwrap();
+        assert_eq!(found.family(), Family::V6);
+        let mut found = parser().family().parse(args(&[])).unwrap();
+        found.defaults(&Config::parse("4 = true\n[tool]\n6 = true\n").unwrap()).unwrap();
+        assert_eq!(found.family(), Family::V6);
+
+        let mut found = parser().parse(args(&[])).unwrap();
+        assert!(found.defaults(&Config::parse("[tool]\nv = 1\n").unwrap()).is_err());
+        let mut found = parser().parse(args(&[])).unwrap();
+        assert!(found.defaults(&Config::parse("[tool]\nverbosity = true\n").unwrap()).is_err());
+        let mut found = parser().parse(args(&[])).unwrap();
+        assert!(found.defaults(&Config::parse("[other]\nverbosity = true\n").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn error_test() {
+        assert_eq!(parser().parse(args(&["-x"])).err(), Some(Error::Invalid("unknown option -x".to_string())));
+        assert_eq!(parser().parse(args(&["host", "-c"])).err(), Some(Error::Invalid("-c needs a value".to_string())));
+        assert_eq!(parser().parse(args(&["--verbose=yes"])).err(), Some(Error::Invalid("--verbose doesn't take a value".to_string())));
+        assert_eq!(parser().parse(args(&["-vh"])).err(), Some(Error::Help));
+        assert_eq!(parser().parse(args(&["--help"])).err(), Some(Error::Help));
+        // -h belongs to tools that have an option called -h
+        let found = Parser::new("whois").opt("h", "host", "host", "Query host").parse(args(&["-h", "whois.example"])).unwrap();
+        assert_eq!(found.value("host"), Some("whois.example"));
+    }
+
+    #[test]
+    fn help_test() {
+        assert_eq!(parser().help(), "usage: tool [-v] [-c count] host\n\
+                                     \n\
+                                     options:\n    \
+                                     -v, --verbose           Print more\n    \
+                                     -n                      Don't resolve names\n    \
+                                     -c, --count count       Stop after count packets\n    \
+                                     --header header         Add a header\n    \
+                                     -h, --help              Print this help\n");
+    }
+}
@@ -0,0 +1,164 @@
+//! ARP over Ethernet (RFC 826) on one interface, through a `rawsock::Link`, which arp and arping
+//! send their requests and announcements with
+
+use std::io::Result;
+use std::net;
+use std::time::{Duration, Instant};
+
+use rawsock::{self, interface};
+use {n16, Arp, ArpHeader, EthernetII, EthernetIIHeader, Ipv4Addr, MacAddr};
+
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+
+fn to_lib(addr: net::Ipv4Addr) -> Ipv4Addr {
+    Ipv4Addr { bytes: addr.octets() }
+}
+
+fn from_lib(addr: Ipv4Addr) -> net::Ipv4Addr {
+    net::Ipv4Addr::new(addr.bytes[0], addr.bytes[1], addr.bytes[2], addr.bytes[3])
+}
+
+/// An ARP packet that was received
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Packet {
+    pub reply: bool,
+    pub sender: net::Ipv4Addr,
+    pub sender_mac: MacAddr,
+    pub target: net::Ipv4Addr,
+}
+
+/// An Ethernet frame to `dst` from `src` holding an ARP packet for IPv4
+fn frame(oper: u16, dst: MacAddr, src: MacAddr, sender: net::Ipv4Addr, target_mac: MacAddr,
+         target: net::Ipv4Addr) -> Vec<u8> {
+    let arp = Arp {
+        header: ArpHeader {
+            htype: n16::new(1),
+            ptype: n16::new(ETHERTYPE_IPV4),
+            hlen: 6,
+            plen: 4,
+            oper: n16::new(oper),
+            src_mac: src,
+            src_ip: to_lib(sender),
+            dst_mac: target_mac,
+            dst_ip: to_lib(target),
+        },
+        data: Vec::new(),
+    };
+    EthernetII {
+        header: EthernetIIHeader {
+            dst: dst,
+            src: src,
+            ethertype: n16::new(ETHERTYPE_ARP),
+        },
+        data: arp.to_bytes(),
+    }.to_bytes()
+}
+
+/// The ARP packet for IPv4 in a frame, if it is a request or reply from a host other than `own`
+fn parse(data: &[u8], own: MacAddr) -> Option<Packet> {
+    let frame = EthernetII::from_bytes(data)?;
+    if frame.header.ethertype.get() != ETHERTYPE_ARP {
+        return None;
+    }
+    let arp = Arp::from_bytes(&frame.data)?;
+    let oper = arp.header.oper.get();
+    // Packet sockets also see the requests sent from this interface
+    if arp.header.ptype.get() != ETHERTYPE_IPV4 || arp.header.src_mac == own ||
+       (oper != ARP_REQUEST && oper != ARP_REPLY) {
+        return None;
+    }
+    Some(Packet {
+        reply: oper == ARP_REPLY,
+        sender: from_lib(arp.header.src_ip),
+        sender_mac: arp.header.src_mac,
+        target: from_lib(arp.header.dst_ip),
+    })
+}
+
+/// ARP over Ethernet on one interface
+pub struct Link {
+    link: rawsock::Link,
+    pub mac: MacAddr,
+    /// The first IPv4 address of the interface, if it has one
+    pub addr: Option<net::Ipv4Addr>,
+}
+
+impl Link {
+    pub fn open(iface: &str) -> Result<Link> {
+        let (mac, addr) = interface(iface)?;
+        Ok(Link {
+            link: rawsock::Link::open(Some(iface), Some(ETHERTYPE_ARP))?,
+            mac: mac,
+            addr: addr,
+        })
+    }
+
+    /// Broadcast a who-has request for `target`, or send it to `dst` alone
+    pub fn request(&mut self, dst: MacAddr, sender: net::Ipv4Addr, target: net::Ipv4Addr) -> Result<()> {
+        let frame = frame(ARP_REQUEST, dst, self.mac, sender, MacAddr::default(), target);
+        self.link.send(&frame)
+    }
+
+    /// Announce that `addr` is at this interface, so that neighbors update their caches. This is
+    /// a request for the address itself, which RFC 5227 prefers over a reply.
+    pub fn announce(&mut self, addr: net::Ipv4Addr) -> Result<()> {
+        self.request(MacAddr::BROADCAST, addr, addr)
+    }
+
+    /// Wait for an ARP packet for IPv4 from another host, requests as well as replies
+    pub fn receive(&mut self, timeout: Duration) -> Result<Option<Packet>> {
+        let deadline = Instant::now() + timeout;
+        let mut buffer = [0; 65536];
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            let count = match self.link.receive(&mut buffer, Some(deadline - now))? {
+                Some(count) => count,
+                None => return Ok(None)
+            };
+            if let Some(packet) = parse(&buffer[..count], self.mac) {
+                return Ok(Some(packet));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+    use MacAddr;
+    use super::{frame, parse, Packet, ARP_REPLY, ARP_REQUEST};
+
+    #[test]
+    fn frame_test() {
+        let own = MacAddr { bytes: [2, 0, 0, 0, 0, 1] };
+        let other = MacAddr { bytes: [2, 0, 0, 0, 0, 2] };
+        let (sender, target) = (Ipv4Addr::new(192, 168, 1, 2), Ipv4Addr::new(192, 168, 1, 1));
+
+        let request = frame(ARP_REQUEST, MacAddr::BROADCAST, own, sender, MacAddr::default(), target);
+        assert_eq!(request.len(), 42);
+        assert_eq!(&request[..14], &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 2, 0, 0, 0, 0, 1, 0x08, 0x06]);
+        assert_eq!(&request[14..22], &[0, 1, 0x08, 0x00, 6, 4, 0, 1]);
+        // The requests sent from this interface are skipped
+        assert_eq!(parse(&request, own), None);
+        assert_eq!(parse(&request, other), Some(Packet {
+            reply: false,
+            sender: sender,
+            sender_mac: own,
+            target: target,
+        }));
+
+        let mut reply = frame(ARP_REPLY, own, other, target, own, sender);
+        assert_eq!(parse(&reply, own).map(|packet| (packet.reply, packet.sender, packet.sender_mac)),
+                   Some((true, target, other)));
+        reply[21] = 3;
+        assert_eq!(parse(&reply, own), None);
+        assert_eq!(parse(&reply[..20], own), None);
+    }
+}
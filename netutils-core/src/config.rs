@@ -0,0 +1,187 @@
+//! The per-user configuration file, `~/.config/netutils/config.toml`, which sets defaults for
+//! the options of the tools. It is the part of TOML that options need: a table for each tool,
+//! holding its options by their long names, and keys before the first table, which are
+//! defaults for every tool that has such an option:
+//!
+//! ```toml
+//! output = "json"
+//!
+//! [whois]
+//! host = "whois.arin.net"
+//!
+//! [ping]
+//! interval = 2
+//! ```
+//!
+//! Options given on the command line take precedence. The keys before the first table may also
+//! set proxies, as `proxy` reads them.
+
+use std::env;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// A string or number, which is given to an option that takes a value
+    Text(String),
+    /// `true` or `false`, for an option that doesn't take a value
+    Bool(bool),
+}
+
+/// The keys of each table, in order. The keys before the first table are in the table named "".
+#[derive(Debug, Default)]
+pub struct Config {
+    tables: Vec<(String, Vec<(String, Value)>)>,
+}
+
+/// The path of the file: `$XDG_CONFIG_HOME/netutils/config.toml`, or
+/// `$HOME/.config/netutils/config.toml`
+pub fn path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config")
+    };
+    Some(dir.join("netutils").join("config.toml"))
+}
+
+/// A key, in quotes or bare
+fn parse_key(key: &str) -> Option<String> {
+    if key.len() >= 2 && key.starts_with('"') && key.ends_with('"') {
+        return Some(key[1..key.len() - 1].to_string());
+    }
+    let bare = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare { Some(key.to_string()) } else { None }
+}
+
+/// A value, and what follows it on the line
+fn parse_value(text: &str) -> ::std::result::Result<(Value, &str), String> {
+    let mut chars = text.char_indices();
+    match chars.next() {
+        Some((_, '"')) => {
+            let mut value = String::new();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => return Ok((Value::Text(value), &text[i + 1..])),
+                    '\\' => match chars.next().map(|(_, c)| c) {
+                        Some('"') => value.push('"'),
+                        Some('\\') => value.push('\\'),
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some(c) => return Err(format!("unknown escape '\\{}'", c)),
+                        None => break
+                    },
+                    _ => value.push(c)
+                }
+            }
+            Err("unterminated string".to_string())
+        },
+        Some((_, '\'')) => match text[1..].find('\'') {
+            Some(end) => Ok((Value::Text(text[1..end + 1].to_string()), &text[end + 2..])),
+            None => Err("unterminated string".to_string())
+        },
+        _ => {
+            let end = text.find(|c: char| c.is_whitespace() || c == '#').unwrap_or(text.len());
+            let value = match &text[..end] {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                number if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit() || "+-._".contains(c)) => {
+                    Value::Text(number.replace('_', ""))
+                },
+                other => return Err(format!("unsupported value '{}'", other))
+            };
+            Ok((value, &text[end..]))
+        }
+    }
+}
+
+impl Config {
+    /// Read the file at `path()`. There being no file is the same as an empty one.
+    pub fn load() -> Result<Config> {
+        let path = match path() {
+            Some(path) => path,
+            None => return Ok(Config::default())
+        };
+        let mut text = String::new();
+        match File::open(&path) {
+            Ok(mut file) => file.read_to_string(&mut text)?,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+        };
+        Config::parse(&text).map_err(|err| Error::new(err.kind(), format!("{}:{}", path.display(), err)))
+    }
+
+    pub fn parse(text: &str) -> Result<Config> {
+        let mut tables = vec![(String::new(), Vec::new())];
+        for (i, line) in text.lines().enumerate() {
+            let invalid = |message: String| Error::new(ErrorKind::InvalidData, format!("{}: {}", i + 1, message));
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                let end = line.find(']').ok_or_else(|| invalid("unterminated table name".to_string()))?;
+                let rest = line[end + 1..].trim();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err(invalid(format!("unexpected '{}' after the table name", rest)));
+                }
+                let name = parse_key(line[1..end].trim()).ok_or_else(|| invalid(format!("invalid table name '{}'", &line[1..end])))?;
+                tables.push((name, Vec::new()));
+                continue;
+            }
+
+            let equals = line.find('=').ok_or_else(|| invalid(format!("expected 'key = value', found '{}'", line)))?;
+            let key = parse_key(line[..equals].trim()).ok_or_else(|| invalid(format!("invalid key '{}'", line[..equals].trim())))?;
+            let (value, rest) = parse_value(line[equals + 1..].trim()).map_err(&invalid)?;
+            let rest = rest.trim();
+            if !rest.is_empty() && !rest.starts_with('#') {
+                return Err(invalid(format!("unexpected '{}' after the value of {}", rest, key)));
+            }
+            tables.last_mut().unwrap().1.push((key, value));
+        }
+        Ok(Config { tables: tables })
+    }
+
+    /// The keys for every tool, in order
+    pub fn global(&self) -> Vec<&(String, Value)> {
+        self.table("")
+    }
+
+    /// The keys of a tool's table, in order
+    pub fn table(&self, name: &str) -> Vec<&(String, Value)> {
+        self.tables.iter()
+            .filter(|&&(ref table, _)| table == name)
+            .flat_map(|&(_, ref keys)| keys.iter())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Config, Value};
+
+    #[test]
+    fn parse_test() {
+        let config = Config::parse("# Defaults\n\
+                                    output = \"json\"\n\
+                                    \n\
+                                    [whois]\n\
+                                    host = 'whois.arin.net' # the ARIN server\n\
+                                    \n\
+                                    [ping]\n\
+                                    interval = 2\n\
+                                    4 = true\n").unwrap();
+        assert_eq!(config.global(), vec![&("output".to_string(), Value::Text("json".to_string()))]);
+        assert_eq!(config.table("whois"), vec![&("host".to_string(), Value::Text("whois.arin.net".to_string()))]);
+        assert_eq!(config.table("ping"), vec![&("interval".to_string(), Value::Text("2".to_string())),
+                                              &("4".to_string(), Value::Bool(true))]);
+        assert!(config.table("wget").is_empty());
+
+        assert_eq!(Config::parse("[ping]\ninterval = [1, 2]\n").unwrap_err().to_string(), "2: unsupported value '[1,'");
+        assert_eq!(Config::parse("host = \"a\" b\n").unwrap_err().to_string(), "1: unexpected 'b' after the value of host");
+        assert!(Config::parse("[ping\n").is_err());
+        assert!(Config::parse("host\n").is_err());
+    }
+}
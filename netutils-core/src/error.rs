@@ -0,0 +1,175 @@
+//! The kinds of error that the tools report, and the exit status of each, so that scripts can
+//! tell a host that is down from a mistyped command:
+//!
+//! | Status | Meaning |
+//! |--------|---------|
+//! | 0 | Success |
+//! | 1 | Any other failure, or a negative result, such as a host that didn't answer a ping |
+//! | 2 | Invalid arguments |
+//! | 3 | A host name couldn't be resolved |
+//! | 4 | A host refused the connection, or there is no route to it |
+//! | 5 | A host didn't answer in time |
+//! | 6 | A peer broke the protocol |
+
+use std::error::Error;
+use std::io::{self, ErrorKind, Write};
+use std::{fmt, process};
+
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_DNS: i32 = 3;
+pub const EXIT_UNREACHABLE: i32 = 4;
+pub const EXIT_TIMEOUT: i32 = 5;
+pub const EXIT_PROTOCOL: i32 = 6;
+
+#[derive(Debug)]
+pub enum NetError {
+    /// The arguments are invalid
+    Usage(String),
+    /// A host name couldn't be resolved
+    Dns(String),
+    /// A host refused the connection, or there is no route to it
+    Unreachable(String),
+    /// A host didn't answer in time
+    Timeout(String),
+    /// A peer sent something that the protocol doesn't allow
+    Protocol(String),
+    /// Any other failure, such as of a local file
+    Io(io::Error),
+}
+
+pub type Result<T> = ::std::result::Result<T, NetError>;
+
+impl NetError {
+    /// A failure that is none of the other kinds
+    pub fn other<S: Into<String>>(message: S) -> NetError {
+        NetError::Io(io::Error::new(ErrorKind::Other, message.into()))
+    }
+
+    /// The status that the program exits with for this error
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            NetError::Usage(_) => EXIT_USAGE,
+            NetError::Dns(_) => EXIT_DNS,
+            NetError::Unreachable(_) => EXIT_UNREACHABLE,
+            NetError::Timeout(_) => EXIT_TIMEOUT,
+            NetError::Protocol(_) => EXIT_PROTOCOL,
+            NetError::Io(_) => EXIT_FAILURE,
+        }
+    }
+
+    /// The same kind of error, with what was being done before the message, as in
+    /// `failed to connect to example.com: connection refused`
+    pub fn context(self, context: &str) -> NetError {
+        match self {
+            NetError::Usage(message) => NetError::Usage(format!("{}: {}", context, message)),
+            NetError::Dns(message) => NetError::Dns(format!("{}: {}", context, message)),
+            NetError::Unreachable(message) => NetError::Unreachable(format!("{}: {}", context, message)),
+            NetError::Timeout(message) => NetError::Timeout(format!("{}: {}", context, message)),
+            NetError::Protocol(message) => NetError::Protocol(format!("{}: {}", context, message)),
+            NetError::Io(err) => NetError::Io(io::Error::new(err.kind(), format!("{}: {}", context, err))),
+        }
+    }
+
+    /// Print the error after the name of the program, and exit with its status
+    pub fn exit(&self, name: &str) -> ! {
+        let _ = writeln!(io::stderr(), "{}: {}", name, self);
+        process::exit(self.exit_code());
+    }
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NetError::Usage(ref message) | NetError::Dns(ref message) | NetError::Unreachable(ref message) |
+            NetError::Timeout(ref message) | NetError::Protocol(ref message) => write!(f, "{}", message),
+            NetError::Io(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for NetError {
+    fn description(&self) -> &str {
+        match *self {
+            NetError::Usage(ref message) | NetError::Dns(ref message) | NetError::Unreachable(ref message) |
+            NetError::Timeout(ref message) | NetError::Protocol(ref message) => message,
+            NetError::Io(ref err) => err.description(),
+        }
+    }
+}
+
+#[cfg(not(target_os = "redox"))]
+fn no_route(err: &io::Error) -> bool {
+    use libc;
+    match err.raw_os_error() {
+        Some(code) => code == libc::ENETUNREACH || code == libc::EHOSTUNREACH || code == libc::EHOSTDOWN,
+        None => false
+    }
+}
+
+#[cfg(target_os = "redox")]
+fn no_route(_err: &io::Error) -> bool {
+    false
+}
+
+/// Connections that were refused, reset or have no route are unreachable, timeouts are timeouts
+/// and invalid data is a protocol error. Every other error is kept as it is, and an error that
+/// was converted to an `io::Error` is unwrapped again.
+impl From<io::Error> for NetError {
+    fn from(err: io::Error) -> NetError {
+        if err.get_ref().map_or(false, |inner| inner.is::<NetError>()) {
+            return *err.into_inner().unwrap().downcast::<NetError>().unwrap();
+        }
+        match err.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => NetError::Timeout(err.to_string()),
+            ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted |
+            ErrorKind::NotConnected | ErrorKind::AddrNotAvailable => NetError::Unreachable(err.to_string()),
+            ErrorKind::InvalidData => NetError::Protocol(err.to_string()),
+            _ if no_route(&err) => NetError::Unreachable(err.to_string()),
+            _ => NetError::Io(err)
+        }
+    }
+}
+
+/// For interfaces that only pass on I/O errors, such as hyper's connectors. The error is kept
+/// inside, so that converting it back gives the same error.
+impl From<NetError> for io::Error {
+    fn from(err: NetError) -> io::Error {
+        let kind = match err {
+            NetError::Io(err) => return err,
+            NetError::Usage(_) => ErrorKind::InvalidInput,
+            NetError::Dns(_) => ErrorKind::Other,
+            NetError::Unreachable(_) => ErrorKind::ConnectionRefused,
+            NetError::Timeout(_) => ErrorKind::TimedOut,
+            NetError::Protocol(_) => ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, Error, ErrorKind};
+    use super::{NetError, EXIT_DNS, EXIT_FAILURE, EXIT_TIMEOUT, EXIT_UNREACHABLE};
+
+    #[test]
+    fn from_io_test() {
+        let err = NetError::from(Error::new(ErrorKind::ConnectionRefused, "refused"));
+        assert_eq!(err.exit_code(), EXIT_UNREACHABLE);
+        let err = NetError::from(Error::new(ErrorKind::TimedOut, "timed out")).context("failed to connect to example.com");
+        assert_eq!(err.exit_code(), EXIT_TIMEOUT);
+        assert_eq!(err.to_string(), "failed to connect to example.com: timed out");
+        let err = NetError::from(Error::new(ErrorKind::NotFound, "no such file")).context("a.txt");
+        assert_eq!(err.exit_code(), EXIT_FAILURE);
+        assert_eq!(err.to_string(), "a.txt: no such file");
+    }
+
+    #[test]
+    fn into_io_test() {
+        let err = io::Error::from(NetError::Dns("failed to resolve example.com".to_string()));
+        assert_eq!(err.to_string(), "failed to resolve example.com");
+        let err = NetError::from(err);
+        assert_eq!(err.exit_code(), EXIT_DNS);
+        assert_eq!(err.to_string(), "failed to resolve example.com");
+    }
+}
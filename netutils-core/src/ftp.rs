@@ -0,0 +1,362 @@
+//! A minimal FTP client (RFC 959) supporting passive mode transfers
+
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A reply from the server, possibly spanning several lines
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reply {
+    pub code: u32,
+    pub message: String,
+}
+
+impl Reply {
+    /// Whether the reply is a positive preliminary (1xx) reply
+    pub fn is_preliminary(&self) -> bool {
+        self.code >= 100 && self.code < 200
+    }
+
+    /// Whether the reply is a positive completion (2xx) reply
+    pub fn is_complete(&self) -> bool {
+        self.code >= 200 && self.code < 300
+    }
+}
+
+/// Representation type used for transfers
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TransferType {
+    Ascii,
+    Binary,
+}
+
+/// Control connection to an FTP server
+pub struct FtpStream {
+    control: BufReader<TcpStream>,
+}
+
+impl FtpStream {
+    /// Connect to a server and read its greeting.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<FtpStream> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(Duration::new(30, 0)))?;
+        let mut ftp = FtpStream {
+            control: BufReader::new(stream),
+        };
+        let reply = ftp.read_reply()?;
+        ftp.expect(reply, &[220])?;
+        Ok(ftp)
+    }
+
+    fn expect(&self, reply: Reply, codes: &[u32]) -> Result<Reply> {
+        if codes.contains(&reply.code) {
+            Ok(reply)
+        } else {
+            Err(Error::new(ErrorKind::Other, format!("unexpected reply: {} {}", reply.code, reply.message)))
+        }
+    }
+
+    /// Read a single, possibly multi-line, reply from the control connection.
+    pub fn read_reply(&mut self) -> Result<Reply> {
+        let mut line = String::new();
+        if self.control.read_line(&mut line)? == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "control connection closed"));
+        }
+
+        let code = match line.get(..3).and_then(|code| code.parse::<u32>().ok()) {
+            Some(code) => code,
+            None => return Err(Error::new(ErrorKind::InvalidData, format!("invalid reply: {}", line.trim_right()))),
+        };
+
+        let mut message = line.get(4..).unwrap_or("").trim_right().to_string();
+        // A multi-line reply starts with "123-" and ends with a line starting with "123 "
+        if line.as_bytes().get(3) == Some(&b'-') {
+            let end = format!("{} ", code);
+            loop {
+                line.clear();
+                if self.control.read_line(&mut line)? == 0 {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "control connection closed"));
+                }
+                if line.starts_with(&end) {
+                    message.push('\n');
+                    message.push_str(line[4..].trim_right());
+                    break;
+                }
+                message.push('\n');
+                message.push_str(line.trim_right());
+            }
+        }
+
+        Ok(Reply {
+            code: code,
+            message: message,
+        })
+    }
+
+    /// Send a command and read the reply, without checking the reply code. A command with a
+    /// line break or NUL in it, such as from a decoded URL, is refused rather than letting it
+    /// send a command of its own.
+    pub fn command(&mut self, command: &str) -> Result<Reply> {
+        if command.contains(|c| c == '\r' || c == '\n' || c == '\0') {
+            return Err(Error::new(ErrorKind::InvalidInput, "FTP command contains a line break or NUL"));
+        }
+        {
+            let stream = self.control.get_mut();
+            stream.write_all(command.as_bytes())?;
+            stream.write_all(b"\r\n")?;
+            stream.flush()?;
+        }
+        self.read_reply()
+    }
+
+    /// Log in with the given credentials, an empty `user` logs in anonymously.
+    pub fn login(&mut self, user: &str, password: &str) -> Result<()> {
+        let (user, password) = if user.is_empty() {
+            ("anonymous", "anonymous@")
+        } else {
+            (user, password)
+        };
+
+        let reply = self.command(&format!("USER {}", user))?;
+        match reply.code {
+            230 => Ok(()),
+            331 | 332 => {
+                let reply = self.command(&format!("PASS {}", password))?;
+                self.expect(reply, &[202, 230]).map(|_| ())
+            },
+            _ => self.expect(reply, &[230]).map(|_| ()),
+        }
+    }
+
+    pub fn transfer_type(&mut self, transfer_type: TransferType) -> Result<()> {
+        let reply = self.command(match transfer_type {
+            TransferType::Ascii => "TYPE A",
+            TransferType::Binary => "TYPE I",
+        })?;
+        self.expect(reply, &[200]).map(|_| ())
+    }
+
+    pub fn cwd(&mut self, path: &str) -> Result<()> {
+        let reply = self.command(&format!("CWD {}", path))?;
+        self.expect(reply, &[250]).map(|_| ())
+    }
+
+    pub fn pwd(&mut self) -> Result<String> {
+        let reply = self.command("PWD")?;
+        let reply = self.expect(reply, &[257])?;
+        // The directory is quoted, with embedded quotes doubled
+        let message = reply.message;
+        match (message.find('"'), message.rfind('"')) {
+            (Some(start), Some(end)) if start < end => Ok(message[start + 1..end].replace("\"\"", "\"")),
+            _ => Ok(message),
+        }
+    }
+
+    /// Size of a file in bytes, if the server supports the SIZE extension
+    pub fn size(&mut self, path: &str) -> Result<Option<u64>> {
+        let reply = self.command(&format!("SIZE {}", path))?;
+        if reply.code == 213 {
+            Ok(reply.message.trim().parse().ok())
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Modification time of a file, if the server supports the MDTM extension
+    pub fn modified(&mut self, path: &str) -> Result<Option<SystemTime>> {
+        let reply = self.command(&format!("MDTM {}", path))?;
+        if reply.code == 213 {
+            Ok(parse_mdtm(reply.message.trim()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Open a data connection in passive mode, trying EPSV before PASV.
+    pub fn passive(&mut self) -> Result<TcpStream> {
+        let peer = self.control.get_ref().peer_addr()?;
+
+        let reply = self.command("EPSV")?;
+        let addr = if reply.code == 229 {
+            match parse_epsv(&reply.message) {
+                Some(port) => SocketAddr::new(peer.ip(), port),
+                None => return Err(Error::new(ErrorKind::InvalidData, format!("invalid EPSV reply: {}", reply.message))),
+            }
+        } else {
+            let reply = self.command("PASV")?;
+            let reply = self.expect(reply, &[227])?;
+            match parse_pasv(&reply.message) {
+                // Some servers behind NAT report a private address, use the control peer instead
+                Some(addr) => SocketAddr::new(peer.ip(), addr.port()),
+                None => return Err(Error::new(ErrorKind::InvalidData, format!("invalid PASV reply: {}", reply.message))),
+            }
+        };
+
+        let data = TcpStream::connect(addr)?;
+        data.set_read_timeout(Some(Duration::new(30, 0)))?;
+        Ok(data)
+    }
+
+    /// Open a data connection and send a transfer command on the control connection.
+    fn transfer(&mut self, command: &str) -> Result<TcpStream> {
+        let data = self.passive()?;
+        let reply = self.command(command)?;
+        if reply.is_preliminary() {
+            Ok(data)
+        } else {
+            Err(Error::new(ErrorKind::Other, format!("{} failed: {} {}", command, reply.code, reply.message)))
+        }
+    }
+
+    /// Start retrieving a file. Once the returned stream is read to the end, `finish` must be
+    /// called to read the transfer result.
+    pub fn retr(&mut self, path: &str) -> Result<TcpStream> {
+        self.transfer(&format!("RETR {}", path))
+    }
+
+    /// Start storing a file. Once the returned stream is written and dropped, `finish` must be
+    /// called to read the transfer result.
+    pub fn stor(&mut self, path: &str) -> Result<TcpStream> {
+        self.transfer(&format!("STOR {}", path))
+    }
+
+    /// Start a directory listing, followed by `finish` like `retr`.
+    pub fn list(&mut self, path: Option<&str>) -> Result<TcpStream> {
+        match path {
+            Some(path) => self.transfer(&format!("LIST {}", path)),
+            None => self.transfer("LIST"),
+        }
+    }
+
+    /// Start a listing of plain file names, followed by `finish` like `retr`.
+    pub fn nlst(&mut self, path: Option<&str>) -> Result<TcpStream> {
+        match path {
+            Some(path) => self.transfer(&format!("NLST {}", path)),
+            None => self.transfer("NLST"),
+        }
+    }
+
+    /// Read the reply sent when a data transfer completes.
+    pub fn finish(&mut self) -> Result<()> {
+        let reply = self.read_reply()?;
+        self.expect(reply, &[226, 250]).map(|_| ())
+    }
+
+    /// Read a whole listing into a string.
+    pub fn list_string(&mut self, path: Option<&str>, names_only: bool) -> Result<String> {
+        let mut listing = String::new();
+        {
+            let mut data = if names_only { self.nlst(path)? } else { self.list(path)? };
+            data.read_to_string(&mut listing)?;
+        }
+        self.finish()?;
+        Ok(listing)
+    }
+
+    pub fn quit(&mut self) -> Result<()> {
+        let reply = self.command("QUIT")?;
+        self.expect(reply, &[221]).map(|_| ())
+    }
+}
+
+/// Parse the address from a PASV reply such as "Entering Passive Mode (192,168,1,2,19,137)"
+pub fn parse_pasv(message: &str) -> Option<SocketAddr> {
+    let start = message.find('(')?;
+    let end = message[start..].find(')')? + start;
+    let numbers: Vec<u8> = message[start + 1..end]
+        .split(',')
+        .filter_map(|n| n.trim().parse().ok())
+        .collect();
+
+    if numbers.len() == 6 {
+        let ip = Ipv4Addr::new(numbers[0], numbers[1], numbers[2], numbers[3]);
+        let port = (numbers[4] as u16) << 8 | numbers[5] as u16;
+        Some(SocketAddr::new(IpAddr::V4(ip), port))
+    } else {
+        None
+    }
+}
+
+/// Parse the port from an EPSV reply such as "Entering Extended Passive Mode (|||6446|)"
+pub fn parse_epsv(message: &str) -> Option<u16> {
+    let start = message.find('(')?;
+    let end = message[start..].find(')')? + start;
+    message[start + 1..end].trim_matches('|').parse().ok()
+}
+
+/// Parse an MDTM timestamp of the form YYYYMMDDHHMMSS[.sss] in UTC
+pub fn parse_mdtm(value: &str) -> Option<SystemTime> {
+    let value = value.split('.').next()?;
+    if value.len() != 14 || !value.chars().all(|c| c.is_digit(10)) {
+        return None;
+    }
+
+    let field = |range: ::std::ops::Range<usize>| value[range].parse::<i64>().unwrap_or(0);
+    let (year, month, day) = (field(0..4), field(4..6), field(6..8));
+    let (hour, minute, second) = (field(8..10), field(10..12), field(12..14));
+    if month < 1 || month > 12 || day < 1 || day > 31 {
+        return None;
+    }
+
+    // Days since the epoch from a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second;
+    if secs < 0 {
+        None
+    } else {
+        Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::{parse_epsv, parse_mdtm, parse_pasv, FtpStream};
+
+    #[test]
+    fn command_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"220 ready\r\n").unwrap();
+            let mut received = String::new();
+            stream.read_to_string(&mut received).unwrap();
+            received
+        });
+
+        let mut ftp = FtpStream::connect(addr).unwrap();
+        for command in &["RETR a\r\nDELE b", "RETR a\nDELE b", "USER a\0"] {
+            assert_eq!(ftp.command(command).unwrap_err().kind(), ErrorKind::InvalidInput);
+        }
+        drop(ftp);
+        assert_eq!(server.join().unwrap(), "");
+    }
+
+    #[test]
+    fn parse_passive_test() {
+        assert_eq!(parse_pasv("Entering Passive Mode (192,168,1,2,19,137)"),
+                   Some("192.168.1.2:5001".parse::<SocketAddr>().unwrap()));
+        assert_eq!(parse_pasv("Entering Passive Mode (192,168,1,2,19)"), None);
+        assert_eq!(parse_pasv("Entering Passive Mode"), None);
+        assert_eq!(parse_epsv("Entering Extended Passive Mode (|||6446|)"), Some(6446));
+        assert_eq!(parse_epsv("Entering Extended Passive Mode (|||x|)"), None);
+    }
+
+    #[test]
+    fn parse_mdtm_test() {
+        assert_eq!(parse_mdtm("19700101000000"), Some(UNIX_EPOCH));
+        assert_eq!(parse_mdtm("20180312123456.123"), Some(UNIX_EPOCH + Duration::from_secs(1520858096)));
+        assert_eq!(parse_mdtm("2018031212345"), None);
+        assert_eq!(parse_mdtm("20181312123456"), None);
+    }
+}
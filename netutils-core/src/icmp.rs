@@ -0,0 +1,50 @@
+//! ICMP (RFC 792) and ICMPv6 (RFC 4443) message types
+
+/// The IP protocol number of ICMP
+pub const PROTO_ICMP: u8 = 1;
+/// The IP protocol number of ICMPv6
+pub const PROTO_ICMPV6: u8 = 58;
+
+pub const ECHO_REPLY: u8 = 0;
+pub const DEST_UNREACHABLE: u8 = 3;
+pub const REDIRECT: u8 = 5;
+pub const ECHO_REQUEST: u8 = 8;
+pub const TIME_EXCEEDED: u8 = 11;
+
+pub const V6_DEST_UNREACHABLE: u8 = 1;
+pub const V6_PACKET_TOO_BIG: u8 = 2;
+pub const V6_TIME_EXCEEDED: u8 = 3;
+pub const V6_ECHO_REQUEST: u8 = 128;
+pub const V6_ECHO_REPLY: u8 = 129;
+pub const V6_ROUTER_SOLICITATION: u8 = 133;
+pub const V6_ROUTER_ADVERTISEMENT: u8 = 134;
+pub const V6_NEIGHBOR_SOLICITATION: u8 = 135;
+pub const V6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// A description of an ICMP message
+pub fn name(kind: u8, code: u8) -> String {
+    match kind {
+        ECHO_REPLY => "echo reply".to_string(),
+        DEST_UNREACHABLE => format!("destination unreachable, code {}", code),
+        REDIRECT => "redirect".to_string(),
+        ECHO_REQUEST => "echo request".to_string(),
+        TIME_EXCEEDED => "time exceeded".to_string(),
+        _ => format!("type {} code {}", kind, code),
+    }
+}
+
+/// A description of an ICMPv6 message
+pub fn name_v6(kind: u8, code: u8) -> String {
+    match kind {
+        V6_DEST_UNREACHABLE => format!("destination unreachable, code {}", code),
+        V6_PACKET_TOO_BIG => "packet too big".to_string(),
+        V6_TIME_EXCEEDED => "time exceeded".to_string(),
+        V6_ECHO_REQUEST => "echo request".to_string(),
+        V6_ECHO_REPLY => "echo reply".to_string(),
+        V6_ROUTER_SOLICITATION => "router solicitation".to_string(),
+        V6_ROUTER_ADVERTISEMENT => "router advertisement".to_string(),
+        V6_NEIGHBOR_SOLICITATION => "neighbor solicitation".to_string(),
+        V6_NEIGHBOR_ADVERTISEMENT => "neighbor advertisement".to_string(),
+        _ => format!("type {} code {}", kind, code),
+    }
+}
@@ -1,3 +1,15 @@
+//! Code shared by the network utilities: option parsing and the configuration file, name
+//! resolution, proxies, TLS settings, logging for the daemons, raw sockets, rate limits, and the
+//! headers of the packets that they send and receive
+
+extern crate base64;
+extern crate hyper_rustls;
+#[cfg(not(target_os = "redox"))]
+extern crate libc;
+extern crate rustls;
+#[cfg(target_os = "redox")]
+extern crate syscall;
+
 use std::fs::File;
 use std::io::{Result, Read, Write};
 use std::{mem, slice, u8, u16};
@@ -5,10 +17,27 @@ use std::{mem, slice, u8, u16};
 pub use ip::Ipv4Addr;
 pub use mac::MacAddr;
 
+pub mod arp;
+pub mod args;
+pub mod config;
+pub mod error;
+pub mod ftp;
+pub mod icmp;
 mod ip;
+pub mod log;
 mod mac;
+#[cfg(not(target_os = "redox"))]
+pub mod netlink;
+pub mod output;
+pub mod proxy;
+pub mod rawsock;
+pub mod ratelimit;
+pub mod resolve;
+pub mod targets;
 pub mod tcp;
+pub mod tls;
 pub mod udp;
+pub mod whois;
 
 pub fn getcfg(key: &str) -> Result<String> {
     let mut value = String::new();
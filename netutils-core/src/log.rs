@@ -0,0 +1,298 @@
+//! Logging for the daemons. Messages have a level, and those below the level of the logger are
+//! dropped. They go to the terminal, to a file that is rotated once it reaches a size, or to the
+//! system log: syslog, or the `log:` scheme on Redox.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Result, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many rotated files are kept, as `file.1` to `file.3`, `file.1` being the newest
+const ROTATED_FILES: usize = 3;
+
+static MONTHS: [&'static str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun",
+                                      "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Format a time as used in the Common Log Format, such as "10/Oct/2000:13:55:36 +0000"
+pub fn format_time(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0) as i64;
+    let days = secs / 86400;
+    let rem = secs % 86400;
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+            day, MONTHS[(month - 1) as usize], year,
+            rem / 3600, rem / 60 % 60, rem % 60)
+}
+
+/// How severe a message is, from the most to the least
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+
+    /// The syslog severity (RFC 5424 section 6.2.1)
+    fn severity(&self) -> u8 {
+        match *self {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug => 7,
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = ();
+
+    fn from_str(text: &str) -> ::std::result::Result<Level, ()> {
+        match text {
+            "error" => Ok(Level::Error),
+            "warn" | "warning" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(())
+        }
+    }
+}
+
+/// A file that is moved aside once it reaches `max_size` bytes
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: Option<u64>,
+}
+
+impl LogFile {
+    fn open(path: PathBuf, max_size: Option<u64>) -> Result<LogFile> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(LogFile {
+            path: path,
+            file: file,
+            size: size,
+            max_size: max_size,
+        })
+    }
+
+    /// The path of the rotated file `n`, such as `httpd.log.1`
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        for n in (1..ROTATED_FILES).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+        *self = LogFile::open(self.path.clone(), self.max_size)?;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        let len = line.len() as u64 + 1;
+        if let Some(max_size) = self.max_size {
+            if self.size > 0 && self.size + len > max_size {
+                self.rotate()?;
+            }
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "redox"))]
+struct Syslog(::std::os::unix::net::UnixDatagram);
+
+#[cfg(not(target_os = "redox"))]
+impl Syslog {
+    fn open() -> Result<Syslog> {
+        let socket = ::std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect("/dev/log")?;
+        Ok(Syslog(socket))
+    }
+
+    /// Send a message from the daemon facility (RFC 3164)
+    fn send(&mut self, name: &str, level: Level, message: &str) -> Result<()> {
+        let line = format!("<{}>{}[{}]: {}", 3 * 8 + level.severity(), name, ::std::process::id(), message);
+        self.0.send(line.as_bytes()).map(|_| ())
+    }
+}
+
+#[cfg(target_os = "redox")]
+struct Syslog(File);
+
+#[cfg(target_os = "redox")]
+impl Syslog {
+    fn open() -> Result<Syslog> {
+        OpenOptions::new().write(true).open("log:").map(Syslog)
+    }
+
+    fn send(&mut self, name: &str, level: Level, message: &str) -> Result<()> {
+        writeln!(self.0, "{} [{}] {}", name, level.name(), message)
+    }
+}
+
+enum Output {
+    /// Info and debug messages on stdout, and warnings and errors on stderr after the name of
+    /// the program, as the tools print them
+    Console,
+    File(LogFile),
+    Syslog(Syslog),
+}
+
+/// A log shared between the threads of a daemon
+pub struct Logger {
+    name: &'static str,
+    /// The least severe level that is logged
+    pub level: Level,
+    output: Mutex<Output>,
+}
+
+impl Logger {
+    /// Log info and more severe messages to the terminal
+    pub fn console(name: &'static str) -> Logger {
+        Logger {
+            name: name,
+            level: Level::Info,
+            output: Mutex::new(Output::Console),
+        }
+    }
+
+    /// Log to `dest`: `-` for the terminal, `syslog` for the system log, or else a file that is
+    /// rotated once it reaches `max_size` bytes
+    pub fn open(name: &'static str, dest: &str, max_size: Option<u64>) -> Result<Logger> {
+        let output = match dest {
+            "-" => Output::Console,
+            "syslog" => Output::Syslog(Syslog::open()?),
+            path => Output::File(LogFile::open(PathBuf::from(path), max_size)?)
+        };
+        Ok(Logger {
+            name: name,
+            level: Level::Info,
+            output: Mutex::new(output),
+        })
+    }
+
+    pub fn enabled(&self, level: Level) -> bool {
+        level <= self.level
+    }
+
+    pub fn log(&self, level: Level, message: &str) {
+        if !self.enabled(level) {
+            return;
+        }
+        let mut output = match self.output.lock() {
+            Ok(output) => output,
+            Err(_) => return
+        };
+        let result = match *output {
+            Output::Console if level <= Level::Warn => writeln!(io::stderr(), "{}: {}", self.name, message),
+            Output::Console => {
+                let stdout = io::stdout();
+                let mut stdout = stdout.lock();
+                writeln!(stdout, "{}", message).and_then(|_| stdout.flush())
+            },
+            Output::File(ref mut file) => {
+                file.write_line(&format!("[{}] [{}] {}", format_time(SystemTime::now()), level.name(), message))
+            },
+            Output::Syslog(ref mut syslog) => syslog.send(self.name, level, message)
+        };
+        // A log that can't be written is reported where it can still be seen
+        if let Err(err) = result {
+            let _ = writeln!(io::stderr(), "{}: failed to write log: {}", self.name, err);
+        }
+    }
+
+    pub fn error(&self, message: &str) {
+        self.log(Level::Error, message);
+    }
+
+    pub fn warn(&self, message: &str) {
+        self.log(Level::Warn, message);
+    }
+
+    pub fn info(&self, message: &str) {
+        self.log(Level::Info, message);
+    }
+
+    pub fn debug(&self, message: &str) {
+        self.log(Level::Debug, message);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Read;
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::{format_time, Level, Logger};
+
+    #[test]
+    fn format_time_test() {
+        assert_eq!(format_time(UNIX_EPOCH), "01/Jan/1970:00:00:00 +0000");
+        assert_eq!(format_time(UNIX_EPOCH + Duration::from_secs(971186136)), "10/Oct/2000:13:55:36 +0000");
+        assert_eq!(format_time(UNIX_EPOCH + Duration::from_secs(951782400)), "29/Feb/2000:00:00:00 +0000");
+    }
+
+    #[test]
+    fn rotate_test() {
+        let dir = env::temp_dir().join(format!("netutils-log-test-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("test.log");
+
+        let mut log = Logger::open("test", path.to_str().unwrap(), Some(100)).unwrap();
+        log.level = Level::Warn;
+        log.info("dropped");
+        for i in 0..5 {
+            log.warn(&format!("message {}", i));
+        }
+
+        let read = |name: &str| {
+            let mut text = String::new();
+            File::open(dir.join(name)).unwrap().read_to_string(&mut text).unwrap();
+            text
+        };
+        // Each line is 46 bytes, so two fit in a file before it is rotated
+        assert!(read("test.log").ends_with("[warn] message 4\n"));
+        assert_eq!(read("test.log.1").lines().count(), 2);
+        assert!(read("test.log.2").ends_with("[warn] message 1\n"));
+        assert!(!dir.join("test.log.3").exists());
+        assert!(!read("test.log.2").contains("dropped"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
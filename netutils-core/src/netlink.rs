@@ -0,0 +1,265 @@
+//! A minimal rtnetlink client, for reading and changing the kernel's network tables on Linux
+
+use libc;
+use std::io::{Error, ErrorKind, Result};
+use std::{cmp, mem};
+
+pub const RTM_NEWLINK: u16 = 16;
+pub const RTM_GETLINK: u16 = 18;
+pub const RTM_NEWADDR: u16 = 20;
+pub const RTM_DELADDR: u16 = 21;
+pub const RTM_GETADDR: u16 = 22;
+pub const RTM_NEWROUTE: u16 = 24;
+pub const RTM_DELROUTE: u16 = 25;
+pub const RTM_GETROUTE: u16 = 26;
+pub const RTM_NEWNEIGH: u16 = 28;
+pub const RTM_DELNEIGH: u16 = 29;
+pub const RTM_GETNEIGH: u16 = 30;
+
+pub const NLM_F_REQUEST: u16 = 0x1;
+pub const NLM_F_ACK: u16 = 0x4;
+pub const NLM_F_REPLACE: u16 = 0x100;
+pub const NLM_F_EXCL: u16 = 0x200;
+pub const NLM_F_CREATE: u16 = 0x400;
+pub const NLM_F_DUMP: u16 = 0x300;
+
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+
+/// Size of struct nlmsghdr
+const HEADER_LEN: usize = 16;
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Read a field of a message, in native byte order
+pub fn read_u16(data: &[u8]) -> u16 {
+    (data[0] as u16) | (data[1] as u16) << 8
+}
+
+pub fn read_u32(data: &[u8]) -> u32 {
+    (data[0] as u32) | (data[1] as u32) << 8 | (data[2] as u32) << 16 | (data[3] as u32) << 24
+}
+
+/// A message to the kernel: the fixed header of its family, such as struct ndmsg, followed by
+/// attributes. Netlink uses native byte order, which is little endian on the supported targets.
+pub struct Request {
+    kind: u16,
+    flags: u16,
+    payload: Vec<u8>,
+}
+
+impl Request {
+    pub fn new(kind: u16, flags: u16, header: &[u8]) -> Request {
+        let mut payload = header.to_vec();
+        payload.resize(align(header.len()), 0);
+        Request {
+            kind: kind,
+            flags: flags,
+            payload: payload,
+        }
+    }
+
+    /// Append a struct rtattr
+    pub fn attr(mut self, kind: u16, data: &[u8]) -> Request {
+        let len = 4 + data.len();
+        self.payload.extend_from_slice(&[len as u8, (len >> 8) as u8, kind as u8, (kind >> 8) as u8]);
+        self.payload.extend_from_slice(data);
+        let aligned = align(self.payload.len());
+        self.payload.resize(aligned, 0);
+        self
+    }
+
+    fn to_bytes(&self, seq: u32) -> Vec<u8> {
+        let len = (HEADER_LEN + self.payload.len()) as u32;
+        let mut bytes = Vec::with_capacity(len as usize);
+        bytes.extend_from_slice(&[len as u8, (len >> 8) as u8, (len >> 16) as u8, (len >> 24) as u8]);
+        bytes.extend_from_slice(&[self.kind as u8, (self.kind >> 8) as u8, self.flags as u8, (self.flags >> 8) as u8]);
+        bytes.extend_from_slice(&[seq as u8, (seq >> 8) as u8, (seq >> 16) as u8, (seq >> 24) as u8]);
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+}
+
+/// A message from the kernel
+pub struct Message {
+    pub kind: u16,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    /// Split a datagram into its messages
+    fn parse(mut data: &[u8]) -> Vec<(Message, u32)> {
+        let mut messages = Vec::new();
+        while data.len() >= HEADER_LEN {
+            let len = read_u32(data) as usize;
+            if len < HEADER_LEN || len > data.len() {
+                break;
+            }
+            messages.push((Message {
+                kind: read_u16(&data[4..]),
+                payload: data[HEADER_LEN..len].to_vec(),
+            }, read_u32(&data[8..])));
+            data = &data[cmp::min(align(len), data.len())..];
+        }
+        messages
+    }
+
+    /// The fixed header of the message family, which is `len` bytes
+    pub fn header(&self, len: usize) -> Option<&[u8]> {
+        self.payload.get(..len)
+    }
+
+    /// The attributes after a fixed header of `header_len` bytes, as pairs of type and data
+    pub fn attrs(&self, header_len: usize) -> Vec<(u16, &[u8])> {
+        let mut attrs = Vec::new();
+        let mut data = self.payload.get(align(header_len)..).unwrap_or(&[]);
+        while data.len() >= 4 {
+            let len = read_u16(data) as usize;
+            if len < 4 || len > data.len() {
+                break;
+            }
+            // The top bits of the type are the nested and byte order flags
+            attrs.push((read_u16(&data[2..]) & 0x3FFF, &data[4..len]));
+            data = &data[cmp::min(align(len), data.len())..];
+        }
+        attrs
+    }
+
+    pub fn attr(&self, header_len: usize, kind: u16) -> Option<&[u8]> {
+        self.attrs(header_len).into_iter().find(|attr| attr.0 == kind).map(|attr| attr.1)
+    }
+}
+
+/// A NETLINK_ROUTE socket
+pub struct Socket {
+    fd: libc::c_int,
+    seq: u32,
+}
+
+impl Socket {
+    pub fn open() -> Result<Socket> {
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_RAW | libc::SOCK_CLOEXEC, libc::NETLINK_ROUTE) };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        // Replies go to the port the kernel assigns on the first send
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        let socket = Socket { fd: fd, seq: 0 };
+        if unsafe { libc::bind(fd, &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                               mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t) } < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(socket)
+    }
+
+    fn send(&mut self, request: &Request) -> Result<u32> {
+        self.seq = self.seq.wrapping_add(1);
+        let bytes = request.to_bytes(self.seq);
+        if unsafe { libc::send(self.fd, bytes.as_ptr() as *const libc::c_void, bytes.len(), 0) } < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(self.seq)
+    }
+
+    /// Receive the replies to request `seq`, until `done` returns true for one of them
+    fn receive<F: FnMut(Message) -> Result<bool>>(&mut self, seq: u32, mut done: F) -> Result<()> {
+        let mut buffer = vec![0u8; 65536];
+        loop {
+            let count = unsafe { libc::recv(self.fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0) };
+            if count < 0 {
+                return Err(Error::last_os_error());
+            }
+
+            for (message, message_seq) in Message::parse(&buffer[..count as usize]) {
+                if message_seq != seq {
+                    continue;
+                }
+                match message.kind {
+                    NLMSG_DONE => return Ok(()),
+                    // An error of 0 acknowledges a request
+                    NLMSG_ERROR => {
+                        let errno = message.payload.get(..4).map(|data| read_u32(data) as i32).unwrap_or(0);
+                        return if errno == 0 { Ok(()) } else { Err(Error::from_raw_os_error(-errno)) };
+                    },
+                    _ => if done(message)? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Request a whole table, such as RTM_GETNEIGH, returning its entries
+    pub fn dump(&mut self, kind: u16, header: &[u8]) -> Result<Vec<Message>> {
+        let seq = self.send(&Request::new(kind, NLM_F_REQUEST | NLM_F_DUMP, header))?;
+        let mut messages = Vec::new();
+        self.receive(seq, |message| {
+            messages.push(message);
+            Ok(false)
+        })?;
+        Ok(messages)
+    }
+
+    /// Make a change, waiting for the kernel to acknowledge it
+    pub fn execute(&mut self, request: Request) -> Result<()> {
+        let request = Request {
+            flags: request.flags | NLM_F_REQUEST | NLM_F_ACK,
+            ..request
+        };
+        let seq = self.send(&request)?;
+        self.receive(seq, |_| Err(Error::new(ErrorKind::InvalidData, "unexpected reply")))
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd); }
+    }
+}
+
+/// The index of an interface by name
+pub fn if_index(name: &str) -> Result<u32> {
+    let mut cname = name.as_bytes().to_vec();
+    cname.push(0);
+    match unsafe { libc::if_nametoindex(cname.as_ptr() as *const libc::c_char) } {
+        0 => Err(Error::new(ErrorKind::NotFound, format!("no interface named {}", name))),
+        index => Ok(index)
+    }
+}
+
+/// The name of an interface by index
+pub fn if_name(index: u32) -> Option<String> {
+    let mut name = [0u8; libc::IF_NAMESIZE];
+    if unsafe { libc::if_indextoname(index, name.as_mut_ptr() as *mut libc::c_char) }.is_null() {
+        return None;
+    }
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    Some(String::from_utf8_lossy(&name[..len]).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Message, Request, RTM_NEWNEIGH};
+
+    #[test]
+    fn request_test() {
+        let request = Request::new(RTM_NEWNEIGH, 0, &[2; 12]).attr(1, &[192, 168, 0, 1]).attr(2, &[1, 2, 3, 4, 5, 6]);
+        let bytes = request.to_bytes(7);
+        assert_eq!(bytes.len(), 16 + 12 + 8 + 12);
+        assert_eq!(&bytes[..4], &[48, 0, 0, 0]);
+
+        let messages = Message::parse(&bytes);
+        assert_eq!(messages.len(), 1);
+        let (ref message, seq) = messages[0];
+        assert_eq!((message.kind, seq), (RTM_NEWNEIGH, 7));
+        assert_eq!(message.header(12), Some(&[2; 12][..]));
+        assert_eq!(message.attr(12, 1), Some(&[192, 168, 0, 1][..]));
+        assert_eq!(message.attr(12, 2), Some(&[1, 2, 3, 4, 5, 6][..]));
+        assert_eq!(message.attr(12, 3), None);
+    }
+}
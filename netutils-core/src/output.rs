@@ -0,0 +1,122 @@
+//! Formatting of machine readable output. Tools that take `--output json` print a JSON object
+//! on a line for each result, and then one that sums them up, so that scripts can read them a
+//! line at a time.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// How a tool prints its results, chosen with `--output`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    /// A line of tab separated fields for each result
+    Tsv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Format, ()> {
+        match name {
+            "text" => Ok(Format::Text),
+            "tsv" => Ok(Format::Tsv),
+            "json" => Ok(Format::Json),
+            _ => Err(())
+        }
+    }
+}
+
+/// A JSON string literal of `text`, with quotes
+pub fn json_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _ if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// A JSON string literal of `text`, or `null`
+pub fn json_option<S: AsRef<str>>(text: Option<S>) -> String {
+    text.map(|text| json_string(text.as_ref())).unwrap_or("null".to_string())
+}
+
+/// A JSON object, built a field at a time, that is displayed on one line
+pub struct Object {
+    text: String,
+}
+
+impl Object {
+    pub fn new() -> Object {
+        Object { text: "{".to_string() }
+    }
+
+    /// Add a field whose value is already JSON
+    fn field(mut self, name: &str, value: &str) -> Object {
+        if self.text.len() > 1 {
+            self.text.push(',');
+        }
+        self.text.push_str(&json_string(name));
+        self.text.push(':');
+        self.text.push_str(value);
+        self
+    }
+
+    pub fn string(self, name: &str, value: &str) -> Object {
+        self.field(name, &json_string(value))
+    }
+
+    /// A string, or `null`
+    pub fn option<S: AsRef<str>>(self, name: &str, value: Option<S>) -> Object {
+        self.field(name, &json_option(value))
+    }
+
+    pub fn number<N: fmt::Display>(self, name: &str, value: N) -> Object {
+        self.field(name, &value.to_string())
+    }
+
+    /// A number, or `null`
+    pub fn number_option<N: fmt::Display>(self, name: &str, value: Option<N>) -> Object {
+        self.field(name, &value.map(|value| value.to_string()).unwrap_or("null".to_string()))
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}}}", self.text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{json_option, json_string, Object};
+
+    #[test]
+    fn json_string_test() {
+        assert_eq!(json_string("SSH-2.0"), "\"SSH-2.0\"");
+        assert_eq!(json_string("a \"b\" \\ c\u{1}"), "\"a \\\"b\\\" \\\\ c\\u0001\"");
+        assert_eq!(json_option(Some("x")), "\"x\"");
+        assert_eq!(json_option(None::<&str>), "null");
+    }
+
+    #[test]
+    fn object_test() {
+        assert_eq!(Object::new().to_string(), "{}");
+        let object = Object::new()
+            .string("host", "192.0.2.1")
+            .number("port", 80)
+            .option("banner", None::<&str>)
+            .number_option("time_ms", Some(1.5));
+        assert_eq!(object.to_string(), "{\"host\":\"192.0.2.1\",\"port\":80,\"banner\":null,\"time_ms\":1.5}");
+    }
+}
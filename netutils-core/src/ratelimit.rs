@@ -0,0 +1,209 @@
+//! Traffic shaping with token buckets, for the --limit-rate options of wget and nc and the
+//! bandwidth caps of httpd. A bucket holds up to a burst of bytes and fills at the rate, and
+//! each read or write takes as many bytes out of it as it moves, waiting for them if it must.
+//! One bucket shapes one direction of a connection, or every connection that shares it.
+
+use std::io::{Read, Result, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A rate in bytes a second and the largest burst at that rate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limit {
+    pub rate: u64,
+    pub burst: u64,
+}
+
+impl Limit {
+    /// A limit of `rate` bytes a second, in bursts of up to `burst` bytes, or a second's
+    /// worth if not given
+    pub fn new(rate: u64, burst: Option<u64>) -> Limit {
+        Limit {
+            rate: rate,
+            burst: burst.unwrap_or(rate),
+        }
+    }
+}
+
+/// Parse a number of bytes, such as 200k, with k, m and g being powers of 1024 as in wget
+pub fn parse_size(value: &str) -> Option<u64> {
+    let (number, multiplier) = match value.chars().last() {
+        Some('k') | Some('K') => (&value[..value.len() - 1], 1 << 10),
+        Some('m') | Some('M') => (&value[..value.len() - 1], 1 << 20),
+        Some('g') | Some('G') => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1)
+    };
+    let number = number.parse::<f64>().ok()?;
+    let bytes = (number * multiplier as f64) as u64;
+    if bytes > 0 {
+        Some(bytes)
+    } else {
+        None
+    }
+}
+
+struct State {
+    /// Below zero when bytes were taken before they were there, which their takers wait out
+    tokens: f64,
+    filled: Instant,
+}
+
+/// A token bucket, which may be shared between threads
+pub struct TokenBucket {
+    limit: Limit,
+    state: Mutex<State>,
+}
+
+impl TokenBucket {
+    /// A full bucket
+    pub fn new(limit: Limit) -> TokenBucket {
+        TokenBucket {
+            limit: limit,
+            state: Mutex::new(State {
+                tokens: limit.burst as f64,
+                filled: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> Limit {
+        self.limit
+    }
+
+    /// Take `bytes` out of the bucket at `now`, returning how long until they would have been
+    /// there
+    fn reserve(&self, bytes: usize, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        if now > state.filled {
+            let elapsed = now - state.filled;
+            let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+            state.tokens = (state.tokens + elapsed * self.limit.rate as f64).min(self.limit.burst as f64);
+            state.filled = now;
+        }
+        state.tokens -= bytes as f64;
+        if state.tokens >= 0.0 {
+            Duration::new(0, 0)
+        } else {
+            let secs = -state.tokens / self.limit.rate as f64;
+            Duration::new(secs as u64, (secs.fract() * 1e9) as u32)
+        }
+    }
+
+    /// Take `bytes` out of the bucket, sleeping until they are there
+    pub fn take(&self, bytes: usize) {
+        let wait = self.reserve(bytes, Instant::now());
+        if wait > Duration::new(0, 0) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// A reader or writer that is held to the rates of some buckets, such as one of its own and
+/// one shared with other connections
+pub struct Limited<T> {
+    inner: T,
+    buckets: Vec<Arc<TokenBucket>>,
+}
+
+impl<T> Limited<T> {
+    /// Hold `inner` to a bucket of its own, or pass everything through without a limit
+    pub fn new(inner: T, limit: Option<Limit>) -> Limited<T> {
+        Limited::shared(inner, limit.map(|limit| Arc::new(TokenBucket::new(limit))).into_iter().collect())
+    }
+
+    pub fn shared(inner: T, buckets: Vec<Arc<TokenBucket>>) -> Limited<T> {
+        Limited {
+            inner: inner,
+            buckets: buckets,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// The most to move at once, so that no read or write is larger than a burst
+    fn chunk(&self, len: usize) -> usize {
+        self.buckets.iter().fold(len, |len, bucket| {
+            let burst = bucket.limit.burst;
+            if burst < len as u64 { burst as usize } else { len }
+        })
+    }
+
+    fn wait_for(&self, bytes: usize) {
+        for bucket in self.buckets.iter() {
+            bucket.take(bytes);
+        }
+    }
+}
+
+impl<T: Read> Read for Limited<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.chunk(buf.len());
+        let count = self.inner.read(&mut buf[..len])?;
+        self.wait_for(count);
+        Ok(count)
+    }
+}
+
+impl<T: Write> Write for Limited<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let len = self.chunk(buf.len());
+        let count = self.inner.write(&buf[..len])?;
+        self.wait_for(count);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+    use std::time::{Duration, Instant};
+    use super::{parse_size, Limit, Limited, TokenBucket};
+
+    #[test]
+    fn parse_size_test() {
+        assert_eq!(parse_size("1500"), Some(1500));
+        assert_eq!(parse_size("20k"), Some(20 * 1024));
+        assert_eq!(parse_size("1.5M"), Some(1536 * 1024));
+        assert_eq!(parse_size("0"), None);
+        assert_eq!(parse_size("k"), None);
+    }
+
+    #[test]
+    fn bucket_test() {
+        let now = Instant::now();
+        let bucket = TokenBucket::new(Limit::new(1000, Some(500)));
+        // The burst goes through at once, but then the bucket is empty
+        assert_eq!(bucket.reserve(500, now), Duration::new(0, 0));
+        assert_eq!(bucket.reserve(250, now), Duration::from_millis(250));
+        // A second on, the debt is paid and the bucket is full again, and no fuller
+        let later = now + Duration::from_secs(1);
+        assert_eq!(bucket.reserve(500, later), Duration::new(0, 0));
+        assert_eq!(bucket.reserve(100, later), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn limited_test() {
+        let mut limited = Limited::new(Vec::new(), Some(Limit::new(1 << 30, Some(4))));
+        assert_eq!(limited.write(b"abcdefgh").unwrap(), 4);
+        limited.write_all(b"efgh").unwrap();
+        assert_eq!(limited.into_inner(), b"abcdefgh".to_vec());
+
+        let mut unlimited = Limited::new(Vec::new(), None);
+        assert_eq!(unlimited.write(b"abcdefgh").unwrap(), 8);
+    }
+}
@@ -0,0 +1,742 @@
+//! Raw sockets, over the schemes of Redox or the sockets of Linux: ICMP echo requests, which
+//! ping sends, link layer frames, which arp and arping send and pktdump captures, and the
+//! packets of an IP protocol, which portscan sends SYNs with. Opening them needs root, or
+//! CAP_NET_RAW on Linux, except for echo requests where the kernel allows unprivileged ping
+//! sockets.
+//!
+//! The tools call `drop_privileges` once their sockets are open, before reading anything from
+//! them, so that a bug in parsing what arrives can't use the rights that opening them needed.
+
+pub use self::sys::{drop_privileges, interface, Echo, Ip, Link};
+
+/// The ethertypes captured when a link is opened for every type, where frames can only be
+/// read by type
+#[cfg(target_os = "redox")]
+const ETHERTYPES: [u16; 3] = [0x0800, 0x0806, 0x86DD];
+
+/// The ethertype of a frame, after the destination and source addresses
+fn ethertype(frame: &[u8]) -> Option<u16> {
+    if frame.len() < 14 {
+        return None;
+    }
+    Some((frame[12] as u16) << 8 | frame[13] as u16)
+}
+
+/// The internet checksum (RFC 1071)
+#[cfg(not(target_os = "redox"))]
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    for word in data.chunks(2) {
+        sum += (word[0] as u32) << 8 | word.get(1).cloned().unwrap_or(0) as u32;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// An echo request with an ICMP or ICMPv6 header. The kernel computes the checksum of ICMPv6
+/// messages, since it covers the addresses of the IPv6 header.
+#[cfg(not(target_os = "redox"))]
+fn echo_request(v6: bool, id: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+    use icmp::{ECHO_REQUEST, V6_ECHO_REQUEST};
+
+    let mut packet = vec![if v6 { V6_ECHO_REQUEST } else { ECHO_REQUEST }, 0, 0, 0,
+                          (id >> 8) as u8, id as u8, (seq >> 8) as u8, seq as u8];
+    packet.extend_from_slice(payload);
+    if !v6 {
+        let sum = checksum(&packet);
+        packet[2] = (sum >> 8) as u8;
+        packet[3] = sum as u8;
+    }
+    packet
+}
+
+/// The sequence number and payload of an echo reply, if the message is one with the
+/// identifier `id`, or with any identifier if None
+#[cfg(not(target_os = "redox"))]
+fn echo_reply(v6: bool, id: Option<u16>, message: &[u8]) -> Option<(u16, &[u8])> {
+    use icmp::{ECHO_REPLY, V6_ECHO_REPLY};
+
+    if message.len() < 8 || message[0] != if v6 { V6_ECHO_REPLY } else { ECHO_REPLY } {
+        return None;
+    }
+    let reply_id = (message[4] as u16) << 8 | message[5] as u16;
+    if id.map_or(false, |id| id != reply_id) {
+        return None;
+    }
+    Some(((message[6] as u16) << 8 | message[7] as u16, &message[8..]))
+}
+
+#[cfg(not(target_os = "redox"))]
+mod sys {
+    use libc;
+    use mac::MacAddr;
+    use netlink::{self, Socket, RTM_GETADDR};
+    use std::fs::File;
+    use std::io::{Error, ErrorKind, Read, Result, Write};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::time::{Duration, Instant};
+    use std::{cmp, mem, process};
+
+    use super::{echo_reply, echo_request, ethertype};
+
+    const ETH_P_ALL: u16 = 0x0003;
+    const SOL_PACKET: libc::c_int = 263;
+    const PACKET_ADD_MEMBERSHIP: libc::c_int = 1;
+    const PACKET_MR_PROMISC: libc::c_ushort = 1;
+    const PACKET_OUTGOING: u8 = 4;
+    const ARPHRD_LOOPBACK: libc::c_ushort = 772;
+
+    const IFA_ADDRESS: u16 = 1;
+    const IFA_LOCAL: u16 = 2;
+
+    const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+    /// The user that root becomes if there is no `nobody` in the password database
+    const NOBODY: (libc::uid_t, libc::gid_t) = (65534, 65534);
+
+    /// struct __user_cap_header_struct
+    #[repr(C)]
+    struct CapHeader {
+        version: u32,
+        pid: libc::c_int,
+    }
+
+    /// struct __user_cap_data_struct
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct CapData {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+
+    #[repr(C)]
+    struct PacketMreq {
+        mr_ifindex: libc::c_int,
+        mr_type: libc::c_ushort,
+        mr_alen: libc::c_ushort,
+        mr_address: [u8; 8],
+    }
+
+    fn socket(domain: libc::c_int, kind: libc::c_int, protocol: libc::c_int) -> Result<File> {
+        let fd = unsafe { libc::socket(domain, kind | libc::SOCK_CLOEXEC, protocol) };
+        if fd < 0 {
+            let err = Error::last_os_error();
+            return Err(if err.kind() == ErrorKind::PermissionDenied {
+                Error::new(err.kind(), "raw sockets require root or CAP_NET_RAW")
+            } else {
+                err
+            });
+        }
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// Wait until `file` can be read, returning false if `timeout` passes first
+    fn wait(file: &File, timeout: Duration) -> Result<bool> {
+        let mut pollfd = libc::pollfd {
+            fd: file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = cmp::max(timeout.as_secs() * 1000 + timeout.subsec_nanos() as u64 / 1_000_000, 1);
+        match unsafe { libc::poll(&mut pollfd, 1, cmp::min(millis, i32::max_value() as u64) as libc::c_int) } {
+            count if count < 0 => Err(Error::last_os_error()),
+            count => Ok(count > 0)
+        }
+    }
+
+    fn nobody() -> (libc::uid_t, libc::gid_t) {
+        let passwd = unsafe { libc::getpwnam(b"nobody\0".as_ptr() as *const libc::c_char) };
+        if passwd.is_null() {
+            NOBODY
+        } else {
+            unsafe { ((*passwd).pw_uid, (*passwd).pw_gid) }
+        }
+    }
+
+    /// Become the user that ran the program if it is set-user-ID, or `nobody` if that user is
+    /// root, and give up every capability, such as CAP_NET_RAW given to the file
+    pub fn drop_privileges() -> Result<()> {
+        let (uid, gid) = match unsafe { libc::getuid() } {
+            0 => nobody(),
+            uid => (uid, unsafe { libc::getgid() })
+        };
+        if unsafe { libc::geteuid() } == 0 && unsafe { libc::setgroups(0, ::std::ptr::null()) } < 0 {
+            return Err(Error::last_os_error());
+        }
+        if unsafe { libc::setgid(gid) } < 0 || unsafe { libc::setuid(uid) } < 0 {
+            return Err(Error::last_os_error());
+        }
+        if unsafe { libc::setuid(0) } == 0 {
+            return Err(Error::new(ErrorKind::Other, "failed to drop root privileges"));
+        }
+
+        let header = CapHeader {
+            version: LINUX_CAPABILITY_VERSION_3,
+            pid: 0,
+        };
+        let data = [CapData { effective: 0, permitted: 0, inheritable: 0 }; 2];
+        if unsafe { libc::syscall(libc::SYS_capset, &header as *const CapHeader, data.as_ptr()) } < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// The hardware address and first IPv4 address of an interface
+    pub fn interface(iface: &str) -> Result<(MacAddr, Option<Ipv4Addr>)> {
+        let mut text = String::new();
+        File::open(format!("/sys/class/net/{}/address", iface))?.read_to_string(&mut text)?;
+        let mac = MacAddr::from_str(text.trim());
+
+        let index = netlink::if_index(iface)?;
+        let mut addr = None;
+        // struct ifaddrmsg for AF_INET
+        for message in Socket::open()?.dump(RTM_GETADDR, &[libc::AF_INET as u8, 0, 0, 0, 0, 0, 0, 0])? {
+            let header = match message.header(8) {
+                Some(header) => header,
+                None => continue
+            };
+            let message_index = (header[4] as u32) | (header[5] as u32) << 8 | (header[6] as u32) << 16 | (header[7] as u32) << 24;
+            if message_index != index {
+                continue;
+            }
+            if let Some(data) = message.attr(8, IFA_LOCAL).or_else(|| message.attr(8, IFA_ADDRESS)) {
+                if data.len() == 4 {
+                    addr = Some(Ipv4Addr::new(data[0], data[1], data[2], data[3]));
+                    break;
+                }
+            }
+        }
+        Ok((mac, addr))
+    }
+
+    /// Frames read from and written to a packet socket
+    pub struct Link {
+        file: File,
+        index: Option<libc::c_int>,
+    }
+
+    impl Link {
+        /// Frames of `ethertype`, or of every type if None, on `iface`, or on every interface
+        /// if None. Frames sent by this host are received as well.
+        pub fn open(iface: Option<&str>, ethertype: Option<u16>) -> Result<Link> {
+            let protocol = ethertype.unwrap_or(ETH_P_ALL).to_be();
+            let file = socket(libc::AF_PACKET, libc::SOCK_RAW, protocol as libc::c_int)?;
+
+            let index = match iface {
+                Some(iface) => {
+                    let index = netlink::if_index(iface)? as libc::c_int;
+                    let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+                    addr.sll_family = libc::AF_PACKET as libc::c_ushort;
+                    addr.sll_protocol = protocol;
+                    addr.sll_ifindex = index;
+                    if unsafe { libc::bind(file.as_raw_fd(), &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                                           mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t) } < 0 {
+                        return Err(Error::last_os_error());
+                    }
+                    Some(index)
+                },
+                None => None
+            };
+
+            Ok(Link {
+                file: file,
+                index: index,
+            })
+        }
+
+        /// Receive the frames to other hosts as well, until the link is closed. This needs an
+        /// interface.
+        pub fn set_promiscuous(&mut self) -> Result<()> {
+            let index = match self.index {
+                Some(index) => index,
+                None => return Err(Error::new(ErrorKind::InvalidInput, "promiscuous mode needs an interface"))
+            };
+            let mreq = PacketMreq {
+                mr_ifindex: index,
+                mr_type: PACKET_MR_PROMISC,
+                mr_alen: 0,
+                mr_address: [0; 8],
+            };
+            if unsafe { libc::setsockopt(self.file.as_raw_fd(), SOL_PACKET, PACKET_ADD_MEMBERSHIP,
+                                         &mreq as *const PacketMreq as *const libc::c_void,
+                                         mem::size_of::<PacketMreq>() as libc::socklen_t) } < 0 {
+                return Err(Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        pub fn send(&mut self, frame: &[u8]) -> Result<()> {
+            if ethertype(frame).is_none() {
+                return Err(Error::new(ErrorKind::InvalidInput, "frame is shorter than its header"));
+            }
+            self.file.write(frame).map(|_| ())
+        }
+
+        /// Wait for a frame, returning its length, or None if none arrives within `timeout`
+        pub fn receive(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> Result<Option<usize>> {
+            let deadline = timeout.map(|timeout| Instant::now() + timeout);
+            loop {
+                if let Some(deadline) = deadline {
+                    let now = Instant::now();
+                    if now >= deadline || !wait(&self.file, deadline - now)? {
+                        return Ok(None);
+                    }
+                }
+
+                let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+                let mut addr_len = mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+                let count = unsafe {
+                    libc::recvfrom(self.file.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0,
+                                   &mut addr as *mut libc::sockaddr_ll as *mut libc::sockaddr, &mut addr_len)
+                };
+                if count < 0 {
+                    return Err(Error::last_os_error());
+                }
+                // Frames sent over loopback are received again, so they would be seen twice
+                if addr.sll_pkttype == PACKET_OUTGOING && addr.sll_hatype == ARPHRD_LOOPBACK {
+                    continue;
+                }
+                return Ok(Some(count as usize));
+            }
+        }
+    }
+
+    /// The packets of one IP protocol over IPv4, such as TCP segments. They are sent without an
+    /// IP header, which the kernel adds, and every packet of the protocol that arrives is
+    /// received with its IP header.
+    pub struct Ip {
+        file: File,
+    }
+
+    impl Ip {
+        pub fn open(protocol: u8) -> Result<Ip> {
+            Ok(Ip {
+                file: socket(libc::AF_INET, libc::SOCK_RAW, protocol as libc::c_int)?,
+            })
+        }
+
+        pub fn send_to(&mut self, host: Ipv4Addr, packet: &[u8]) -> Result<()> {
+            let (addr, addr_len) = socket_addr(SocketAddr::new(IpAddr::V4(host), 0));
+            if unsafe { libc::sendto(self.file.as_raw_fd(), packet.as_ptr() as *const libc::c_void, packet.len(), 0,
+                                     &addr as *const libc::sockaddr_storage as *const libc::sockaddr, addr_len) } < 0 {
+                return Err(Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        /// Wait for a packet, returning its length, or None if none arrives within `timeout`
+        pub fn receive(&mut self, buf: &mut [u8], timeout: Duration) -> Result<Option<usize>> {
+            if !wait(&self.file, timeout)? {
+                return Ok(None);
+            }
+            self.file.read(buf).map(Some)
+        }
+    }
+
+    /// Echo requests to one host, or to any host of a family. A ping socket is used if the
+    /// kernel allows one, and a raw socket otherwise.
+    pub struct Echo {
+        file: File,
+        v6: bool,
+        /// The identifier of the requests, which the kernel chooses for ping sockets
+        id: Option<u16>,
+        /// Raw IPv4 sockets receive the IP header as well
+        ip_header: bool,
+    }
+
+    impl Echo {
+        pub fn open(host: IpAddr) -> Result<Echo> {
+            let echo = Echo::open_any(host.is_ipv6())?;
+
+            // Connecting filters out the messages of other hosts
+            let (addr, addr_len) = socket_addr(SocketAddr::new(host, 0));
+            if unsafe { libc::connect(echo.file.as_raw_fd(), &addr as *const libc::sockaddr_storage as *const libc::sockaddr,
+                                      addr_len) } < 0 {
+                return Err(Error::last_os_error());
+            }
+            Ok(echo)
+        }
+
+        /// Echo requests to any host, of IPv6 if `v6`, which are sent with `send_to`
+        pub fn open_any(v6: bool) -> Result<Echo> {
+            let (domain, protocol) = if v6 {
+                (libc::AF_INET6, libc::IPPROTO_ICMPV6)
+            } else {
+                (libc::AF_INET, libc::IPPROTO_ICMP)
+            };
+            let (file, raw) = match socket(domain, libc::SOCK_DGRAM, protocol) {
+                Ok(file) => (file, false),
+                Err(_) => (socket(domain, libc::SOCK_RAW, protocol)?, true)
+            };
+
+            Ok(Echo {
+                file: file,
+                v6: v6,
+                id: if raw { Some(process::id() as u16) } else { None },
+                ip_header: raw && !v6,
+            })
+        }
+
+        pub fn send(&mut self, seq: u16, payload: &[u8]) -> Result<()> {
+            let request = echo_request(self.v6, self.id.unwrap_or(0), seq, payload);
+            self.file.write(&request).map(|_| ())
+        }
+
+        pub fn send_to(&mut self, host: IpAddr, seq: u16, payload: &[u8]) -> Result<()> {
+            let request = echo_request(self.v6, self.id.unwrap_or(0), seq, payload);
+            let (addr, addr_len) = socket_addr(SocketAddr::new(host, 0));
+            if unsafe { libc::sendto(self.file.as_raw_fd(), request.as_ptr() as *const libc::c_void, request.len(), 0,
+                                     &addr as *const libc::sockaddr_storage as *const libc::sockaddr, addr_len) } < 0 {
+                return Err(Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        /// Wait for a reply, returning its sequence number and the length of the payload copied
+        /// into `buf`, or None if none arrives within `timeout`
+        pub fn receive(&mut self, buf: &mut [u8], timeout: Duration) -> Result<Option<(u16, usize)>> {
+            self.receive_from(buf, timeout).map(|reply| reply.map(|(_, seq, len)| (seq, len)))
+        }
+
+        /// Wait for a reply as `receive` does, returning the host that sent it as well
+        pub fn receive_from(&mut self, buf: &mut [u8], timeout: Duration) -> Result<Option<(IpAddr, u16, usize)>> {
+            let deadline = Instant::now() + timeout;
+            let mut packet = [0; 65536];
+            loop {
+                let now = Instant::now();
+                if now >= deadline || !wait(&self.file, deadline - now)? {
+                    return Ok(None);
+                }
+                let mut from: libc::sockaddr_storage = unsafe { mem::zeroed() };
+                let mut from_len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+                let count = unsafe {
+                    libc::recvfrom(self.file.as_raw_fd(), packet.as_mut_ptr() as *mut libc::c_void, packet.len(), 0,
+                                   &mut from as *mut libc::sockaddr_storage as *mut libc::sockaddr, &mut from_len)
+                };
+                if count < 0 {
+                    return Err(Error::last_os_error());
+                }
+                let count = count as usize;
+                let host = match ip_addr(&from) {
+                    Some(host) => host,
+                    None => continue
+                };
+
+                let mut message = &packet[..count];
+                if self.ip_header {
+                    let header_len = (message.first().cloned().unwrap_or(0) & 0xF) as usize * 4;
+                    if message.len() < header_len {
+                        continue;
+                    }
+                    message = &message[header_len..];
+                }
+                // Errors and the replies to other pings on raw sockets are skipped
+                if let Some((seq, payload)) = echo_reply(self.v6, self.id, message) {
+                    let len = cmp::min(payload.len(), buf.len());
+                    buf[..len].copy_from_slice(&payload[..len]);
+                    return Ok(Some((host, seq, len)));
+                }
+            }
+        }
+    }
+
+    fn socket_addr(addr: SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let len = match addr {
+            SocketAddr::V4(addr) => {
+                let sin = unsafe { &mut *(&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in) };
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_addr.s_addr = u32::from(*addr.ip()).to_be();
+                mem::size_of::<libc::sockaddr_in>()
+            },
+            SocketAddr::V6(addr) => {
+                let sin6 = unsafe { &mut *(&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in6) };
+                sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sin6.sin6_addr.s6_addr = addr.ip().octets();
+                sin6.sin6_scope_id = addr.scope_id();
+                mem::size_of::<libc::sockaddr_in6>()
+            }
+        };
+        (storage, len as libc::socklen_t)
+    }
+
+    fn ip_addr(storage: &libc::sockaddr_storage) -> Option<IpAddr> {
+        match storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                let sin = unsafe { &*(storage as *const libc::sockaddr_storage as *const libc::sockaddr_in) };
+                Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr))))
+            },
+            libc::AF_INET6 => {
+                let sin6 = unsafe { &*(storage as *const libc::sockaddr_storage as *const libc::sockaddr_in6) };
+                Some(IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr)))
+            },
+            _ => None
+        }
+    }
+}
+
+/// Frames are read and written through the ethernet scheme, a file for each ethertype, and echo
+/// requests through the icmp scheme. The network stack does not offer a promiscuous mode, and
+/// its schemes serve every interface.
+#[cfg(target_os = "redox")]
+mod sys {
+    use mac::MacAddr;
+    use std::fs::{File, OpenOptions};
+    use std::io::{Error, ErrorKind, Read, Result, Write};
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::str::FromStr;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use std::cmp;
+    use syscall;
+
+    use super::{ethertype, ETHERTYPES};
+
+    /// How long to sleep between reads of the non-blocking files
+    const POLL_INTERVAL_MS: u64 = 1;
+
+    fn cfg_value(iface: &str, cfg: &str) -> Result<String> {
+        let mut value = String::new();
+        File::open(format!("netcfg:ifaces/{}/{}", iface, cfg))?.read_to_string(&mut value)?;
+        Ok(value)
+    }
+
+    fn open(path: &str) -> Result<File> {
+        OpenOptions::new().read(true).write(true)
+            .custom_flags(syscall::O_NONBLOCK as i32)
+            .open(path)
+    }
+
+    /// Read the first of `files` that has anything, starting from `next`, until `deadline`
+    fn read(files: &mut [File], next: &mut usize, buf: &mut [u8], deadline: Option<Instant>) -> Result<Option<usize>> {
+        loop {
+            for _ in 0..files.len() {
+                let i = *next;
+                *next = (*next + 1) % files.len();
+                match files[i].read(buf) {
+                    Ok(count) => return Ok(Some(count)),
+                    Err(ref err) if err.kind() == ErrorKind::WouldBlock => (),
+                    Err(err) => return Err(err)
+                }
+            }
+            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                return Ok(None);
+            }
+            thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+        }
+    }
+
+    /// Become the user that ran the program, if it is set-user-ID. There is no unprivileged
+    /// user that root could become.
+    pub fn drop_privileges() -> Result<()> {
+        let result = syscall::getgid()
+            .and_then(|gid| syscall::setregid(gid, gid))
+            .and_then(|_| syscall::getuid())
+            .and_then(|uid| syscall::setreuid(uid, uid));
+        result.map(|_| ()).map_err(|err| Error::from_raw_os_error(err.errno))
+    }
+
+    pub fn interface(iface: &str) -> Result<(MacAddr, Option<Ipv4Addr>)> {
+        let mac = MacAddr::from_str(cfg_value(iface, "mac")?.trim());
+        // Addresses are listed as address/prefix length, one per line
+        let addr = cfg_value(iface, "addr/list")?.lines()
+            .filter_map(|line| Ipv4Addr::from_str(line.split('/').next().unwrap_or("")).ok())
+            .next();
+        Ok((mac, addr))
+    }
+
+    pub struct Link {
+        ethertypes: Vec<u16>,
+        files: Vec<File>,
+        next: usize,
+    }
+
+    impl Link {
+        pub fn open(_iface: Option<&str>, ethertype: Option<u16>) -> Result<Link> {
+            let ethertypes = match ethertype {
+                Some(ethertype) => vec![ethertype],
+                None => ETHERTYPES.to_vec()
+            };
+            let mut files = Vec::new();
+            for ethertype in ethertypes.iter() {
+                files.push(open(&format!("ethernet:{:x}", ethertype))?);
+            }
+            Ok(Link {
+                ethertypes: ethertypes,
+                files: files,
+                next: 0,
+            })
+        }
+
+        pub fn set_promiscuous(&mut self) -> Result<()> {
+            Err(Error::new(ErrorKind::Other, "promiscuous mode is not supported on Redox"))
+        }
+
+        pub fn send(&mut self, frame: &[u8]) -> Result<()> {
+            let kind = ethertype(frame).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "frame is shorter than its header"))?;
+            match self.ethertypes.iter().position(|&ethertype| ethertype == kind) {
+                Some(i) => self.files[i].write(frame).map(|_| ()),
+                None => Err(Error::new(ErrorKind::InvalidInput, format!("link was not opened for ethertype {:04x}", kind)))
+            }
+        }
+
+        pub fn receive(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> Result<Option<usize>> {
+            let deadline = timeout.map(|timeout| Instant::now() + timeout);
+            read(&mut self.files, &mut self.next, buf, deadline)
+        }
+    }
+
+    /// The network stack has no scheme for the packets of a protocol, so none can be opened
+    pub enum Ip {}
+
+    impl Ip {
+        pub fn open(_protocol: u8) -> Result<Ip> {
+            Err(Error::new(ErrorKind::Other, "raw IP sockets are not supported on Redox"))
+        }
+
+        pub fn send_to(&mut self, _host: Ipv4Addr, _packet: &[u8]) -> Result<()> {
+            match *self {}
+        }
+
+        pub fn receive(&mut self, _buf: &mut [u8], _timeout: Duration) -> Result<Option<usize>> {
+            match *self {}
+        }
+    }
+
+    /// The icmp scheme keeps the identifier, and reads and writes the sequence number before
+    /// the payload. Its files are opened for one host each, so sending to any host opens a file
+    /// for each host sent to.
+    pub struct Echo {
+        hosts: Vec<Ipv4Addr>,
+        files: Vec<File>,
+        next: usize,
+    }
+
+    fn ipv4(host: IpAddr) -> Result<Ipv4Addr> {
+        match host {
+            IpAddr::V4(addr) => Ok(addr),
+            IpAddr::V6(_) => Err(Error::new(ErrorKind::Other, "ICMPv6 is not supported on Redox"))
+        }
+    }
+
+    impl Echo {
+        pub fn open(host: IpAddr) -> Result<Echo> {
+            let mut echo = Echo::open_any(host.is_ipv6())?;
+            echo.file(ipv4(host)?)?;
+            Ok(echo)
+        }
+
+        pub fn open_any(v6: bool) -> Result<Echo> {
+            if v6 {
+                return Err(Error::new(ErrorKind::Other, "ICMPv6 is not supported on Redox"));
+            }
+            Ok(Echo {
+                hosts: Vec::new(),
+                files: Vec::new(),
+                next: 0,
+            })
+        }
+
+        /// The file for echo requests to `host`, opened the first time
+        fn file(&mut self, host: Ipv4Addr) -> Result<&mut File> {
+            let i = match self.hosts.iter().position(|&addr| addr == host) {
+                Some(i) => i,
+                None => {
+                    self.files.push(open(&format!("icmp:echo/{}", host))?);
+                    self.hosts.push(host);
+                    self.hosts.len() - 1
+                }
+            };
+            Ok(&mut self.files[i])
+        }
+
+        pub fn send(&mut self, seq: u16, payload: &[u8]) -> Result<()> {
+            let host = self.hosts[0];
+            self.send_to(IpAddr::V4(host), seq, payload)
+        }
+
+        pub fn send_to(&mut self, host: IpAddr, seq: u16, payload: &[u8]) -> Result<()> {
+            let mut request = vec![(seq >> 8) as u8, seq as u8];
+            request.extend_from_slice(payload);
+            self.file(ipv4(host)?)?.write(&request).map(|_| ())
+        }
+
+        pub fn receive(&mut self, buf: &mut [u8], timeout: Duration) -> Result<Option<(u16, usize)>> {
+            self.receive_from(buf, timeout).map(|reply| reply.map(|(_, seq, len)| (seq, len)))
+        }
+
+        pub fn receive_from(&mut self, buf: &mut [u8], timeout: Duration) -> Result<Option<(IpAddr, u16, usize)>> {
+            if self.files.is_empty() {
+                thread::sleep(timeout);
+                return Ok(None);
+            }
+            let mut reply = [0; 65536];
+            let count = match read(&mut self.files, &mut self.next, &mut reply, Some(Instant::now() + timeout))? {
+                Some(count) if count >= 2 => count,
+                _ => return Ok(None)
+            };
+            // `read` moves on past the file that it read
+            let host = self.hosts[(self.next + self.files.len() - 1) % self.files.len()];
+            let len = cmp::min(count - 2, buf.len());
+            buf[..len].copy_from_slice(&reply[2..2 + len]);
+            Ok(Some((IpAddr::V4(host), (reply[0] as u16) << 8 | reply[1] as u16, len)))
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "redox")))]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+    use super::{checksum, echo_reply, echo_request, ethertype, Echo};
+
+    #[test]
+    fn echo_test() {
+        let request = echo_request(false, 0x1234, 7, b"ping");
+        assert_eq!(&request[..2], &[8, 0]);
+        assert_eq!(&request[4..], &[0x12, 0x34, 0, 7, b'p', b'i', b'n', b'g']);
+        assert_eq!(checksum(&request), 0);
+
+        let mut reply = request.clone();
+        reply[0] = 0;
+        assert_eq!(echo_reply(false, Some(0x1234), &reply), Some((7, &b"ping"[..])));
+        assert_eq!(echo_reply(false, None, &reply), Some((7, &b"ping"[..])));
+        assert_eq!(echo_reply(false, Some(0x4321), &reply), None);
+        assert_eq!(echo_reply(true, None, &reply), None);
+        assert_eq!(echo_reply(false, None, &request), None);
+
+        // ICMPv6 checksums are left to the kernel
+        assert_eq!(&echo_request(true, 1, 2, &[])[..], &[128, 0, 0, 0, 0, 1, 0, 2]);
+        assert_eq!(ethertype(&[0; 13]), None);
+    }
+
+    #[test]
+    fn loopback_test() {
+        // Ping sockets are only allowed to the groups in net.ipv4.ping_group_range, and raw
+        // sockets only to root, so there may be no way to send
+        let mut echo = match Echo::open(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))) {
+            Ok(echo) => echo,
+            Err(_) => return
+        };
+        echo.send(1, b"netutils").unwrap();
+        let mut buf = [0; 64];
+        assert_eq!(echo.receive(&mut buf, Duration::from_secs(5)).unwrap(), Some((1, 8)));
+        assert_eq!(&buf[..8], b"netutils");
+    }
+
+    #[test]
+    fn any_test() {
+        let mut echo = match Echo::open_any(false) {
+            Ok(echo) => echo,
+            Err(_) => return
+        };
+        let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        echo.send_to(localhost, 2, b"netutils").unwrap();
+        let mut buf = [0; 64];
+        assert_eq!(echo.receive_from(&mut buf, Duration::from_secs(5)).unwrap(), Some((localhost, 2, 8)));
+    }
+}
@@ -0,0 +1,283 @@
+//! Name resolution with the system resolver, and connecting to hosts by name. Hosts may be names
+//! or address literals, and IPv6 literals may be in brackets and have a zone, as in
+//! `[fe80::1%eth0]`.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use error::{NetError, Result};
+
+/// How long a connection attempt has before the next one is started alongside it (RFC 8305
+/// section 5)
+const CONNECTION_ATTEMPT_DELAY_MS: u64 = 250;
+
+/// The address families that a tool may use, chosen with -4 and -6
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Family {
+    Any,
+    V4,
+    V6,
+}
+
+impl Family {
+    pub fn matches(&self, addr: &IpAddr) -> bool {
+        match *self {
+            Family::Any => true,
+            Family::V4 => addr.is_ipv4(),
+            Family::V6 => addr.is_ipv6(),
+        }
+    }
+
+    /// The address to listen on every interface with: `::` for IPv6, and otherwise `0.0.0.0`
+    pub fn unspecified(&self) -> IpAddr {
+        match *self {
+            Family::V6 => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
+            _ => IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Family::Any => "",
+            Family::V4 => "IPv4 ",
+            Family::V6 => "IPv6 ",
+        }
+    }
+}
+
+/// A host without the brackets around it, if it has them
+fn unbracket(host: &str) -> &str {
+    if host.starts_with('[') && host.ends_with(']') {
+        &host[1..host.len() - 1]
+    } else {
+        host
+    }
+}
+
+#[cfg(not(target_os = "redox"))]
+fn interface_index(name: &str) -> Option<u32> {
+    use libc;
+    use std::ffi::CString;
+    let name = CString::new(name).ok()?;
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => None,
+        index => Some(index)
+    }
+}
+
+#[cfg(target_os = "redox")]
+fn interface_index(_name: &str) -> Option<u32> {
+    None
+}
+
+/// Parse an address literal, which for IPv6 may have a zone, given by the name or the index of
+/// an interface
+fn literal(host: &str, port: u16) -> Result<Option<SocketAddr>> {
+    let host = unbracket(host);
+    match host.find('%') {
+        Some(percent) => {
+            let invalid = || NetError::Usage(format!("invalid address '{}'", host));
+            let addr = host[..percent].parse::<Ipv6Addr>().map_err(|_| invalid())?;
+            let zone = &host[percent + 1..];
+            let scope_id = zone.parse().ok().or_else(|| interface_index(zone))
+                .ok_or_else(|| NetError::Usage(format!("unknown interface '{}' in '{}'", zone, host)))?;
+            Ok(Some(SocketAddr::V6(SocketAddrV6::new(addr, port, 0, scope_id))))
+        },
+        None => Ok(host.parse::<IpAddr>().ok().map(|addr| SocketAddr::new(addr, port)))
+    }
+}
+
+/// Split a `host:port` operand into the host, without brackets, and the port if there is one.
+/// An IPv6 address has to be in brackets to be followed by a port, as in `[2001:db8::1]:80`.
+pub fn split_port(text: &str) -> Result<(&str, Option<u16>)> {
+    let (host, port) = if text.starts_with('[') {
+        match text.find(']') {
+            Some(end) if end + 1 == text.len() => (&text[1..end], None),
+            Some(end) if text[end + 1..].starts_with(':') => (&text[1..end], Some(&text[end + 2..])),
+            _ => return Err(NetError::Usage(format!("invalid address '{}'", text)))
+        }
+    } else {
+        match text.rfind(':') {
+            // More than one colon is an IPv6 address without a port
+            Some(colon) if !text[..colon].contains(':') => (&text[..colon], Some(&text[colon + 1..])),
+            _ => (text, None)
+        }
+    };
+    match port {
+        Some(port) => match port.parse() {
+            Ok(port) => Ok((host, Some(port))),
+            Err(_) => Err(NetError::Usage(format!("invalid port '{}'", port)))
+        },
+        None => Ok((host, None))
+    }
+}
+
+/// The addresses of a host of the family, in the order that the resolver returned them
+pub fn addrs(host: &str, port: u16, family: Family) -> Result<Vec<SocketAddr>> {
+    if let Some(addr) = literal(host, port)? {
+        if !family.matches(&addr.ip()) {
+            return Err(NetError::Usage(format!("{} is not an {}address", host, family.name())));
+        }
+        return Ok(vec![addr]);
+    }
+
+    let addrs: Vec<SocketAddr> = match (unbracket(host), port).to_socket_addrs() {
+        Ok(addrs) => addrs.filter(|addr| family.matches(&addr.ip())).collect(),
+        Err(err) => return Err(NetError::Dns(format!("failed to resolve {}: {}", host, err)))
+    };
+    if addrs.is_empty() {
+        return Err(NetError::Dns(format!("failed to resolve {}: no {}address found", host, family.name())));
+    }
+    Ok(addrs)
+}
+
+/// The addresses of a host name or address literal, without duplicates, in the order that the
+/// resolver returned them
+pub fn lookup(host: &str, family: Family) -> Result<Vec<IpAddr>> {
+    let mut ips = Vec::new();
+    for addr in addrs(host, 0, family)? {
+        if !ips.contains(&addr.ip()) {
+            ips.push(addr.ip());
+        }
+    }
+    Ok(ips)
+}
+
+/// The first address of a host
+pub fn resolve(host: &str, port: u16, family: Family) -> Result<SocketAddr> {
+    Ok(addrs(host, port, family)?[0])
+}
+
+/// Alternate between the families of the addresses, starting with the family of the first, so
+/// that a family that is broken delays the connection by no more than an attempt (RFC 8305
+/// section 4)
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_v6 = addrs.get(0).map_or(false, |addr| addr.is_ipv6());
+    let (preferred, other): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs.into_iter()
+        .partition(|addr| addr.is_ipv6() == first_v6);
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    let mut interleaved = Vec::new();
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return interleaved,
+            (first, second) => interleaved.extend(first.into_iter().chain(second))
+        }
+    }
+}
+
+/// Race connections to the addresses, starting each after the one before has failed or had
+/// the attempt delay to itself, and return the first that succeeds. The attempts that lose
+/// are closed when they finish.
+fn race(addrs: Vec<SocketAddr>, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let (sender, receiver) = mpsc::channel();
+    let mut queue = addrs.into_iter();
+    let mut pending = 0;
+    let mut last = None;
+    loop {
+        if let Some(addr) = queue.next() {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let _ = sender.send(match timeout {
+                    Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                    None => TcpStream::connect(addr)
+                });
+            });
+            pending += 1;
+        }
+        if pending == 0 {
+            return Err(last.expect("no addresses to connect to"));
+        }
+
+        let result = if queue.len() > 0 {
+            match receiver.recv_timeout(Duration::from_millis(CONNECTION_ATTEMPT_DELAY_MS)) {
+                Ok(result) => result,
+                Err(_) => continue
+            }
+        } else {
+            receiver.recv().expect("connection attempt was lost")
+        };
+        pending -= 1;
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last = Some(err)
+        }
+    }
+}
+
+/// Connect to a host with Happy Eyeballs (RFC 8305): the addresses of both families are tried
+/// in turn, each attempt getting a head start of 250 ms before the next joins it, and the first
+/// connection wins. A host whose IPv6 addresses are broken then takes a moment longer to reach
+/// rather than a timeout. `timeout` limits each attempt, rather than the whole.
+pub fn connect(host: &str, port: u16, family: Family, timeout: Option<Duration>) -> Result<TcpStream> {
+    race(interleave(addrs(host, port, family)?), timeout)
+        .map_err(|err| NetError::from(err).context(&format!("failed to connect to {} port {}", host, port)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, SocketAddr, SocketAddrV6, TcpListener};
+    use std::time::{Duration, Instant};
+    use error::{EXIT_UNREACHABLE, EXIT_USAGE};
+    use super::{addrs, connect, interleave, lookup, race, resolve, split_port, Family};
+
+    #[test]
+    fn literal_test() {
+        assert_eq!(lookup("192.0.2.1", Family::Any).unwrap(), vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(lookup("2001:db8::1", Family::Any).unwrap(), vec!["2001:db8::1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(resolve("127.0.0.1", 80, Family::V4).unwrap(), "127.0.0.1:80".parse().unwrap());
+        assert_eq!(resolve("[::1]", 80, Family::Any).unwrap(), "[::1]:80".parse().unwrap());
+        assert_eq!(addrs("[fe80::1%3]", 80, Family::V6).unwrap(),
+                   vec![SocketAddr::V6(SocketAddrV6::new("fe80::1".parse().unwrap(), 80, 0, 3))]);
+        assert_eq!(resolve("127.0.0.1", 80, Family::V6).unwrap_err().exit_code(), EXIT_USAGE);
+        assert!(addrs("fe80::1%no-such-interface", 80, Family::Any).is_err());
+    }
+
+    #[test]
+    fn split_port_test() {
+        assert_eq!(split_port("example.com:80").unwrap(), ("example.com", Some(80)));
+        assert_eq!(split_port("example.com").unwrap(), ("example.com", None));
+        assert_eq!(split_port("[2001:db8::1]:80").unwrap(), ("2001:db8::1", Some(80)));
+        assert_eq!(split_port("[fe80::1%eth0]").unwrap(), ("fe80::1%eth0", None));
+        assert_eq!(split_port("2001:db8::1").unwrap(), ("2001:db8::1", None));
+        assert_eq!(split_port(":8080").unwrap(), ("", Some(8080)));
+        assert!(split_port("example.com:http").is_err());
+        assert!(split_port("[2001:db8::1").is_err());
+    }
+
+    #[test]
+    fn connect_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stream = connect("127.0.0.1", port, Family::Any, None).unwrap();
+        assert_eq!(stream.peer_addr().unwrap().port(), port);
+
+        drop(stream);
+        drop(listener);
+        assert_eq!(connect("127.0.0.1", port, Family::Any, None).unwrap_err().exit_code(), EXIT_UNREACHABLE);
+    }
+
+    #[test]
+    fn interleave_test() {
+        let addr = |text: &str| text.parse::<SocketAddr>().unwrap();
+        let addrs = vec![addr("[2001:db8::1]:80"), addr("[2001:db8::2]:80"), addr("[2001:db8::3]:80"),
+                         addr("192.0.2.1:80"), addr("192.0.2.2:80")];
+        assert_eq!(interleave(addrs), vec![addr("[2001:db8::1]:80"), addr("192.0.2.1:80"), addr("[2001:db8::2]:80"),
+                                           addr("192.0.2.2:80"), addr("[2001:db8::3]:80")]);
+    }
+
+    #[test]
+    fn race_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let local = listener.local_addr().unwrap();
+        // An address that doesn't answer only delays the next attempt, rather than the whole
+        let blackhole = "192.0.2.1:9".parse().unwrap();
+        let start = Instant::now();
+        let stream = race(vec![blackhole, local], Some(Duration::new(10, 0))).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), local);
+        assert!(start.elapsed() < Duration::new(5, 0));
+    }
+}
@@ -0,0 +1,89 @@
+//! The hosts to probe, as portscan and ping --sweep take them: addresses, host names, IPv4
+//! networks and ranges of the last byte of an IPv4 address
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+use error::{NetError, Result};
+use resolve::{self, Family};
+
+/// Networks larger than this are refused, since probing them takes hours
+pub const MIN_PREFIX: u32 = 16;
+
+/// The hosts of an IPv4 network. The network and broadcast addresses are left out, except of
+/// networks too small to have them.
+fn network(addr: Ipv4Addr, prefix: u32) -> ::std::result::Result<Vec<IpAddr>, String> {
+    if prefix > 32 {
+        return Err(format!("invalid prefix length {}", prefix));
+    }
+    if prefix < MIN_PREFIX {
+        return Err(format!("/{} is too large, the largest network is /{}", prefix, MIN_PREFIX));
+    }
+    let mask = !0u32 << (32 - prefix);
+    let first = u32::from(addr) & mask;
+    let last = first | !mask;
+    let (first, last) = if prefix >= 31 { (first, last) } else { (first + 1, last - 1) };
+    Ok((first as u64..last as u64 + 1).map(|addr| IpAddr::V4(Ipv4Addr::from(addr as u32))).collect())
+}
+
+/// Parse a target: an address, a network such as `192.0.2.0/24`, a range of the last byte of an
+/// IPv4 address such as `192.0.2.10-20`, or a host name, which is resolved to its first address
+/// of `family`
+pub fn parse_target(text: &str, family: Family) -> Result<Vec<IpAddr>> {
+    let addrs = parse_addrs(text)?;
+    match addrs {
+        Some(ref addrs) if addrs.iter().any(|addr| !family.matches(addr)) => {
+            let name = if family == Family::V4 { "IPv4" } else { "IPv6" };
+            Err(NetError::Usage(format!("'{}' is not {}", text, name)))
+        },
+        Some(addrs) => Ok(addrs),
+        // Only the first address of a name is probed
+        None => resolve::lookup(text, family).map(|addrs| vec![addrs[0]])
+    }
+}
+
+/// The addresses of a target given by address, or None for a host name
+fn parse_addrs(text: &str) -> Result<Option<Vec<IpAddr>>> {
+    if let Some(slash) = text.find('/') {
+        let invalid = || NetError::Usage(format!("invalid network '{}'", text));
+        let addr = Ipv4Addr::from_str(&text[..slash]).map_err(|_| invalid())?;
+        let prefix = text[slash + 1..].parse().map_err(|_| invalid())?;
+        return network(addr, prefix).map(Some).map_err(NetError::Usage);
+    }
+
+    if let Some(dash) = text.find('-') {
+        if let Ok(first) = Ipv4Addr::from_str(&text[..dash]) {
+            let octets = first.octets();
+            let last = match text[dash + 1..].parse::<u8>() {
+                Ok(last) if last >= octets[3] => last,
+                _ => return Err(NetError::Usage(format!("invalid address range '{}'", text)))
+            };
+            return Ok(Some((octets[3] as u16..last as u16 + 1)
+                .map(|byte| IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], byte as u8))).collect()));
+        }
+    }
+
+    Ok(IpAddr::from_str(text).ok().map(|addr| vec![addr]))
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use resolve::Family;
+    use super::parse_target;
+
+    #[test]
+    fn parse_target_test() {
+        let addr = |text: &str| text.parse::<IpAddr>().unwrap();
+        let network = parse_target("192.0.2.77/24", Family::Any).unwrap();
+        assert_eq!(network.len(), 254);
+        assert_eq!(network[0], addr("192.0.2.1"));
+        assert_eq!(network[253], addr("192.0.2.254"));
+        assert_eq!(parse_target("192.0.2.8/31", Family::Any).unwrap(), vec![addr("192.0.2.8"), addr("192.0.2.9")]);
+        assert_eq!(parse_target("192.0.2.250-255", Family::V4).unwrap().len(), 6);
+        assert_eq!(parse_target("2001:db8::1", Family::Any).unwrap(), vec![addr("2001:db8::1")]);
+        assert!(parse_target("2001:db8::1", Family::V4).is_err());
+        assert!(parse_target("10.0.0.0/8", Family::Any).is_err());
+        assert!(parse_target("192.0.2.20-10", Family::Any).is_err());
+    }
+}
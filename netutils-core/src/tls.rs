@@ -0,0 +1,217 @@
+//! TLS settings and connections for the clients and servers, with rustls. Every tool loads
+//! certificates and reports TLS errors through here, so that they all trust the same roots and
+//! fail the same way.
+
+use hyper_rustls;
+use rustls::{self, Certificate, ClientSession, PrivateKey, RootCertStore, ServerCertVerifier, ServerSession,
+             Session, TLSError};
+use rustls::internal::pemfile;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Result, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+
+fn invalid(path: &str, what: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{}: {}", path, what))
+}
+
+/// Open a PEM file, with its path in the error if it can't be
+fn open(path: &str) -> Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| Error::new(err.kind(), format!("{}: {}", path, err)))
+}
+
+/// Load a PEM certificate chain
+pub fn load_certs(path: &str) -> Result<Vec<Certificate>> {
+    let certs = pemfile::certs(&mut open(path)?)
+        .map_err(|_| invalid(path, "invalid certificate"))?;
+    if certs.is_empty() {
+        return Err(invalid(path, "no certificates found"));
+    }
+    Ok(certs)
+}
+
+/// Load the first PKCS #8 or RSA private key of a PEM file
+pub fn load_private_key(path: &str) -> Result<PrivateKey> {
+    let mut keys = pemfile::pkcs8_private_keys(&mut open(path)?)
+        .map_err(|_| invalid(path, "invalid private key"))?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut open(path)?)
+            .map_err(|_| invalid(path, "invalid private key"))?;
+    }
+    keys.into_iter().next().ok_or_else(|| invalid(path, "no private key found"))
+}
+
+/// What a client trusts, and what it presents to servers
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    /// A PEM file of the certificate authorities to trust instead of the Mozilla roots
+    pub ca_file: Option<String>,
+    /// Whether the certificate of the server is checked, which is only worth turning off to
+    /// reach a test server
+    pub verify: bool,
+    /// The PEM certificate chain to present, if the server asks for one
+    pub cert: Option<String>,
+    /// The PEM private key of the certificate
+    pub key: Option<String>,
+    /// The protocols to offer with ALPN, such as `http/1.1`
+    pub protocols: Vec<String>,
+}
+
+impl ClientOptions {
+    /// Check certificates against the Mozilla roots, as hyper does, and present none
+    pub fn new() -> ClientOptions {
+        ClientOptions {
+            ca_file: None,
+            verify: true,
+            cert: None,
+            key: None,
+            protocols: Vec::new(),
+        }
+    }
+}
+
+/// Accepts every certificate, for `verify: false`
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(&self, _roots: &RootCertStore, _presented_certs: &[Certificate], _dns_name: &str)
+                          -> ::std::result::Result<(), TLSError> {
+        Ok(())
+    }
+}
+
+/// Client settings for the options
+pub fn client_config(options: &ClientOptions) -> Result<Arc<rustls::ClientConfig>> {
+    let mut config = (*hyper_rustls::TlsClient::new().cfg).clone();
+
+    if let Some(ref path) = options.ca_file {
+        config.root_store = RootCertStore::empty();
+        let (added, _) = config.root_store.add_pem_file(&mut open(path)?)
+            .map_err(|_| invalid(path, "invalid certificate"))?;
+        if added == 0 {
+            return Err(invalid(path, "no certificates found"));
+        }
+    }
+    if !options.verify {
+        config.dangerous().set_certificate_verifier(Arc::new(NoVerification));
+    }
+    match (&options.cert, &options.key) {
+        (&Some(ref cert_path), &Some(ref key_path)) => {
+            config.set_single_client_cert(load_certs(cert_path)?, load_private_key(key_path)?);
+        },
+        (&None, &None) => (),
+        _ => return Err(Error::new(ErrorKind::InvalidInput, "a client certificate needs both a certificate and a key"))
+    }
+    if !options.protocols.is_empty() {
+        config.set_protocols(&options.protocols);
+    }
+
+    Ok(Arc::new(config))
+}
+
+/// Server settings that present the certificate chain and key of PEM files
+pub fn server_config(cert_path: &str, key_path: &str) -> Result<rustls::ServerConfig> {
+    let certs = load_certs(cert_path)?;
+    let key = load_private_key(key_path)?;
+    let mut config = rustls::ServerConfig::new();
+    config.set_single_cert(certs, key);
+    Ok(config)
+}
+
+/// A TLS connection over TCP, read and written like the TCP stream under it. The handshake is
+/// done by the first reads, and what is written before it completes is sent once it has.
+/// A read timeout on the TCP stream makes reads time out the same way.
+pub struct Stream<S: Session> {
+    tcp: TcpStream,
+    session: S,
+}
+
+pub type ClientStream = Stream<ClientSession>;
+pub type ServerStream = Stream<ServerSession>;
+
+/// Start a TLS connection to a server. The certificate has to be valid for `server_name`,
+/// which is also sent with SNI.
+pub fn connect(config: &Arc<rustls::ClientConfig>, tcp: TcpStream, server_name: &str) -> ClientStream {
+    Stream {
+        tcp: tcp,
+        session: ClientSession::new(config, server_name),
+    }
+}
+
+/// Start a TLS connection from a client that was accepted
+pub fn accept(config: &Arc<rustls::ServerConfig>, tcp: TcpStream) -> ServerStream {
+    Stream {
+        tcp: tcp,
+        session: ServerSession::new(config),
+    }
+}
+
+impl<S: Session> Stream<S> {
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.tcp
+    }
+
+    /// Send the records that the session has ready, such as handshake messages
+    fn write_pending(&mut self) -> Result<()> {
+        while self.session.wants_write() {
+            self.session.write_tls(&mut self.tcp)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: Session> Read for Stream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            self.write_pending()?;
+
+            let count = self.session.read(buf)?;
+            if count > 0 || buf.is_empty() {
+                return Ok(count);
+            }
+
+            if self.session.read_tls(&mut self.tcp)? == 0 {
+                return Ok(0);
+            }
+            if let Err(err) = self.session.process_new_packets() {
+                // Send the alert explaining why before giving up
+                let _ = self.write_pending();
+                return Err(Error::new(ErrorKind::InvalidData, format!("tls: {:?}", err)));
+            }
+        }
+    }
+}
+
+impl<S: Session> Write for Stream<S> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let count = self.session.write(buf)?;
+        self.write_pending()?;
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.session.flush()?;
+        self.write_pending()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::ErrorKind;
+    use super::{client_config, ClientOptions};
+
+    #[test]
+    fn client_config_test() {
+        assert!(client_config(&ClientOptions::new()).is_ok());
+
+        let mut options = ClientOptions::new();
+        options.cert = Some("client.pem".to_string());
+        assert_eq!(client_config(&options).err().map(|err| err.kind()), Some(ErrorKind::InvalidInput));
+
+        let mut options = ClientOptions::new();
+        options.ca_file = Some("/nonexistent/ca.pem".to_string());
+        assert_eq!(client_config(&options).err().map(|err| err.kind()), Some(ErrorKind::NotFound));
+    }
+}
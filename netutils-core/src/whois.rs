@@ -0,0 +1,145 @@
+//! WHOIS (RFC 3912) queries, and Team Cymru's IP to ASN service, which is queried over WHOIS
+
+use std::io::{Read, Write};
+use std::net::IpAddr;
+use std::time::Duration;
+
+use error::{NetError, Result};
+use proxy;
+use resolve::Family;
+
+/// The port that WHOIS servers listen on
+pub const PORT: u16 = 43;
+
+/// The server of Team Cymru's IP to ASN service
+pub const CYMRU_HOST: &'static str = "whois.cymru.com";
+
+/// Send `query` to a server, with an address of `family` or through the proxy in `all_proxy`,
+/// returning the whole response
+pub fn query(host: &str, port: u16, family: Family, query: &str) -> Result<String> {
+    let mut stream = proxy::connect("whois", host, port, family, None)?;
+    let context = |err| NetError::from(err).context(&format!("failed to query {}", host));
+    stream.set_read_timeout(Some(Duration::new(30, 0))).map_err(&context)?;
+    // A carriage return and a newline end the query
+    write!(stream, "{}\r\n", query).map_err(&context)?;
+
+    let mut data = Vec::new();
+    stream.read_to_end(&mut data).map_err(&context)?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+/// The server that a response refers the query to, if any. There's no reliable way to tell a
+/// thin response from a thick one, so this is the method of the FreeBSD client.
+pub fn referral(response: &str) -> Option<String> {
+    for line in response.lines() {
+        let line = line.trim_left();
+        for prefix in [
+            "whois:",
+            "Whois Server:",
+            "Registrar WHOIS Server:",
+            "ReferralServer:  whois://",
+            "descr:          region. Please query",
+        ].iter()
+        {
+            if line.starts_with(prefix) {
+                let host = line[prefix.len()..]
+                    .trim_left()
+                    .trim_right_matches(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '-'))
+                    .to_ascii_lowercase();
+                return if host.is_empty() { None } else { Some(host) };
+            }
+        }
+    }
+    None
+}
+
+/// The AS that announces the prefix of an address
+#[derive(Clone, Debug, PartialEq)]
+pub struct Origin {
+    pub addr: IpAddr,
+    /// `None` if no AS announces the address
+    pub asn: Option<u32>,
+    pub prefix: Option<String>,
+    /// ISO 3166 code of the country that the prefix was allocated in
+    pub country: String,
+    /// The registry that allocated the prefix, such as `arin` or `ripencc`
+    pub registry: String,
+    pub allocated: String,
+    pub name: String,
+}
+
+fn field(value: &str) -> Option<String> {
+    match value.trim() {
+        "" | "NA" => None,
+        value => Some(value.to_string())
+    }
+}
+
+/// Parse the response to a verbose bulk query, as lines of
+/// `AS | IP | BGP Prefix | CC | Registry | Allocated | AS Name`, skipping the header and any
+/// errors
+pub fn parse_origins(response: &str) -> Vec<Origin> {
+    let mut origins = Vec::new();
+    for line in response.lines() {
+        let fields: Vec<&str> = line.splitn(7, '|').collect();
+        if fields.len() < 7 {
+            continue;
+        }
+        let addr = match fields[1].trim().parse() {
+            Ok(addr) => addr,
+            Err(_) => continue
+        };
+        origins.push(Origin {
+            addr: addr,
+            asn: fields[0].trim().parse().ok(),
+            prefix: field(fields[2]),
+            country: field(fields[3]).unwrap_or_default(),
+            registry: field(fields[4]).unwrap_or_default(),
+            allocated: field(fields[5]).unwrap_or_default(),
+            name: field(fields[6]).unwrap_or_default(),
+        });
+    }
+    origins
+}
+
+/// Look up the origins of many addresses with a single bulk query. Addresses that the service
+/// doesn't answer for are left out.
+pub fn origins(host: &str, port: u16, addrs: &[IpAddr]) -> Result<Vec<Origin>> {
+    let mut request = "begin\r\nverbose".to_string();
+    for addr in addrs {
+        request.push_str(&format!("\r\n{}", addr));
+    }
+    request.push_str("\r\nend");
+    query(host, port, Family::Any, &request).map(|response| parse_origins(&response))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_origins, referral};
+
+    #[test]
+    fn referral_test() {
+        let response = "% IANA WHOIS server\r\nrefer:        whois.verisign-grs.com\r\n\r\nwhois:        whois.verisign-grs.com\r\n";
+        assert_eq!(referral(response), Some("whois.verisign-grs.com".to_string()));
+        assert_eq!(referral("Registrar WHOIS Server: WHOIS.Example.NET\r\n"), Some("whois.example.net".to_string()));
+        assert_eq!(referral("domain: example\r\n"), None);
+    }
+
+    #[test]
+    fn parse_origins_test() {
+        let response = "Bulk mode; whois.cymru.com [2018-03-01 12:00:00 +0000]\n\
+                        15169   | 8.8.8.8          | 8.8.8.0/24          | US | arin     | 1992-12-01 | GOOGLE - Google LLC, US\n\
+                        NA      | 10.0.0.1         | NA                  |    | other    |            | NA\n\
+                        Error: no ASN or IP match on line 4.\n";
+        let origins = parse_origins(response);
+        assert_eq!(origins.len(), 2);
+        assert_eq!(origins[0].asn, Some(15169));
+        assert_eq!(origins[0].prefix, Some("8.8.8.0/24".to_string()));
+        assert_eq!(origins[0].country, "US");
+        assert_eq!(origins[0].name, "GOOGLE - Google LLC, US");
+        assert_eq!(origins[1].asn, None);
+        assert_eq!(origins[1].prefix, None);
+        assert_eq!(origins[1].registry, "other");
+        assert_eq!(origins[1].name, "");
+    }
+}
@@ -0,0 +1,265 @@
+extern crate netutils_core;
+
+use netutils_core::MacAddr;
+use netutils_core::arp::Link;
+use netutils_core::args::Parser;
+use netutils_core::error::{NetError, Result, EXIT_FAILURE};
+use netutils_core::rawsock;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use std::{process, thread};
+
+use neighbor::format_mac;
+
+mod neighbor;
+
+static ARP_MAN: &'static str = /* @MANSTART{arp} */ r#"
+NAME
+    arp - show and change the neighbor cache, and send ARP requests
+
+SYNOPSIS
+    arp [-4 | -6] [-i iface]
+    arp -s address hwaddress -i iface [temp]
+    arp -d address [-i iface]
+    arp -r address -i iface [-c count] [-w timeout]
+    arp -g -i iface [address]
+    arp [-h | --help]
+
+DESCRIPTION
+    Without a mode, arp lists the IPv4 ARP cache and the IPv6 neighbor
+    cache, optionally only the entries of one family or interface.
+
+OPTIONS
+    -4
+    -6
+        List only IPv4 or IPv6 entries.
+
+    -i iface
+        The interface of the entries, or to send requests on.
+
+    -s address hwaddress
+        Add a static entry, or a temporary one if followed by temp.
+
+    -d address
+        Delete the entry for address, from every interface it is on if no
+        interface is given.
+
+    -r address
+        Resolve address by broadcasting ARP requests, printing the hardware
+        address and round trip time of each reply.
+
+    -c count
+        Number of requests to send with -r, 3 by default.
+
+    -w timeout
+        Seconds to wait for each reply with -r, 1 by default.
+
+    -g
+        Send a gratuitous ARP announcing address, the first IPv4 address of
+        the interface by default, so that neighbors update their caches.
+
+    -h
+    --help
+        Print this manual page.
+
+NOTES
+    Sending ARP requests requires root. The neighbor cache is read and
+    changed through netlink on Linux. On Redox the network stack resolves
+    addresses itself and has no cache to read, so listing, -s and -d do not
+    work there, and only -r and -g are available.
+"#; /* @MANEND */
+
+const DEFAULT_COUNT: u32 = 3;
+const DEFAULT_TIMEOUT: u64 = 1;
+
+enum Mode {
+    List,
+    Add(IpAddr, MacAddr, bool),
+    Delete(IpAddr),
+    Resolve(Ipv4Addr),
+    Announce(Option<Ipv4Addr>),
+}
+
+fn parse_ip(value: &str) -> Result<IpAddr> {
+    IpAddr::from_str(value).map_err(|_| NetError::Usage(format!("invalid address '{}'", value)))
+}
+
+fn parse_ipv4(value: &str) -> Result<Ipv4Addr> {
+    Ipv4Addr::from_str(value).map_err(|_| NetError::Usage(format!("invalid IPv4 address '{}'", value)))
+}
+
+/// MacAddr::from_str returns the null address when it fails, which is never a valid entry
+fn parse_mac(value: &str) -> Result<MacAddr> {
+    let mac = MacAddr::from_str(value);
+    if mac == MacAddr::default() {
+        return Err(NetError::Usage(format!("invalid hardware address '{}'", value)));
+    }
+    Ok(mac)
+}
+
+fn list(family: Option<bool>, iface: Option<&str>) -> Result<()> {
+    let neighbors = neighbor::list().map_err(|err| NetError::from(err).context("failed to read the neighbor cache"))?;
+    println!("{:<40} {:<18} {:<11} {}", "Address", "HWaddress", "State", "Iface");
+    for neighbor in neighbors {
+        if family.map_or(false, |ipv4| ipv4 != neighbor.addr.is_ipv4()) {
+            continue;
+        }
+        if iface.map_or(false, |iface| iface != neighbor.iface) {
+            continue;
+        }
+        let mac = neighbor.mac.as_ref().map(format_mac).unwrap_or("(incomplete)".to_string());
+        println!("{:<40} {:<18} {:<11} {}", neighbor.addr.to_string(), mac, neighbor.state.name(), neighbor.iface);
+    }
+    Ok(())
+}
+
+fn delete(addr: IpAddr, iface: Option<&str>) -> Result<()> {
+    let ifaces = match iface {
+        Some(iface) => vec![iface.to_string()],
+        None => neighbor::find(addr)
+            .map_err(|err| NetError::from(err).context("failed to read the neighbor cache"))?
+            .into_iter().map(|neighbor| neighbor.iface).collect()
+    };
+    if ifaces.is_empty() {
+        return Err(NetError::other(format!("no entry for {}", addr)));
+    }
+    for iface in ifaces {
+        neighbor::delete(&iface, addr)
+            .map_err(|err| NetError::from(err).context(&format!("failed to delete {} from {}", addr, iface)))?;
+    }
+    Ok(())
+}
+
+fn open(iface: &str) -> Result<Link> {
+    let link = Link::open(iface).map_err(|err| NetError::from(err).context(&format!("failed to open {}", iface)))?;
+    rawsock::drop_privileges().map_err(|err| NetError::from(err).context("failed to drop privileges"))?;
+    Ok(link)
+}
+
+/// Returns true if any request was answered
+fn resolve(iface: &str, target: Ipv4Addr, count: u32, timeout: Duration) -> Result<bool> {
+    let mut link = open(iface)?;
+    // Requests from the unspecified address are probes, which are still answered
+    let sender = link.addr.unwrap_or(Ipv4Addr::new(0, 0, 0, 0));
+    println!("ARPING {} from {} {}", target, sender, iface);
+
+    let mut received = 0;
+    for i in 0..count {
+        if i > 0 {
+            thread::sleep(Duration::from_secs(1));
+        }
+        let start = Instant::now();
+        link.request(MacAddr::BROADCAST, sender, target)
+            .map_err(|err| NetError::from(err).context("failed to send request"))?;
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                println!("no reply from {}", target);
+                break;
+            }
+            match link.receive(timeout - elapsed) {
+                Ok(Some(packet)) => if packet.reply && packet.sender == target {
+                    let rtt = start.elapsed();
+                    println!("reply from {} [{}] {:.3} ms", packet.sender, format_mac(&packet.sender_mac),
+                             rtt.as_secs() as f64 * 1000.0 + rtt.subsec_nanos() as f64 / 1_000_000.0);
+                    received += 1;
+                    break;
+                },
+                Ok(None) => (),
+                Err(err) => return Err(NetError::from(err).context("failed to receive reply"))
+            }
+        }
+    }
+    println!("{} requests sent, {} replies received", count, received);
+    Ok(received > 0)
+}
+
+fn announce(iface: &str, addr: Option<Ipv4Addr>) -> Result<()> {
+    let mut link = open(iface)?;
+    let addr = match addr.or(link.addr) {
+        Some(addr) => addr,
+        None => return Err(NetError::other(format!("{} has no IPv4 address", iface)))
+    };
+    link.announce(addr).map_err(|err| NetError::from(err).context("failed to send announcement"))?;
+    println!("announced {} at {} on {}", addr, format_mac(&link.mac), iface);
+    Ok(())
+}
+
+/// Run the mode that the arguments ask for, returning false if ARP requests weren't answered
+fn run() -> Result<bool> {
+    let args = Parser::new("arp")
+        .synopsis("[-4 | -6] [-i iface]")
+        .synopsis("-s address hwaddress -i iface [temp]")
+        .synopsis("-d address [-i iface]")
+        .synopsis("-r address -i iface [-c count] [-w timeout]")
+        .synopsis("-g -i iface [address]")
+        .man(ARP_MAN)
+        .flag("4", "", "List only IPv4 entries")
+        .flag("6", "", "List only IPv6 entries")
+        .opt("i", "", "iface", "The interface of the entries, or to send requests on")
+        .opt("s", "", "address", "Add a static entry for address")
+        .opt("d", "", "address", "Delete the entry for address")
+        .opt("r", "", "address", "Resolve address with ARP requests")
+        .opt("c", "", "count", "Number of requests to send with -r")
+        .opt("w", "", "timeout", "Seconds to wait for each reply with -r")
+        .flag("g", "", "Send a gratuitous ARP")
+        .parse_env();
+
+    let family = match (args.flag("4"), args.flag("6")) {
+        (true, true) => args.usage(),
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        (false, false) => None
+    };
+    let iface = args.value("i");
+    let count = args.parse("c").unwrap_or(DEFAULT_COUNT);
+    let timeout = args.parse("w").unwrap_or(DEFAULT_TIMEOUT);
+    if ["s", "d", "r", "g"].iter().filter(|mode| args.flag(mode)).count() > 1 {
+        args.usage();
+    }
+    let mode = if let Some(addr) = args.value("s") {
+        let permanent = match args.free.len() {
+            1 => true,
+            2 if args.free[1] == "temp" => false,
+            _ => args.usage()
+        };
+        Mode::Add(parse_ip(addr)?, parse_mac(&args.free[0])?, permanent)
+    } else if args.flag("g") {
+        match args.free.len() {
+            0 => Mode::Announce(None),
+            1 => Mode::Announce(Some(parse_ipv4(&args.free[0])?)),
+            _ => args.usage()
+        }
+    } else if !args.free.is_empty() {
+        args.usage()
+    } else if let Some(addr) = args.value("d") {
+        Mode::Delete(parse_ip(addr)?)
+    } else if let Some(addr) = args.value("r") {
+        Mode::Resolve(parse_ipv4(addr)?)
+    } else {
+        Mode::List
+    };
+
+    let iface_for = |what: &str| iface.ok_or_else(|| NetError::Usage(format!("an interface is required to {}", what)));
+    match mode {
+        Mode::List => list(family, iface)?,
+        Mode::Add(addr, mac, permanent) => {
+            let iface = iface_for("add an entry")?;
+            neighbor::add(iface, addr, mac, permanent)
+                .map_err(|err| NetError::from(err).context(&format!("failed to add {}", addr)))?;
+        },
+        Mode::Delete(addr) => delete(addr, iface)?,
+        Mode::Resolve(addr) => return resolve(iface_for("send requests")?, addr, count, Duration::from_secs(timeout)),
+        Mode::Announce(addr) => announce(iface_for("send requests")?, addr)?
+    }
+    Ok(true)
+}
+
+fn main() {
+    match run() {
+        Ok(true) => (),
+        Ok(false) => process::exit(EXIT_FAILURE),
+        Err(err) => err.exit("arp")
+    }
+}
@@ -0,0 +1,225 @@
+use netutils_core::MacAddr;
+use std::io::Result;
+use std::net::IpAddr;
+
+/// Reachability of a neighbor, as tracked by the kernel (RFC 4861 section 7.3.2)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Incomplete,
+    Reachable,
+    Stale,
+    Delay,
+    Probe,
+    Failed,
+    /// Entries for devices that do not resolve addresses, such as loopback
+    Noarp,
+    /// Static entries, which never expire
+    Permanent,
+}
+
+impl State {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            State::Incomplete => "incomplete",
+            State::Reachable => "reachable",
+            State::Stale => "stale",
+            State::Delay => "delay",
+            State::Probe => "probe",
+            State::Failed => "failed",
+            State::Noarp => "noarp",
+            State::Permanent => "permanent",
+        }
+    }
+}
+
+/// An entry of the ARP cache or IPv6 neighbor cache
+#[derive(Clone, Debug)]
+pub struct Neighbor {
+    pub addr: IpAddr,
+    /// None while the address is being resolved
+    pub mac: Option<MacAddr>,
+    pub iface: String,
+    pub state: State,
+}
+
+pub use self::sys::{add, delete, list};
+
+#[cfg(not(target_os = "redox"))]
+mod sys {
+    use netutils_core::MacAddr;
+    use netutils_core::netlink::{self, Request, Socket, NLM_F_CREATE, NLM_F_REPLACE, RTM_DELNEIGH, RTM_GETNEIGH, RTM_NEWNEIGH};
+    use std::io::Result;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::{Neighbor, State};
+
+    const AF_INET: u8 = 2;
+    const AF_INET6: u8 = 10;
+
+    /// Size of struct ndmsg
+    const NDMSG_LEN: usize = 12;
+
+    const NDA_DST: u16 = 1;
+    const NDA_LLADDR: u16 = 2;
+
+    const NUD_INCOMPLETE: u16 = 0x01;
+    const NUD_REACHABLE: u16 = 0x02;
+    const NUD_STALE: u16 = 0x04;
+    const NUD_DELAY: u16 = 0x08;
+    const NUD_PROBE: u16 = 0x10;
+    const NUD_NOARP: u16 = 0x40;
+    const NUD_PERMANENT: u16 = 0x80;
+
+    fn ndmsg(family: u8, index: u32, state: u16) -> [u8; NDMSG_LEN] {
+        let index = index as i32;
+        [family, 0, 0, 0,
+         index as u8, (index >> 8) as u8, (index >> 16) as u8, (index >> 24) as u8,
+         state as u8, (state >> 8) as u8, 0, 0]
+    }
+
+    fn family(addr: &IpAddr) -> u8 {
+        match *addr {
+            IpAddr::V4(_) => AF_INET,
+            IpAddr::V6(_) => AF_INET6,
+        }
+    }
+
+    fn addr_bytes(addr: &IpAddr) -> Vec<u8> {
+        match *addr {
+            IpAddr::V4(addr) => addr.octets().to_vec(),
+            IpAddr::V6(addr) => addr.octets().to_vec(),
+        }
+    }
+
+    fn parse_addr(data: &[u8]) -> Option<IpAddr> {
+        match data.len() {
+            4 => Some(IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3]))),
+            16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                Some(IpAddr::V6(Ipv6Addr::from(octets)))
+            },
+            _ => None
+        }
+    }
+
+    fn parse_state(state: u16) -> State {
+        if state & NUD_PERMANENT != 0 {
+            State::Permanent
+        } else if state & NUD_NOARP != 0 {
+            State::Noarp
+        } else if state & NUD_REACHABLE != 0 {
+            State::Reachable
+        } else if state & NUD_STALE != 0 {
+            State::Stale
+        } else if state & NUD_DELAY != 0 {
+            State::Delay
+        } else if state & NUD_PROBE != 0 {
+            State::Probe
+        } else if state & NUD_INCOMPLETE != 0 {
+            State::Incomplete
+        } else {
+            // NUD_FAILED, or NUD_NONE for entries that are being removed
+            State::Failed
+        }
+    }
+
+    /// The IPv4 and IPv6 neighbor caches
+    pub fn list() -> Result<Vec<Neighbor>> {
+        let mut socket = Socket::open()?;
+        let mut neighbors = Vec::new();
+        for message in socket.dump(RTM_GETNEIGH, &ndmsg(0, 0, 0))? {
+            let header = match message.header(NDMSG_LEN) {
+                Some(header) => header,
+                None => continue
+            };
+            let index = (header[4] as u32) | (header[5] as u32) << 8 | (header[6] as u32) << 16 | (header[7] as u32) << 24;
+            let state = (header[8] as u16) | (header[9] as u16) << 8;
+
+            let addr = match message.attr(NDMSG_LEN, NDA_DST).and_then(parse_addr) {
+                Some(addr) => addr,
+                None => continue
+            };
+            let mac = message.attr(NDMSG_LEN, NDA_LLADDR).and_then(|data| if data.len() == 6 {
+                let mut mac = MacAddr::default();
+                mac.bytes.copy_from_slice(data);
+                Some(mac)
+            } else {
+                None
+            });
+
+            neighbors.push(Neighbor {
+                addr: addr,
+                mac: mac,
+                iface: netlink::if_name(index).unwrap_or_else(|| index.to_string()),
+                state: parse_state(state),
+            });
+        }
+        Ok(neighbors)
+    }
+
+    /// Add or replace an entry, which is static if `permanent`
+    pub fn add(iface: &str, addr: IpAddr, mac: MacAddr, permanent: bool) -> Result<()> {
+        let index = netlink::if_index(iface)?;
+        let state = if permanent { NUD_PERMANENT } else { NUD_REACHABLE };
+        let request = Request::new(RTM_NEWNEIGH, NLM_F_CREATE | NLM_F_REPLACE, &ndmsg(family(&addr), index, state))
+            .attr(NDA_DST, &addr_bytes(&addr))
+            .attr(NDA_LLADDR, &mac.bytes);
+        Socket::open()?.execute(request)
+    }
+
+    pub fn delete(iface: &str, addr: IpAddr) -> Result<()> {
+        let index = netlink::if_index(iface)?;
+        let request = Request::new(RTM_DELNEIGH, 0, &ndmsg(family(&addr), index, 0))
+            .attr(NDA_DST, &addr_bytes(&addr));
+        Socket::open()?.execute(request)
+    }
+}
+
+/// The network stack of Redox resolves addresses internally, without a table to read or change,
+/// as netcfg only has interfaces, addresses and routes
+#[cfg(target_os = "redox")]
+mod sys {
+    use netutils_core::MacAddr;
+    use std::io::{Error, ErrorKind, Result};
+    use std::net::IpAddr;
+
+    use super::Neighbor;
+
+    fn unsupported() -> Error {
+        Error::new(ErrorKind::Other, "the neighbor cache does not work on Redox, whose network stack has none to read")
+    }
+
+    pub fn list() -> Result<Vec<Neighbor>> {
+        Err(unsupported())
+    }
+
+    pub fn add(_iface: &str, _addr: IpAddr, _mac: MacAddr, _permanent: bool) -> Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn delete(_iface: &str, _addr: IpAddr) -> Result<()> {
+        Err(unsupported())
+    }
+}
+
+/// Entries for `addr`, to find the interface to delete it from
+pub fn find(addr: IpAddr) -> Result<Vec<Neighbor>> {
+    Ok(list()?.into_iter().filter(|neighbor| neighbor.addr == addr).collect())
+}
+
+pub fn format_mac(mac: &MacAddr) -> String {
+    let parts: Vec<String> = mac.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    parts.join(":")
+}
+
+#[cfg(test)]
+mod test {
+    use netutils_core::MacAddr;
+    use super::format_mac;
+
+    #[test]
+    fn format_mac_test() {
+        assert_eq!(format_mac(&MacAddr { bytes: [0x52, 0x54, 0, 0x12, 0x34, 0xab] }), "52:54:00:12:34:ab");
+    }
+}
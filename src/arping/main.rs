@@ -0,0 +1,234 @@
+extern crate netutils_core;
+
+use netutils_core::MacAddr;
+use netutils_core::arp::Link;
+use netutils_core::args::Parser;
+use netutils_core::error::{NetError, Result, EXIT_FAILURE};
+use netutils_core::rawsock;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use std::{process, thread};
+
+static ARPING_MAN: &'static str = /* @MANSTART{arping} */ r#"
+NAME
+    arping - send ARP requests to a neighbor
+
+SYNOPSIS
+    arping -I iface [-c count] [-i interval] [-w timeout] [-s source] [-b] [-f] target
+    arping -I iface -D [-c count] [-i interval] [-w timeout] target
+    arping [-h | --help]
+
+DESCRIPTION
+    Send ARP who-has requests for target on an interface, printing the
+    hardware address and round trip time of each reply. Unlike ping, this
+    works for a neighbor that drops ICMP, or that has an address on another
+    subnet, since only the link is used.
+
+    Once target replies, further requests are sent to its hardware address
+    alone, which shows whether it still answers for the address.
+
+    arping exits with 0 if any request was answered, and 1 otherwise.
+
+OPTIONS
+    -I iface
+        The interface to send the requests on.
+
+    -c count
+        Number of requests to send, 4 by default, or 3 with -D. arping -c 0
+        sends requests until interrupted.
+
+    -i interval
+        Seconds between requests, 1 by default.
+
+    -w timeout
+        Seconds to wait for the reply to each request, 1 by default.
+
+    -s source
+        The sender address of the requests, the first IPv4 address of the
+        interface by default.
+
+    -b
+        Keep broadcasting the requests after target replies.
+
+    -f
+        Stop at the first reply.
+
+    -D
+        Duplicate address detection (RFC 5227): send probes from the
+        unspecified address, to find out whether anyone else is using
+        target. arping exits with 1 as soon as anyone answers, or probes for
+        target too, and with 0 if no one does, so it can be run before a
+        static address is assigned.
+
+    -h
+    --help
+        Print this manual page.
+
+NOTES
+    Sending ARP requests requires root, which arping gives up once its socket
+    is open.
+"#; /* @MANEND */
+
+const DEFAULT_COUNT: u32 = 4;
+/// PROBE_NUM of RFC 5227
+const DEFAULT_PROBES: u32 = 3;
+const DEFAULT_INTERVAL: u64 = 1;
+const DEFAULT_TIMEOUT: u64 = 1;
+
+fn format_mac(mac: &MacAddr) -> String {
+    let b = mac.bytes;
+    format!("{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", b[0], b[1], b[2], b[3], b[4], b[5])
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+struct Options {
+    count: u32,
+    interval: Duration,
+    timeout: Duration,
+    broadcast: bool,
+    first: bool,
+}
+
+/// Send requests for `target`, returning true if any was answered
+fn ping(link: &mut Link, iface: &str, sender: Ipv4Addr, target: Ipv4Addr, options: &Options) -> Result<bool> {
+    println!("ARPING {} from {} {}", target, sender, iface);
+    let mut dst = MacAddr::BROADCAST;
+    let mut sent = 0;
+    let mut received = 0;
+    while options.count == 0 || sent < options.count {
+        if sent > 0 {
+            thread::sleep(options.interval);
+        }
+        let start = Instant::now();
+        link.request(dst, sender, target).map_err(|err| NetError::from(err).context("failed to send request"))?;
+        sent += 1;
+
+        let unicast = dst != MacAddr::BROADCAST;
+        let mut answered = false;
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed >= options.timeout {
+                break;
+            }
+            match link.receive(options.timeout - elapsed) {
+                // All hosts that answer are shown, since more than one means that the address is
+                // in use twice
+                Ok(Some(packet)) => if packet.reply && packet.sender == target {
+                    println!("reply from {} [{}] {:.3} ms{}", packet.sender, format_mac(&packet.sender_mac),
+                             millis(start.elapsed()), if unicast { " (unicast)" } else { "" });
+                    received += 1;
+                    answered = true;
+                    if !options.broadcast {
+                        dst = packet.sender_mac;
+                    }
+                },
+                Ok(None) => break,
+                Err(err) => return Err(NetError::from(err).context("failed to receive reply"))
+            }
+        }
+        if !answered {
+            println!("no reply from {}", target);
+        } else if options.first {
+            break;
+        }
+    }
+    println!("{} requests sent, {} replies received", sent, received);
+    Ok(received > 0)
+}
+
+/// Probe for `target`, returning true if another host is using it or probing for it
+fn detect(link: &mut Link, iface: &str, target: Ipv4Addr, options: &Options) -> Result<bool> {
+    let unspecified = Ipv4Addr::new(0, 0, 0, 0);
+    println!("probing for {} on {}", target, iface);
+    let mut sent = 0;
+    while options.count == 0 || sent < options.count {
+        if sent > 0 {
+            thread::sleep(options.interval);
+        }
+        let start = Instant::now();
+        link.request(MacAddr::BROADCAST, unspecified, target)
+            .map_err(|err| NetError::from(err).context("failed to send probe"))?;
+        sent += 1;
+
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed >= options.timeout {
+                break;
+            }
+            let packet = match link.receive(options.timeout - elapsed) {
+                Ok(Some(packet)) => packet,
+                Ok(None) => break,
+                Err(err) => return Err(NetError::from(err).context("failed to receive reply"))
+            };
+            // Any packet from the address is a conflict, as is a probe from another host that
+            // wants the address too (RFC 5227 section 2.1.1)
+            if packet.sender == target {
+                println!("{} is in use by {}", target, format_mac(&packet.sender_mac));
+                return Ok(true);
+            }
+            if !packet.reply && packet.sender == unspecified && packet.target == target {
+                println!("{} is also being probed for by {}", target, format_mac(&packet.sender_mac));
+                return Ok(true);
+            }
+        }
+    }
+    println!("{} probes sent, no one is using {}", sent, target);
+    Ok(false)
+}
+
+/// Ping or probe for the target, returning false if it didn't answer or the address is in use
+fn run() -> Result<bool> {
+    let args = Parser::new("arping")
+        .synopsis("-I iface [-c count] [-i interval] [-w timeout] [-s source] [-b] [-f] target")
+        .synopsis("-I iface -D [-c count] [-i interval] [-w timeout] target")
+        .man(ARPING_MAN)
+        .opt("I", "", "iface", "The interface to send the requests on")
+        .opt("c", "", "count", "Number of requests to send")
+        .opt("i", "", "interval", "Seconds between requests")
+        .opt("w", "", "timeout", "Seconds to wait for each reply")
+        .opt("s", "", "source", "The sender address of the requests")
+        .flag("b", "", "Keep broadcasting after target replies")
+        .flag("f", "", "Stop at the first reply")
+        .flag("D", "", "Duplicate address detection")
+        .parse_env();
+
+    let detection = args.flag("D");
+    let options = Options {
+        count: args.parse("c").unwrap_or(if detection { DEFAULT_PROBES } else { DEFAULT_COUNT }),
+        interval: Duration::from_secs(args.parse("i").unwrap_or(DEFAULT_INTERVAL)),
+        timeout: Duration::from_secs(args.parse("w").unwrap_or(DEFAULT_TIMEOUT)),
+        broadcast: args.flag("b"),
+        first: args.flag("f"),
+    };
+    let source = args.value("s").map(|value| Ipv4Addr::from_str(value).unwrap_or_else(|_| args.invalid(&format!("invalid IPv4 address '{}'", value))));
+    if args.free.len() != 1 {
+        args.usage();
+    }
+    let target = Ipv4Addr::from_str(&args.free[0]).unwrap_or_else(|_| args.invalid(&format!("invalid IPv4 address '{}'", args.free[0])));
+    let iface = args.required("I");
+    if detection && source.is_some() {
+        args.invalid("probes are always sent from 0.0.0.0, so -s can't be used with -D");
+    }
+
+    let mut link = Link::open(iface).map_err(|err| NetError::from(err).context(&format!("failed to open {}", iface)))?;
+    rawsock::drop_privileges().map_err(|err| NetError::from(err).context("failed to drop privileges"))?;
+    if detection {
+        detect(&mut link, iface, target, &options).map(|in_use| !in_use)
+    } else {
+        // Requests from the unspecified address are probes, which are still answered
+        let sender = source.or(link.addr).unwrap_or(Ipv4Addr::new(0, 0, 0, 0));
+        ping(&mut link, iface, sender, target, &options)
+    }
+}
+
+fn main() {
+    match run() {
+        Ok(true) => (),
+        Ok(false) => process::exit(EXIT_FAILURE),
+        Err(err) => err.exit("arping")
+    }
+}
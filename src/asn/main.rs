@@ -0,0 +1,164 @@
+extern crate netutils_core;
+
+use netutils_core::args::Parser;
+use netutils_core::error::{NetError, EXIT_FAILURE};
+use netutils_core::output::{Format, Object};
+use netutils_core::whois::{self, Origin};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::net::IpAddr;
+use std::process;
+
+static ASN_MAN: &'static str = /* @MANSTART{asn} */ r#"
+NAME
+    asn - look up the autonomous systems that announce addresses
+
+SYNOPSIS
+    asn [-o tsv|json] [-H] [-s server] [-p port] [address...]
+    asn [-h | --help]
+
+DESCRIPTION
+    Print the origin AS of each address, with the BGP prefix that it is
+    announced in, the country and registry that the prefix was allocated
+    by, and the name of the AS. Addresses are read from standard input, one
+    on each line, if none are given, and are looked up in batches over a
+    single WHOIS connection for each, using Team Cymru's IP to ASN service.
+
+    Results are printed in the order of the addresses. Addresses that no AS
+    announces have an empty AS and prefix.
+
+    asn exits with 0 if every address was looked up, 1 if any was invalid
+    or had no answer, and with the status of the error if the service
+    couldn't be queried: 3 if its name couldn't be resolved, 4 if it was
+    unreachable, and 5 if it didn't answer in time.
+
+OPTIONS
+    -o tsv|json
+    --output tsv|json
+        Print a line of tab separated fields for each address, by default,
+        with a header line, or a JSON object for each address on a line of
+        its own.
+
+    -H
+        Leave out the header line of tab separated output.
+
+    -s server
+        Query this server, rather than whois.cymru.com.
+
+    -p port
+        Query this port, 43 by default.
+
+    -h
+    --help
+        Print this manual page.
+
+NOTES
+    Lines of standard input that are empty or start with # are skipped.
+"#; /* @MANEND */
+
+/// Addresses sent in each bulk query
+const BATCH_SIZE: usize = 1000;
+
+fn print_origin(origin: &Origin, output: Format) {
+    match output {
+        Format::Json => println!("{}", Object::new()
+                                 .string("ip", &origin.addr.to_string())
+                                 .number_option("asn", origin.asn)
+                                 .option("prefix", origin.prefix.as_ref())
+                                 .string("country", &origin.country)
+                                 .string("registry", &origin.registry)
+                                 .string("allocated", &origin.allocated)
+                                 .string("name", &origin.name)),
+        _ => println!("{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                      origin.asn.map(|asn| asn.to_string()).unwrap_or_default(), origin.addr,
+                      origin.prefix.as_ref().map(|prefix| prefix.as_str()).unwrap_or(""),
+                      origin.country, origin.registry, origin.allocated, origin.name)
+    }
+}
+
+/// Look up a batch of addresses, printing the origin of each in order. Returns the status to
+/// exit with if the lookup failed, or left out any address.
+fn lookup(server: &str, port: u16, addrs: &[IpAddr], output: Format) -> Option<i32> {
+    let origins = match whois::origins(server, port, addrs) {
+        Ok(origins) => origins,
+        Err(err) => {
+            let _ = writeln!(io::stderr(), "asn: {}", err);
+            return Some(err.exit_code());
+        }
+    };
+    let origins: BTreeMap<IpAddr, Origin> = origins.into_iter().map(|origin| (origin.addr, origin)).collect();
+
+    let mut status = None;
+    for addr in addrs {
+        match origins.get(addr) {
+            Some(origin) => print_origin(origin, output),
+            None => {
+                let _ = writeln!(io::stderr(), "asn: no answer for {}", addr);
+                status = Some(EXIT_FAILURE);
+            }
+        }
+    }
+    status
+}
+
+fn parse_addr(text: &str) -> Option<IpAddr> {
+    match text.parse() {
+        Ok(addr) => Some(addr),
+        Err(_) => {
+            let _ = writeln!(io::stderr(), "asn: invalid address '{}'", text);
+            None
+        }
+    }
+}
+
+fn main() {
+    let args = Parser::new("asn")
+        .synopsis("[-o tsv|json] [-H] [-s server] [-p port] [address...]")
+        .man(ASN_MAN)
+        .output_formats("o", "tsv|json", "The output format")
+        .flag("H", "", "Leave out the header line of tab separated output")
+        .opt("s", "", "server", "Query this server")
+        .opt("p", "", "port", "Query this port")
+        .parse_env();
+
+    let output = args.output();
+    let header = !args.flag("H");
+    let server = args.value("s").unwrap_or(whois::CYMRU_HOST);
+    let port = args.parse("p").unwrap_or(whois::PORT);
+
+    let mut status = 0;
+    let mut addrs = Vec::new();
+    if args.free.is_empty() {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = line.unwrap_or_else(|err| NetError::from(err).context("failed to read standard input").exit("asn"));
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_addr(line) {
+                Some(addr) => addrs.push(addr),
+                None => status = EXIT_FAILURE
+            }
+        }
+    } else {
+        for arg in args.free.iter() {
+            match parse_addr(arg) {
+                Some(addr) => addrs.push(addr),
+                None => status = EXIT_FAILURE
+            }
+        }
+    }
+
+    if output == Format::Tsv && header {
+        println!("AS\tIP\tPREFIX\tCC\tREGISTRY\tALLOCATED\tNAME");
+    }
+    for batch in addrs.chunks(BATCH_SIZE) {
+        if let Some(code) = lookup(server, port, batch, output) {
+            status = code;
+        }
+    }
+    if status != 0 {
+        process::exit(status);
+    }
+}
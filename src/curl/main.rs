@@ -0,0 +1,282 @@
+#![deny(warnings)]
+
+extern crate hyper;
+extern crate hyper_rustls;
+extern crate netutils_core;
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use hyper::Client;
+use hyper::client::RedirectPolicy;
+use hyper::header::{Authorization, Basic, ContentType, Headers};
+use hyper::method::Method;
+use hyper::net::{HttpStream, HttpsStream, NetworkConnector, SslClient};
+use netutils_core::args::Parser;
+use netutils_core::error::NetError;
+use netutils_core::proxy;
+use netutils_core::resolve::Family;
+use netutils_core::tls;
+
+static MAN_PAGE: &'static str = /* @MANSTART{curl} */ r#"
+NAME
+    curl - transfer data to or from an HTTP server
+
+SYNOPSIS
+    curl [-h | --help] [-X method] [-H header]... [-d data]... [-F name=value]...
+         [-u user:password] [-i] [-L] [-o file] [--cacert file] [-k] url
+
+DESCRIPTION
+    curl sends a single HTTP or HTTPS request and writes the response body to stdout or a file.
+    The request method defaults to GET, or POST when a request body is given.
+
+OPTIONS
+    -h
+    --help
+        Print this manual page.
+
+    -X method
+    --request method
+        Use the given request method.
+
+    -H header
+    --header header
+        Add a "Name: value" header to the request. May be given more than once.
+
+    -d data
+    --data data
+        Send data as an application/x-www-form-urlencoded body. If data starts with @, the rest
+        is a file name to read the data from. Multiple -d options are joined with "&".
+
+    -F name=value
+    --form name=value
+        Send a multipart/form-data body. If value starts with @, the rest is a file to upload.
+        May be given more than once.
+
+    -u user:password
+    --user user:password
+        Use HTTP basic authentication.
+
+    -i
+    --include
+        Include the response status line and headers in the output.
+
+    -L
+    --location
+        Follow redirects.
+
+    -o file
+    --output file
+        Write the output to file instead of stdout.
+
+    --cacert file
+        Trust the certificate authorities in the PEM file instead of the Mozilla roots.
+
+    -k
+    --insecure
+        Don't check the certificate of the server.
+
+ENVIRONMENT
+    http_proxy, https_proxy, all_proxy
+        The proxy to connect through for http and https urls, which may be an HTTP proxy that
+        allows CONNECT to the port of the server, or a socks5:// or socks5h:// one.
+
+    no_proxy
+        A comma-separated list of hosts and domains that are reached without a proxy, or *.
+"#; /* @MANEND */
+
+/// Connects through the proxy that the environment sets for the scheme, if any. `HttpsConnector`
+/// only asks its connector for http streams, so https streams are wrapped in TLS here instead.
+struct Connector(hyper_rustls::TlsClient);
+
+impl NetworkConnector for Connector {
+    type Stream = HttpsStream<<hyper_rustls::TlsClient as SslClient>::Stream>;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> hyper::Result<Self::Stream> {
+        let stream = proxy::connect(scheme, host, port, Family::Any, None)
+            .map(HttpStream)
+            .map_err(|err| hyper::Error::Io(err.into()))?;
+        if scheme == "https" {
+            self.0.wrap_client(stream, host).map(HttpsStream::Https)
+        } else {
+            Ok(HttpsStream::Http(stream))
+        }
+    }
+}
+
+/// Connection failures keep the kind of their I/O error, and anything else that hyper rejects
+/// is a protocol error
+fn request_error(err: hyper::Error) -> NetError {
+    match err {
+        hyper::Error::Io(err) => NetError::from(err),
+        hyper::Error::Uri(err) => NetError::Usage(format!("invalid url: {}", err)),
+        err => NetError::Protocol(err.to_string()),
+    }
+}
+
+fn read_data(value: &str) -> Vec<u8> {
+    if value.starts_with('@') {
+        let path = &value[1..];
+        let mut data = Vec::new();
+        if let Err(err) = File::open(path).and_then(|mut file| file.read_to_end(&mut data)) {
+            NetError::from(err).context(&format!("failed to read '{}'", path)).exit("curl");
+        }
+        data
+    } else {
+        value.as_bytes().to_vec()
+    }
+}
+
+/// Build a multipart/form-data body from `name=value` and `name=@path` fields, returning the
+/// boundary and the encoded body.
+fn multipart(fields: &[String]) -> (String, Vec<u8>) {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    let boundary = format!("------------------------netutils{:08x}", nanos);
+
+    let mut body = Vec::new();
+    for field in fields {
+        let mut parts = field.splitn(2, '=');
+        let name = parts.next().unwrap_or("");
+        let value = match parts.next() {
+            Some(value) => value,
+            None => NetError::Usage(format!("form field '{}' is not of the form name=value", field)).exit("curl"),
+        };
+
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        if value.starts_with('@') {
+            let path = &value[1..];
+            let filename = path.rsplit('/').next().unwrap_or(path);
+            body.extend_from_slice(format!("Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n", name, filename).as_bytes());
+            body.extend_from_slice(b"Content-Type: application/octet-stream\r\n\r\n");
+        } else {
+            body.extend_from_slice(format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes());
+        }
+        body.extend_from_slice(&read_data(value));
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    (boundary, body)
+}
+
+fn main() {
+    let args = Parser::new("curl")
+        .synopsis("[-X method] [-H header]... [-d data]... [-F name=value]... [-u user:password] [-i] [-L] [-o file] [--cacert file] [-k] url")
+        .man(MAN_PAGE)
+        .opt("X", "request", "method", "The request method")
+        .opt("H", "header", "header", "Add a header, of the form 'Name: value'")
+        .opt("d", "data", "data", "Send data, or the contents of a file given as @file")
+        .opt("F", "form", "name=value", "Send a multipart form field, or a file given as name=@file")
+        .opt("u", "user", "user:password", "Authenticate with basic authentication")
+        .flag("i", "include", "Include the response headers in the output")
+        .flag("L", "location", "Follow redirects")
+        .opt("o", "output", "file", "Write the output to file")
+        .opt("", "cacert", "file", "Trust the certificate authorities in the PEM file")
+        .flag("k", "insecure", "Don't check the certificate of the server")
+        .parse_env();
+
+    let method = args.value("X").map(|value| match Method::from_str(value) {
+        Ok(method) => method,
+        Err(err) => args.invalid(&format!("invalid method '{}': {}", value, err)),
+    });
+
+    let mut headers = Headers::new();
+    for value in args.values("H") {
+        let mut parts = value.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim().to_string();
+        match parts.next() {
+            Some(value) if !name.is_empty() => {
+                headers.append_raw(name, value.trim().as_bytes().to_vec());
+            },
+            _ => args.invalid(&format!("header '{}' is not of the form 'Name: value'", value)),
+        }
+    }
+
+    let data: Vec<Vec<u8>> = args.values("d").into_iter().map(read_data).collect();
+    let form: Vec<String> = args.values("F").into_iter().map(|field| field.to_string()).collect();
+
+    if let Some(value) = args.value("u") {
+        let mut parts = value.splitn(2, ':');
+        let username = parts.next().unwrap_or("").to_string();
+        let password = parts.next().map(|password| password.to_string());
+        headers.set(Authorization(Basic {
+            username: username,
+            password: password,
+        }));
+    }
+
+    let include = args.flag("i");
+    let follow = args.flag("L");
+    let output = args.value("o");
+
+    let url = match args.free.len() {
+        0 => args.usage(),
+        1 => args.free[0].as_str(),
+        _ => args.invalid("only one url may be given"),
+    };
+
+    if !data.is_empty() && !form.is_empty() {
+        args.invalid("-d and -F can not be used together");
+    }
+
+    let body = if !form.is_empty() {
+        let (boundary, body) = multipart(&form);
+        headers.set_raw("Content-Type", vec![format!("multipart/form-data; boundary={}", boundary).into_bytes()]);
+        Some(body)
+    } else if !data.is_empty() {
+        if !headers.has::<ContentType>() {
+            headers.set(ContentType::form_url_encoded());
+        }
+        Some(data.join(&b'&'))
+    } else {
+        None
+    };
+
+    let method = method.unwrap_or(if body.is_some() { Method::Post } else { Method::Get });
+
+    let mut options = tls::ClientOptions::new();
+    options.ca_file = args.value("cacert").map(|file| file.to_string());
+    options.verify = !args.flag("insecure");
+    let cfg = tls::client_config(&options)
+        .unwrap_or_else(|err| NetError::Io(err).context("failed to load TLS settings").exit("curl"));
+
+    let mut client = Client::with_connector(Connector(hyper_rustls::TlsClient { cfg: cfg }));
+    client.set_read_timeout(Some(Duration::new(30, 0)));
+    client.set_write_timeout(Some(Duration::new(30, 0)));
+    if !follow {
+        client.set_redirect_policy(RedirectPolicy::FollowNone);
+    }
+
+    let mut request = client.request(method, url).headers(headers);
+    if let Some(ref body) = body {
+        request = request.body(&body[..]);
+    }
+
+    let mut response = match request.send() {
+        Ok(response) => response,
+        Err(err) => request_error(err).context("failed to send request").exit("curl"),
+    };
+
+    let mut writer: Box<Write> = match output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(err) => NetError::from(err).context(&format!("failed to create '{}'", path)).exit("curl"),
+        },
+        None => Box::new(io::stdout()),
+    };
+
+    if include {
+        if let Err(err) = write!(writer, "{} {}\r\n{}\r\n", response.version, response.status, response.headers) {
+            NetError::from(err).context("failed to write headers").exit("curl");
+        }
+    }
+
+    if let Err(err) = io::copy(&mut response, &mut writer) {
+        NetError::from(err).context("failed to transfer data").exit("curl");
+    }
+
+    if let Err(err) = writer.flush() {
+        NetError::from(err).context("failed to flush data").exit("curl");
+    }
+}
@@ -1,3 +1,37 @@
+use std::{cmp, mem, slice};
+
+pub mod v6;
+
+pub const DISCOVER: u8 = 1;
+pub const OFFER: u8 = 2;
+pub const REQUEST: u8 = 3;
+pub const DECLINE: u8 = 4;
+pub const ACK: u8 = 5;
+pub const NAK: u8 = 6;
+pub const RELEASE: u8 = 7;
+pub const INFORM: u8 = 8;
+
+pub const OPT_SUBNET_MASK: u8 = 1;
+pub const OPT_ROUTER: u8 = 3;
+pub const OPT_DNS: u8 = 6;
+pub const OPT_HOSTNAME: u8 = 12;
+pub const OPT_DOMAIN_NAME: u8 = 15;
+pub const OPT_MTU: u8 = 26;
+pub const OPT_NTP: u8 = 42;
+pub const OPT_REQUESTED_IP: u8 = 50;
+pub const OPT_LEASE_TIME: u8 = 51;
+pub const OPT_MESSAGE_TYPE: u8 = 53;
+pub const OPT_SERVER_ID: u8 = 54;
+pub const OPT_PARAMETER_LIST: u8 = 55;
+pub const OPT_RENEWAL_TIME: u8 = 58;
+pub const OPT_REBINDING_TIME: u8 = 59;
+pub const OPT_VENDOR_CLASS: u8 = 60;
+pub const OPT_CLIENT_ID: u8 = 61;
+pub const OPT_CLASSLESS_ROUTES: u8 = 121;
+
+const MAGIC: u32 = 0x63825363;
+
+#[derive(Copy, Clone)]
 #[repr(packed)]
 pub struct Dhcp {
     pub op: u8,
@@ -17,3 +51,208 @@ pub struct Dhcp {
     pub magic: u32,
     pub options: [u8; 308]
 }
+
+impl Dhcp {
+    /// A client message for the hardware address `mac`
+    pub fn request(tid: u32, mac: [u8; 6]) -> Dhcp {
+        let mut chaddr = [0; 16];
+        chaddr[..6].copy_from_slice(&mac);
+
+        Dhcp {
+            op: 1,
+            htype: 1,
+            hlen: 6,
+            hops: 0,
+            tid: tid,
+            secs: 0,
+            flags: 0,
+            ciaddr: [0; 4],
+            yiaddr: [0; 4],
+            siaddr: [0; 4],
+            giaddr: [0; 4],
+            chaddr: chaddr,
+            sname: [0; 64],
+            file: [0; 128],
+            magic: MAGIC.to_be(),
+            options: [0; 308],
+        }
+    }
+
+    /// A server message answering `request`
+    pub fn reply(request: &Dhcp) -> Dhcp {
+        let mut reply = Dhcp::request(request.tid, [0; 6]);
+        reply.op = 2;
+        reply.flags = request.flags;
+        reply.giaddr = request.giaddr;
+        reply.chaddr = request.chaddr;
+        reply
+    }
+
+    /// The client's hardware address
+    pub fn mac(&self) -> [u8; 6] {
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&self.chaddr[..6]);
+        mac
+    }
+
+    /// Parse a message, which may have fewer options than fit in the structure
+    pub fn from_bytes(bytes: &[u8]) -> Option<Dhcp> {
+        let options_offset = mem::size_of::<Dhcp>() - 308;
+        if bytes.len() < options_offset {
+            return None;
+        }
+
+        let mut dhcp = Dhcp::request(0, [0; 6]);
+        unsafe {
+            let len = cmp::min(bytes.len(), mem::size_of::<Dhcp>());
+            let dhcp_ptr = (&mut dhcp as *mut Dhcp) as *mut u8;
+            slice::from_raw_parts_mut(dhcp_ptr, len).copy_from_slice(&bytes[..len]);
+        }
+
+        if u32::from_be(dhcp.magic) == MAGIC {
+            Some(dhcp)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        unsafe {
+            Vec::from(slice::from_raw_parts((self as *const Dhcp) as *const u8, mem::size_of::<Dhcp>()))
+        }
+    }
+
+    /// The options of the message, as pairs of code and data
+    pub fn options(&self) -> Vec<(u8, &[u8])> {
+        let mut options = Vec::new();
+        let mut i = 0;
+        while i < self.options.len() {
+            match self.options[i] {
+                0 => i += 1,
+                255 => break,
+                code => {
+                    if i + 1 >= self.options.len() {
+                        break;
+                    }
+                    let len = self.options[i + 1] as usize;
+                    if i + 2 + len > self.options.len() {
+                        break;
+                    }
+                    options.push((code, &self.options[i + 2 .. i + 2 + len]));
+                    i += 2 + len;
+                }
+            }
+        }
+        options
+    }
+
+    pub fn option(&self, code: u8) -> Option<&[u8]> {
+        self.options().into_iter().find(|option| option.0 == code).map(|option| option.1)
+    }
+
+    pub fn message_type(&self) -> Option<u8> {
+        self.option(OPT_MESSAGE_TYPE).and_then(|data| data.get(0).cloned())
+    }
+
+    /// Replace the options, returning false if they do not fit
+    pub fn set_options(&mut self, options: &[(u8, &[u8])]) -> bool {
+        let mut data = Vec::new();
+        for &(code, value) in options {
+            data.push(code);
+            data.push(value.len() as u8);
+            data.extend_from_slice(value);
+        }
+        data.push(255);
+
+        if data.len() > self.options.len() {
+            return false;
+        }
+        self.options = [0; 308];
+        self.options[..data.len()].copy_from_slice(&data);
+        true
+    }
+}
+
+/// Read a network order u32 from option data
+pub fn option_u32(data: &[u8]) -> Option<u32> {
+    if data.len() == 4 {
+        Some((data[0] as u32) << 24 | (data[1] as u32) << 16 | (data[2] as u32) << 8 | data[3] as u32)
+    } else {
+        None
+    }
+}
+
+/// Read an address from option data
+pub fn option_addr(data: &[u8]) -> Option<[u8; 4]> {
+    if data.len() >= 4 {
+        Some([data[0], data[1], data[2], data[3]])
+    } else {
+        None
+    }
+}
+
+/// Read the routes of a classless static route option (RFC 3442) as destination, prefix length
+/// and router, or None if the option is malformed
+pub fn option_routes(data: &[u8]) -> Option<Vec<([u8; 4], u8, [u8; 4])>> {
+    let mut routes = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let prefix_len = data[i];
+        if prefix_len > 32 {
+            return None;
+        }
+        // Only the significant octets of the destination are sent
+        let octets = (prefix_len as usize + 7) / 8;
+        if i + 1 + octets + 4 > data.len() {
+            return None;
+        }
+
+        let mut dest = [0; 4];
+        dest[..octets].copy_from_slice(&data[i + 1 .. i + 1 + octets]);
+        let router = [data[i + 1 + octets], data[i + 2 + octets], data[i + 3 + octets], data[i + 4 + octets]];
+        routes.push((dest, prefix_len, router));
+
+        i += 1 + octets + 4;
+    }
+    Some(routes)
+}
+
+/// Parse a client identifier given as colon separated hex octets, which start with the hardware
+/// type, or as text, which is sent with type 0 (RFC 2132 section 9.14)
+pub fn client_id(value: &str) -> Vec<u8> {
+    let octets: Vec<Option<u8>> = value.split(':')
+        .map(|part| if part.len() == 2 { u8::from_str_radix(part, 16).ok() } else { None })
+        .collect();
+    if octets.len() > 1 && octets.iter().all(|octet| octet.is_some()) {
+        octets.into_iter().map(|octet| octet.unwrap_or(0)).collect()
+    } else {
+        let mut id = vec![0];
+        id.extend_from_slice(value.as_bytes());
+        id
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{client_id, option_routes};
+
+    #[test]
+    fn client_id_test() {
+        assert_eq!(client_id("01:52:54:00:12:34:56"), vec![1, 0x52, 0x54, 0, 0x12, 0x34, 0x56]);
+        assert_eq!(client_id("redox"), b"\0redox".to_vec());
+        assert_eq!(client_id("01:5"), b"\001:5".to_vec());
+    }
+
+    #[test]
+    fn option_routes_test() {
+        // Examples from RFC 3442 section 3 and a default route
+        let data = [24, 10, 17, 0, 10, 0, 0, 1, 0, 10, 0, 0, 2, 32, 10, 229, 0, 128, 10, 0, 0, 3];
+        assert_eq!(option_routes(&data), Some(vec![
+            ([10, 17, 0, 0], 24, [10, 0, 0, 1]),
+            ([0, 0, 0, 0], 0, [10, 0, 0, 2]),
+            ([10, 229, 0, 128], 32, [10, 0, 0, 3]),
+        ]));
+        assert_eq!(option_routes(&[24, 10, 17]), None);
+        assert_eq!(option_routes(&[33, 0, 0, 0, 0, 0, 0, 0, 0]), None);
+    }
+}
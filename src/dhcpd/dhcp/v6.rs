@@ -0,0 +1,275 @@
+use std::net::Ipv6Addr;
+
+pub const SOLICIT: u8 = 1;
+pub const ADVERTISE: u8 = 2;
+pub const REQUEST: u8 = 3;
+pub const RENEW: u8 = 5;
+pub const REBIND: u8 = 6;
+pub const REPLY: u8 = 7;
+pub const RELEASE: u8 = 8;
+pub const INFORMATION_REQUEST: u8 = 11;
+
+pub const OPT_CLIENTID: u16 = 1;
+pub const OPT_SERVERID: u16 = 2;
+pub const OPT_IA_NA: u16 = 3;
+pub const OPT_IAADDR: u16 = 5;
+pub const OPT_ORO: u16 = 6;
+pub const OPT_PREFERENCE: u16 = 7;
+pub const OPT_ELAPSED_TIME: u16 = 8;
+pub const OPT_STATUS_CODE: u16 = 13;
+pub const OPT_DNS_SERVERS: u16 = 23;
+pub const OPT_DOMAIN_LIST: u16 = 24;
+
+pub const STATUS_SUCCESS: u16 = 0;
+
+fn read_u16(data: &[u8]) -> u16 {
+    (data[0] as u16) << 8 | data[1] as u16
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    (data[0] as u32) << 24 | (data[1] as u32) << 16 | (data[2] as u32) << 8 | data[3] as u32
+}
+
+fn push_u16(data: &mut Vec<u8>, value: u16) {
+    data.push((value >> 8) as u8);
+    data.push(value as u8);
+}
+
+fn push_u32(data: &mut Vec<u8>, value: u32) {
+    push_u16(data, (value >> 16) as u16);
+    push_u16(data, value as u16);
+}
+
+/// Parse a sequence of options, which appear both in messages and inside other options
+pub fn parse_options(mut data: &[u8]) -> Option<Vec<(u16, Vec<u8>)>> {
+    let mut options = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 4 {
+            return None;
+        }
+        let code = read_u16(&data[0..]);
+        let len = read_u16(&data[2..]) as usize;
+        if data.len() < 4 + len {
+            return None;
+        }
+        options.push((code, data[4..4 + len].to_vec()));
+        data = &data[4 + len..];
+    }
+    Some(options)
+}
+
+pub fn encode_options(data: &mut Vec<u8>, options: &[(u16, Vec<u8>)]) {
+    for &(code, ref value) in options {
+        push_u16(data, code);
+        push_u16(data, value.len() as u16);
+        data.extend_from_slice(value);
+    }
+}
+
+fn find(options: &[(u16, Vec<u8>)], code: u16) -> Option<&[u8]> {
+    options.iter().find(|option| option.0 == code).map(|option| option.1.as_slice())
+}
+
+/// The status code of a set of options, which is success when absent
+pub fn status(options: &[(u16, Vec<u8>)]) -> u16 {
+    find(options, OPT_STATUS_CODE).and_then(|data| if data.len() >= 2 { Some(read_u16(data)) } else { None })
+        .unwrap_or(STATUS_SUCCESS)
+}
+
+/// A DHCPv6 client or server message (RFC 8415 section 8)
+pub struct Message {
+    pub msg_type: u8,
+    pub tid: [u8; 3],
+    pub options: Vec<(u16, Vec<u8>)>,
+}
+
+impl Message {
+    pub fn new(msg_type: u8, tid: [u8; 3]) -> Message {
+        Message {
+            msg_type: msg_type,
+            tid: tid,
+            options: Vec::new(),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Message> {
+        if bytes.len() < 4 {
+            return None;
+        }
+        Some(Message {
+            msg_type: bytes[0],
+            tid: [bytes[1], bytes[2], bytes[3]],
+            options: parse_options(&bytes[4..])?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![self.msg_type, self.tid[0], self.tid[1], self.tid[2]];
+        encode_options(&mut data, &self.options);
+        data
+    }
+
+    pub fn option(&self, code: u16) -> Option<&[u8]> {
+        find(&self.options, code)
+    }
+
+    pub fn add(&mut self, code: u16, data: Vec<u8>) {
+        self.options.push((code, data));
+    }
+
+    /// Server preference of an ADVERTISE, higher is better
+    pub fn preference(&self) -> u8 {
+        self.option(OPT_PREFERENCE).and_then(|data| data.get(0).cloned()).unwrap_or(0)
+    }
+}
+
+/// A DUID based on the link-layer address (DUID-LL), which stays the same across restarts
+pub fn duid_ll(mac: [u8; 6]) -> Vec<u8> {
+    let mut duid = vec![0, 3, 0, 1];
+    duid.extend_from_slice(&mac);
+    duid
+}
+
+/// Option list asking for DNS configuration
+pub fn oro() -> Vec<u8> {
+    let mut data = Vec::new();
+    push_u16(&mut data, OPT_DNS_SERVERS);
+    push_u16(&mut data, OPT_DOMAIN_LIST);
+    data
+}
+
+/// Elapsed time in hundredths of a second, saturating as required
+pub fn elapsed_time(centiseconds: u64) -> Vec<u8> {
+    let mut data = Vec::new();
+    push_u16(&mut data, if centiseconds > 0xFFFF { 0xFFFF } else { centiseconds as u16 });
+    data
+}
+
+/// An identity association for a non-temporary address
+#[derive(Clone, Debug, PartialEq)]
+pub struct IaNa {
+    pub iaid: u32,
+    pub t1: u32,
+    pub t2: u32,
+    /// Addresses with their preferred and valid lifetimes
+    pub addrs: Vec<(Ipv6Addr, u32, u32)>,
+    pub status: u16,
+}
+
+impl IaNa {
+    pub fn new(iaid: u32) -> IaNa {
+        IaNa {
+            iaid: iaid,
+            t1: 0,
+            t2: 0,
+            addrs: Vec::new(),
+            status: STATUS_SUCCESS,
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Option<IaNa> {
+        if data.len() < 12 {
+            return None;
+        }
+
+        let options = parse_options(&data[12..])?;
+        let mut addrs = Vec::new();
+        for &(code, ref value) in options.iter() {
+            if code == OPT_IAADDR && value.len() >= 24 {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(&value[..16]);
+                let addr_options = parse_options(&value[24..])?;
+                // Addresses the server can no longer assign carry a failure status
+                if status(&addr_options) == STATUS_SUCCESS {
+                    addrs.push((Ipv6Addr::from(octets), read_u32(&value[16..]), read_u32(&value[20..])));
+                }
+            }
+        }
+
+        Some(IaNa {
+            iaid: read_u32(&data[0..]),
+            t1: read_u32(&data[4..]),
+            t2: read_u32(&data[8..]),
+            addrs: addrs,
+            status: status(&options),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        push_u32(&mut data, self.iaid);
+        push_u32(&mut data, self.t1);
+        push_u32(&mut data, self.t2);
+        for &(addr, preferred, valid) in self.addrs.iter() {
+            let mut value = addr.octets().to_vec();
+            push_u32(&mut value, preferred);
+            push_u32(&mut value, valid);
+            encode_options(&mut data, &[(OPT_IAADDR, value)]);
+        }
+        data
+    }
+}
+
+pub fn parse_dns_servers(data: &[u8]) -> Vec<Ipv6Addr> {
+    data.chunks(16).filter(|chunk| chunk.len() == 16).map(|chunk| {
+        let mut octets = [0; 16];
+        octets.copy_from_slice(chunk);
+        Ipv6Addr::from(octets)
+    }).collect()
+}
+
+/// Parse a list of uncompressed DNS names, as used by the domain search list
+pub fn parse_domain_list(data: &[u8]) -> Vec<String> {
+    let mut domains = Vec::new();
+    let mut labels: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let len = data[i] as usize;
+        i += 1;
+        if len == 0 {
+            if !labels.is_empty() {
+                domains.push(labels.join("."));
+                labels.clear();
+            }
+        } else if i + len <= data.len() {
+            labels.push(String::from_utf8_lossy(&data[i..i + len]).into_owned());
+            i += len;
+        } else {
+            break;
+        }
+    }
+    domains
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv6Addr;
+    use super::{parse_domain_list, IaNa, Message, OPT_CLIENTID, OPT_IA_NA, SOLICIT};
+
+    #[test]
+    fn message_test() {
+        let mut message = Message::new(SOLICIT, [1, 2, 3]);
+        message.add(OPT_CLIENTID, vec![0, 3, 0, 1, 1, 2, 3, 4, 5, 6]);
+
+        let mut ia_na = IaNa::new(1);
+        ia_na.addrs.push(("2001:db8::1".parse().unwrap(), 3600, 7200));
+        message.add(OPT_IA_NA, ia_na.to_bytes());
+
+        let bytes = message.to_bytes();
+        assert_eq!(&bytes[..8], &[SOLICIT, 1, 2, 3, 0, 1, 0, 10]);
+
+        let parsed = Message::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.tid, [1, 2, 3]);
+        let parsed_ia_na = IaNa::parse(parsed.option(OPT_IA_NA).unwrap()).unwrap();
+        assert_eq!(parsed_ia_na, ia_na);
+        assert_eq!(parsed_ia_na.addrs[0].0, "2001:db8::1".parse::<Ipv6Addr>().unwrap());
+
+        assert!(Message::from_bytes(&[SOLICIT, 1, 2, 3, 0, 1, 0, 10, 0]).is_none());
+    }
+
+    #[test]
+    fn domain_list_test() {
+        let data = b"\x07example\x03com\x00\x03lan\x00";
+        assert_eq!(parse_domain_list(data), vec!["example.com".to_string(), "lan".to_string()]);
+    }
+}
@@ -0,0 +1,275 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::str::FromStr;
+
+use dhcp::{self, Dhcp};
+
+/// An address lease, as acknowledged by a server
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lease {
+    pub addr: Ipv4Addr,
+    pub server: Ipv4Addr,
+    pub subnet: Option<Ipv4Addr>,
+    pub router: Option<Ipv4Addr>,
+    pub dns: Vec<Ipv4Addr>,
+    pub domain: Option<String>,
+    pub ntp: Vec<Ipv4Addr>,
+    pub mtu: Option<u16>,
+    /// Classless static routes as destination, prefix length and router
+    pub routes: Vec<(Ipv4Addr, u8, Ipv4Addr)>,
+    /// Seconds the lease is valid for after it was acquired
+    pub lease_time: u32,
+    /// Seconds until the lease should be renewed with its server (T1)
+    pub renewal_time: u32,
+    /// Seconds until the lease should be renewed with any server (T2)
+    pub rebinding_time: u32,
+    /// Unix time the lease was acknowledged
+    pub acquired: u64,
+}
+
+fn addrs(data: &[u8]) -> Vec<Ipv4Addr> {
+    data.chunks(4).filter_map(dhcp::option_addr).map(Ipv4Addr::from).collect()
+}
+
+/// Whether a domain name from a server can be written to resolv.conf as it is. A name with
+/// whitespace or control characters could add lines or search domains of its own choosing.
+pub fn valid_domain(domain: &str) -> bool {
+    !domain.is_empty() && !domain.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+impl Lease {
+    /// Create a lease from an ACK received at the Unix time `now`
+    pub fn from_ack(ack: &Dhcp, now: u64) -> Option<Lease> {
+        let server = match ack.option(dhcp::OPT_SERVER_ID).and_then(dhcp::option_addr) {
+            Some(server) => server,
+            None => ack.siaddr
+        };
+
+        let lease_time = ack.option(dhcp::OPT_LEASE_TIME).and_then(dhcp::option_u32).unwrap_or(0xFFFFFFFF);
+        // Defaults from RFC 2131 section 4.4.5
+        let renewal_time = ack.option(dhcp::OPT_RENEWAL_TIME).and_then(dhcp::option_u32)
+            .unwrap_or((lease_time as u64 / 2) as u32);
+        let rebinding_time = ack.option(dhcp::OPT_REBINDING_TIME).and_then(dhcp::option_u32)
+            .unwrap_or((lease_time as u64 * 7 / 8) as u32);
+
+        if ack.yiaddr == [0; 4] {
+            return None;
+        }
+
+        Some(Lease {
+            addr: Ipv4Addr::from(ack.yiaddr),
+            server: Ipv4Addr::from(server),
+            subnet: ack.option(dhcp::OPT_SUBNET_MASK).and_then(dhcp::option_addr).map(Ipv4Addr::from),
+            router: ack.option(dhcp::OPT_ROUTER).and_then(dhcp::option_addr).map(Ipv4Addr::from),
+            dns: ack.option(dhcp::OPT_DNS).map(addrs).unwrap_or(Vec::new()),
+            domain: ack.option(dhcp::OPT_DOMAIN_NAME)
+                .map(|data| String::from_utf8_lossy(data).trim_right_matches('\0').to_string())
+                .and_then(|domain| if valid_domain(&domain) { Some(domain) } else { None }),
+            ntp: ack.option(dhcp::OPT_NTP).map(addrs).unwrap_or(Vec::new()),
+            // The minimum MTU is 68 (RFC 2132 section 5.1)
+            mtu: ack.option(dhcp::OPT_MTU)
+                .and_then(|data| if data.len() == 2 { Some((data[0] as u16) << 8 | data[1] as u16) } else { None })
+                .and_then(|mtu| if mtu >= 68 { Some(mtu) } else { None }),
+            routes: ack.option(dhcp::OPT_CLASSLESS_ROUTES).and_then(dhcp::option_routes).unwrap_or(Vec::new())
+                .into_iter()
+                .map(|(dest, prefix_len, router)| (Ipv4Addr::from(dest), prefix_len, Ipv4Addr::from(router)))
+                .collect(),
+            lease_time: lease_time,
+            renewal_time: renewal_time,
+            rebinding_time: rebinding_time,
+            acquired: now,
+        })
+    }
+
+    /// Prefix length of the subnet mask
+    pub fn prefix_len(&self) -> u32 {
+        self.subnet.map_or(0, |subnet| {
+            let subnet = subnet.octets();
+            let mask = (subnet[0] as u32) << 24 | (subnet[1] as u32) << 16 |
+                       (subnet[2] as u32) << 8 | subnet[3] as u32;
+            (!mask).leading_zeros()
+        })
+    }
+
+    /// Unix time to renew the lease with its server
+    pub fn t1(&self) -> u64 {
+        self.acquired + self.renewal_time as u64
+    }
+
+    /// Unix time to renew the lease with any server
+    pub fn t2(&self) -> u64 {
+        self.acquired + self.rebinding_time as u64
+    }
+
+    /// Unix time the lease expires
+    pub fn expiry(&self) -> u64 {
+        self.acquired + self.lease_time as u64
+    }
+
+    /// Parse a lease file, which has a "key value" pair on each line
+    pub fn parse(text: &str) -> Result<Lease, String> {
+        let mut lease = Lease {
+            addr: Ipv4Addr::new(0, 0, 0, 0),
+            server: Ipv4Addr::new(0, 0, 0, 0),
+            subnet: None,
+            router: None,
+            dns: Vec::new(),
+            domain: None,
+            ntp: Vec::new(),
+            mtu: None,
+            routes: Vec::new(),
+            lease_time: 0,
+            renewal_time: 0,
+            rebinding_time: 0,
+            acquired: 0,
+        };
+
+        fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+            value.parse().map_err(|_| format!("invalid {} '{}'", key, value))
+        }
+
+        for line in text.lines() {
+            let mut parts = line.trim().splitn(2, ' ');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("").trim();
+            match key {
+                "" => (),
+                "addr" => lease.addr = parse(key, value)?,
+                "server" => lease.server = parse(key, value)?,
+                "subnet" => lease.subnet = Some(parse(key, value)?),
+                "router" => lease.router = Some(parse(key, value)?),
+                "dns" => lease.dns.push(parse(key, value)?),
+                "domain" => lease.domain = Some(value.to_string()),
+                "ntp" => lease.ntp.push(parse(key, value)?),
+                "mtu" => lease.mtu = Some(parse(key, value)?),
+                "route" => {
+                    // "destination/prefix via router"
+                    let invalid = || format!("invalid route '{}'", value);
+                    let mut parts = value.split_whitespace();
+                    let mut dest = parts.next().unwrap_or("").splitn(2, '/');
+                    let addr = dest.next().unwrap_or("").parse().map_err(|_| invalid())?;
+                    let prefix_len = dest.next().unwrap_or("").parse().map_err(|_| invalid())?;
+                    if parts.next() != Some("via") {
+                        return Err(invalid());
+                    }
+                    let router = parts.next().unwrap_or("").parse().map_err(|_| invalid())?;
+                    lease.routes.push((addr, prefix_len, router));
+                },
+                "lease_time" => lease.lease_time = parse(key, value)?,
+                "renewal_time" => lease.renewal_time = parse(key, value)?,
+                "rebinding_time" => lease.rebinding_time = parse(key, value)?,
+                "acquired" => lease.acquired = parse(key, value)?,
+                _ => return Err(format!("unknown lease setting '{}'", key))
+            }
+        }
+
+        if lease.addr.is_unspecified() {
+            return Err("lease has no address".to_string());
+        }
+
+        Ok(lease)
+    }
+
+    pub fn to_string(&self) -> String {
+        let mut text = String::new();
+        text.push_str(&format!("addr {}\n", self.addr));
+        text.push_str(&format!("server {}\n", self.server));
+        if let Some(subnet) = self.subnet {
+            text.push_str(&format!("subnet {}\n", subnet));
+        }
+        if let Some(router) = self.router {
+            text.push_str(&format!("router {}\n", router));
+        }
+        for dns in self.dns.iter() {
+            text.push_str(&format!("dns {}\n", dns));
+        }
+        if let Some(ref domain) = self.domain {
+            text.push_str(&format!("domain {}\n", domain));
+        }
+        for ntp in self.ntp.iter() {
+            text.push_str(&format!("ntp {}\n", ntp));
+        }
+        if let Some(mtu) = self.mtu {
+            text.push_str(&format!("mtu {}\n", mtu));
+        }
+        for &(dest, prefix_len, router) in self.routes.iter() {
+            text.push_str(&format!("route {}/{} via {}\n", dest, prefix_len, router));
+        }
+        text.push_str(&format!("lease_time {}\n", self.lease_time));
+        text.push_str(&format!("renewal_time {}\n", self.renewal_time));
+        text.push_str(&format!("rebinding_time {}\n", self.rebinding_time));
+        text.push_str(&format!("acquired {}\n", self.acquired));
+        text
+    }
+
+    pub fn load(path: &str) -> Result<Lease, String> {
+        let mut text = String::new();
+        File::open(path).and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|err| format!("failed to read {}: {}", path, err))?;
+        Lease::parse(&text).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Write the lease to a temporary file and rename it, so a crash never leaves half a lease
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir).map_err(|err| format!("failed to create {}: {}", dir.display(), err))?;
+        }
+
+        let tmp = format!("{}.tmp", path);
+        File::create(&tmp)
+            .and_then(|mut file| {
+                file.write_all(self.to_string().as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|err| format!("failed to write {}: {}", path, err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+    use dhcp::{self, Dhcp};
+    use super::Lease;
+
+    #[test]
+    fn lease_test() {
+        let mut ack = Dhcp::request(1, [0; 6]);
+        ack.yiaddr = [10, 0, 2, 15];
+        ack.set_options(&[
+            (dhcp::OPT_MESSAGE_TYPE, &[dhcp::ACK]),
+            (dhcp::OPT_SUBNET_MASK, &[255, 255, 255, 0]),
+            (dhcp::OPT_DNS, &[10, 0, 2, 3, 8, 8, 8, 8]),
+            (dhcp::OPT_SERVER_ID, &[10, 0, 2, 2]),
+            (dhcp::OPT_LEASE_TIME, &[0, 0, 0x0E, 0x10]),
+            (dhcp::OPT_DOMAIN_NAME, b"example.com"),
+            (dhcp::OPT_MTU, &[0x05, 0xDC]),
+            (dhcp::OPT_CLASSLESS_ROUTES, &[0, 10, 0, 2, 1, 16, 192, 168, 10, 0, 2, 254]),
+        ]);
+
+        let lease = Lease::from_ack(&ack, 1000).unwrap();
+        assert_eq!(lease.addr, Ipv4Addr::new(10, 0, 2, 15));
+        assert_eq!(lease.server, Ipv4Addr::new(10, 0, 2, 2));
+        assert_eq!(lease.dns, vec![Ipv4Addr::new(10, 0, 2, 3), Ipv4Addr::new(8, 8, 8, 8)]);
+        assert_eq!(lease.prefix_len(), 24);
+        assert_eq!(lease.domain, Some("example.com".to_string()));
+        assert_eq!(lease.mtu, Some(1500));
+        assert_eq!(lease.routes, vec![
+            (Ipv4Addr::new(0, 0, 0, 0), 0, Ipv4Addr::new(10, 0, 2, 1)),
+            (Ipv4Addr::new(192, 168, 0, 0), 16, Ipv4Addr::new(10, 0, 2, 254)),
+        ]);
+        assert_eq!((lease.t1(), lease.t2(), lease.expiry()), (2800, 4150, 4600));
+
+        assert_eq!(Lease::parse(&lease.to_string()), Ok(lease));
+        assert!(Lease::parse("server 10.0.2.2").is_err());
+
+        // A domain that would add lines to resolv.conf is dropped
+        ack.set_options(&[
+            (dhcp::OPT_MESSAGE_TYPE, &[dhcp::ACK]),
+            (dhcp::OPT_DOMAIN_NAME, b"example.com\nnameserver 192.0.2.1"),
+        ]);
+        assert_eq!(Lease::from_ack(&ack, 1000).unwrap().domain, None);
+    }
+}
@@ -1,16 +1,19 @@
-extern crate netutils;
+extern crate netutils_core;
 extern crate syscall;
-
-use netutils::{MacAddr};
-use std::{env, process, time};
-use std::io::{self, Read, Write};
-use std::fs::{File, OpenOptions};
-use std::net::UdpSocket;
-use std::time::Duration;
-
-use dhcp::Dhcp;
-
-mod dhcp;
+#[cfg(not(target_os = "redox"))]
+extern crate libc;
+
+use netutils_core::{MacAddr};
+use netutils_core::args::Parser;
+use netutils_core::error::NetError;
+use netutils_core::log::{Level, Logger};
+use std::{cmp, process, thread};
+use std::io::{self, ErrorKind, Read, Write};
+use std::fs::{self, File, OpenOptions};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 macro_rules! try_fmt {
     ($e:expr, $m:expr) =>(
@@ -21,6 +24,116 @@ macro_rules! try_fmt {
     )
 }
 
+use dhcp::Dhcp;
+use lease::Lease;
+use server::{Pool, Server};
+use v6::Client6;
+
+mod dhcp;
+mod lease;
+mod server;
+mod v6;
+
+/// Retransmissions of a message before giving up, with the delay doubling from 4 seconds
+const RETRIES: u32 = 4;
+
+/// Shortest wait between renewal attempts, from RFC 2131 section 4.4.5
+const MIN_RENEW_WAIT: u64 = 60;
+
+#[cfg(target_os = "redox")]
+static LEASE_DIR: &'static str = "file:/var/lib/dhcpd";
+#[cfg(not(target_os = "redox"))]
+static LEASE_DIR: &'static str = "/var/lib/dhcpd";
+
+static RESOLV_CONF: &'static str = "/etc/resolv.conf";
+
+#[cfg(target_os = "redox")]
+static HOSTNAME_FILE: &'static str = "file:/etc/hostname";
+#[cfg(not(target_os = "redox"))]
+static HOSTNAME_FILE: &'static str = "/etc/hostname";
+
+/// Which of the optional settings of a lease are applied to the system
+struct Settings {
+    dns: bool,
+    domain: bool,
+    ntp: bool,
+    mtu: bool,
+    routes: bool,
+}
+
+/// How the client names itself to servers
+struct Identity {
+    /// Sent as the host name option, so routers and DDNS can show the machine
+    hostname: Option<String>,
+    /// Defaults to the hardware address
+    client_id: Option<Vec<u8>>,
+    vendor_class: Option<String>,
+}
+
+/// The host name of the machine, if one is set
+fn hostname() -> Option<String> {
+    let mut hostname = String::new();
+    File::open(HOSTNAME_FILE).and_then(|mut file| file.read_to_string(&mut hostname)).ok()?;
+    let hostname = hostname.trim();
+    if hostname.is_empty() { None } else { Some(hostname.to_string()) }
+}
+
+/// Set when the client is asked to exit, so it can release its lease first
+static STOPPING: AtomicBool = ATOMIC_BOOL_INIT;
+
+#[cfg(not(target_os = "redox"))]
+fn handle_signals() {
+    extern "C" fn stop(_signal: libc::c_int) {
+        STOPPING.store(true, Ordering::SeqCst);
+    }
+
+    unsafe {
+        libc::signal(libc::SIGINT, stop as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, stop as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+/// Redox has no signal handlers here yet, shutdown scripts can run `dhcpd --release` instead
+#[cfg(target_os = "redox")]
+fn handle_signals() {}
+
+/// Sleep for `secs`, returning false early if the client is exiting
+fn wait(secs: u64) -> bool {
+    wait_unless(secs, || false)
+}
+
+/// Sleep for `secs`, returning false early if the client is exiting or `cancel` returns true,
+/// which is asked once a second
+fn wait_unless<F: Fn() -> bool>(secs: u64, cancel: F) -> bool {
+    for _ in 0..secs {
+        if STOPPING.load(Ordering::SeqCst) || cancel() {
+            return false;
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+    !STOPPING.load(Ordering::SeqCst) && !cancel()
+}
+
+/// Whether the interface is up, which it is while netcfg lists it
+#[cfg(target_os = "redox")]
+fn link_up(iface: &str) -> bool {
+    get_iface_cfg_value(iface, "mac").is_ok()
+}
+
+/// Whether the interface is up and has a carrier. An interface whose state can't be read is
+/// assumed to be up, unless it is gone.
+#[cfg(not(target_os = "redox"))]
+fn link_up(iface: &str) -> bool {
+    let mut state = String::new();
+    match File::open(format!("/sys/class/net/{}/operstate", iface)).and_then(|mut file| file.read_to_string(&mut state)) {
+        Ok(_) => match state.trim() {
+            "down" | "lowerlayerdown" | "notpresent" => false,
+            _ => true
+        },
+        Err(err) => err.kind() != ErrorKind::NotFound
+    }
+}
+
 fn get_cfg_value(path: &str) -> Result<String, String> {
     let path = format!("netcfg:{}", path);
     let mut file = File::open(&path).map_err(|_| format!("Can't open {}", &path))?;
@@ -51,345 +164,650 @@ fn set_iface_cfg_value(iface: &str, cfg: &str, value: &str) -> Result<(), String
     set_cfg_value(&path, value)
 }
 
-fn dhcp(iface: &str, quiet: bool) -> Result<(), String> {
-    let current_mac = MacAddr::from_str(get_iface_cfg_value(iface, "mac")?.trim());
+fn write_resolv_conf(resolv: &str) -> io::Result<()> {
+    File::create(RESOLV_CONF).and_then(|mut file| file.write_all(resolv.as_bytes()))
+}
 
-    let current_ip = get_iface_cfg_value(iface, "addr/list")?
-        .lines()
-        .next()
-        .map(|l| l.to_owned())
-        .unwrap_or("0.0.0.0".to_string());
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
 
-    if !quiet {
-        println!(
-            "DHCP: MAC: {} Current IP: {}",
-            current_mac.to_string(),
-            current_ip.trim()
-        );
-    }
+fn new_tid() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.subsec_nanos()).unwrap_or(0)
+}
 
-    let tid = try_fmt!(
-        time::SystemTime::now().duration_since(time::UNIX_EPOCH),
-        "failed to get time"
-    ).subsec_nanos();
-
-    let socket = try_fmt!(UdpSocket::bind(("0.0.0.0", 68)), "failed to bind udp");
-    try_fmt!(
-        socket.connect(("255.255.255.255", 67)),
-        "failed to connect udp"
-    );
-    try_fmt!(
-        socket.set_read_timeout(Some(Duration::new(5, 0))),
-        "failed to set read timeout"
-    );
-    try_fmt!(
-        socket.set_write_timeout(Some(Duration::new(5, 0))),
-        "failed to set write timeout"
-    );
-
-    {
-        let mut discover = Dhcp {
-            op: 1,
-            htype: 1,
-            hlen: 6,
-            hops: 0,
-            tid: tid,
-            secs: 0,
-            flags: 0x8000u16.to_be(),
-            ciaddr: [0, 0, 0, 0],
-            yiaddr: [0, 0, 0, 0],
-            siaddr: [0, 0, 0, 0],
-            giaddr: [0, 0, 0, 0],
-            chaddr: [
-                current_mac.bytes[0],
-                current_mac.bytes[1],
-                current_mac.bytes[2],
-                current_mac.bytes[3],
-                current_mac.bytes[4],
-                current_mac.bytes[5],
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-            ],
-            sname: [0; 64],
-            file: [0; 128],
-            magic: 0x63825363u32.to_be(),
-            options: [0; 308],
-        };
+struct Client {
+    iface: String,
+    mac: [u8; 6],
+    socket: UdpSocket,
+    settings: Settings,
+    identity: Identity,
+    logger: Arc<Logger>,
+}
 
-        for (s, mut d) in [53, 1, 1, 255].iter().zip(discover.options.iter_mut()) {
-            *d = *s;
+/// The reply to a request, if any arrived
+enum Reply {
+    Ack(Dhcp),
+    Nak,
+    Timeout,
+}
+
+impl Client {
+    fn new(iface: &str, settings: Settings, mut identity: Identity, logger: Arc<Logger>) -> Result<Client, String> {
+        let mac = MacAddr::from_str(get_iface_cfg_value(iface, "mac")?.trim());
+        if identity.client_id.is_none() {
+            // Hardware type 1 is ethernet
+            let mut client_id = vec![1];
+            client_id.extend_from_slice(&mac.bytes);
+            identity.client_id = Some(client_id);
         }
 
-        let discover_data = unsafe {
-            std::slice::from_raw_parts(
-                (&discover as *const Dhcp) as *const u8,
-                std::mem::size_of::<Dhcp>(),
-            )
-        };
+        let socket = try_fmt!(UdpSocket::bind(("0.0.0.0", 68)), "failed to bind udp");
+        try_fmt!(socket.set_broadcast(true), "failed to enable broadcast");
+        try_fmt!(
+            socket.set_write_timeout(Some(Duration::new(5, 0))),
+            "failed to set write timeout"
+        );
+
+        Ok(Client {
+            iface: iface.to_string(),
+            mac: mac.bytes,
+            socket: socket,
+            settings: settings,
+            identity: identity,
+            logger: logger,
+        })
+    }
 
-        let _sent = try_fmt!(socket.send(discover_data), "failed to send discover");
+    fn log(&self, message: &str) {
+        self.logger.info(&format!("DHCP: {}", message));
+    }
 
-        if !quiet {
-            println!("DHCP: Sent Discover");
+    /// Report a setting that could not be applied, which does not stop the lease being used
+    fn warn(&self, message: &str) {
+        self.logger.warn(message);
+    }
+
+    /// The options to ask servers for
+    fn parameters(&self) -> Vec<u8> {
+        let mut parameters = vec![dhcp::OPT_SUBNET_MASK, dhcp::OPT_ROUTER];
+        let settings = &self.settings;
+        for &(enabled, option) in [(settings.dns, dhcp::OPT_DNS), (settings.domain, dhcp::OPT_DOMAIN_NAME),
+                                   (settings.mtu, dhcp::OPT_MTU), (settings.ntp, dhcp::OPT_NTP),
+                                   (settings.routes, dhcp::OPT_CLASSLESS_ROUTES)].iter() {
+            if enabled {
+                parameters.push(option);
+            }
         }
+        parameters
     }
 
-    let mut offer_data = [0; 65536];
-    try_fmt!(socket.recv(&mut offer_data), "failed to receive offer");
-    let offer = unsafe { &*(offer_data.as_ptr() as *const Dhcp) };
-    if !quiet {
-        println!(
-            "DHCP: Offer IP: {:?}, Server IP: {:?}",
-            offer.yiaddr, offer.siaddr
-        );
+    /// Set the options of a DISCOVER or REQUEST, followed by the parameter list and identity
+    fn set_options(&self, packet: &mut Dhcp, options: &[(u8, &[u8])]) {
+        let parameters = self.parameters();
+        let mut options = options.to_vec();
+        options.push((dhcp::OPT_PARAMETER_LIST, &parameters));
+        let required = options.len();
+        if let Some(ref hostname) = self.identity.hostname {
+            options.push((dhcp::OPT_HOSTNAME, hostname.as_bytes()));
+        }
+        if let Some(ref client_id) = self.identity.client_id {
+            options.push((dhcp::OPT_CLIENT_ID, client_id));
+        }
+        if let Some(ref vendor_class) = self.identity.vendor_class {
+            options.push((dhcp::OPT_VENDOR_CLASS, vendor_class.as_bytes()));
+        }
+
+        if !packet.set_options(&options) {
+            self.warn("options do not fit in the packet, leaving out the identity");
+            options.truncate(required);
+            packet.set_options(&options);
+        }
     }
 
-    {
-        let mut subnet_option = None;
-        let mut router_option = None;
-        let mut dns_option = None;
-
-        let mut options = offer.options.iter();
-        while let Some(option) = options.next() {
-            match *option {
-                0 => (),
-                255 => break,
-                _ => if let Some(len) = options.next() {
-                    if *len as usize <= options.as_slice().len() {
-                        let data = &options.as_slice()[..*len as usize];
-                        for _data_i in 0..*len {
-                            options.next();
-                        }
-                        match *option {
-                            1 => {
-                                if !quiet {
-                                    println!("DHCP: Subnet Mask: {:?}", data);
-                                }
-                                if data.len() == 4 && subnet_option.is_none() {
-                                    subnet_option = Some(Vec::from(data));
-                                }
-                            }
-                            3 => {
-                                if !quiet {
-                                    println!("DHCP: Router: {:?}", data);
-                                }
-                                if data.len() == 4 && router_option.is_none() {
-                                    router_option = Some(Vec::from(data));
-                                }
-                            }
-                            6 => {
-                                if !quiet {
-                                    println!("DHCP: Domain Name Server: {:?}", data);
-                                }
-                                if data.len() == 4 && dns_option.is_none() {
-                                    dns_option = Some(Vec::from(data));
-                                }
-                            }
-                            51 => {
-                                if !quiet {
-                                    println!("DHCP: Lease Time: {:?}", data);
-                                }
-                            }
-                            53 => {
-                                if !quiet {
-                                    println!("DHCP: Message Type: {:?}", data);
-                                }
-                            }
-                            54 => {
-                                if !quiet {
-                                    println!("DHCP: Server ID: {:?}", data);
-                                }
-                            }
-                            _ => {
-                                if !quiet {
-                                    println!("DHCP: {}: {:?}", option, data);
-                                }
-                            }
+    /// Send `packet` to `dest`, retransmitting until a reply of one of the `types` arrives
+    fn exchange(&self, packet: &Dhcp, dest: Ipv4Addr, types: &[u8]) -> Result<Option<Dhcp>, String> {
+        let dest = SocketAddr::V4(SocketAddrV4::new(dest, 67));
+        let data = packet.to_bytes();
+
+        for attempt in 0..RETRIES {
+            try_fmt!(self.socket.send_to(&data, dest), "failed to send");
+
+            let deadline = Instant::now() + Duration::from_secs(4 << attempt);
+            loop {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                try_fmt!(self.socket.set_read_timeout(Some(deadline - now)), "failed to set read timeout");
+
+                let mut reply_data = [0; 65536];
+                let count = match self.socket.recv(&mut reply_data) {
+                    Ok(count) => count,
+                    Err(ref err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => break,
+                    Err(err) => return Err(format!("failed to receive: {}", err))
+                };
+
+                // Ignore replies to other clients and transactions
+                if let Some(reply) = Dhcp::from_bytes(&reply_data[..count]) {
+                    if reply.op == 2 && reply.tid == packet.tid && reply.chaddr[..6] == self.mac {
+                        if reply.message_type().map_or(false, |message_type| types.contains(&message_type)) {
+                            return Ok(Some(reply));
                         }
                     }
-                },
+                }
             }
         }
 
-        let mask_len = if let Some(subnet) = subnet_option {
-            let mut subnet: u32 = (subnet[0] as u32) << 24 | (subnet[1] as u32) << 16 |
-                                  (subnet[2] as u32) << 8 | subnet[3] as u32;
-            subnet = !subnet;
-            subnet.leading_zeros()
-        } else {
-            0
+        Ok(None)
+    }
+
+    /// Send a REQUEST and wait for the server to acknowledge it
+    fn request(&self, request: Dhcp, dest: Ipv4Addr) -> Result<Reply, String> {
+        match self.exchange(&request, dest, &[dhcp::ACK, dhcp::NAK])? {
+            Some(reply) => if reply.message_type() == Some(dhcp::ACK) {
+                Ok(Reply::Ack(reply))
+            } else {
+                self.log("Received NAK");
+                Ok(Reply::Nak)
+            },
+            None => Ok(Reply::Timeout)
+        }
+    }
+
+    fn lease(&self, reply: Reply) -> Option<Lease> {
+        match reply {
+            Reply::Ack(ack) => {
+                let lease = Lease::from_ack(&ack, unix_time());
+                if let Some(ref lease) = lease {
+                    self.log(&format!("Ack IP: {}, Server IP: {}, Lease Time: {}",
+                                      lease.addr, lease.server, lease.lease_time));
+                }
+                lease
+            },
+            _ => None
+        }
+    }
+
+    /// Acquire a new lease with DISCOVER, OFFER, REQUEST and ACK
+    fn discover(&self) -> Result<Option<Lease>, String> {
+        let tid = new_tid();
+
+        let mut discover = Dhcp::request(tid, self.mac);
+        discover.flags = 0x8000u16.to_be();
+        self.set_options(&mut discover, &[
+            (dhcp::OPT_MESSAGE_TYPE, &[dhcp::DISCOVER]),
+        ]);
+
+        self.log("Sent Discover");
+        let offer = match self.exchange(&discover, Ipv4Addr::new(255, 255, 255, 255), &[dhcp::OFFER])? {
+            Some(offer) => offer,
+            None => return Err("no offer received".to_string())
         };
 
-        let new_ips = format!("{}.{}.{}.{}/{}\n127.0.0.1/8\n",
-                              offer.yiaddr[0], offer.yiaddr[1], offer.yiaddr[2], offer.yiaddr[3], mask_len);
+        let server = offer.option(dhcp::OPT_SERVER_ID).and_then(dhcp::option_addr).unwrap_or(offer.siaddr);
+        self.log(&format!("Offer IP: {}, Server IP: {}", Ipv4Addr::from(offer.yiaddr), Ipv4Addr::from(server)));
+
+        let mut request = Dhcp::request(tid, self.mac);
+        request.flags = 0x8000u16.to_be();
+        request.siaddr = offer.siaddr;
+        self.set_options(&mut request, &[
+            (dhcp::OPT_MESSAGE_TYPE, &[dhcp::REQUEST]),
+            (dhcp::OPT_REQUESTED_IP, &offer.yiaddr),
+            (dhcp::OPT_SERVER_ID, &server),
+        ]);
+
+        self.log("Sent Request");
+        Ok(self.lease(self.request(request, Ipv4Addr::new(255, 255, 255, 255))?))
+    }
+
+    /// Ask to keep using a recorded lease after a restart (INIT-REBOOT)
+    fn reboot(&self, lease: &Lease) -> Result<Option<Lease>, String> {
+        let mut request = Dhcp::request(new_tid(), self.mac);
+        request.flags = 0x8000u16.to_be();
+        self.set_options(&mut request, &[
+            (dhcp::OPT_MESSAGE_TYPE, &[dhcp::REQUEST]),
+            (dhcp::OPT_REQUESTED_IP, &lease.addr.octets()),
+        ]);
+
+        self.log(&format!("Requesting recorded lease {}", lease.addr));
+        Ok(self.lease(self.request(request, Ipv4Addr::new(255, 255, 255, 255))?))
+    }
+
+    /// Extend a lease, with its server when renewing or any server when rebinding
+    fn renew(&self, lease: &Lease, rebind: bool) -> Result<Reply, String> {
+        let mut request = Dhcp::request(new_tid(), self.mac);
+        request.ciaddr = lease.addr.octets();
+        self.set_options(&mut request, &[
+            (dhcp::OPT_MESSAGE_TYPE, &[dhcp::REQUEST]),
+        ]);
+
+        if rebind {
+            self.log("Rebinding");
+            self.request(request, Ipv4Addr::new(255, 255, 255, 255))
+        } else {
+            self.log(&format!("Renewing with {}", lease.server));
+            self.request(request, lease.server)
+        }
+    }
+
+    /// Configure the interface with a lease, which renews `previous` if that was applied before
+    fn apply(&self, lease: &Lease, previous: Option<&Lease>) -> Result<(), String> {
+        let new_ips = format!("{}/{}\n127.0.0.1/8\n", lease.addr, lease.prefix_len());
         try_fmt!(
-            set_iface_cfg_value(iface, "addr/set", &new_ips),
+            set_iface_cfg_value(&self.iface, "addr/set", &new_ips),
             "failed to set ip"
         );
 
-        if !quiet {
-            let new_ip = try_fmt!(get_iface_cfg_value(iface, "addr/list"), "failed to get ip");
-            println!("DHCP: New IP: {}", new_ip.trim());
+        if self.logger.enabled(Level::Info) {
+            let new_ip = try_fmt!(get_iface_cfg_value(&self.iface, "addr/list"), "failed to get ip");
+            self.log(&format!("New IP: {}", new_ip.trim()));
         }
 
-        if let Some(router) = router_option {
-            let default_route = format!("default via {}.{}.{}.{}",
-                                        router[0], router[1], router[2], router[3]);
+        self.configure(lease, previous)
+    }
 
-            try_fmt!(
-                set_cfg_value("route/add", &default_route),
-                "failed to set default route"
-            );
+    /// The routes of a lease, as they are added to `route/add`
+    fn routes(&self, lease: &Lease) -> Vec<String> {
+        // Classless static routes replace the router option when present (RFC 3442)
+        if self.settings.routes && !lease.routes.is_empty() {
+            lease.routes.iter().map(|&(dest, prefix_len, router)| if prefix_len == 0 {
+                format!("default via {}", router)
+            } else {
+                format!("{}/{} via {}", dest, prefix_len, router)
+            }).collect()
+        } else {
+            lease.router.map(|router| format!("default via {}", router)).into_iter().collect()
+        }
+    }
 
-            if !quiet {
-                let new_router = try_fmt!(get_cfg_value("route/list"), "failed to get ip router");
-                println!("DHCP: New Router: {}", new_router.trim());
+    /// Apply the routes, name servers and other settings of a lease. The routes are only added
+    /// if they differ from those of `previous`, which a renewal would otherwise add again.
+    fn configure(&self, lease: &Lease, previous: Option<&Lease>) -> Result<(), String> {
+        let routes = self.routes(lease);
+        let changed = previous.map_or(true, |previous| self.routes(previous) != routes);
+
+        if changed {
+            for route in routes.iter() {
+                try_fmt!(
+                    set_cfg_value("route/add", route),
+                    "failed to add route"
+                );
             }
         }
 
-        if let Some(mut dns) = dns_option {
-            if dns[0] == 127 {
-                let opendns = [208, 67, 222, 222].to_vec();
-                if !quiet {
-                    println!("DHCP: Received sarcastic DNS suggestion {}.{}.{}.{}, using {}.{}.{}.{} instead",
-                            dns[0], dns[1], dns[2], dns[3], opendns[0], opendns[1], opendns[2], opendns[3]);
+        if self.logger.enabled(Level::Info) && changed && !routes.is_empty() {
+            let new_router = try_fmt!(get_cfg_value("route/list"), "failed to get ip router");
+            self.log(&format!("New Router: {}", new_router.trim()));
+        }
+
+        let mut nameservers = Vec::new();
+        if self.settings.dns {
+            for dns in lease.dns.iter() {
+                if dns.octets()[0] == 127 {
+                    let opendns = Ipv4Addr::new(208, 67, 222, 222);
+                    self.log(&format!("Received sarcastic DNS suggestion {}, using {} instead", dns, opendns));
+                    nameservers.push(opendns);
+                } else {
+                    nameservers.push(*dns);
                 }
-                dns = opendns;
             }
+        }
 
-            let nameserver = format!("{}.{}.{}.{}", dns[0], dns[1], dns[2], dns[3]);
-
+        if let Some(dns) = nameservers.get(0) {
             try_fmt!(
-                set_cfg_value("resolv/nameserver", &nameserver),
+                set_cfg_value("resolv/nameserver", &format!("{}", dns)),
                 "failed to set name server"
             );
 
-            if !quiet {
+            if self.logger.enabled(Level::Info) {
                 let new_dns = try_fmt!(get_cfg_value("resolv/nameserver"), "failed to get dns");
-                println!("DHCP: New DNS: {}", new_dns.trim());
+                self.log(&format!("New DNS: {}", new_dns.trim()));
             }
         }
-    }
 
-    {
-        let mut request = Dhcp {
-            op: 1,
-            htype: 1,
-            hlen: 6,
-            hops: 0,
-            tid: tid,
-            secs: 0,
-            flags: 0,
-            ciaddr: [0; 4],
-            yiaddr: [0; 4],
-            siaddr: offer.siaddr,
-            giaddr: [0; 4],
-            chaddr: [
-                current_mac.bytes[0],
-                current_mac.bytes[1],
-                current_mac.bytes[2],
-                current_mac.bytes[3],
-                current_mac.bytes[4],
-                current_mac.bytes[5],
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-                0x00,
-            ],
-            sname: [0; 64],
-            file: [0; 128],
-            magic: 0x63825363u32.to_be(),
-            options: [0; 308],
-        };
+        let domain = lease.domain.as_ref().filter(|domain| self.settings.domain && lease::valid_domain(domain));
+        if domain.is_some() || !nameservers.is_empty() {
+            let mut resolv = String::new();
+            if let Some(domain) = domain {
+                self.log(&format!("Domain: {}", domain));
+                resolv.push_str(&format!("search {}\n", domain));
+            }
+            for dns in nameservers.iter() {
+                resolv.push_str(&format!("nameserver {}\n", dns));
+            }
+            if let Err(err) = write_resolv_conf(&resolv) {
+                self.warn(&format!("failed to write {}: {}", RESOLV_CONF, err));
+            }
+        }
 
-        for (s, mut d) in [
-            53,
-            1,
-            3,
-            50,
-            4,
-            offer.yiaddr[0],
-            offer.yiaddr[1],
-            offer.yiaddr[2],
-            offer.yiaddr[3],
-            255,
-        ].iter()
-            .zip(request.options.iter_mut())
-        {
-            *d = *s;
+        if self.settings.ntp && !lease.ntp.is_empty() {
+            let servers = lease.ntp.iter().map(|ntp| format!("{}", ntp)).collect::<Vec<String>>().join("\n");
+            self.log(&format!("NTP: {}", servers.replace('\n', " ")));
+            if let Err(err) = netutils_core::setcfg("ntp", &servers) {
+                self.warn(&format!("failed to set ntp servers: {}", err));
+            }
         }
 
-        let request_data = unsafe {
-            std::slice::from_raw_parts(
-                (&request as *const Dhcp) as *const u8,
-                std::mem::size_of::<Dhcp>(),
-            )
-        };
+        if let (true, Some(mtu)) = (self.settings.mtu, lease.mtu) {
+            self.log(&format!("MTU: {}", mtu));
+            if let Err(err) = set_iface_cfg_value(&self.iface, "mtu", &format!("{}", mtu)) {
+                self.warn(&format!("failed to set mtu: {}", err));
+            }
+        }
 
-        let _sent = try_fmt!(socket.send(request_data), "failed to send request");
+        Ok(())
+    }
 
-        if !quiet {
-            println!("DHCP: Sent Request");
+    /// Give a lease back to its server, which does not reply
+    fn release(&self, lease: &Lease) -> Result<(), String> {
+        let mut release = Dhcp::request(new_tid(), self.mac);
+        release.ciaddr = lease.addr.octets();
+        let server = lease.server.octets();
+        let mut options: Vec<(u8, &[u8])> = vec![
+            (dhcp::OPT_MESSAGE_TYPE, &[dhcp::RELEASE]),
+            (dhcp::OPT_SERVER_ID, &server),
+        ];
+        if let Some(ref client_id) = self.identity.client_id {
+            options.push((dhcp::OPT_CLIENT_ID, client_id));
         }
+        release.set_options(&options);
+
+        self.log(&format!("Releasing {} to {}", lease.addr, lease.server));
+        let dest = SocketAddr::V4(SocketAddrV4::new(lease.server, 67));
+        try_fmt!(self.socket.send_to(&release.to_bytes(), dest), "failed to send");
+        Ok(())
     }
 
-    {
-        let mut ack_data = [0; 65536];
-        try_fmt!(socket.recv(&mut ack_data), "failed to receive ack");
-        let ack = unsafe { &*(ack_data.as_ptr() as *const Dhcp) };
-        if !quiet {
-            println!(
-                "DHCP: Ack IP: {:?}, Server IP: {:?}",
-                ack.yiaddr, ack.siaddr
-            );
+    /// Ask for the settings of the network without an address, for hosts configured statically
+    fn inform(&self) -> Result<(), String> {
+        let current_ip = get_iface_cfg_value(&self.iface, "addr/list")?
+            .lines()
+            .filter_map(|line| line.split('/').next().and_then(|addr| addr.trim().parse::<Ipv4Addr>().ok()))
+            .find(|addr| !addr.is_loopback() && !addr.is_unspecified());
+        let addr = match current_ip {
+            Some(addr) => addr,
+            None => return Err("interface has no address to inform with".to_string())
+        };
+
+        let mut inform = Dhcp::request(new_tid(), self.mac);
+        inform.ciaddr = addr.octets();
+        self.set_options(&mut inform, &[
+            (dhcp::OPT_MESSAGE_TYPE, &[dhcp::INFORM]),
+        ]);
+
+        self.log(&format!("Sent Inform from {}", addr));
+        let mut ack = match self.exchange(&inform, Ipv4Addr::new(255, 255, 255, 255), &[dhcp::ACK])? {
+            Some(ack) => ack,
+            None => return Err("no ack received".to_string())
+        };
+        // The reply has no address, the client keeps its own
+        ack.yiaddr = addr.octets();
+
+        match Lease::from_ack(&ack, unix_time()) {
+            Some(lease) => self.configure(&lease, None),
+            None => Err("invalid ack received".to_string())
         }
     }
 
-    Ok(())
-}
+    /// Remove the address of a lease that expired or was released
+    fn unconfigure(&self) -> Result<(), String> {
+        set_iface_cfg_value(&self.iface, "addr/set", "127.0.0.1/8\n")
+    }
 
-fn main() {
-    let mut background = false;
-    let mut quiet = false;
-    let iface = "eth0";
+    /// Keep a lease by renewing it at T1 and rebinding at T2, returning the extended lease, or
+    /// None once it has expired, a server refused it, the link went down or the client is exiting
+    fn maintain(&self, lease: &Lease) -> Result<Option<Lease>, String> {
+        let link_down = || !link_up(&self.iface);
+        loop {
+            let now = unix_time();
+            let (rebind, deadline) = if now < lease.t1() {
+                if !wait_unless(lease.t1() - now, &link_down) {
+                    return Ok(None);
+                }
+                continue;
+            } else if now < lease.t2() {
+                (false, lease.t2())
+            } else if now < lease.expiry() {
+                (true, lease.expiry())
+            } else {
+                return Ok(None);
+            };
+
+            let reply = match self.renew(lease, rebind) {
+                Ok(reply) => reply,
+                Err(_) if link_down() => return Ok(None),
+                Err(err) => return Err(err)
+            };
+            match reply {
+                Reply::Ack(ack) => if let Some(lease) = self.lease(Reply::Ack(ack)) {
+                    return Ok(Some(lease));
+                },
+                Reply::Nak => return Ok(None),
+                Reply::Timeout => ()
+            }
 
-    //TODO: parse iface from the args
-    for arg in env::args().skip(1) {
-        match arg.as_ref() {
-            "-b" => background = true,
-            "-q" => quiet = true,
-            _ => (),
+            // Retry after half of the remaining time, as RFC 2131 recommends
+            let now = unix_time();
+            if now < deadline {
+                let delay = cmp::max((deadline - now) / 2, MIN_RENEW_WAIT);
+                if !wait_unless(cmp::min(delay, deadline - now), &link_down) {
+                    return Ok(None);
+                }
+            }
         }
     }
+}
+
+fn dhcp(iface: &str, settings: Settings, identity: Identity, logger: Arc<Logger>, once: bool,
+        lease_path: &str) -> Result<(), String> {
+    let client = Client::new(iface, settings, identity, logger)?;
+
+    if client.logger.enabled(Level::Info) {
+        let current_ip = get_iface_cfg_value(iface, "addr/list")?
+            .lines()
+            .next()
+            .map(|l| l.to_owned())
+            .unwrap_or("0.0.0.0".to_string());
 
-    println!("Running with {} and {}", background, quiet);
+        client.log(&format!(
+            "MAC: {} Current IP: {}",
+            MacAddr { bytes: client.mac }.to_string(),
+            current_ip.trim()
+        ));
+    }
 
-    if background {
-        if unsafe { syscall::clone(0).unwrap() } == 0 {
-            if let Err(err) = dhcp(iface, quiet) {
-                writeln!(io::stderr(), "dhcpd: {}", err).unwrap();
-                process::exit(1);
+    // Try to keep a recorded lease before asking for a new one
+    let mut lease = match Lease::load(lease_path) {
+        Ok(recorded) => if unix_time() < recorded.expiry() {
+            client.reboot(&recorded)?
+        } else {
+            None
+        },
+        Err(_) => None
+    };
+
+    // The lease whose settings are in place, which a renewal of it needn't add routes for
+    let mut applied = None;
+    loop {
+        let current = match lease.take() {
+            Some(current) => current,
+            None => match client.discover() {
+                Ok(Some(current)) => current,
+                Ok(None) if once => return Err("no lease acknowledged".to_string()),
+                Err(err) if once => return Err(err),
+                Ok(None) | Err(_) => {
+                    client.log(&format!("No lease, retrying in {} seconds", MIN_RENEW_WAIT));
+                    if !wait(MIN_RENEW_WAIT) {
+                        return Ok(());
+                    }
+                    continue;
+                }
             }
+        };
+
+        client.apply(&current, applied.as_ref())?;
+        if let Err(err) = current.save(lease_path) {
+            client.warn(&err);
         }
-    } else {
-        if let Err(err) = dhcp(iface, quiet) {
-            println!("Error {}", err);
-            writeln!(io::stderr(), "dhcpd: {}", err).unwrap();
-            process::exit(1);
+
+        if once {
+            return Ok(());
         }
+
+        lease = client.maintain(&current)?;
+        if STOPPING.load(Ordering::SeqCst) {
+            return release_lease(&client, &current, lease_path);
+        } else if lease.is_none() && !link_up(iface) {
+            // The network may be another one once the link is back, so the lease isn't kept
+            client.log("Link down, releasing the lease");
+            if let Err(err) = release_lease(&client, &current, lease_path) {
+                client.warn(&err);
+            }
+            while !link_up(iface) {
+                if !wait(1) {
+                    return Ok(());
+                }
+            }
+            client.log("Link up");
+        } else if lease.is_none() {
+            client.log("Lease expired");
+            client.unconfigure()?;
+        }
+        applied = if lease.is_some() { Some(current) } else { None };
+    }
+}
+
+/// Release a lease, remove its address and forget it. The address is given up even if the
+/// server couldn't be told, such as when the link is down.
+fn release_lease(client: &Client, lease: &Lease, lease_path: &str) -> Result<(), String> {
+    let released = client.release(lease);
+    client.unconfigure()?;
+    match fs::remove_file(lease_path) {
+        Ok(()) => (),
+        Err(ref err) if err.kind() == ErrorKind::NotFound => (),
+        Err(err) => return Err(format!("failed to remove {}: {}", lease_path, err))
+    }
+    released
+}
+
+/// Release the recorded lease, as done on shutdown
+fn release(iface: &str, settings: Settings, identity: Identity, logger: Arc<Logger>, lease_path: &str) -> Result<(), String> {
+    let client = Client::new(iface, settings, identity, logger)?;
+    let lease = Lease::load(lease_path)?;
+    if unix_time() < lease.expiry() {
+        release_lease(&client, &lease, lease_path)
+    } else {
+        client.log("Recorded lease has already expired");
+        Ok(())
+    }
+}
+
+fn inform(iface: &str, settings: Settings, identity: Identity, logger: Arc<Logger>) -> Result<(), String> {
+    Client::new(iface, settings, identity, logger)?.inform()
+}
+
+fn dhcp6(iface: &str, stateless: bool, logger: Arc<Logger>, once: bool) -> Result<(), String> {
+    let mac = MacAddr::from_str(get_iface_cfg_value(iface, "mac")?.trim());
+    let client = Client6::new(iface, mac.bytes, logger)?;
+    if stateless {
+        client.inform()
+    } else {
+        client.run(once)
+    }
+}
+
+fn serve(config_path: &str, lease_path: &str, logger: Arc<Logger>) -> Result<(), String> {
+    let pool = Pool::load(config_path)?;
+    Server::new(pool, lease_path, logger)?.run()
+}
+
+fn main() {
+    let args = Parser::new("dhcpd")
+        .synopsis("[-b] [-q] [-1] [--inform | --release] [--lease-file file] [option...]")
+        .synopsis("-6 [-b] [-q] [-1] [--stateless]")
+        .synopsis("--server config [-b] [-q] [--lease-file file]")
+        .flag("b", "", "Run in the background")
+        .flag("q", "", "Only log warnings and errors")
+        .flag("1", "once", "Exit once an address is configured, rather than renewing its lease")
+        .flag("6", "", "Configure IPv6 with DHCPv6")
+        .flag("", "stateless", "Only ask DHCPv6 for settings, not an address")
+        .opt("", "server", "config", "Serve addresses as configured in config")
+        .opt("", "lease-file", "file", "Keep the lease in file")
+        .flag("", "inform", "Only ask for settings, for an address configured by hand")
+        .flag("", "release", "Give the address of the lease back to its server")
+        .flag("", "no-dns", "Don't configure name servers")
+        .flag("", "no-domain", "Don't configure the domain")
+        .flag("", "no-ntp", "Don't configure time servers")
+        .flag("", "no-mtu", "Don't configure the MTU")
+        .flag("", "no-routes", "Don't configure routes")
+        .opt("", "hostname", "name", "Send this host name, rather than the name of this host")
+        .flag("", "no-hostname", "Don't send a host name")
+        .opt("", "client-id", "id", "Identify as id, rather than by hardware address")
+        .opt("", "vendor-class", "class", "Send this vendor class")
+        .logging()
+        .parse_env();
+    if !args.free.is_empty() {
+        args.usage();
+    }
+
+    let background = args.flag("b");
+    let logger = Arc::new(args.logger(if args.flag("q") { Level::Warn } else { Level::Info }));
+    let once = args.flag("once");
+    let ipv6 = args.flag("6");
+    let stateless = args.flag("stateless");
+    let server_config = args.value("server").map(|config| config.to_string());
+    let informing = args.flag("inform");
+    let releasing = args.flag("release");
+    let lease_path = args.value("lease-file").map(|path| path.to_string());
+    let settings = Settings {
+        dns: !args.flag("no-dns"),
+        domain: !args.flag("no-domain"),
+        ntp: !args.flag("no-ntp"),
+        mtu: !args.flag("no-mtu"),
+        routes: !args.flag("no-routes"),
+    };
+    let identity = Identity {
+        hostname: if args.flag("no-hostname") {
+            None
+        } else {
+            args.value("hostname").map(|name| name.to_string()).or_else(hostname)
+        },
+        client_id: args.value("client-id").map(dhcp::client_id),
+        vendor_class: args.value("vendor-class").map(|class| class.to_string()),
+    };
+    //TODO: parse iface from the args
+    let iface = "eth0";
+
+    let lease_path = lease_path.unwrap_or(if server_config.is_some() {
+        format!("{}/server.leases", LEASE_DIR)
+    } else {
+        format!("{}/{}.lease", LEASE_DIR, iface)
+    });
+
+    let run_logger = logger.clone();
+    let run = move || if let Some(ref config) = server_config {
+        serve(config, &lease_path, run_logger)
+    } else if ipv6 {
+        dhcp6(iface, stateless, run_logger, once)
+    } else if releasing {
+        release(iface, settings, identity, run_logger, &lease_path)
+    } else if informing {
+        inform(iface, settings, identity, run_logger)
+    } else {
+        dhcp(iface, settings, identity, run_logger, once, &lease_path)
+    };
+
+    handle_signals();
+
+    if background && unsafe { syscall::clone(0).unwrap() } != 0 {
+        return;
+    }
+    // The error is reported to the log rather than by NetError::exit, since a daemon's log may
+    // be a file or syslog, and it would be printed twice when the log is the terminal
+    if let Err(err) = run() {
+        let err = NetError::other(err);
+        logger.error(&err.to_string());
+        process::exit(err.exit_code());
     }
 }
@@ -0,0 +1,517 @@
+use netutils_core::MacAddr;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use syscall;
+
+use dhcp::{self, Dhcp};
+use netutils_core::log::Logger;
+use unix_time;
+
+/// Time a client has to request an offered address before it may be offered to others
+const OFFER_TIME: u64 = 60;
+
+/// Time an address that answered a ping, or that a client declined, is kept out of the pool
+const CONFLICT_TIME: u64 = 3600;
+
+const PING_TIMEOUT_MS: u64 = 500;
+
+/// Addresses and settings handed out by the server
+#[derive(Debug)]
+pub struct Pool {
+    pub start: Ipv4Addr,
+    pub end: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    pub gateway: Option<Ipv4Addr>,
+    pub dns: Vec<Ipv4Addr>,
+    pub domain: Option<String>,
+    pub lease_time: u32,
+    /// The address of this server, sent as the server identifier
+    pub server_id: Ipv4Addr,
+    /// Addresses always given to a hardware address
+    pub reservations: Vec<([u8; 6], Ipv4Addr)>,
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid {} '{}'", key, value))
+}
+
+fn parse_mac(value: &str) -> Result<[u8; 6], String> {
+    let parts: Vec<&str> = value.split(|c| c == ':' || c == '-').collect();
+    if parts.len() != 6 || parts.iter().any(|part| part.len() != 2 || u8::from_str_radix(part, 16).is_err()) {
+        return Err(format!("invalid hardware address '{}'", value));
+    }
+    Ok(MacAddr::from_str(value).bytes)
+}
+
+impl Pool {
+    /// Parse a configuration with a "key value" setting on each line:
+    ///
+    /// ```text
+    /// range 192.168.1.100 192.168.1.200
+    /// netmask 255.255.255.0
+    /// gateway 192.168.1.1
+    /// dns 192.168.1.1
+    /// lease_time 86400
+    /// reserve 52:54:00:12:34:56 192.168.1.10
+    /// ```
+    pub fn parse(text: &str) -> Result<Pool, String> {
+        let mut range = None;
+        let mut pool = Pool {
+            start: Ipv4Addr::new(0, 0, 0, 0),
+            end: Ipv4Addr::new(0, 0, 0, 0),
+            netmask: Ipv4Addr::new(255, 255, 255, 0),
+            gateway: None,
+            dns: Vec::new(),
+            domain: None,
+            lease_time: 86400,
+            server_id: Ipv4Addr::new(0, 0, 0, 0),
+            reservations: Vec::new(),
+        };
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut words = line.split_whitespace();
+            let key = match words.next() {
+                Some(key) => key,
+                None => continue
+            };
+            let values: Vec<&str> = words.collect();
+
+            let invalid = || format!("{}: invalid setting '{}'", i + 1, line);
+            let value = |index: usize| values.get(index).cloned().ok_or_else(&invalid);
+            let at_line = |err: String| format!("{}: {}", i + 1, err);
+
+            match (key, values.len()) {
+                ("range", 2) => range = Some((parse(key, value(0)?).map_err(at_line)?,
+                                              parse(key, value(1)?).map_err(at_line)?)),
+                ("netmask", 1) => pool.netmask = parse(key, value(0)?).map_err(at_line)?,
+                ("gateway", 1) => pool.gateway = Some(parse(key, value(0)?).map_err(at_line)?),
+                ("dns", count) if count > 0 => for dns in values.iter() {
+                    pool.dns.push(parse(key, dns).map_err(at_line)?);
+                },
+                ("domain", 1) => pool.domain = Some(value(0)?.to_string()),
+                ("lease_time", 1) => pool.lease_time = parse(key, value(0)?).map_err(at_line)?,
+                ("server_id", 1) => pool.server_id = parse(key, value(0)?).map_err(at_line)?,
+                ("reserve", 2) => {
+                    let mac = parse_mac(value(0)?).map_err(at_line)?;
+                    pool.reservations.push((mac, parse(key, value(1)?).map_err(at_line)?));
+                },
+                _ => return Err(invalid())
+            }
+        }
+
+        match range {
+            Some((start, end)) => if u32::from(start) <= u32::from(end) {
+                pool.start = start;
+                pool.end = end;
+            } else {
+                return Err("range start is after its end".to_string());
+            },
+            None => return Err("no address range".to_string())
+        }
+
+        // Most small networks run the server on the gateway
+        if pool.server_id.is_unspecified() {
+            pool.server_id = pool.gateway.ok_or("server_id or gateway is required".to_string())?;
+        }
+
+        Ok(pool)
+    }
+
+    pub fn load(path: &str) -> Result<Pool, String> {
+        let mut text = String::new();
+        File::open(path).and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|err| format!("failed to read {}: {}", path, err))?;
+        Pool::parse(&text).map_err(|err| format!("{}:{}", path, err))
+    }
+
+    fn contains(&self, addr: Ipv4Addr) -> bool {
+        u32::from(self.start) <= u32::from(addr) && u32::from(addr) <= u32::from(self.end)
+    }
+
+    fn reservation(&self, mac: [u8; 6]) -> Option<Ipv4Addr> {
+        self.reservations.iter().find(|reservation| reservation.0 == mac).map(|reservation| reservation.1)
+    }
+
+    fn is_reserved(&self, addr: Ipv4Addr) -> bool {
+        self.reservations.iter().any(|reservation| reservation.1 == addr)
+    }
+}
+
+/// An address given to a client, or kept out of the pool when `mac` is None
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binding {
+    pub mac: Option<[u8; 6]>,
+    pub addr: Ipv4Addr,
+    pub expiry: u64,
+}
+
+/// Lease database, saved with a "mac address expiry" line for each binding
+pub struct Leases {
+    pub bindings: Vec<Binding>,
+}
+
+impl Leases {
+    pub fn parse(text: &str) -> Result<Leases, String> {
+        let mut bindings = Vec::new();
+        for line in text.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.len() {
+                0 => (),
+                3 => bindings.push(Binding {
+                    mac: if words[0] == "-" { None } else { Some(parse_mac(words[0])?) },
+                    addr: parse("address", words[1])?,
+                    expiry: parse("expiry", words[2])?,
+                }),
+                _ => return Err(format!("invalid lease '{}'", line))
+            }
+        }
+        Ok(Leases {
+            bindings: bindings,
+        })
+    }
+
+    pub fn to_string(&self) -> String {
+        let mut text = String::new();
+        for binding in self.bindings.iter() {
+            let mac = binding.mac.map_or("-".to_string(), |mac| MacAddr { bytes: mac }.to_string());
+            text.push_str(&format!("{} {} {}\n", mac, binding.addr, binding.expiry));
+        }
+        text
+    }
+
+    pub fn load(path: &str) -> Result<Leases, String> {
+        let mut text = String::new();
+        match File::open(path) {
+            Ok(mut file) => file.read_to_string(&mut text).map_err(|err| format!("failed to read {}: {}", path, err))?,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => 0,
+            Err(err) => return Err(format!("failed to read {}: {}", path, err))
+        };
+        Leases::parse(&text).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir).map_err(|err| format!("failed to create {}: {}", dir.display(), err))?;
+        }
+
+        let tmp = format!("{}.tmp", path);
+        File::create(&tmp)
+            .and_then(|mut file| {
+                file.write_all(self.to_string().as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|err| format!("failed to write {}: {}", path, err))
+    }
+
+    fn by_mac(&self, mac: [u8; 6]) -> Option<&Binding> {
+        self.bindings.iter().find(|binding| binding.mac == Some(mac))
+    }
+
+    /// Whether an address is bound to anyone other than `mac` at the Unix time `now`
+    fn taken(&self, addr: Ipv4Addr, mac: [u8; 6], now: u64) -> bool {
+        self.bindings.iter().any(|binding| binding.addr == addr && binding.mac != Some(mac) && binding.expiry > now)
+    }
+
+    /// Bind `addr` to `mac` until `expiry`, replacing any earlier binding of either
+    fn bind(&mut self, mac: Option<[u8; 6]>, addr: Ipv4Addr, expiry: u64) {
+        self.bindings.retain(|binding| binding.addr != addr && (mac.is_none() || binding.mac != mac));
+        self.bindings.push(Binding {
+            mac: mac,
+            addr: addr,
+            expiry: expiry,
+        });
+    }
+
+    fn release(&mut self, mac: [u8; 6], addr: Ipv4Addr) {
+        self.bindings.retain(|binding| binding.mac != Some(mac) || binding.addr != addr);
+    }
+}
+
+/// Choose an address for `mac`, preferring its reservation, its previous address and then the
+/// address it asked for. `probe` reports addresses already in use on the network.
+pub fn allocate<F: Fn(Ipv4Addr) -> bool>(pool: &Pool, leases: &mut Leases, mac: [u8; 6], requested: Option<Ipv4Addr>,
+                                         now: u64, probe: F) -> Option<Ipv4Addr> {
+    if let Some(addr) = pool.reservation(mac) {
+        return Some(addr);
+    }
+
+    let available = |leases: &Leases, addr: Ipv4Addr| {
+        pool.contains(addr) && !pool.is_reserved(addr) && !leases.taken(addr, mac, now)
+    };
+
+    let previous = leases.by_mac(mac).map(|binding| binding.addr);
+    for addr in previous.into_iter().chain(requested) {
+        if available(leases, addr) {
+            return Some(addr);
+        }
+    }
+
+    for addr in u32::from(pool.start)..u32::from(pool.end) + 1 {
+        let addr = Ipv4Addr::from(addr);
+        if available(leases, addr) {
+            if probe(addr) {
+                // Something on the network is using the address without a lease
+                leases.bind(None, addr, now + CONFLICT_TIME);
+            } else {
+                return Some(addr);
+            }
+        }
+    }
+
+    None
+}
+
+/// Send an ICMP echo request to `addr` and report whether anything answered
+fn ping(addr: Ipv4Addr) -> bool {
+    let path = format!("icmp:echo/{}", addr);
+    let fd = match syscall::open(&path, syscall::O_RDWR | syscall::O_NONBLOCK) {
+        Ok(fd) => fd,
+        Err(_) => return false
+    };
+    let mut file = unsafe { File::from_raw_fd(fd as RawFd) };
+    if file.write(&[0; 16]).is_err() {
+        return false;
+    }
+
+    let deadline = Instant::now() + Duration::from_millis(PING_TIMEOUT_MS);
+    let mut reply = [0; 64];
+    while Instant::now() < deadline {
+        match file.read(&mut reply) {
+            Ok(count) if count > 0 => return true,
+            _ => thread::sleep(Duration::from_millis(10))
+        }
+    }
+    false
+}
+
+pub struct Server {
+    pool: Pool,
+    leases: Leases,
+    lease_path: String,
+    logger: Arc<Logger>,
+}
+
+impl Server {
+    pub fn new(pool: Pool, lease_path: &str, logger: Arc<Logger>) -> Result<Server, String> {
+        Ok(Server {
+            pool: pool,
+            leases: Leases::load(lease_path)?,
+            lease_path: lease_path.to_string(),
+            logger: logger,
+        })
+    }
+
+    fn log(&self, message: &str) {
+        self.logger.info(&format!("DHCP: {}", message));
+    }
+
+    fn save(&self) {
+        if let Err(err) = self.leases.save(&self.lease_path) {
+            self.logger.warn(&err);
+        }
+    }
+
+    /// A reply to `request` with the pool's settings
+    fn reply(&self, request: &Dhcp, message_type: u8, addr: Option<Ipv4Addr>) -> Dhcp {
+        let mut reply = Dhcp::reply(request);
+        if let Some(addr) = addr {
+            reply.yiaddr = addr.octets();
+        }
+        reply.siaddr = self.pool.server_id.octets();
+
+        let lease_time = [(self.pool.lease_time >> 24) as u8, (self.pool.lease_time >> 16) as u8,
+                          (self.pool.lease_time >> 8) as u8, self.pool.lease_time as u8];
+        let message_type = [message_type];
+        let server_id = self.pool.server_id.octets();
+        let netmask = self.pool.netmask.octets();
+        let gateway = self.pool.gateway.map(|gateway| gateway.octets());
+        let dns = self.pool.dns.iter().flat_map(|dns| dns.octets().to_vec()).collect::<Vec<u8>>();
+
+        let mut options: Vec<(u8, &[u8])> = vec![
+            (dhcp::OPT_MESSAGE_TYPE, &message_type),
+            (dhcp::OPT_SERVER_ID, &server_id),
+        ];
+        if message_type[0] != dhcp::NAK {
+            // INFORM replies carry settings only, the client already has an address
+            if addr.is_some() {
+                options.push((dhcp::OPT_LEASE_TIME, &lease_time));
+            }
+            options.push((dhcp::OPT_SUBNET_MASK, &netmask));
+            if let Some(ref gateway) = gateway {
+                options.push((dhcp::OPT_ROUTER, gateway));
+            }
+            if !dns.is_empty() {
+                options.push((dhcp::OPT_DNS, &dns));
+            }
+            if let Some(ref domain) = self.pool.domain {
+                options.push((dhcp::OPT_DOMAIN_NAME, domain.as_bytes()));
+            }
+        }
+        reply.set_options(&options);
+        reply
+    }
+
+    /// Handle a client message, returning the reply to send
+    pub fn handle(&mut self, request: &Dhcp) -> Option<Dhcp> {
+        if request.op != 1 {
+            return None;
+        }
+
+        let now = unix_time();
+        let mac = request.mac();
+        let mac_name = MacAddr { bytes: mac }.to_string();
+        let requested = request.option(dhcp::OPT_REQUESTED_IP).and_then(dhcp::option_addr).map(Ipv4Addr::from);
+        let server_id = request.option(dhcp::OPT_SERVER_ID).and_then(dhcp::option_addr).map(Ipv4Addr::from);
+
+        match request.message_type() {
+            Some(dhcp::DISCOVER) => {
+                let addr = allocate(&self.pool, &mut self.leases, mac, requested, now, ping);
+                match addr {
+                    Some(addr) => {
+                        self.log(&format!("Offer {} to {}", addr, mac_name));
+                        // Hold the address until the client requests it
+                        if self.leases.by_mac(mac).map_or(true, |binding| binding.addr != addr || binding.expiry < now) {
+                            self.leases.bind(Some(mac), addr, now + OFFER_TIME);
+                        }
+                        Some(self.reply(request, dhcp::OFFER, Some(addr)))
+                    },
+                    None => {
+                        self.log(&format!("No address available for {}", mac_name));
+                        None
+                    }
+                }
+            },
+            Some(dhcp::REQUEST) => {
+                if let Some(server_id) = server_id {
+                    if server_id != self.pool.server_id {
+                        // The client chose another server's offer
+                        if let Some(binding) = self.leases.by_mac(mac).cloned() {
+                            if binding.expiry <= now + OFFER_TIME {
+                                self.leases.release(mac, binding.addr);
+                            }
+                        }
+                        return None;
+                    }
+                }
+
+                let addr = requested.or_else(|| {
+                    let ciaddr = Ipv4Addr::from(request.ciaddr);
+                    if ciaddr.is_unspecified() { None } else { Some(ciaddr) }
+                });
+
+                let valid = addr.map_or(false, |addr| match self.pool.reservation(mac) {
+                    Some(reserved) => reserved == addr,
+                    None => self.pool.contains(addr) && !self.pool.is_reserved(addr) && !self.leases.taken(addr, mac, now)
+                });
+
+                match addr {
+                    Some(addr) if valid => {
+                        self.log(&format!("Ack {} to {}", addr, mac_name));
+                        self.leases.bind(Some(mac), addr, now + self.pool.lease_time as u64);
+                        self.save();
+                        Some(self.reply(request, dhcp::ACK, Some(addr)))
+                    },
+                    _ => {
+                        self.log(&format!("Nak to {}", mac_name));
+                        Some(self.reply(request, dhcp::NAK, None))
+                    }
+                }
+            },
+            Some(dhcp::DECLINE) => {
+                if let Some(addr) = requested {
+                    self.log(&format!("{} declined {}", mac_name, addr));
+                    self.leases.release(mac, addr);
+                    self.leases.bind(None, addr, now + CONFLICT_TIME);
+                    self.save();
+                }
+                None
+            },
+            Some(dhcp::RELEASE) => {
+                let addr = Ipv4Addr::from(request.ciaddr);
+                self.log(&format!("{} released {}", mac_name, addr));
+                self.leases.release(mac, addr);
+                self.save();
+                None
+            },
+            Some(dhcp::INFORM) => Some(self.reply(request, dhcp::ACK, None)),
+            _ => None
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), String> {
+        let socket = try_fmt!(UdpSocket::bind(("0.0.0.0", 67)), "failed to bind udp");
+        try_fmt!(socket.set_broadcast(true), "failed to enable broadcast");
+
+        self.log(&format!("Serving {} - {}", self.pool.start, self.pool.end));
+
+        loop {
+            let mut data = [0; 65536];
+            let count = try_fmt!(socket.recv(&mut data), "failed to receive");
+
+            let request = match Dhcp::from_bytes(&data[..count]) {
+                Some(request) => request,
+                None => continue
+            };
+
+            if let Some(reply) = self.handle(&request) {
+                // Relays get replies at their address, configured clients at theirs, others by broadcast
+                let dest = if request.giaddr != [0; 4] {
+                    SocketAddrV4::new(Ipv4Addr::from(request.giaddr), 67)
+                } else if request.ciaddr != [0; 4] && reply.message_type() != Some(dhcp::NAK) {
+                    SocketAddrV4::new(Ipv4Addr::from(request.ciaddr), 68)
+                } else {
+                    SocketAddrV4::new(Ipv4Addr::new(255, 255, 255, 255), 68)
+                };
+                try_fmt!(socket.send_to(&reply.to_bytes(), SocketAddr::V4(dest)), "failed to send");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+    use super::{allocate, Leases, Pool};
+
+    #[test]
+    fn allocate_test() {
+        let pool = Pool::parse("
+            range 10.0.0.10 10.0.0.13
+            gateway 10.0.0.1
+            dns 10.0.0.1 8.8.8.8
+            reserve 02:00:00:00:00:01 10.0.0.11
+        ").unwrap();
+        assert_eq!(pool.server_id, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(pool.dns.len(), 2);
+
+        let mut leases = Leases::parse("02:00:00:00:00:02 10.0.0.12 2000\n").unwrap();
+        let free = |_| false;
+
+        // Reservations and previous addresses come first
+        assert_eq!(allocate(&pool, &mut leases, [2, 0, 0, 0, 0, 1], None, 1000, free), Some(Ipv4Addr::new(10, 0, 0, 11)));
+        assert_eq!(allocate(&pool, &mut leases, [2, 0, 0, 0, 0, 2], None, 1000, free), Some(Ipv4Addr::new(10, 0, 0, 12)));
+
+        // Others get a free address, skipping reserved, leased and conflicting ones
+        let in_use = |addr| addr == Ipv4Addr::new(10, 0, 0, 10);
+        assert_eq!(allocate(&pool, &mut leases, [2, 0, 0, 0, 0, 3], None, 1000, in_use), Some(Ipv4Addr::new(10, 0, 0, 13)));
+        assert_eq!(leases.bindings.len(), 2);
+        assert_eq!(allocate(&pool, &mut leases, [2, 0, 0, 0, 0, 3], Some(Ipv4Addr::new(10, 0, 0, 10)), 1000, free),
+                   Some(Ipv4Addr::new(10, 0, 0, 13)));
+
+        // Expired leases are free again
+        assert_eq!(allocate(&pool, &mut leases, [2, 0, 0, 0, 0, 3], Some(Ipv4Addr::new(10, 0, 0, 12)), 3000, free),
+                   Some(Ipv4Addr::new(10, 0, 0, 12)));
+
+        assert_eq!(Leases::parse(&leases.to_string()).unwrap().bindings, leases.bindings);
+        assert!(Pool::parse("gateway 10.0.0.1").is_err());
+    }
+}
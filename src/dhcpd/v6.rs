@@ -0,0 +1,308 @@
+use std::io::ErrorKind;
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6, UdpSocket};
+use std::cmp;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use dhcp::v6::{self, IaNa, Message};
+use lease;
+use netutils_core::log::{Level, Logger};
+use {get_iface_cfg_value, set_iface_cfg_value, wait, write_resolv_conf, unix_time, MIN_RENEW_WAIT, RESOLV_CONF, RETRIES};
+
+/// All_DHCP_Relay_Agents_and_Servers (RFC 8415 section 7.1)
+fn servers() -> SocketAddr {
+    SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 1, 2), 547, 0, 0))
+}
+
+fn new_tid() -> [u8; 3] {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.subsec_nanos()).unwrap_or(0);
+    [(nanos >> 16) as u8, (nanos >> 8) as u8, nanos as u8]
+}
+
+pub struct Client6 {
+    iface: String,
+    socket: UdpSocket,
+    duid: Vec<u8>,
+    iaid: u32,
+    logger: Arc<Logger>,
+}
+
+/// Addresses and configuration acknowledged by a server
+struct Binding {
+    server: Vec<u8>,
+    ia_na: IaNa,
+    acquired: u64,
+}
+
+impl Binding {
+    /// Unix time to renew with the server, RFC 8415 suggests half the preferred lifetime if the
+    /// server leaves it up to the client
+    fn t1(&self) -> u64 {
+        let preferred = self.ia_na.addrs.iter().map(|addr| addr.1).min().unwrap_or(0);
+        self.acquired + if self.ia_na.t1 > 0 { self.ia_na.t1 } else { preferred / 2 } as u64
+    }
+
+    fn t2(&self) -> u64 {
+        let preferred = self.ia_na.addrs.iter().map(|addr| addr.1).min().unwrap_or(0);
+        self.acquired + if self.ia_na.t2 > 0 { self.ia_na.t2 } else { (preferred as u64 * 4 / 5) as u32 } as u64
+    }
+
+    fn expiry(&self) -> u64 {
+        self.acquired + self.ia_na.addrs.iter().map(|addr| addr.2).max().unwrap_or(0) as u64
+    }
+}
+
+impl Client6 {
+    pub fn new(iface: &str, mac: [u8; 6], logger: Arc<Logger>) -> Result<Client6, String> {
+        let socket = try_fmt!(UdpSocket::bind("[::]:546"), "failed to bind udp");
+        try_fmt!(
+            socket.set_write_timeout(Some(Duration::new(5, 0))),
+            "failed to set write timeout"
+        );
+
+        Ok(Client6 {
+            iface: iface.to_string(),
+            socket: socket,
+            duid: v6::duid_ll(mac),
+            iaid: (mac[2] as u32) << 24 | (mac[3] as u32) << 16 | (mac[4] as u32) << 8 | mac[5] as u32,
+            logger: logger,
+        })
+    }
+
+    fn log(&self, message: &str) {
+        self.logger.info(&format!("DHCPv6: {}", message));
+    }
+
+    fn warn(&self, message: &str) {
+        self.logger.warn(&format!("DHCPv6: {}", message));
+    }
+
+    fn message(&self, msg_type: u8) -> Message {
+        let mut message = Message::new(msg_type, new_tid());
+        message.add(v6::OPT_CLIENTID, self.duid.clone());
+        message.add(v6::OPT_ORO, v6::oro());
+        message
+    }
+
+    /// Send `message` to the servers, retransmitting until a reply of `reply_type` for this client arrives
+    fn exchange(&self, message: &Message, reply_type: u8) -> Result<Option<Message>, String> {
+        let start = Instant::now();
+
+        for attempt in 0..RETRIES {
+            // The elapsed time tells servers how long the client has been trying
+            let elapsed = start.elapsed();
+            let mut attempt_message = Message::new(message.msg_type, message.tid);
+            attempt_message.options = message.options.clone();
+            attempt_message.add(v6::OPT_ELAPSED_TIME,
+                                v6::elapsed_time(elapsed.as_secs() * 100 + elapsed.subsec_nanos() as u64 / 10_000_000));
+            try_fmt!(self.socket.send_to(&attempt_message.to_bytes(), servers()), "failed to send");
+
+            let deadline = Instant::now() + Duration::from_secs(1 << attempt);
+            loop {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                try_fmt!(self.socket.set_read_timeout(Some(deadline - now)), "failed to set read timeout");
+
+                let mut reply_data = [0; 65536];
+                let count = match self.socket.recv(&mut reply_data) {
+                    Ok(count) => count,
+                    Err(ref err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => break,
+                    Err(err) => return Err(format!("failed to receive: {}", err))
+                };
+
+                if let Some(reply) = Message::from_bytes(&reply_data[..count]) {
+                    if reply.msg_type == reply_type && reply.tid == message.tid
+                        && reply.option(v6::OPT_CLIENTID) == Some(&self.duid[..]) {
+                        return Ok(Some(reply));
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The binding in a reply, if the server assigned an address
+    fn binding(&self, reply: &Message) -> Option<Binding> {
+        if v6::status(&reply.options) != v6::STATUS_SUCCESS {
+            return None;
+        }
+
+        let server = reply.option(v6::OPT_SERVERID)?.to_vec();
+        let ia_na = IaNa::parse(reply.option(v6::OPT_IA_NA)?)?;
+        if ia_na.iaid != self.iaid || ia_na.status != v6::STATUS_SUCCESS || ia_na.addrs.is_empty() {
+            return None;
+        }
+
+        Some(Binding {
+            server: server,
+            ia_na: ia_na,
+            acquired: unix_time(),
+        })
+    }
+
+    /// Acquire an address with SOLICIT, ADVERTISE, REQUEST and REPLY
+    fn solicit(&self) -> Result<Option<(Binding, Message)>, String> {
+        let mut solicit = self.message(v6::SOLICIT);
+        solicit.add(v6::OPT_IA_NA, IaNa::new(self.iaid).to_bytes());
+
+        self.log("Sent Solicit");
+        let advertise = match self.exchange(&solicit, v6::ADVERTISE)? {
+            Some(advertise) => advertise,
+            None => return Err("no advertise received".to_string())
+        };
+        let offered = match self.binding(&advertise) {
+            Some(offered) => offered,
+            None => return Ok(None)
+        };
+        self.log(&format!("Advertise IP: {}, Preference: {}", offered.ia_na.addrs[0].0, advertise.preference()));
+
+        let mut request = self.message(v6::REQUEST);
+        request.add(v6::OPT_SERVERID, offered.server.clone());
+        request.add(v6::OPT_IA_NA, offered.ia_na.to_bytes());
+
+        self.log("Sent Request");
+        Ok(self.exchange(&request, v6::REPLY)?.and_then(|reply| self.binding(&reply).map(|binding| (binding, reply))))
+    }
+
+    /// Extend a binding, with its server when renewing or any server when rebinding
+    fn renew(&self, binding: &Binding, rebind: bool) -> Result<Option<(Binding, Message)>, String> {
+        let mut renew = self.message(if rebind { v6::REBIND } else { v6::RENEW });
+        if !rebind {
+            renew.add(v6::OPT_SERVERID, binding.server.clone());
+        }
+        renew.add(v6::OPT_IA_NA, binding.ia_na.to_bytes());
+
+        self.log(if rebind { "Rebinding" } else { "Renewing" });
+        Ok(self.exchange(&renew, v6::REPLY)?.and_then(|reply| self.binding(&reply).map(|binding| (binding, reply))))
+    }
+
+    /// Write the DNS servers and search list of a reply, if it has any
+    fn apply_dns(&self, reply: &Message) {
+        let nameservers = reply.option(v6::OPT_DNS_SERVERS).map(v6::parse_dns_servers).unwrap_or(Vec::new());
+        let domains: Vec<String> = reply.option(v6::OPT_DOMAIN_LIST).map(v6::parse_domain_list).unwrap_or(Vec::new())
+            .into_iter()
+            .filter(|domain| lease::valid_domain(domain))
+            .collect();
+        if nameservers.is_empty() && domains.is_empty() {
+            return;
+        }
+
+        let mut resolv = String::new();
+        if !domains.is_empty() {
+            self.log(&format!("Domains: {}", domains.join(" ")));
+            resolv.push_str(&format!("search {}\n", domains.join(" ")));
+        }
+        for dns in nameservers.iter() {
+            self.log(&format!("DNS: {}", dns));
+            resolv.push_str(&format!("nameserver {}\n", dns));
+        }
+        if let Err(err) = write_resolv_conf(&resolv) {
+            self.logger.warn(&format!("failed to write {}: {}", RESOLV_CONF, err));
+        }
+    }
+
+    fn apply(&self, binding: &Binding, reply: &Message) -> Result<(), String> {
+        // DHCPv6 does not assign prefixes, the on-link prefix comes from router advertisements
+        let addrs = binding.ia_na.addrs.iter().map(|addr| format!("{}/128\n", addr.0)).collect::<String>();
+        try_fmt!(
+            set_iface_cfg_value(&self.iface, "addr6/set", &addrs),
+            "failed to set ipv6 address"
+        );
+
+        if self.logger.enabled(Level::Info) {
+            let new_ip = try_fmt!(get_iface_cfg_value(&self.iface, "addr6/list"), "failed to get ipv6 address");
+            self.log(&format!("New IP: {}", new_ip.trim()));
+        }
+
+        self.apply_dns(reply);
+        Ok(())
+    }
+
+    /// Give the addresses of a binding back to its server and remove them. The reply of the
+    /// server isn't waited for, since the client is exiting, and the addresses are removed even
+    /// if it couldn't be told.
+    fn release(&self, binding: &Binding) -> Result<(), String> {
+        let mut release = Message::new(v6::RELEASE, new_tid());
+        release.add(v6::OPT_CLIENTID, self.duid.clone());
+        release.add(v6::OPT_SERVERID, binding.server.clone());
+        release.add(v6::OPT_IA_NA, binding.ia_na.to_bytes());
+        release.add(v6::OPT_ELAPSED_TIME, v6::elapsed_time(0));
+
+        self.log(&format!("Releasing {}", binding.ia_na.addrs[0].0));
+        let sent = self.socket.send_to(&release.to_bytes(), servers()).map_err(|err| format!("failed to send: {}", err));
+        try_fmt!(set_iface_cfg_value(&self.iface, "addr6/set", ""), "failed to remove ipv6 address");
+        sent.map(|_| ())
+    }
+
+    /// Ask for DNS configuration only, for networks that assign addresses with SLAAC
+    pub fn inform(&self) -> Result<(), String> {
+        self.log("Sent Information-request");
+        match self.exchange(&self.message(v6::INFORMATION_REQUEST), v6::REPLY)? {
+            Some(reply) => {
+                self.apply_dns(&reply);
+                Ok(())
+            },
+            None => Err("no reply received".to_string())
+        }
+    }
+
+    /// Acquire an address and keep it by renewing at T1 and rebinding at T2, until the client
+    /// is asked to exit, when the address is released. Failed exchanges are tried again later.
+    pub fn run(&self, once: bool) -> Result<(), String> {
+        loop {
+            let (mut binding, reply) = match self.solicit() {
+                Ok(Some(acquired)) => acquired,
+                Ok(None) if once => return Err("no address assigned".to_string()),
+                Err(err) if once => return Err(err),
+                result => {
+                    if let Err(err) = result {
+                        self.warn(&err);
+                    }
+                    self.log(&format!("No address, retrying in {} seconds", MIN_RENEW_WAIT));
+                    if !wait(MIN_RENEW_WAIT) {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+            self.apply(&binding, &reply)?;
+
+            if once {
+                return Ok(());
+            }
+
+            loop {
+                let now = unix_time();
+                if now < binding.t1() {
+                    if !wait(binding.t1() - now) {
+                        return self.release(&binding);
+                    }
+                    continue;
+                } else if now >= binding.expiry() {
+                    self.log("Binding expired");
+                    break;
+                }
+
+                match self.renew(&binding, now >= binding.t2()) {
+                    Ok(Some((renewed, reply))) => {
+                        self.apply(&renewed, &reply)?;
+                        binding = renewed;
+                    },
+                    result => {
+                        if let Err(err) = result {
+                            self.warn(&err);
+                        }
+                        let now = unix_time();
+                        let deadline = if now < binding.t2() { binding.t2() } else { binding.expiry() };
+                        if now < deadline && !wait(cmp::max((deadline - now) / 2, 1)) {
+                            return self.release(&binding);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
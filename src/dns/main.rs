@@ -1,16 +1,73 @@
-#![feature(lookup_host)]
+extern crate netutils_core;
 
-use std::{env, process};
-use std::io::{stderr, Write};
-use std::net::lookup_host;
+use netutils_core::args::Parser;
+use netutils_core::error::{NetError, Result};
+use netutils_core::output::{Format, Object};
+use netutils_core::resolve::{self, Family};
+use std::net::IpAddr;
+
+mod query;
+
+/// The addresses of `name` that the DNS server `server`, as `host[:port]`, answers with, IPv4
+/// ones first
+fn ask(server: &str, name: &str, family: Family) -> Result<Vec<IpAddr>> {
+    if name.parse::<IpAddr>().is_ok() {
+        return resolve::lookup(name, family);
+    }
+    let (host, port) = resolve::split_port(server)?;
+    let server = resolve::resolve(host, port.unwrap_or(53), Family::Any)?;
+
+    let types: &[u16] = match family {
+        Family::Any => &[query::TYPE_A, query::TYPE_AAAA],
+        Family::V4 => &[query::TYPE_A],
+        Family::V6 => &[query::TYPE_AAAA],
+    };
+    let mut addrs = Vec::new();
+    for &qtype in types {
+        for addr in query::lookup(server, name, qtype)? {
+            if family.matches(&addr) && !addrs.contains(&addr) {
+                addrs.push(addr);
+            }
+        }
+    }
+    if addrs.is_empty() {
+        let family = match family {
+            Family::Any => "",
+            Family::V4 => "IPv4 ",
+            Family::V6 => "IPv6 ",
+        };
+        return Err(NetError::Dns(format!("failed to resolve {}: no {}address found", name, family)));
+    }
+    Ok(addrs)
+}
 
 fn main(){
-    if let Some(name) = env::args().nth(1) {
-        for addr in lookup_host(&name).unwrap() {
-            println!("{}", addr.ip());
+    let args = Parser::new("dns")
+        .synopsis("[-4 | -6] [--server host[:port]] [--output text|json] name")
+        .family()
+        .opt("", "server", "host[:port]", "Ask the DNS server on host, rather than the system resolver")
+        .output()
+        .parse_env();
+    let output = args.output();
+
+    if let Some(name) = args.free.get(0) {
+        let addrs = match args.value("server") {
+            Some(server) => ask(server, name, args.family()),
+            None => resolve::lookup(name, args.family())
+        }.unwrap_or_else(|err| err.exit("dns"));
+        for addr in addrs.iter() {
+            match output {
+                Format::Text | Format::Tsv => println!("{}", addr),
+                Format::Json => println!("{}", Object::new()
+                                         .string("name", name)
+                                         .string("address", &addr.to_string())
+                                         .string("family", if addr.is_ipv4() { "ipv4" } else { "ipv6" }))
+            }
+        }
+        if output == Format::Json {
+            println!("{}", Object::new().string("name", name).number("addresses", addrs.len()));
         }
     } else {
-        write!(stderr(), "dns: no hostname provided\n").unwrap();
-        process::exit(1);
+        args.invalid("no hostname provided");
     }
 }
@@ -0,0 +1,241 @@
+//! Looking up addresses with a DNS server of the user's choosing (RFC 1035), rather than the
+//! system resolver. Queries go over UDP, and again over TCP if the reply was truncated.
+
+use netutils_core::error::{NetError, Result};
+use netutils_core::resolve::{self, Family};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_TRUNCATED: u16 = 0x0200;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+
+const RCODE_NXDOMAIN: u16 = 3;
+
+/// The longest name, in the encoding of a query (RFC 1035 section 2.3.4)
+const MAX_NAME_LEN: usize = 255;
+
+/// How long each query over UDP waits for a reply, and how many are sent before giving up
+const TIMEOUT_SECS: u64 = 2;
+const TRIES: usize = 3;
+
+/// What a server answered to a query
+#[derive(Debug, PartialEq)]
+enum Reply {
+    /// The addresses of the name, of which there may be none of the type asked for
+    Addrs(Vec<IpAddr>),
+    NoSuchName,
+    /// The reply didn't fit in a datagram, so the query has to be sent over TCP instead
+    Truncated,
+}
+
+fn new_id() -> u16 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.subsec_nanos() as u16).unwrap_or(0)
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some((*data.get(at)? as u16) << 8 | *data.get(at + 1)? as u16)
+}
+
+/// A query for the records of `qtype` of `name`, asking the server to recurse
+fn query(id: u16, name: &str, qtype: u16) -> Result<Vec<u8>> {
+    let mut packet = vec![(id >> 8) as u8, id as u8,
+                          (FLAG_RECURSION_DESIRED >> 8) as u8, FLAG_RECURSION_DESIRED as u8,
+                          0, 1, 0, 0, 0, 0, 0, 0];
+    for label in name.trim_right_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(NetError::Usage(format!("invalid name '{}'", name)));
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    if packet.len() - 12 > MAX_NAME_LEN {
+        return Err(NetError::Usage(format!("name '{}' is too long", name)));
+    }
+    packet.extend_from_slice(&[(qtype >> 8) as u8, qtype as u8, (CLASS_IN >> 8) as u8, CLASS_IN as u8]);
+    Ok(packet)
+}
+
+/// The offset after the name at `at`, which may end with a pointer to another name
+fn skip_name(data: &[u8], mut at: usize) -> Option<usize> {
+    loop {
+        let len = *data.get(at)? as usize;
+        if len & 0xc0 == 0xc0 {
+            return if at + 2 <= data.len() { Some(at + 2) } else { None };
+        }
+        at += 1 + len;
+        if len == 0 {
+            return Some(at);
+        }
+    }
+}
+
+/// The A and AAAA records in the answers of a reply, skipping others such as CNAME
+fn parse_answers(data: &[u8]) -> Option<Vec<IpAddr>> {
+    let questions = read_u16(data, 4)?;
+    let answers = read_u16(data, 6)?;
+
+    let mut at = 12;
+    for _ in 0..questions {
+        at = skip_name(data, at)? + 4;
+    }
+
+    let mut addrs = Vec::new();
+    for _ in 0..answers {
+        at = skip_name(data, at)?;
+        let rtype = read_u16(data, at)?;
+        let class = read_u16(data, at + 2)?;
+        let len = read_u16(data, at + 8)? as usize;
+        at += 10;
+        let rdata = data.get(at..at + len)?;
+        at += len;
+
+        if class != CLASS_IN {
+            continue;
+        }
+        match (rtype, len) {
+            (TYPE_A, 4) => addrs.push(IpAddr::V4(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]))),
+            (TYPE_AAAA, 16) => {
+                let mut segments = [0; 8];
+                for (i, segment) in segments.iter_mut().enumerate() {
+                    *segment = (rdata[i * 2] as u16) << 8 | rdata[i * 2 + 1] as u16;
+                }
+                addrs.push(IpAddr::V6(Ipv6Addr::new(segments[0], segments[1], segments[2], segments[3],
+                                                    segments[4], segments[5], segments[6], segments[7])));
+            },
+            (TYPE_A, _) | (TYPE_AAAA, _) => return None,
+            _ => ()
+        }
+    }
+    Some(addrs)
+}
+
+/// Parse a reply to the query `id`, returning None for a message that isn't one
+fn parse_reply(id: u16, data: &[u8]) -> Result<Option<Reply>> {
+    match (read_u16(data, 0), read_u16(data, 2)) {
+        (Some(reply_id), Some(flags)) if reply_id == id && flags & FLAG_RESPONSE != 0 => {
+            if flags & FLAG_TRUNCATED != 0 {
+                return Ok(Some(Reply::Truncated));
+            }
+            match flags & 0xf {
+                0 => (),
+                RCODE_NXDOMAIN => return Ok(Some(Reply::NoSuchName)),
+                rcode => return Err(NetError::Dns(match rcode {
+                    1 => "the server couldn't read the query".to_string(),
+                    2 => "the server failed".to_string(),
+                    4 => "the server doesn't support the query".to_string(),
+                    5 => "the server refused the query".to_string(),
+                    rcode => format!("the server answered with error {}", rcode),
+                }))
+            }
+            parse_answers(data).map(|addrs| Some(Reply::Addrs(addrs)))
+                .ok_or_else(|| NetError::Protocol("invalid reply".to_string()))
+        },
+        _ => Ok(None)
+    }
+}
+
+/// Send a query over UDP, trying again if it isn't answered
+fn ask_udp(server: SocketAddr, id: u16, request: &[u8]) -> Result<Reply> {
+    let bind = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind).map_err(|err| NetError::from(err).context("failed to bind udp"))?;
+    // Connected, so that a server that isn't listening is reported as refusing the query
+    socket.connect(server).map_err(|err| NetError::from(err).context(&format!("failed to connect to {}", server)))?;
+    socket.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SECS)))
+        .map_err(|err| NetError::from(err).context("failed to set read timeout"))?;
+
+    let mut data = [0; 65536];
+    for _ in 0..TRIES {
+        socket.send(request).map_err(|err| NetError::from(err).context(&format!("failed to send to {}", server)))?;
+        loop {
+            let count = match socket.recv(&mut data) {
+                Ok(count) => count,
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => break,
+                Err(err) => return Err(NetError::from(err).context(&format!("no reply from {}", server)))
+            };
+            if let Some(reply) = parse_reply(id, &data[..count]).map_err(|err| err.context(&server.to_string()))? {
+                return Ok(reply);
+            }
+        }
+    }
+    Err(NetError::Timeout(format!("no reply from {}", server)))
+}
+
+/// Send a query over TCP, where each message is preceded by its length (RFC 1035 section 4.2.2)
+fn ask_tcp(server: SocketAddr, id: u16, request: &[u8]) -> Result<Reply> {
+    let timeout = Duration::from_secs(TIMEOUT_SECS);
+    let mut stream = resolve::connect(&server.ip().to_string(), server.port(), Family::Any, Some(timeout))?;
+    stream.set_read_timeout(Some(timeout)).map_err(|err| NetError::from(err).context("failed to set read timeout"))?;
+
+    let mut message = vec![(request.len() >> 8) as u8, request.len() as u8];
+    message.extend_from_slice(request);
+    stream.write_all(&message).map_err(|err| NetError::from(err).context(&format!("failed to send to {}", server)))?;
+
+    let mut len = [0; 2];
+    stream.read_exact(&mut len).map_err(|err| NetError::from(err).context(&format!("no reply from {}", server)))?;
+    let mut reply = vec![0; (len[0] as usize) << 8 | len[1] as usize];
+    stream.read_exact(&mut reply).map_err(|err| NetError::from(err).context(&format!("no reply from {}", server)))?;
+    match parse_reply(id, &reply).map_err(|err| err.context(&server.to_string()))? {
+        Some(Reply::Truncated) | None => Err(NetError::Protocol(format!("{}: invalid reply", server))),
+        Some(reply) => Ok(reply)
+    }
+}
+
+/// Ask `server` for the records of `qtype` of `name`, returning the addresses in the answer
+pub fn lookup(server: SocketAddr, name: &str, qtype: u16) -> Result<Vec<IpAddr>> {
+    let id = new_id();
+    let request = query(id, name, qtype)?;
+    let reply = match ask_udp(server, id, &request)? {
+        Reply::Truncated => ask_tcp(server, id, &request)?,
+        reply => reply
+    };
+    match reply {
+        Reply::Addrs(addrs) => Ok(addrs),
+        _ => Err(NetError::Dns(format!("failed to resolve {}: no such name", name)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use super::{parse_reply, query, Reply, TYPE_A};
+
+    #[test]
+    fn query_test() {
+        assert_eq!(query(0x1234, "example.com.", TYPE_A).unwrap(),
+                   b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+                     \x07example\x03com\x00\x00\x01\x00\x01".to_vec());
+        assert!(query(1, "example..com", TYPE_A).is_err());
+        assert!(query(1, &"a".repeat(64), TYPE_A).is_err());
+        assert!(query(1, &["a"; 200].join("."), TYPE_A).is_err());
+    }
+
+    #[test]
+    fn reply_test() {
+        let question = b"\x07example\x03com\x00\x00\x01\x00\x01";
+        let mut reply = b"\x12\x34\x81\x80\x00\x01\x00\x02\x00\x00\x00\x00".to_vec();
+        reply.extend_from_slice(question);
+        // A CNAME to a name that is partly a pointer, and then the address of that name
+        reply.extend_from_slice(b"\xc0\x0c\x00\x05\x00\x01\x00\x00\x01\x2c\x00\x06\x03www\xc0\x0c");
+        reply.extend_from_slice(b"\xc0\x29\x00\x01\x00\x01\x00\x00\x01\x2c\x00\x04\x5d\xb8\xd8\x22");
+        assert_eq!(parse_reply(0x1234, &reply).unwrap(), Some(Reply::Addrs(vec!["93.184.216.34".parse::<IpAddr>().unwrap()])));
+        // Replies to other queries are ignored
+        assert_eq!(parse_reply(0x4321, &reply).unwrap(), None);
+        // A reply that was cut short is invalid
+        assert!(parse_reply(0x1234, &reply[..reply.len() - 1]).is_err());
+
+        let mut reply = b"\x12\x34\x81\x83\x00\x01\x00\x00\x00\x00\x00\x00".to_vec();
+        reply.extend_from_slice(question);
+        assert_eq!(parse_reply(0x1234, &reply).unwrap(), Some(Reply::NoSuchName));
+        reply[3] = 0x82;
+        assert!(parse_reply(0x1234, &reply).is_err());
+        reply[2] = 0x83;
+        assert_eq!(parse_reply(0x1234, &reply).unwrap(), Some(Reply::Truncated));
+    }
+}
@@ -0,0 +1,72 @@
+//! ASCII transfers end lines with CR LF on the wire (RFC 959 section 3.1.1.1)
+
+use std::io::{Read, Result, Write};
+
+/// Copy ASCII data received from the server, ending its lines with LF
+pub fn copy_from_network(reader: &mut Read, writer: &mut Write) -> Result<u64> {
+    let mut buf = [0; 8192];
+    let mut cr = false;
+    let mut total = 0;
+    loop {
+        let count = reader.read(&mut buf)?;
+        let mut data = Vec::with_capacity(count + 1);
+        if count == 0 {
+            if cr {
+                data.push(b'\r');
+            }
+            writer.write_all(&data)?;
+            return Ok(total + data.len() as u64);
+        }
+        for &b in buf[..count].iter() {
+            if cr && b != b'\n' {
+                data.push(b'\r');
+            }
+            cr = b == b'\r';
+            if !cr {
+                data.push(b);
+            }
+        }
+        writer.write_all(&data)?;
+        total += data.len() as u64;
+    }
+}
+
+/// Copy local text to the server, ending its lines with CR LF
+pub fn copy_to_network(reader: &mut Read, writer: &mut Write) -> Result<u64> {
+    let mut buf = [0; 8192];
+    let mut last = 0;
+    let mut total = 0;
+    loop {
+        let count = reader.read(&mut buf)?;
+        if count == 0 {
+            return Ok(total);
+        }
+        let mut data = Vec::with_capacity(count * 2);
+        for &b in buf[..count].iter() {
+            // Lines that already end with CR LF are left alone
+            if b == b'\n' && last != b'\r' {
+                data.push(b'\r');
+            }
+            data.push(b);
+            last = b;
+        }
+        writer.write_all(&data)?;
+        total += data.len() as u64;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{copy_from_network, copy_to_network};
+
+    #[test]
+    fn ascii_test() {
+        let mut local = Vec::new();
+        assert_eq!(copy_from_network(&mut &b"one\r\ntwo\rthree\r\n\r"[..], &mut local).unwrap(), 15);
+        assert_eq!(local, b"one\ntwo\rthree\n\r".to_vec());
+
+        let mut network = Vec::new();
+        assert_eq!(copy_to_network(&mut &b"one\ntwo\r\n"[..], &mut network).unwrap(), 10);
+        assert_eq!(network, b"one\r\ntwo\r\n".to_vec());
+    }
+}
@@ -0,0 +1,403 @@
+extern crate netutils_core;
+extern crate termion;
+
+use std::fs::File;
+use std::io::{self, Error, ErrorKind, Result, Write};
+use std::path::Path;
+use std::time::Instant;
+use std::env;
+
+use netutils_core::args::Parser;
+use netutils_core::error::NetError;
+use netutils_core::ftp::{FtpStream, TransferType};
+use netutils_core::resolve::{self, Family};
+use termion::input::TermRead;
+
+mod ascii;
+
+static FTP_MAN: &'static str = /* @MANSTART{ftp} */ r#"
+NAME
+    ftp - file transfer protocol client
+
+SYNOPSIS
+    ftp [-4 | -6] [-u user[:password]] [-c commands] [host [port]]
+    ftp [-h | --help]
+
+DESCRIPTION
+    Connect to an FTP server, log in and run commands typed at the prompt.
+    All transfers use passive mode, so they work from behind a firewall or
+    NAT. Without -u, the user name is asked for, and an empty name logs in
+    anonymously.
+
+    With -c, the commands, which are separated by semicolons, are run
+    instead, stopping at the first that fails. A user that isn't given with
+    -u logs in anonymously.
+
+COMMANDS
+    open host [port]    Connect to a server, and log in
+    user name [pass]    Log in as another user
+    ls [path]           List the names of files
+    dir [path]          List files with their details
+    cd path             Change the remote directory
+    pwd                 Print the remote directory
+    lcd [path]          Change the local directory, by default to the home
+    get remote [local]  Download a file
+    put local [remote]  Upload a file
+    mget pattern...     Download the files matching each pattern, in which
+                        * matches any characters and ? any one character
+    binary              Transfer files as they are, the default
+    ascii               Convert line endings of transferred files
+    close               Disconnect from the server
+    quit, bye           Disconnect and exit
+    help, ?             Show the commands
+
+OPTIONS
+    -4
+    -6
+        Connect over IPv4 only or IPv6 only.
+
+    -u user[:password]
+        Log in as this user, asking for the password if it isn't given.
+
+    -c commands
+        Run these commands, separated by semicolons, and exit.
+
+    -h
+    --help
+        Print this manual page.
+"#; /* @MANEND */
+
+static HELP: &'static str = "commands: open, user, ls, dir, cd, pwd, lcd, get, put, mget, binary, ascii, close, quit";
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+/// Match a file name against a pattern of * and ?
+fn glob(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some(&b'*') => (0..name.len() + 1).any(|skip| glob(&pattern[1..], &name[skip..])),
+        Some(&b'?') => !name.is_empty() && glob(&pattern[1..], &name[1..]),
+        Some(&c) => name.first() == Some(&c) && glob(&pattern[1..], &name[1..]),
+    }
+}
+
+fn read_line(prompt: &str) -> Result<Option<String>> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+fn read_password() -> Result<String> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(b"Password: ")?;
+    stdout.flush()?;
+    let password = io::stdin().read_passwd(&mut stdout)?;
+    writeln!(stdout)?;
+    Ok(password.unwrap_or_default())
+}
+
+fn report(name: &str, bytes: u64, start: Instant) {
+    let elapsed = start.elapsed();
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+    if secs > 0.0 {
+        println!("{}: {} bytes in {:.2} s ({:.2} KB/s)", name, bytes, secs, bytes as f64 / secs / 1024.0);
+    } else {
+        println!("{}: {} bytes", name, bytes);
+    }
+}
+
+struct Session {
+    ftp: Option<FtpStream>,
+    transfer_type: TransferType,
+    /// Whether to ask for what isn't given, rather than running a script
+    interactive: bool,
+    family: Family,
+}
+
+impl Session {
+    fn ftp(&mut self) -> Result<&mut FtpStream> {
+        self.ftp.as_mut().ok_or(Error::new(ErrorKind::NotConnected, "not connected"))
+    }
+
+    fn open(&mut self, host: &str, port: u16, user: Option<&str>) -> Result<()> {
+        self.close();
+        let addr = resolve::resolve(host, port, self.family)?;
+        let ftp = FtpStream::connect(addr).map_err(|err| NetError::from(err).context(&format!("failed to connect to {}", host)))?;
+        self.ftp = Some(ftp);
+        println!("Connected to {}.", host);
+
+        let user = match user {
+            Some(user) => Some(user.to_string()),
+            None if self.interactive => read_line(&format!("Name ({}): ", host))?,
+            None => None
+        };
+        self.login(user.as_ref().map(|user| user.as_str()).unwrap_or(""))
+    }
+
+    /// Log in as `user`, which may include the password after a colon. The password of a user
+    /// other than anonymous is asked for if it isn't given.
+    fn login(&mut self, user: &str) -> Result<()> {
+        let (user, password) = match user.find(':') {
+            Some(colon) => (&user[..colon], Some(user[colon + 1..].to_string())),
+            None => (user, None)
+        };
+        let password = match password {
+            Some(password) => password,
+            None if !user.is_empty() && user != "anonymous" && user != "ftp" => read_password()?,
+            None => String::new()
+        };
+        let transfer_type = self.transfer_type;
+        let ftp = self.ftp()?;
+        ftp.login(user, &password)?;
+        ftp.transfer_type(transfer_type)?;
+        println!("Logged in as {}.", if user.is_empty() { "anonymous" } else { user });
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        if let Some(mut ftp) = self.ftp.take() {
+            let _ = ftp.quit();
+        }
+    }
+
+    fn get(&mut self, remote: &str, local: &str) -> Result<()> {
+        let transfer_type = self.transfer_type;
+        let ftp = self.ftp()?;
+        let start = Instant::now();
+        let bytes = {
+            let mut data = ftp.retr(remote)?;
+            // The file is only created once the server has agreed to send it
+            let mut file = match File::create(local) {
+                Ok(file) => file,
+                Err(err) => {
+                    drop(data);
+                    let _ = ftp.finish();
+                    return Err(err);
+                }
+            };
+            match transfer_type {
+                TransferType::Binary => io::copy(&mut data, &mut file)?,
+                TransferType::Ascii => ascii::copy_from_network(&mut data, &mut file)?
+            }
+        };
+        ftp.finish()?;
+        report(remote, bytes, start);
+        Ok(())
+    }
+
+    fn put(&mut self, local: &str, remote: &str) -> Result<()> {
+        let transfer_type = self.transfer_type;
+        let ftp = self.ftp()?;
+        let mut file = File::open(local)?;
+        let start = Instant::now();
+        let bytes = {
+            let mut data = ftp.stor(remote)?;
+            match transfer_type {
+                TransferType::Binary => io::copy(&mut file, &mut data)?,
+                TransferType::Ascii => ascii::copy_to_network(&mut file, &mut data)?
+            }
+        };
+        ftp.finish()?;
+        report(local, bytes, start);
+        Ok(())
+    }
+
+    /// Download the files matching `pattern`, which only has wildcards in its last component
+    fn mget(&mut self, pattern: &str) -> Result<()> {
+        let (directory, base) = match pattern.rfind('/') {
+            Some(slash) => (Some(&pattern[..slash + 1]), &pattern[slash + 1..]),
+            None => (None, pattern)
+        };
+        if !base.contains('*') && !base.contains('?') {
+            return self.get(pattern, base);
+        }
+
+        let listing = self.ftp()?.list_string(directory, true)?;
+        let mut names: Vec<&str> = listing.lines()
+            // Some servers list each name with the directory in front
+            .map(|line| line.trim().rsplit('/').next().unwrap_or(""))
+            .filter(|name| glob(base.as_bytes(), name.as_bytes()))
+            .collect();
+        names.sort();
+        if names.is_empty() {
+            return Err(Error::new(ErrorKind::NotFound, format!("no files match {}", pattern)));
+        }
+        for name in names {
+            self.get(&format!("{}{}", directory.unwrap_or(""), name), name)?;
+        }
+        Ok(())
+    }
+
+    /// Run a command, returning false if it was to quit
+    fn run(&mut self, words: &[&str]) -> Result<bool> {
+        let arg = |i: usize| words.get(i).map(|word| *word);
+        match words[0] {
+            "open" => {
+                let host = arg(1).ok_or(invalid("usage: open host [port]"))?;
+                let port = match arg(2) {
+                    Some(port) => port.parse().map_err(|_| invalid("invalid port"))?,
+                    None => 21
+                };
+                self.open(host, port, None)?;
+            },
+            "user" => {
+                let user = match (arg(1), arg(2)) {
+                    (Some(user), Some(password)) => format!("{}:{}", user, password),
+                    (Some(user), None) => user.to_string(),
+                    _ => return Err(invalid("usage: user name [password]"))
+                };
+                self.login(&user)?;
+            },
+            "ls" | "dir" => {
+                let listing = self.ftp()?.list_string(arg(1), words[0] == "ls")?;
+                print!("{}", listing.replace("\r\n", "\n"));
+            },
+            "cd" => self.ftp()?.cwd(arg(1).ok_or(invalid("usage: cd path"))?)?,
+            "pwd" => println!("{}", self.ftp()?.pwd()?),
+            "lcd" => {
+                let path = match arg(1) {
+                    Some(path) => path.to_string(),
+                    None => env::var("HOME").map_err(|_| invalid("HOME is not set"))?
+                };
+                env::set_current_dir(&path)?;
+                println!("Local directory now {}", env::current_dir()?.display());
+            },
+            "get" | "recv" => {
+                let remote = arg(1).ok_or(invalid("usage: get remote [local]"))?;
+                let local = arg(2).unwrap_or(remote.rsplit('/').next().unwrap_or(remote));
+                self.get(remote, local)?;
+            },
+            "put" | "send" => {
+                let local = arg(1).ok_or(invalid("usage: put local [remote]"))?;
+                let name = Path::new(local).file_name().map(|name| name.to_string_lossy().into_owned());
+                let remote = match arg(2) {
+                    Some(remote) => remote.to_string(),
+                    None => name.ok_or(invalid("usage: put local [remote]"))?
+                };
+                self.put(local, &remote)?;
+            },
+            "mget" => {
+                if words.len() < 2 {
+                    return Err(invalid("usage: mget pattern..."));
+                }
+                for pattern in words[1..].iter() {
+                    self.mget(pattern)?;
+                }
+            },
+            "binary" | "image" | "ascii" => {
+                let transfer_type = if words[0] == "ascii" { TransferType::Ascii } else { TransferType::Binary };
+                if let Some(ftp) = self.ftp.as_mut() {
+                    ftp.transfer_type(transfer_type)?;
+                }
+                self.transfer_type = transfer_type;
+            },
+            "close" => self.close(),
+            "quit" | "bye" | "exit" => {
+                self.close();
+                return Ok(false);
+            },
+            "help" | "?" => println!("{}", HELP),
+            command => return Err(invalid(&format!("unknown command {}, try help", command)))
+        }
+        Ok(true)
+    }
+}
+
+fn main() {
+    let args = Parser::new("ftp")
+        .synopsis("[-4 | -6] [-u user[:password]] [-c commands] [host [port]]")
+        .man(FTP_MAN)
+        .family()
+        .opt("u", "", "user[:password]", "Log in as user")
+        .opt("c", "", "commands", "Run commands, separated by semicolons, and exit")
+        .parse_env();
+    let user = args.value("u");
+    let commands = args.value("c");
+    let operands = &args.free;
+    if operands.len() > 2 {
+        args.usage();
+    }
+
+    let mut session = Session {
+        ftp: None,
+        transfer_type: TransferType::Binary,
+        interactive: commands.is_none(),
+        family: args.family(),
+    };
+
+    if let Some(host) = operands.get(0) {
+        let port = operands.get(1).map(|port| port.parse().unwrap_or_else(|_| args.invalid(&format!("invalid port '{}'", port)))).unwrap_or(21);
+        if let Err(err) = session.open(host, port, user) {
+            let err = NetError::from(err);
+            if commands.is_some() {
+                err.exit("ftp");
+            }
+            let _ = writeln!(io::stderr(), "ftp: {}", err);
+        }
+    }
+
+    if let Some(commands) = commands {
+        for command in commands.split(';') {
+            let words: Vec<&str> = command.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            match session.run(&words) {
+                Ok(true) => (),
+                Ok(false) => return,
+                Err(err) => {
+                    session.close();
+                    NetError::from(err).context(words[0]).exit("ftp");
+                }
+            }
+        }
+        session.close();
+        return;
+    }
+
+    let prompt = if termion::is_tty(&io::stdin()) { "ftp> " } else { "" };
+    loop {
+        let line = match read_line(prompt) {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                let _ = writeln!(io::stderr(), "ftp: failed to read a command: {}", err);
+                break;
+            }
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        match session.run(&words) {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(err) => {
+                let _ = writeln!(io::stderr(), "ftp: {}: {}", words[0], err);
+            }
+        }
+    }
+    session.close();
+}
+
+#[cfg(test)]
+mod test {
+    use super::glob;
+
+    #[test]
+    fn glob_test() {
+        assert!(glob(b"*.txt", b"notes.txt"));
+        assert!(glob(b"*.txt", b".txt"));
+        assert!(!glob(b"*.txt", b"notes.txt.gz"));
+        assert!(glob(b"file?.bin", b"file1.bin"));
+        assert!(!glob(b"file?.bin", b"file.bin"));
+        assert!(glob(b"*", b"anything"));
+    }
+}
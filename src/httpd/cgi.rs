@@ -0,0 +1,208 @@
+use std::env;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str;
+use std::thread;
+use std::time::{Duration, Instant};
+use hyper::header::{ContentLength, Headers, Host};
+use hyper::server::Request;
+use hyper::status::StatusCode;
+
+/// Milliseconds between checks of whether a script has exited
+const CGI_POLL_MS: u64 = 10;
+
+/// The PATH of scripts if httpd has none
+const DEFAULT_PATH: &'static str = "/usr/bin:/bin";
+
+/// Find the script for `path` inside `cgi_dir`, returning it with the remaining PATH_INFO
+pub fn find_script(cgi_dir: &Path, path: &Path) -> Option<(PathBuf, String)> {
+    let relative = match path.strip_prefix(cgi_dir) {
+        Ok(relative) => relative,
+        Err(_) => return None
+    };
+
+    let mut script = cgi_dir.to_path_buf();
+    let mut components = relative.components();
+    while let Some(component) = components.next() {
+        script.push(component.as_os_str());
+        if script.is_file() {
+            let rest = match components.as_path().to_str() {
+                Some(rest) => rest,
+                None => return None
+            };
+            let path_info = if rest.is_empty() {
+                String::new()
+            } else {
+                format!("/{}", rest)
+            };
+            return Some((script, path_info));
+        }
+    }
+
+    None
+}
+
+/// The part of `url_path` that names the script, which is what is left once the segments of
+/// `path_info` are taken off the end. Empty and "." segments are skipped, as `Path::components`
+/// skips them when PATH_INFO is found, so that it needn't be a byte for byte suffix.
+fn script_name<'a>(url_path: &'a str, path_info: &str) -> &'a str {
+    let mut count = path_info.split('/').filter(|segment| ! segment.is_empty() && *segment != ".").count();
+    let mut name = url_path;
+    loop {
+        let trimmed = name.trim_right_matches('/');
+        let start = trimmed.rfind('/').map(|i| i + 1).unwrap_or(0);
+        let segment = &trimmed[start..];
+        if segment == "." || (count > 0 && ! segment.is_empty()) {
+            if segment != "." {
+                count -= 1;
+            }
+            name = &trimmed[..start];
+        } else {
+            return trimmed;
+        }
+    }
+}
+
+/// Split the output of a script into its headers and body
+fn split_output(output: &[u8]) -> Option<(&[u8], &[u8])> {
+    for i in 0..output.len() {
+        if output[i..].starts_with(b"\r\n\r\n") {
+            return Some((&output[..i], &output[i + 4..]));
+        } else if output[i..].starts_with(b"\n\n") {
+            return Some((&output[..i], &output[i + 2..]));
+        }
+    }
+    None
+}
+
+/// Execute a CGI/1.1 script (RFC 3875) for `req`, where `url_path` is the decoded request path.
+/// A script that hasn't exited by the timeout is killed, and one isn't run for a request body
+/// of more than `max_body` bytes.
+pub fn run(script: &Path, path_info: &str, url_path: &str, query: &str, timeout: Duration, max_body: u64,
+           req: &mut Request) -> Result<(StatusCode, Headers, Vec<u8>)> {
+    let too_large = || (StatusCode::PayloadTooLarge, Headers::new(), b"Request body is too large".to_vec());
+    if req.headers.get::<ContentLength>().map_or(false, |length| length.0 > max_body) {
+        return Ok(too_large());
+    }
+    // A chunked body has no length until it has been read
+    let mut body = Vec::new();
+    req.by_ref().take(max_body + 1).read_to_end(&mut body)?;
+    if body.len() as u64 > max_body {
+        return Ok(too_large());
+    }
+
+    let script_name = script_name(url_path, path_info);
+
+    let mut command = Command::new(script);
+    // Scripts run with only the variables of RFC 3875, and a PATH for running other programs
+    // and for `#!/usr/bin/env` lines
+    command.env_clear()
+        .env("PATH", env::var_os("PATH").unwrap_or_else(|| DEFAULT_PATH.into()))
+        .env("GATEWAY_INTERFACE", "CGI/1.1")
+        .env("SERVER_SOFTWARE", "netutils-httpd")
+        .env("SERVER_PROTOCOL", format!("{}", req.version))
+        .env("REQUEST_METHOD", format!("{}", req.method))
+        .env("SCRIPT_NAME", script_name)
+        .env("SCRIPT_FILENAME", script)
+        .env("PATH_INFO", path_info)
+        .env("QUERY_STRING", query)
+        .env("REMOTE_ADDR", format!("{}", req.remote_addr.ip()))
+        .env("REMOTE_PORT", format!("{}", req.remote_addr.port()))
+        .env("CONTENT_LENGTH", format!("{}", body.len()));
+
+    if let Some(host) = req.headers.get::<Host>() {
+        command.env("SERVER_NAME", &host.hostname);
+        if let Some(port) = host.port {
+            command.env("SERVER_PORT", format!("{}", port));
+        }
+    }
+
+    for header in req.headers.iter() {
+        let name = header.name().to_uppercase().replace('-', "_");
+        match name.as_str() {
+            "CONTENT_TYPE" => { command.env("CONTENT_TYPE", header.value_string()); },
+            "CONTENT_LENGTH" | "AUTHORIZATION" | "PROXY" => (),
+            _ => { command.env(format!("HTTP_{}", name), header.value_string()); }
+        }
+    }
+
+    if let Some(dir) = script.parent() {
+        command.current_dir(dir);
+    }
+
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+
+    // Write the body from another thread so that a script writing before reading can not deadlock
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(&body));
+
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+
+    let deadline = Instant::now() + timeout;
+    while child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::new(ErrorKind::TimedOut, "CGI script timed out"));
+        }
+        thread::sleep(Duration::from_millis(CGI_POLL_MS));
+    }
+    let output = match reader.join() {
+        Ok(output) => output?,
+        Err(_) => return Err(Error::new(ErrorKind::Other, "failed to read the output of the CGI script"))
+    };
+    let _ = writer.join();
+
+    let (header_data, body) = match split_output(&output) {
+        Some(parts) => parts,
+        None => return Err(Error::new(ErrorKind::Other, "CGI script sent malformed headers"))
+    };
+
+    let mut status = None;
+    let mut headers = Headers::new();
+    for line in String::from_utf8_lossy(header_data).lines() {
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim().to_string();
+        let value = parts.next().unwrap_or("").trim().to_string();
+        if name.eq_ignore_ascii_case("status") {
+            status = value.split(' ').next().and_then(|code| code.parse().ok()).map(StatusCode::from_u16);
+        } else if ! name.is_empty() {
+            headers.append_raw(name, value.into_bytes());
+        }
+    }
+
+    let status = status.unwrap_or(if headers.get_raw("Location").is_some() {
+        StatusCode::Found
+    } else {
+        StatusCode::Ok
+    });
+
+    headers.set(ContentLength(body.len() as u64));
+
+    Ok((status, headers, body.to_vec()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::script_name;
+
+    #[test]
+    fn script_name_test() {
+        assert_eq!(script_name("/cgi-bin/s", ""), "/cgi-bin/s");
+        assert_eq!(script_name("/cgi-bin/s/", ""), "/cgi-bin/s");
+        assert_eq!(script_name("/cgi-bin/s/x/y", "/x/y"), "/cgi-bin/s");
+        // PATH_INFO has lost the repeated slashes and "." segments that the request had
+        assert_eq!(script_name("/cgi-bin/s/\u{e9}///x", "/\u{e9}/x"), "/cgi-bin/s");
+        assert_eq!(script_name("/cgi-bin/\u{e9}/./\u{e9}//x/", "/\u{e9}/x"), "/cgi-bin/\u{e9}");
+        assert_eq!(script_name("/cgi-bin/s/./x", "/x"), "/cgi-bin/s");
+    }
+}
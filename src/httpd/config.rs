@@ -0,0 +1,371 @@
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::Log;
+use netutils_core::log::Logger;
+use netutils_core::ratelimit::{self, Limit};
+use mime::MimeTypes;
+use websocket::{self, Handler};
+
+/// Settings for one document root, either the default site or a virtual host
+pub struct Site {
+    pub root: PathBuf,
+    /// Generate listings for directories without an index.html
+    pub autoindex: bool,
+    /// Directory of CGI scripts to execute instead of serving
+    pub cgi_dir: Option<PathBuf>,
+    /// Files served instead of the default message for an error status, as paths under the
+    /// root, which may be set after them
+    pub error_pages: Vec<(u16, String)>,
+    /// URL prefixes served from directories outside of the root
+    pub aliases: Vec<(String, PathBuf)>,
+    /// URL paths that accept WebSocket connections
+    pub websockets: Vec<(String, Arc<Handler>)>,
+}
+
+impl Site {
+    pub fn new(root: PathBuf) -> Site {
+        Site {
+            root: root,
+            autoindex: false,
+            cgi_dir: None,
+            error_pages: Vec::new(),
+            aliases: Vec::new(),
+            websockets: Vec::new(),
+        }
+    }
+
+    pub fn error_page(&self, status: u16) -> Option<PathBuf> {
+        self.error_pages.iter().find(|page| page.0 == status).map(|page| self.root.join(page.1.trim_left_matches('/')))
+    }
+
+    pub fn websocket(&self, path: &str) -> Option<&Handler> {
+        self.websockets.iter().find(|entry| entry.0 == path).map(|entry| &*entry.1)
+    }
+}
+
+/// Server settings shared by all requests
+pub struct Config {
+    pub default: Site,
+    /// Virtual hosts with the host names they serve
+    pub hosts: Vec<(Vec<String>, Site)>,
+    pub listen: Vec<String>,
+    pub cert: Option<String>,
+    pub key: Option<String>,
+    pub redirect_from: Option<String>,
+    /// Worker threads, which is also the limit of simultaneous connections
+    pub threads: usize,
+    /// Read and write timeout of connections, and how long CGI scripts may run
+    pub timeout: Duration,
+    /// The longest request body that a CGI script is given
+    pub max_body: u64,
+    /// How long idle persistent connections are kept open, None disables keep-alive
+    pub keep_alive: Option<Duration>,
+    /// Requests served on a persistent connection before it is closed
+    pub max_requests: usize,
+    /// Bytes a second that each connection is sent, and that all of them are sent together
+    pub limit_rate: Option<u64>,
+    pub server_limit_rate: Option<u64>,
+    /// Bytes sent at once before the limits apply, a second's worth by default
+    pub limit_burst: Option<u64>,
+    pub mime_types: MimeTypes,
+    pub access_log: Log,
+    pub error_log: Logger,
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" => Some(false),
+        _ => None
+    }
+}
+
+fn parse_number<T: FromStr>(value: &str) -> Option<T> {
+    value.parse().ok()
+}
+
+fn canonicalize(base: &Path, path: &str) -> Result<PathBuf> {
+    fs::canonicalize(base.join(path))
+        .map_err(|err| Error::new(err.kind(), format!("{}: {}", path, err)))
+}
+
+impl Config {
+    pub fn new(root: PathBuf) -> Config {
+        Config {
+            default: Site::new(root),
+            hosts: Vec::new(),
+            listen: Vec::new(),
+            cert: None,
+            key: None,
+            redirect_from: None,
+            threads: 64,
+            timeout: Duration::from_secs(30),
+            max_body: 1024 * 1024,
+            keep_alive: Some(Duration::from_secs(5)),
+            max_requests: 100,
+            limit_rate: None,
+            server_limit_rate: None,
+            limit_burst: None,
+            mime_types: MimeTypes::new(),
+            access_log: Log::stdout(),
+            error_log: Logger::console("httpd"),
+        }
+    }
+
+    /// The limit of each connection
+    pub fn limit(&self) -> Option<Limit> {
+        self.limit_rate.map(|rate| Limit::new(rate, self.limit_burst))
+    }
+
+    /// The limit of all connections together
+    pub fn server_limit(&self) -> Option<Limit> {
+        self.server_limit_rate.map(|rate| Limit::new(rate, self.limit_burst))
+    }
+
+    /// The site serving requests for the Host header `host`
+    pub fn site(&self, host: Option<&str>) -> &Site {
+        if let Some(host) = host {
+            let host = host.to_lowercase();
+            for &(ref names, ref site) in self.hosts.iter() {
+                if names.iter().any(|name| *name == host) {
+                    return site;
+                }
+            }
+        }
+        &self.default
+    }
+
+    /// Load a configuration file, relative paths are relative to the file's directory.
+    pub fn load(path: &str) -> Result<Config> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+
+        let base = match Path::new(path).parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new(".")
+        };
+        Config::parse(&text, base).map_err(|err| Error::new(err.kind(), format!("{}:{}", path, err)))
+    }
+
+    /// Parse an ini style configuration:
+    ///
+    /// ```text
+    /// listen = 0.0.0.0:8080
+    /// root = /var/www
+    /// error_page 404 = /404.html
+    ///
+    /// [example.com www.example.com]
+    /// root = /srv/example
+    /// alias /docs = /usr/share/doc
+    /// websocket /chat = broadcast
+    /// ```
+    ///
+    /// Settings before the first section apply to the server and the default site, each section
+    /// is a virtual host for the host names in its header.
+    pub fn parse(text: &str, base: &Path) -> Result<Config> {
+        // The default root, like every other path, is absolute, so that request paths can be
+        // compared with it
+        let base = &fs::canonicalize(base)
+            .map_err(|err| Error::new(err.kind(), format!("{}: {}", base.display(), err)))?;
+        let mut config = Config::new(base.to_path_buf());
+        let mut host: Option<(Vec<String>, Site)> = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let invalid = |message: String| Error::new(ErrorKind::InvalidData, format!("{}: {}", i + 1, message));
+
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let names: Vec<String> = line[1..line.len() - 1].split_whitespace().map(|name| name.to_lowercase()).collect();
+                if names.is_empty() {
+                    return Err(invalid("virtual host without a host name".to_string()));
+                }
+                if let Some(host) = host.take() {
+                    config.hosts.push(host);
+                }
+                // Virtual hosts without a root setting serve from the default root
+                host = Some((names, Site::new(config.default.root.clone())));
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let mut key = parts.next().unwrap_or("").split_whitespace();
+            let name = key.next().unwrap_or("");
+            let arg = key.next();
+            let value = match parts.next() {
+                Some(value) => value.trim(),
+                None => return Err(invalid(format!("expected '{} = value'", name)))
+            };
+
+            let site = match host {
+                Some((_, ref mut site)) => site,
+                None => &mut config.default
+            };
+
+            match (name, arg) {
+                ("root", None) => site.root = canonicalize(base, value)?,
+                ("autoindex", None) => site.autoindex = parse_bool(value).ok_or_else(|| {
+                    invalid(format!("invalid boolean '{}'", value))
+                })?,
+                ("cgi_dir", None) => site.cgi_dir = Some(canonicalize(base, value)?),
+                ("error_page", Some(status)) => {
+                    let status = status.parse().map_err(|_| invalid(format!("invalid status '{}'", status)))?;
+                    site.error_pages.push((status, value.to_string()));
+                },
+                ("alias", Some(prefix)) => {
+                    if ! prefix.starts_with('/') {
+                        return Err(invalid(format!("alias '{}' must start with '/'", prefix)));
+                    }
+                    site.aliases.push((prefix.trim_right_matches('/').to_string(), canonicalize(base, value)?));
+                },
+                ("websocket", Some(path)) => {
+                    let handler = websocket::handler(value).ok_or_else(|| {
+                        invalid(format!("unknown WebSocket handler '{}'", value))
+                    })?;
+                    site.websockets.push((path.to_string(), handler));
+                },
+                _ if host.is_some() => return Err(invalid(format!("unknown virtual host setting '{}'", line))),
+                ("listen", None) => config.listen.push(value.to_string()),
+                ("cert", None) => config.cert = Some(base.join(value).to_string_lossy().into_owned()),
+                ("key", None) => config.key = Some(base.join(value).to_string_lossy().into_owned()),
+                ("redirect_from", None) => config.redirect_from = Some(value.to_string()),
+                ("threads", None) => config.threads = match parse_number(value) {
+                    Some(threads) if threads > 0 => threads,
+                    _ => return Err(invalid(format!("invalid thread count '{}'", value)))
+                },
+                ("timeout", None) => config.timeout = parse_number(value).map(Duration::from_secs).ok_or_else(|| {
+                    invalid(format!("invalid timeout '{}'", value))
+                })?,
+                ("max_body", None) => config.max_body = ratelimit::parse_size(value).ok_or_else(|| {
+                    invalid(format!("invalid size '{}'", value))
+                })?,
+                ("keep_alive", None) => config.keep_alive = match parse_number(value) {
+                    Some(0) => None,
+                    Some(secs) => Some(Duration::from_secs(secs)),
+                    None => return Err(invalid(format!("invalid keep-alive timeout '{}'", value)))
+                },
+                ("max_requests", None) => config.max_requests = parse_number(value).ok_or_else(|| {
+                    invalid(format!("invalid request count '{}'", value))
+                })?,
+                ("limit_rate", None) => config.limit_rate = Some(ratelimit::parse_size(value).ok_or_else(|| {
+                    invalid(format!("invalid rate '{}'", value))
+                })?),
+                ("server_limit_rate", None) => config.server_limit_rate = Some(ratelimit::parse_size(value).ok_or_else(|| {
+                    invalid(format!("invalid rate '{}'", value))
+                })?),
+                ("limit_burst", None) => config.limit_burst = Some(ratelimit::parse_size(value).ok_or_else(|| {
+                    invalid(format!("invalid burst '{}'", value))
+                })?),
+                ("mime_types", None) => config.mime_types.load(&base.join(value).to_string_lossy())?,
+                ("access_log", None) => config.access_log = if value == "-" {
+                    Log::open(value)?
+                } else {
+                    Log::open(&base.join(value).to_string_lossy())?
+                },
+                ("error_log", None) => config.error_log = if value == "-" || value == "syslog" {
+                    Logger::open("httpd", value, None)?
+                } else {
+                    Logger::open("httpd", &base.join(value).to_string_lossy(), None)?
+                },
+                _ => return Err(invalid(format!("unknown setting '{}'", line)))
+            }
+        }
+
+        if let Some(host) = host.take() {
+            config.hosts.push(host);
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use netutils_core::ratelimit::Limit;
+    use std::path::{Component, Path, PathBuf};
+    use std::{env, fs, process};
+    use super::Config;
+
+    #[test]
+    fn parse_test() {
+        let config = Config::parse("
+            # Global settings
+            listen = 0.0.0.0:8080
+            listen = [::]:8080
+            threads = 8
+            keep_alive = 0
+            max_body = 64k
+            limit_rate = 100k
+            server_limit_rate = 1m
+            root = /
+            error_page 404 = /404.html
+
+            [example.com www.example.com]
+            autoindex = yes
+            alias /docs/ = /
+        ", Path::new("/")).unwrap();
+
+        assert_eq!(config.listen, vec!["0.0.0.0:8080".to_string(), "[::]:8080".to_string()]);
+        assert_eq!(config.threads, 8);
+        assert_eq!(config.keep_alive, None);
+        assert_eq!(config.max_body, 64 * 1024);
+        assert_eq!(config.limit(), Some(Limit::new(100 * 1024, None)));
+        assert_eq!(config.server_limit().map(|limit| limit.rate), Some(1024 * 1024));
+        assert_eq!(config.default.error_page(404), Some(PathBuf::from("/404.html")));
+        assert!(!config.default.autoindex);
+
+        assert!(config.site(Some("WWW.example.com")).autoindex);
+        assert_eq!(config.site(Some("example.com")).aliases, vec![("/docs".to_string(), PathBuf::from("/"))]);
+        assert!(!config.site(Some("example.org")).autoindex);
+
+        // Error pages are under the root, even one set after them
+        let config = Config::parse("
+            error_page 404 = /404.html
+            root = /tmp
+
+            [example.com]
+            error_page 500 = 500.html
+            root = /
+        ", Path::new("/")).unwrap();
+        let tmp = Path::new("/tmp").canonicalize().unwrap();
+        assert_eq!(config.default.error_page(404), Some(tmp.join("404.html")));
+        assert_eq!(config.default.error_page(500), None);
+        assert_eq!(config.site(Some("example.com")).error_page(500), Some(PathBuf::from("/500.html")));
+
+        assert!(Config::parse("[]", Path::new("/")).is_err());
+        assert!(Config::parse("[example.com]\nlisten = :80", Path::new("/")).is_err());
+        assert!(Config::parse("autoindex = maybe", Path::new("/")).is_err());
+        assert!(Config::parse("threads = 0", Path::new("/")).is_err());
+        assert!(Config::parse("limit_rate = fast", Path::new("/")).is_err());
+    }
+
+    #[test]
+    fn relative_test() {
+        let dir = env::temp_dir().join(format!("httpd-config-{}", process::id()));
+        fs::create_dir_all(dir.join("cgi-bin")).unwrap();
+        fs::write(dir.join("httpd.conf"), "cgi_dir = cgi-bin\nerror_page 404 = /404.html\n").unwrap();
+        let dir = dir.canonicalize().unwrap();
+
+        // The same file, by a path relative to the working directory
+        let mut path = PathBuf::new();
+        for _ in env::current_dir().unwrap().components().filter(|component| *component != Component::RootDir) {
+            path.push("..");
+        }
+        path.push(dir.strip_prefix("/").unwrap());
+        path.push("httpd.conf");
+        let config = Config::load(&path.to_string_lossy()).unwrap();
+        assert_eq!(config.default.root, dir);
+        assert_eq!(config.default.cgi_dir, Some(dir.join("cgi-bin")));
+        assert_eq!(config.default.error_page(404), Some(dir.join("404.html")));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
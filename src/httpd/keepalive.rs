@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use netutils_core::ratelimit::{Limit, TokenBucket};
+
+struct Connection {
+    requests: usize,
+    last: Instant,
+    /// The rate limit of the connection, which each response on it takes from
+    bucket: Option<Arc<TokenBucket>>,
+}
+
+/// Counts the requests served on each connection, so that persistent connections can be capped,
+/// and keeps the rate limit of each connection for as long as it is open.
+///
+/// Connections are identified by their remote address, which is unique while they are open.
+pub struct RequestCounter {
+    max_requests: usize,
+    idle: Duration,
+    limit: Option<Limit>,
+    connections: Mutex<HashMap<SocketAddr, Connection>>,
+}
+
+impl RequestCounter {
+    /// Allow `max_requests` per connection, each held to `limit`, forgetting connections idle for
+    /// longer than `idle`
+    pub fn new(max_requests: usize, idle: Duration, limit: Option<Limit>) -> RequestCounter {
+        RequestCounter {
+            max_requests: max_requests,
+            idle: idle,
+            limit: limit,
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Find the connection of `remote`, or start one, having forgotten those that are idle
+    fn connection<'a>(&self, connections: &'a mut HashMap<SocketAddr, Connection>, remote: SocketAddr,
+                      now: Instant) -> &'a mut Connection {
+        // Connections idle past the keep-alive timeout have been closed by the server
+        let idle = self.idle;
+        connections.retain(|_, connection| now.duration_since(connection.last) <= idle);
+
+        let limit = self.limit;
+        let connection = connections.entry(remote).or_insert_with(|| Connection {
+            requests: 0,
+            last: now,
+            bucket: limit.map(|limit| Arc::new(TokenBucket::new(limit))),
+        });
+        connection.last = now;
+        connection
+    }
+
+    /// The rate limit of the connection of `remote`, which is the same for every response on it
+    pub fn bucket(&self, remote: SocketAddr) -> Option<Arc<TokenBucket>> {
+        if self.limit.is_none() {
+            return None;
+        }
+        match self.connections.lock() {
+            Ok(mut connections) => self.connection(&mut connections, remote, Instant::now()).bucket.clone(),
+            Err(_) => self.limit.map(|limit| Arc::new(TokenBucket::new(limit)))
+        }
+    }
+
+    /// Record a request from `remote`, returning false if its connection should then be closed
+    pub fn request(&self, remote: SocketAddr) -> bool {
+        let mut connections = match self.connections.lock() {
+            Ok(connections) => connections,
+            Err(_) => return false
+        };
+
+        let count = {
+            let connection = self.connection(&mut connections, remote, Instant::now());
+            connection.requests += 1;
+            connection.requests
+        };
+
+        if count >= self.max_requests {
+            connections.remove(&remote);
+            false
+        } else {
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use netutils_core::ratelimit::Limit;
+    use super::RequestCounter;
+
+    #[test]
+    fn request_test() {
+        let counter = RequestCounter::new(3, Duration::from_secs(60), None);
+        let a = "127.0.0.1:1000".parse().unwrap();
+        let b = "127.0.0.1:1001".parse().unwrap();
+        assert!(counter.request(a));
+        assert!(counter.request(b));
+        assert!(counter.request(a));
+        assert!(!counter.request(a));
+        assert!(counter.request(a));
+        assert!(counter.request(b));
+        assert!(!counter.request(b));
+        assert!(counter.bucket(a).is_none());
+    }
+
+    #[test]
+    fn bucket_test() {
+        let counter = RequestCounter::new(2, Duration::from_secs(60), Some(Limit::new(1000, None)));
+        let a = "127.0.0.1:1000".parse().unwrap();
+        let b = "127.0.0.1:1001".parse().unwrap();
+        // Every response on a connection takes from the same budget, and only from its own
+        let first = counter.bucket(a).unwrap();
+        assert!(counter.request(a));
+        let second = counter.bucket(a).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &counter.bucket(b).unwrap()));
+        // Once the connection is closed, the next one on the address starts over
+        assert!(!counter.request(a));
+        assert!(!Arc::ptr_eq(&first, &counter.bucket(a).unwrap()));
+    }
+}
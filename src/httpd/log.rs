@@ -0,0 +1,37 @@
+use std::fs::OpenOptions;
+use std::io::{self, Result, Write};
+use std::sync::Mutex;
+
+pub use netutils_core::log::format_time;
+
+/// The access log, in the Common Log Format, shared between connection threads
+pub struct Log {
+    output: Mutex<Box<Write + Send>>,
+}
+
+impl Log {
+    /// Open a log file for appending, "-" logs to stdout
+    pub fn open(path: &str) -> Result<Log> {
+        if path == "-" {
+            Ok(Log::stdout())
+        } else {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            Ok(Log {
+                output: Mutex::new(Box::new(file)),
+            })
+        }
+    }
+
+    pub fn stdout() -> Log {
+        Log {
+            output: Mutex::new(Box::new(io::stdout())),
+        }
+    }
+
+    pub fn write_line(&self, line: &str) {
+        if let Ok(mut output) = self.output.lock() {
+            let _ = writeln!(output, "{}", line);
+            let _ = output.flush();
+        }
+    }
+}
@@ -1,142 +1,530 @@
-#![cfg_attr(not(target_os = "redox"), feature(libc))]
-
+extern crate base64;
 extern crate hyper;
+extern crate hyper_rustls;
+extern crate ring;
+extern crate netutils_core;
+extern crate rustls;
 
-use std::{env, str};
+use std::{cmp, env, str};
 use std::fs::{self, File};
-use std::io::{Error, ErrorKind, Result, Read, Write};
-use std::path::{Path, PathBuf};
+use std::io::{self, Error, ErrorKind, Result, Read, Seek, SeekFrom, Write};
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use hyper::net::NetworkListener;
 use hyper::server::{Server, Request, Response};
 use hyper::status::StatusCode;
 use hyper::uri::RequestUri::AbsolutePath;
-use hyper::header::{Headers, ContentType, ContentLength};
+use hyper::header::{Headers, AcceptRanges, ByteRangeSpec, Connection, ContentLength, ContentRange, ContentRangeSpec,
+                    ContentType, ETag, EntityTag, Host, HttpDate, IfModifiedSince, IfNoneMatch,
+                    LastModified, Location, Range, RangeUnit, Referer, UserAgent};
+use hyper_rustls::TlsServer;
+use netutils_core::args::Parser;
+use netutils_core::error::{self, NetError};
+use netutils_core::log::{Level, Logger};
+use netutils_core::ratelimit::{Limited, TokenBucket};
+use netutils_core::resolve::{self, Family};
+use netutils_core::tls;
+
+use config::{Config, Site};
+use keepalive::RequestCounter;
+use log::{format_time, Log};
+use mime::MimeTypes;
+
+mod cgi;
+mod config;
+mod keepalive;
+mod log;
+mod mime;
+mod websocket;
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Percent-encode a path for use in a URL, leaving '/' separators alone
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for &b in path.as_bytes() {
+        match b {
+            b'A' ... b'Z' | b'a' ... b'z' | b'0' ... b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => encoded.push(b as char),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+fn decode_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(byte) = str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The body of a response. Files are sent from where the body starts rather than read into
+/// memory, so that large files and ranges of them don't take their size in memory.
+enum Body {
+    Bytes(Vec<u8>),
+    /// A file, at the start of the body, and the length of the body
+    File(File, u64),
+}
 
-fn read_dir(root: &Path, path: &Path) -> Result<(Headers, Vec<u8>)> {
-    let mut names = vec![];
+impl Body {
+    fn len(&self) -> u64 {
+        match *self {
+            Body::Bytes(ref bytes) => bytes.len() as u64,
+            Body::File(_, len) => len,
+        }
+    }
+
+    /// The bytes from `start` to `end`, inclusive, of a body that starts at its beginning
+    fn range(self, start: u64, end: u64) -> Result<Body> {
+        match self {
+            Body::Bytes(bytes) => Ok(Body::Bytes(bytes[start as usize .. end as usize + 1].to_vec())),
+            Body::File(mut file, _) => {
+                file.seek(SeekFrom::Start(start))?;
+                Ok(Body::File(file, end - start + 1))
+            }
+        }
+    }
+}
+
+/// Generate a listing of the directory at `path`, which was requested as `url_path`
+fn read_dir(url_path: &str, path: &Path) -> Result<(Headers, Vec<u8>)> {
+    let mut entries = vec![];
     for entry in fs::read_dir(path)? {
         let entry = entry?;
         if let Some(name) = entry.file_name().to_str() {
-            names.push(name.to_string());
+            let metadata = entry.metadata()?;
+            entries.push((name.to_string(), metadata));
         }
     }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let dir = url_path.trim_right_matches('/');
+    let title = escape_html(&format!("Index of {}/", dir));
 
     let mut response = String::new();
-    response.push_str("<!DOCTYPE html>\n<html><body>");
-    if let Ok(relative) = path.strip_prefix(root){
-        if let Some(href) = relative.to_str() {
-            if ! href.is_empty() {
-                names.push("..".to_string());
-            }
-            response.push_str("<h1>Index of /");
-            response.push_str(href);
-            response.push_str("</h1>\n");
-        }
-    }
-
-    names.sort();
-    for name in names {
-        let mut name_path = path.to_path_buf();
-        name_path.push(&name);
-        if let Ok(relative) = name_path.as_path().strip_prefix(root) {
-            if let Some(href) = relative.to_str() {
-                response.push_str("<a href='/");
-                response.push_str(href);
-                response.push_str("'>");
-                response.push_str(&name);
-                response.push_str("</a><br/>\n");
-            } else {
-                response.push_str(&name);
-                response.push_str("<br/>\n");
-            }
+    response.push_str("<!DOCTYPE html>\n<html><head><meta charset='utf-8'><title>");
+    response.push_str(&title);
+    response.push_str("</title></head><body>\n<h1>");
+    response.push_str(&title);
+    response.push_str("</h1>\n<table>\n<tr><th>Name</th><th>Size</th><th>Last modified</th></tr>\n");
+
+    if ! dir.is_empty() {
+        let parent = &dir[.. dir.rfind('/').unwrap_or(0)];
+        response.push_str("<tr><td><a href='");
+        response.push_str(&escape_html(&encode_path(parent)));
+        response.push_str("/'>../</a></td><td>-</td><td></td></tr>\n");
+    }
+
+    for (name, metadata) in entries {
+        let href = format!("{}/{}{}", dir, name, if metadata.is_dir() { "/" } else { "" });
+
+        let (name, size) = if metadata.is_dir() {
+            (format!("{}/", name), "-".to_string())
         } else {
-            response.push_str(&name);
-            response.push_str("<br/>\n");
-        }
+            (name, format!("{}", metadata.len()))
+        };
+        let modified = metadata.modified().map(|time| format!("{}", HttpDate::from(time))).unwrap_or(String::new());
+
+        response.push_str("<tr><td><a href='");
+        response.push_str(&escape_html(&encode_path(&href)));
+        response.push_str("'>");
+        response.push_str(&escape_html(&name));
+        response.push_str("</a></td><td>");
+        response.push_str(&size);
+        response.push_str("</td><td>");
+        response.push_str(&modified);
+        response.push_str("</td></tr>\n");
     }
-    response.push_str("</body></html>");
+    response.push_str("</table>\n</body></html>");
 
     let mut headers = Headers::new();
-    headers.set(ContentType("text/html".parse().unwrap()));
+    headers.set(ContentType::html());
     headers.set(ContentLength(response.len() as u64));
 
     Ok((headers, response.into_bytes()))
 }
 
-fn read_file(_root: &Path, path: &Path) -> Result<(Headers, Vec<u8>)> {
-    let mut file = File::open(path)?;
+fn read_file(mime_types: &MimeTypes, path: &Path) -> Result<(Headers, Body)> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
 
-    let mut response = Vec::new();
-    file.read_to_end(&mut response)?;
+    let mut headers = Headers::new();
+    headers.set_raw("Content-Type", vec![mime_types.content_type(path).into_bytes()]);
+    headers.set(ContentLength(metadata.len()));
+    headers.set(AcceptRanges(vec![RangeUnit::Bytes]));
+    if let Ok(modified) = metadata.modified() {
+        // Like most servers, derive the entity tag from the modification time and size
+        headers.set(ETag(EntityTag::strong(format!("{:x}-{:x}", unix_secs(modified), metadata.len()))));
+        headers.set(LastModified(HttpDate::from(modified)));
+    }
 
-    let extension = path.extension().map_or("", |ext_os| ext_os.to_str().unwrap_or(""));
-    let mime_type = match extension {
-        "css" => "text/css",
-        "html" => "text/html",
-        "js" => "text/javascript",
-        "jpg" | "jpeg" => "text/jpeg",
-        "png" => "image/png",
-        "svg" => "image/svg+xml",
-        _ => "text/plain"
+    Ok((headers, Body::File(file, metadata.len())))
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+/// Apply the conditional and Range headers of a request to a successful response
+fn conditional(req: &Request, mut headers: Headers, body: Body) -> Result<(StatusCode, Headers, Body)> {
+    // If-None-Match takes precedence over If-Modified-Since
+    let not_modified = match (req.headers.get::<IfNoneMatch>(), headers.get::<ETag>()) {
+        (Some(&IfNoneMatch::Any), _) => true,
+        (Some(&IfNoneMatch::Items(ref tags)), Some(etag)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        (Some(_), None) => false,
+        (None, _) => match (req.headers.get::<IfModifiedSince>(), headers.get::<LastModified>()) {
+            (Some(since), Some(modified)) => unix_secs(SystemTime::from(modified.0)) <= unix_secs(SystemTime::from(since.0)),
+            _ => false
+        }
     };
 
-    let mut headers = Headers::new();
-    headers.set(ContentType(mime_type.parse().unwrap()));
-    headers.set(ContentLength(response.len() as u64));
+    if not_modified {
+        headers.remove::<ContentLength>();
+        headers.remove::<ContentType>();
+        return Ok((StatusCode::NotModified, headers, Body::Bytes(Vec::new())));
+    }
+
+    // Only single ranges of files are supported, other requests get the full response
+    let range = match req.headers.get::<Range>() {
+        Some(&Range::Bytes(ref ranges)) if ranges.len() == 1 && headers.has::<ETag>() => {
+            let len = body.len();
+            Some(match ranges[0] {
+                ByteRangeSpec::FromTo(start, end) if start <= end && start < len => Some((start, cmp::min(end, len - 1))),
+                ByteRangeSpec::AllFrom(start) if start < len => Some((start, len - 1)),
+                ByteRangeSpec::Last(count) if count > 0 && len > 0 => Some((len - cmp::min(count, len), len - 1)),
+                _ => None
+            })
+        },
+        _ => None
+    };
 
-    Ok((headers, response))
+    match range {
+        Some(Some((start, end))) => {
+            headers.set(ContentRange(ContentRangeSpec::Bytes {
+                range: Some((start, end)),
+                instance_length: Some(body.len()),
+            }));
+            headers.set(ContentLength(end - start + 1));
+            Ok((StatusCode::PartialContent, headers, body.range(start, end)?))
+        },
+        Some(None) => {
+            headers.set(ContentRange(ContentRangeSpec::Bytes {
+                range: None,
+                instance_length: Some(body.len()),
+            }));
+            headers.set(ContentLength(0));
+            Ok((StatusCode::RangeNotSatisfiable, headers, Body::Bytes(Vec::new())))
+        },
+        None => Ok((StatusCode::Ok, headers, body))
+    }
 }
 
-fn read_path(root: &Path, path: &Path) -> Result<(Headers, Vec<u8>)> {
+fn read_path(config: &Config, site: &Site, url_path: &str, path: &Path) -> Result<(Headers, Body)> {
     if path.is_dir() {
         let mut index_path = path.to_path_buf();
         index_path.push("index.html");
         if index_path.is_file() {
-            read_file(root, &index_path)
+            read_file(&config.mime_types, &index_path)
+        } else if site.autoindex {
+            read_dir(url_path, path).map(|(headers, listing)| (headers, Body::Bytes(listing)))
         } else {
-            read_dir(root, path)
+            Err(Error::new(ErrorKind::PermissionDenied, "Directory listing is disabled"))
         }
     } else {
-        read_file(root, path)
+        read_file(&config.mime_types, path)
     }
 }
 
-fn read_req(root: &Path, request: &Request) -> Result<(Headers, Vec<u8>)> {
+/// Decoded URL path, query string and file system path of a request
+struct Target {
+    url_path: String,
+    query: String,
+    path: PathBuf,
+}
+
+fn resolve(site: &Site, request: &Request) -> Result<Target> {
     if let AbsolutePath(ref path) = request.uri {
-        let mut full_path = root.to_path_buf();
-        full_path.push(path.trim_left_matches('/'));
-        if full_path.as_path().strip_prefix(root).is_ok() {
-            read_path(root, &full_path)
-        } else {
-            Err(Error::new(ErrorKind::InvalidInput, "Path is invalid"))
-        }
+        let path = path.split('#').next().unwrap_or("");
+        let mut parts = path.splitn(2, '?');
+        let url_path = decode_path(parts.next().unwrap_or(""));
+        let query = parts.next().unwrap_or("").to_string();
+
+        // Serve aliased prefixes from their own directory, otherwise from the root
+        let (dir, rest) = site.aliases.iter()
+            .find(|alias| url_path == alias.0 || url_path.starts_with(&format!("{}/", alias.0)))
+            .map(|alias| (&alias.1, &url_path[alias.0.len()..]))
+            .unwrap_or((&site.root, url_path.as_str()));
+
+        let full_path = {
+            let relative = Path::new(rest.trim_left_matches('/'));
+            // Only allow plain names, so that ".." can not escape the root
+            if ! relative.components().all(|component| match component {
+                Component::Normal(_) | Component::CurDir => true,
+                _ => false
+            }) {
+                return Err(Error::new(ErrorKind::InvalidInput, "Path is invalid"));
+            }
+            dir.join(relative)
+        };
+
+        Ok(Target {
+            url_path: url_path.clone(),
+            query: query,
+            path: full_path,
+        })
     } else {
         Err(Error::new(ErrorKind::InvalidInput, "Path not found"))
     }
 }
 
-fn http(root: PathBuf) {
-    Server::http("0.0.0.0:8080").unwrap().handle(move |req: Request, mut res: Response| {
-        match req.method {
-            hyper::Get => {
-                match read_req(&root, &req) {
-                    Ok((headers, response)) => {
-                        *res.headers_mut() = headers;
-                        res.start().unwrap().write(&response).unwrap();
-                    },
-                    Err(err) => {
-                        *res.status_mut() = match err.kind() {
-                            ErrorKind::NotFound => StatusCode::NotFound,
-                            ErrorKind::InvalidInput => StatusCode::BadRequest,
-                            _ => StatusCode::InternalServerError
-                        };
-
-                        write!(res.start().unwrap(), "{}", err);
-                    }
-                }
+fn respond(config: &Config, site: &Site, req: &mut Request) -> Result<(StatusCode, Headers, Body)> {
+    let target = resolve(site, req)?;
+
+    if let Some(ref cgi_dir) = site.cgi_dir {
+        if let Some((script, path_info)) = cgi::find_script(cgi_dir, &target.path) {
+            return cgi::run(&script, &path_info, &target.url_path, &target.query, config.timeout, config.max_body,
+                            req)
+                .map(|(status, headers, output)| (status, headers, Body::Bytes(output)));
+        }
+    }
+
+    match req.method {
+        hyper::Get => {
+            let (headers, response) = read_path(config, site, &target.url_path, &target.path)?;
+            conditional(req, headers, response)
+        },
+        _ => Ok((StatusCode::MethodNotAllowed, Headers::new(), Body::Bytes(Vec::new())))
+    }
+}
+
+/// Complete a WebSocket handshake and run the connection, returning the status of the handshake
+fn upgrade(config: &Config, handler: &websocket::Handler, req: Request, mut res: Response) -> StatusCode {
+    let accept = match websocket::accept(&req.headers) {
+        Some(accept) => accept,
+        None => {
+            // Only version 13 exists, clients sending another version are told which one to use
+            *res.status_mut() = StatusCode::UpgradeRequired;
+            res.headers_mut().set_raw("Sec-WebSocket-Version", vec![b"13".to_vec()]);
+            return StatusCode::UpgradeRequired;
+        }
+    };
+
+    *res.status_mut() = StatusCode::SwitchingProtocols;
+    res.headers_mut().set_raw("Upgrade", vec![b"websocket".to_vec()]);
+    res.headers_mut().set_raw("Connection", vec![b"Upgrade".to_vec()]);
+    res.headers_mut().set_raw("Sec-WebSocket-Accept", vec![accept.into_bytes()]);
+    if res.start().and_then(|res| res.end()).is_err() {
+        return StatusCode::SwitchingProtocols;
+    }
+
+    // The connection now belongs to the WebSocket, read and write the stream directly
+    let remote = req.remote_addr;
+    let (_, _, _, _, _, reader) = req.deconstruct();
+    if let Err(err) = websocket::serve(reader.into_inner(), handler, config.timeout) {
+        config.error_log.error(&format!("[client {}] WebSocket: {}", remote.ip(), err));
+    }
+
+    StatusCode::SwitchingProtocols
+}
+
+/// Send a response body, held to the limit of its connection, which every response on the
+/// connection takes from, and to that of the server, which `server` is shared by all of them for
+fn send_body(connection: Option<&Arc<TokenBucket>>, server: Option<&Arc<TokenBucket>>, res: Response,
+             body: Body) -> Result<()> {
+    let buckets = connection.into_iter().chain(server).cloned().collect();
+    let mut writer = Limited::shared(res.start()?, buckets);
+    match body {
+        Body::Bytes(bytes) => writer.write_all(&bytes)?,
+        Body::File(file, len) => {
+            io::copy(&mut file.take(len), &mut writer)?;
+        }
+    }
+    writer.into_inner().end()
+}
+
+fn handle(config: &Config, requests: &RequestCounter, server: Option<&Arc<TokenBucket>>, mut req: Request,
+          mut res: Response) {
+    let request_line = format!("{} {} {}", req.method, req.uri, req.version);
+    let remote = req.remote_addr.ip();
+    let referer = req.headers.get::<Referer>().map_or("-".to_string(), |referer| referer.0.replace('"', "\\\""));
+    let user_agent = req.headers.get::<UserAgent>().map_or("-".to_string(), |agent| agent.0.replace('"', "\\\""));
+    let host = req.headers.get::<Host>().map(|host| host.hostname.clone());
+    let site = config.site(host.as_ref().map(|host| host.as_str()));
+    let connection = requests.bucket(req.remote_addr);
+    let close = config.keep_alive.is_some() && ! requests.request(req.remote_addr);
+
+    if websocket::is_upgrade(&req.headers) {
+        let handler = resolve(site, &req).ok().and_then(|target| site.websocket(&target.url_path));
+        if let Some(handler) = handler {
+            let status = upgrade(config, handler, req, res);
+            config.access_log.write_line(&format!("{} - - [{}] \"{}\" {} - \"{}\" \"{}\"",
+                                                  remote, format_time(SystemTime::now()), request_line,
+                                                  status.to_u16(), referer, user_agent));
+            return;
+        }
+    }
+
+    let (status, bytes) = match respond(config, site, &mut req) {
+        Ok((status, headers, response)) => {
+            *res.status_mut() = status;
+            *res.headers_mut() = headers;
+            if close {
+                res.headers_mut().set(Connection::close());
             }
-            _ => *res.status_mut() = StatusCode::MethodNotAllowed
+            let bytes = response.len();
+            if let Err(err) = send_body(connection.as_ref(), server, res, response) {
+                config.error_log.info(&format!("[client {}] {}: failed to send response: {}", remote, request_line, err));
+            }
+            (status, bytes)
+        },
+        Err(err) => {
+            let status = match err.kind() {
+                ErrorKind::NotFound => StatusCode::NotFound,
+                ErrorKind::PermissionDenied => StatusCode::Forbidden,
+                ErrorKind::InvalidInput => StatusCode::BadRequest,
+                _ => StatusCode::InternalServerError
+            };
+            *res.status_mut() = status;
+
+            // Like Apache, missing files and bad requests are the clients' doing, not errors
+            let level = if status == StatusCode::InternalServerError { Level::Error } else { Level::Info };
+            config.error_log.log(level, &format!("[client {}] {}: {}", remote, request_line, err));
+
+            let page = site.error_page(status.to_u16()).and_then(|page| read_file(&config.mime_types, &page).ok());
+            let message = match page {
+                Some((headers, page)) => {
+                    *res.headers_mut() = headers;
+                    page
+                },
+                None => Body::Bytes(format!("{}", err).into_bytes())
+            };
+            if close {
+                res.headers_mut().set(Connection::close());
+            }
+            let bytes = message.len();
+            let _ = send_body(connection.as_ref(), server, res, message);
+            (status, bytes)
         }
-    }).unwrap();
+    };
+
+    // Combined Log Format, the Common Log Format followed by the referer and user agent
+    config.access_log.write_line(&format!("{} - - [{}] \"{}\" {} {} \"{}\" \"{}\"",
+                                          remote, format_time(SystemTime::now()), request_line,
+                                          status.to_u16(), bytes, referer, user_agent));
+}
+
+/// Send a permanent redirect to the same path on the HTTPS listener
+fn redirect(https_port: u16, req: Request, mut res: Response) {
+    let path = match req.uri {
+        AbsolutePath(ref path) => path.clone(),
+        _ => "/".to_string()
+    };
+
+    match req.headers.get::<Host>() {
+        Some(host) => {
+            let location = if https_port == 443 {
+                format!("https://{}{}", host.hostname, path)
+            } else {
+                format!("https://{}:{}{}", host.hostname, https_port, path)
+            };
+            *res.status_mut() = StatusCode::MovedPermanently;
+            res.headers_mut().set(Location(location));
+        },
+        None => *res.status_mut() = StatusCode::BadRequest
+    }
+}
+
+/// Load a PEM certificate chain and private key, advertising HTTP/1.1 over ALPN
+fn load_tls(cert_path: &str, key_path: &str) -> Result<rustls::ServerConfig> {
+    let mut tls = tls::server_config(cert_path, key_path)?;
+    tls.set_protocols(&["http/1.1".to_string()]);
+    Ok(tls)
+}
+
+/// Apply the connection limits, so that slow clients time out instead of holding a thread
+fn configure<L: NetworkListener>(server: &mut Server<L>, config: &Config) {
+    server.keep_alive(config.keep_alive);
+    server.set_read_timeout(Some(config.timeout));
+    server.set_write_timeout(Some(config.timeout));
+}
+
+/// The address of a listen setting, such as `0.0.0.0:8080`, `[::]:8080` or `[fe80::1%eth0]:8080`.
+/// Leaving out the host, as in `:8080`, listens on every interface.
+fn listen_addr(listen: &str, family: Family) -> error::Result<SocketAddr> {
+    match resolve::split_port(listen)? {
+        ("", Some(port)) => Ok(SocketAddr::new(family.unspecified(), port)),
+        (host, Some(port)) => resolve::resolve(host, port, family),
+        (_, None) => Err(NetError::Usage(format!("no port in listen address '{}'", listen)))
+    }
+}
+
+fn http(config: Config, listen: Vec<SocketAddr>, redirect_from: Option<SocketAddr>,
+        tls: Option<rustls::ServerConfig>) -> hyper::Result<()> {
+    let config = Arc::new(config);
+    let mut listening = Vec::new();
+
+    if let Some(addr) = redirect_from {
+        let https_port = listen.get(0).map_or(443, |addr| addr.port());
+        listening.push(Server::http(addr)?.handle(move |req: Request, res: Response| {
+            redirect(https_port, req, res)
+        })?);
+    }
+
+    let requests = Arc::new(RequestCounter::new(config.max_requests, config.keep_alive.unwrap_or(config.timeout),
+                                               config.limit()));
+    let server_limit = config.server_limit().map(|limit| Arc::new(TokenBucket::new(limit)));
+    let tls = tls.map(|tls| TlsServer { cfg: Arc::new(tls) });
+    for &addr in listen.iter() {
+        let handler = {
+            let config = config.clone();
+            let requests = requests.clone();
+            let server_limit = server_limit.clone();
+            move |req: Request, res: Response| handle(&config, &requests, server_limit.as_ref(), req, res)
+        };
+        listening.push(match tls {
+            Some(ref tls) => {
+                let mut server = Server::https(addr, tls.clone())?;
+                configure(&mut server, &config);
+                server.handle_threads(handler, config.threads)?
+            },
+            None => {
+                let mut server = Server::http(addr)?;
+                configure(&mut server, &config);
+                server.handle_threads(handler, config.threads)?
+            }
+        });
+    }
+
+    // Dropping the listeners waits for them to finish, which they never do
+    drop(listening);
+
+    Ok(())
 }
 
 #[cfg(target_os = "redox")]
@@ -151,22 +539,171 @@ fn fork()  -> usize {
     unsafe { libc::fork() as usize }
 }
 
+/// Binding and other I/O failures keep the kind of their error, and the rest are protocol errors
+fn server_error(err: hyper::Error) -> NetError {
+    match err {
+        hyper::Error::Io(err) => NetError::from(err),
+        err => NetError::Protocol(err.to_string()),
+    }
+}
+
 fn main() {
-    let mut background = false;
-    let mut root = env::current_dir().unwrap();
-    for arg in env::args().skip(1) {
-        match arg.as_ref() {
-            "-b" => background = true,
-            _ => root = fs::canonicalize(arg).unwrap()
+    let args = Parser::new("httpd")
+        .synopsis("[-b] [-4 | -6] [--config file] [--listen addr]... [--cert file --key file [--redirect-from addr]] [option...] [root]")
+        .flag("b", "", "Run in the background")
+        .family()
+        .opt("", "config", "file", "Load the configuration file, which the other options override")
+        .flag("", "autoindex", "List directories without an index file")
+        .opt("", "cgi-dir", "dir", "Run the programs in dir as CGI scripts")
+        .opt("", "listen", "addr", "Listen on addr, port 8080 of every interface by default, or 8443 with TLS")
+        .opt("", "cert", "file", "Serve HTTPS with the PEM certificate chain in file")
+        .opt("", "key", "file", "The PEM private key of the certificate")
+        .opt("", "access-log", "file", "Log requests to file")
+        .logging()
+        .opt("", "error-log", "file", "The same as --log")
+        .opt("", "threads", "count", "Number of threads that handle requests")
+        .opt("", "timeout", "secs", "Seconds to wait for a request to be read, or a CGI script to exit")
+        .opt("", "max-body", "bytes", "Refuse request bodies of more than bytes for CGI scripts, 1m by default")
+        .opt("", "keep-alive", "secs", "Seconds to keep an idle connection open, or 0 to close each one")
+        .opt("", "max-requests", "count", "Requests to serve on each connection")
+        .opt("", "limit-rate", "rate", "Send each connection no more than rate bytes a second, with k, m or g for KiB, MiB or GiB")
+        .opt("", "server-limit-rate", "rate", "Send all connections together no more than rate bytes a second")
+        .opt("", "limit-burst", "bytes", "Let bursts of up to bytes through at once, a second's worth by default")
+        .opt("", "websocket", "path[=handler]", "Handle WebSocket connections to path, with echo by default")
+        .opt("", "mime-types", "file", "Load MIME types from file")
+        .opt("", "redirect-from", "addr", "Listen for HTTP on addr, redirecting to HTTPS")
+        .parse_env();
+
+    // Load the configuration file first, so that other options override it
+    let mut config = match args.value("config") {
+        Some(path) => Config::load(path).unwrap_or_else(|err| {
+            NetError::Usage(format!("failed to load configuration: {}", err)).exit("httpd")
+        }),
+        None => Config::new(env::current_dir().unwrap_or_else(|err| {
+            NetError::Io(err).context("failed to read the working directory").exit("httpd")
+        }))
+    };
+
+    let background = args.flag("b");
+    let family = args.family();
+    if args.flag("autoindex") {
+        config.default.autoindex = true;
+    }
+    if let Some(dir) = args.value("cgi-dir") {
+        config.default.cgi_dir = Some(fs::canonicalize(dir).unwrap_or_else(|err| {
+            NetError::Io(err).context(dir).exit("httpd")
+        }));
+    }
+    let listen: Vec<String> = args.values("listen").into_iter().map(|addr| addr.to_string()).collect();
+    if let Some(cert) = args.value("cert") {
+        config.cert = Some(cert.to_string());
+    }
+    if let Some(key) = args.value("key") {
+        config.key = Some(key.to_string());
+    }
+    let open_log = |path: &str| Log::open(path).unwrap_or_else(|err| {
+        NetError::Io(err).context(&format!("failed to open log '{}'", path)).exit("httpd")
+    });
+    if let Some(path) = args.value("access-log") {
+        config.access_log = open_log(path);
+    }
+    if let Some(dest) = args.value("log").or(args.value("error-log")) {
+        config.error_log = Logger::open("httpd", dest, args.parse("log-max-size")).unwrap_or_else(|err| {
+            NetError::Io(err).context(&format!("failed to open log '{}'", dest)).exit("httpd")
+        });
+    }
+    config.error_log.level = args.log_level(config.error_log.level);
+    if let Some(threads) = args.parse("threads") {
+        config.threads = threads;
+    }
+    if let Some(secs) = args.parse("timeout") {
+        config.timeout = Duration::from_secs(secs);
+    }
+    if let Some(max_body) = args.size("max-body") {
+        config.max_body = max_body;
+    }
+    if let Some(secs) = args.parse("keep-alive") {
+        config.keep_alive = match secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs))
+        };
+    }
+    if let Some(max_requests) = args.parse("max-requests") {
+        config.max_requests = max_requests;
+    }
+    if let Some(rate) = args.size("limit-rate") {
+        config.limit_rate = Some(rate);
+    }
+    if let Some(rate) = args.size("server-limit-rate") {
+        config.server_limit_rate = Some(rate);
+    }
+    if let Some(burst) = args.size("limit-burst") {
+        config.limit_burst = Some(burst);
+    }
+    for websocket in args.values("websocket") {
+        let mut parts = websocket.splitn(2, '=');
+        let path = parts.next().unwrap_or("").to_string();
+        let handler = parts.next().unwrap_or("echo");
+        match websocket::handler(handler) {
+            Some(handler) => config.default.websockets.push((path, handler)),
+            None => args.invalid(&format!("unknown WebSocket handler '{}'", handler))
+        }
+    }
+    if let Some(path) = args.value("mime-types") {
+        if let Err(err) = config.mime_types.load(path) {
+            NetError::Io(err).context(&format!("failed to load MIME types '{}'", path)).exit("httpd");
         }
     }
+    if let Some(addr) = args.value("redirect-from") {
+        config.redirect_from = Some(addr.to_string());
+    }
+    match args.free.len() {
+        0 => (),
+        1 => config.default.root = fs::canonicalize(&args.free[0]).unwrap_or_else(|err| {
+            NetError::Io(err).context(&args.free[0]).exit("httpd")
+        }),
+        _ => args.usage()
+    }
+
+    let tls = match (config.cert.as_ref(), config.key.as_ref()) {
+        (Some(cert), Some(key)) => Some(load_tls(cert, key).unwrap_or_else(|err| {
+            NetError::Io(err).context("failed to load TLS certificate").exit("httpd")
+        })),
+        (None, None) => None,
+        _ => args.invalid("--cert and --key must be used together")
+    };
 
-    println!("HTTP: {}", root.display());
+    if config.threads == 0 {
+        args.invalid("--threads must be at least 1");
+    }
+
+    if config.redirect_from.is_some() && tls.is_none() {
+        args.invalid("--redirect-from requires --cert and --key");
+    }
+
+    if ! listen.is_empty() {
+        config.listen = listen;
+    } else if config.listen.is_empty() {
+        let port = if tls.is_some() { 8443 } else { 8080 };
+        config.listen.push(SocketAddr::new(family.unspecified(), port).to_string());
+    }
+    let listen: Vec<SocketAddr> = config.listen.iter()
+        .map(|listen| listen_addr(listen, family).unwrap_or_else(|err| err.exit("httpd")))
+        .collect();
+    let redirect_from = config.redirect_from.as_ref()
+        .map(|addr| listen_addr(addr, family).unwrap_or_else(|err| err.exit("httpd")));
+
+    config.error_log.info(&format!("{}: {} on {}", if tls.is_some() { "HTTPS" } else { "HTTP" },
+                                   config.default.root.display(), config.listen.join(", ")));
     if background {
         if fork() == 0 {
-            http(root);
+            if let Err(err) = http(config, listen, redirect_from, tls) {
+                server_error(err).exit("httpd");
+            }
         }
     } else {
-        http(root);
+        if let Err(err) = http(config, listen, redirect_from, tls) {
+            server_error(err).exit("httpd");
+        }
     }
 }
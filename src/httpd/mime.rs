@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Result};
+use std::path::Path;
+
+/// Types for common extensions, used unless a mime.types file overrides them
+static BUILTIN: &'static [(&'static str, &'static str)] = &[
+    ("css", "text/css"),
+    ("csv", "text/csv"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("ico", "image/x-icon"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "application/javascript"),
+    ("json", "application/json"),
+    ("md", "text/markdown"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("ogg", "audio/ogg"),
+    ("otf", "font/otf"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("rs", "text/plain"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("toml", "text/plain"),
+    ("ttf", "font/ttf"),
+    ("txt", "text/plain"),
+    ("wasm", "application/wasm"),
+    ("webm", "video/webm"),
+    ("webp", "image/webp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("xml", "application/xml"),
+    ("zip", "application/zip"),
+];
+
+/// Served for files without a known extension, so that browsers download rather than render them
+static DEFAULT: &'static str = "application/octet-stream";
+
+/// Mapping from file extensions to media types
+pub struct MimeTypes {
+    types: HashMap<String, String>,
+}
+
+impl MimeTypes {
+    pub fn new() -> MimeTypes {
+        MimeTypes {
+            types: BUILTIN.iter().map(|&(ext, mime)| (ext.to_string(), mime.to_string())).collect(),
+        }
+    }
+
+    /// Add the types of a mime.types file, in which each line is a type followed by its extensions
+    pub fn load(&mut self, path: &str) -> Result<()> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        self.parse(&text);
+        Ok(())
+    }
+
+    pub fn parse(&mut self, text: &str) {
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut words = line.split_whitespace();
+            if let Some(mime) = words.next() {
+                for ext in words {
+                    self.types.insert(ext.to_lowercase(), mime.to_string());
+                }
+            }
+        }
+    }
+
+    /// The Content-Type for a file, with a UTF-8 charset for text
+    pub fn content_type(&self, path: &Path) -> String {
+        let mime = path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.types.get(&ext.to_lowercase()))
+            .map_or(DEFAULT, |mime| mime.as_str());
+
+        if mime.starts_with("text/") || mime == "application/javascript" || mime == "application/json" {
+            format!("{}; charset=utf-8", mime)
+        } else {
+            mime.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use super::MimeTypes;
+
+    #[test]
+    fn content_type_test() {
+        let mut types = MimeTypes::new();
+        assert_eq!(types.content_type(Path::new("index.HTML")), "text/html; charset=utf-8");
+        assert_eq!(types.content_type(Path::new("photo.jpg")), "image/jpeg");
+        assert_eq!(types.content_type(Path::new("README")), "application/octet-stream");
+
+        types.parse("# comment\ntext/x-rust rs\napplication/x-custom foo bar\n");
+        assert_eq!(types.content_type(Path::new("main.rs")), "text/x-rust; charset=utf-8");
+        assert_eq!(types.content_type(Path::new("a.bar")), "application/x-custom");
+    }
+}
@@ -0,0 +1,340 @@
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+use base64;
+use hyper::header::Headers;
+use hyper::net::NetworkStream;
+use ring::digest;
+
+/// Appended to the client's key to compute Sec-WebSocket-Accept (RFC 6455 section 1.3)
+static GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Larger messages close the connection, so that a client can not exhaust memory
+const MAX_MESSAGE: usize = 16 * 1024 * 1024;
+
+/// How often a connection checks for messages queued by other connections while idle
+const POLL_INTERVAL: u64 = 100;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// A connected client, which messages can be sent to from any thread
+#[derive(Clone)]
+pub struct Client {
+    pub id: usize,
+    sender: Sender<Message>,
+}
+
+impl Client {
+    pub fn send(&self, message: Message) {
+        // The connection has closed if the receiver is gone, which the handler will be told about
+        let _ = self.sender.send(message);
+    }
+}
+
+/// Receives the events of the WebSocket connections for a path
+pub trait Handler: Send + Sync {
+    fn open(&self, _client: &Client) {}
+    fn message(&self, client: &Client, message: Message);
+    fn close(&self, _client: &Client) {}
+}
+
+/// Send every message back to the client that sent it
+pub struct Echo;
+
+impl Handler for Echo {
+    fn message(&self, client: &Client, message: Message) {
+        client.send(message);
+    }
+}
+
+/// Send every message to all connected clients
+pub struct Broadcast {
+    clients: Mutex<Vec<Client>>,
+}
+
+impl Broadcast {
+    pub fn new() -> Broadcast {
+        Broadcast {
+            clients: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Handler for Broadcast {
+    fn open(&self, client: &Client) {
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.push(client.clone());
+        }
+    }
+
+    fn message(&self, _client: &Client, message: Message) {
+        if let Ok(clients) = self.clients.lock() {
+            for client in clients.iter() {
+                client.send(message.clone());
+            }
+        }
+    }
+
+    fn close(&self, client: &Client) {
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.retain(|other| other.id != client.id);
+        }
+    }
+}
+
+/// Create one of the built-in handlers by name
+pub fn handler(name: &str) -> Option<Arc<Handler>> {
+    match name {
+        "echo" => Some(Arc::new(Echo)),
+        "broadcast" => Some(Arc::new(Broadcast::new())),
+        _ => None
+    }
+}
+
+fn header_has(headers: &Headers, name: &str, token: &str) -> bool {
+    headers.get_raw(name).map_or(false, |values| values.iter().any(|value| {
+        String::from_utf8_lossy(value).split(',').any(|part| part.trim().eq_ignore_ascii_case(token))
+    }))
+}
+
+/// Whether a request asks to switch to the WebSocket protocol
+pub fn is_upgrade(headers: &Headers) -> bool {
+    header_has(headers, "Upgrade", "websocket") && header_has(headers, "Connection", "upgrade")
+}
+
+/// The Sec-WebSocket-Accept value for a request, or None if the handshake is not valid
+pub fn accept(headers: &Headers) -> Option<String> {
+    if ! header_has(headers, "Sec-WebSocket-Version", "13") {
+        return None;
+    }
+    headers.get_raw("Sec-WebSocket-Key")
+        .and_then(|values| values.get(0))
+        .and_then(|key| String::from_utf8(key.clone()).ok())
+        .map(|key| accept_key(key.trim()))
+}
+
+pub fn accept_key(key: &str) -> String {
+    let hash = digest::digest(&digest::SHA1, format!("{}{}", key, GUID).as_bytes());
+    base64::encode(hash.as_ref())
+}
+
+pub struct Frame {
+    pub fin: bool,
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+/// Read a frame sent by a client, which must be masked
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Frame> {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head)?;
+
+    if head[0] & 0x70 != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "reserved bits set without an extension"));
+    }
+    if head[1] & 0x80 == 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "client frame is not masked"));
+    }
+
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0F;
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut bytes = [0; 2];
+            reader.read_exact(&mut bytes)?;
+            (bytes[0] as u64) << 8 | bytes[1] as u64
+        },
+        127 => {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            bytes.iter().fold(0, |len, &byte| len << 8 | byte as u64)
+        },
+        len => len as u64
+    };
+
+    if opcode & 0x8 != 0 && (len > 125 || ! fin) {
+        return Err(Error::new(ErrorKind::InvalidData, "invalid control frame"));
+    }
+    if len > MAX_MESSAGE as u64 {
+        return Err(Error::new(ErrorKind::InvalidData, "frame is too large"));
+    }
+
+    let mut mask = [0; 4];
+    reader.read_exact(&mut mask)?;
+
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok(Frame {
+        fin: fin,
+        opcode: opcode,
+        payload: payload,
+    })
+}
+
+/// Write an unfragmented frame, which servers send unmasked
+pub fn write_frame<W: Write>(writer: &mut W, opcode: u8, payload: &[u8]) -> Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    if payload.len() < 126 {
+        frame.push(payload.len() as u8);
+    } else if payload.len() <= 0xFFFF {
+        frame.push(126);
+        frame.push((payload.len() >> 8) as u8);
+        frame.push(payload.len() as u8);
+    } else {
+        frame.push(127);
+        for i in (0..8).rev() {
+            frame.push((payload.len() as u64 >> (i * 8)) as u8);
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+fn write_message<W: Write>(writer: &mut W, message: &Message) -> Result<()> {
+    match *message {
+        Message::Text(ref text) => write_frame(writer, OP_TEXT, text.as_bytes()),
+        Message::Binary(ref data) => write_frame(writer, OP_BINARY, data),
+    }
+}
+
+static NEXT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Run a connection after the handshake until either side closes it
+pub fn serve(reader: &mut BufReader<&mut NetworkStream>, handler: &Handler, timeout: Duration) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let client = Client {
+        id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
+        sender: sender,
+    };
+
+    handler.open(&client);
+    let result = serve_client(reader, handler, &client, &receiver, timeout);
+    handler.close(&client);
+
+    result
+}
+
+fn serve_client(reader: &mut BufReader<&mut NetworkStream>, handler: &Handler, client: &Client,
+                receiver: &mpsc::Receiver<Message>, timeout: Duration) -> Result<()> {
+    let mut fragments: Option<(u8, Vec<u8>)> = None;
+    let mut idle = Duration::from_secs(0);
+    let poll = Duration::from_millis(POLL_INTERVAL);
+
+    loop {
+        while let Ok(message) = receiver.try_recv() {
+            write_message(reader.get_mut(), &message)?;
+        }
+
+        // Wait briefly for data, so that queued messages are sent while the client is quiet
+        reader.get_ref().set_read_timeout(Some(poll))?;
+        let ready = match reader.fill_buf() {
+            Ok(data) => if data.is_empty() {
+                return Ok(());
+            } else {
+                true
+            },
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => false,
+            Err(err) => return Err(err)
+        };
+
+        if ! ready {
+            idle += poll;
+            if idle >= timeout {
+                write_frame(reader.get_mut(), OP_CLOSE, &[0x03, 0xE9])?;
+                return Ok(());
+            }
+            continue;
+        }
+        idle = Duration::from_secs(0);
+
+        reader.get_ref().set_read_timeout(Some(timeout))?;
+        let frame = read_frame(reader)?;
+        match frame.opcode {
+            OP_TEXT | OP_BINARY if fragments.is_none() => fragments = Some((frame.opcode, frame.payload)),
+            OP_CONTINUATION if fragments.is_some() => {
+                if let Some((_, ref mut data)) = fragments {
+                    if data.len() + frame.payload.len() > MAX_MESSAGE {
+                        return Err(Error::new(ErrorKind::InvalidData, "message is too large"));
+                    }
+                    data.extend_from_slice(&frame.payload);
+                }
+            },
+            OP_PING => {
+                write_frame(reader.get_mut(), OP_PONG, &frame.payload)?;
+                continue;
+            },
+            OP_PONG => continue,
+            OP_CLOSE => {
+                // Echo the status code, as required to complete the closing handshake
+                let status: &[u8] = if frame.payload.len() >= 2 { &frame.payload[..2] } else { &[] };
+                write_frame(reader.get_mut(), OP_CLOSE, status)?;
+                return Ok(());
+            },
+            _ => return Err(Error::new(ErrorKind::InvalidData, "unexpected frame"))
+        }
+
+        if frame.fin {
+            if let Some((opcode, data)) = fragments.take() {
+                let message = if opcode == OP_TEXT {
+                    match String::from_utf8(data) {
+                        Ok(text) => Message::Text(text),
+                        Err(_) => return Err(Error::new(ErrorKind::InvalidData, "text message is not UTF-8"))
+                    }
+                } else {
+                    Message::Binary(data)
+                };
+                handler.message(client, message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{accept_key, read_frame, write_frame, OP_TEXT};
+
+    #[test]
+    fn accept_key_test() {
+        // Example from RFC 6455 section 1.3
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn frame_test() {
+        // Examples from RFC 6455 section 5.7
+        let masked = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+        let frame = read_frame(&mut &masked[..]).unwrap();
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, OP_TEXT);
+        assert_eq!(frame.payload, b"Hello");
+
+        let unmasked = [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f];
+        assert!(read_frame(&mut &unmasked[..]).is_err());
+
+        let mut output = Vec::new();
+        write_frame(&mut output, OP_TEXT, b"Hello").unwrap();
+        assert_eq!(output, unmasked);
+
+        let mut output = Vec::new();
+        write_frame(&mut output, OP_TEXT, &[0; 256]).unwrap();
+        assert_eq!(&output[..4], &[0x81, 126, 0x01, 0x00]);
+    }
+}
@@ -0,0 +1,312 @@
+use netutils_core::MacAddr;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An address of an interface, with the prefix length of its network
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Address {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    /// Parse `addr/prefix`, where an address without a prefix is a single host
+    fn from_str(value: &str) -> Result<Address, String> {
+        let mut parts = value.splitn(2, '/');
+        let addr = IpAddr::from_str(parts.next().unwrap_or(""))
+            .map_err(|_| format!("invalid address '{}'", value))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match parts.next() {
+            Some(prefix) => match prefix.parse::<u8>() {
+                Ok(prefix) if prefix <= max => prefix,
+                _ => return Err(format!("invalid prefix length '{}'", prefix))
+            },
+            None => max
+        };
+        Ok(Address {
+            addr: addr,
+            prefix: prefix,
+        })
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Flags {
+    pub up: bool,
+    /// The link is connected, such as a cable being plugged in
+    pub running: bool,
+    pub loopback: bool,
+    pub broadcast: bool,
+    pub point_to_point: bool,
+    pub multicast: bool,
+}
+
+impl Flags {
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.up { names.push("UP"); }
+        if self.broadcast { names.push("BROADCAST"); }
+        if self.loopback { names.push("LOOPBACK"); }
+        if self.point_to_point { names.push("POINTOPOINT"); }
+        if self.running { names.push("RUNNING"); }
+        if self.multicast { names.push("MULTICAST"); }
+        names
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Interface {
+    pub name: String,
+    pub flags: Flags,
+    /// None for interfaces without a hardware address, such as tunnels
+    pub mac: Option<MacAddr>,
+    /// None if the platform does not report it
+    pub mtu: Option<u32>,
+    pub addrs: Vec<Address>,
+}
+
+pub use self::sys::{add_addr, del_addr, list, set_up};
+
+/// Interfaces are read and changed over rtnetlink
+#[cfg(not(target_os = "redox"))]
+mod sys {
+    use libc;
+    use netutils_core::MacAddr;
+    use netutils_core::netlink::{self, read_u32, Request, Socket, NLM_F_CREATE, NLM_F_EXCL,
+                            RTM_DELADDR, RTM_GETADDR, RTM_GETLINK, RTM_NEWADDR, RTM_NEWLINK};
+    use std::io::Result;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::{Address, Flags, Interface};
+
+    /// Size of struct ifinfomsg
+    const IFINFOMSG_LEN: usize = 16;
+    /// Size of struct ifaddrmsg
+    const IFADDRMSG_LEN: usize = 8;
+
+    const IFLA_ADDRESS: u16 = 1;
+    const IFLA_IFNAME: u16 = 3;
+    const IFLA_MTU: u16 = 4;
+
+    const IFA_ADDRESS: u16 = 1;
+    const IFA_LOCAL: u16 = 2;
+
+    const IFF_UP: u32 = 0x1;
+    const IFF_BROADCAST: u32 = 0x2;
+    const IFF_LOOPBACK: u32 = 0x8;
+    const IFF_POINTOPOINT: u32 = 0x10;
+    const IFF_RUNNING: u32 = 0x40;
+    const IFF_MULTICAST: u32 = 0x1000;
+
+    fn ifinfomsg(index: u32, flags: u32, change: u32) -> [u8; IFINFOMSG_LEN] {
+        [0, 0, 0, 0,
+         index as u8, (index >> 8) as u8, (index >> 16) as u8, (index >> 24) as u8,
+         flags as u8, (flags >> 8) as u8, (flags >> 16) as u8, (flags >> 24) as u8,
+         change as u8, (change >> 8) as u8, (change >> 16) as u8, (change >> 24) as u8]
+    }
+
+    fn ifaddrmsg(family: u8, prefix: u8, index: u32) -> [u8; IFADDRMSG_LEN] {
+        [family, prefix, 0, 0,
+         index as u8, (index >> 8) as u8, (index >> 16) as u8, (index >> 24) as u8]
+    }
+
+    fn parse_addr(data: &[u8]) -> Option<IpAddr> {
+        match data.len() {
+            4 => Some(IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3]))),
+            16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                Some(IpAddr::V6(Ipv6Addr::from(octets)))
+            },
+            _ => None
+        }
+    }
+
+    fn parse_flags(flags: u32) -> Flags {
+        Flags {
+            up: flags & IFF_UP != 0,
+            running: flags & IFF_RUNNING != 0,
+            loopback: flags & IFF_LOOPBACK != 0,
+            broadcast: flags & IFF_BROADCAST != 0,
+            point_to_point: flags & IFF_POINTOPOINT != 0,
+            multicast: flags & IFF_MULTICAST != 0,
+        }
+    }
+
+    pub fn list() -> Result<Vec<Interface>> {
+        let mut socket = Socket::open()?;
+
+        let mut interfaces = Vec::new();
+        for message in socket.dump(RTM_GETLINK, &ifinfomsg(0, 0, 0))? {
+            let header = match message.header(IFINFOMSG_LEN) {
+                Some(header) => header,
+                None => continue
+            };
+            let index = read_u32(&header[4..]);
+            let name = match message.attr(IFINFOMSG_LEN, IFLA_IFNAME) {
+                Some(name) => String::from_utf8_lossy(name).trim_right_matches('\0').to_string(),
+                None => continue
+            };
+            let mac = message.attr(IFINFOMSG_LEN, IFLA_ADDRESS).and_then(|data| if data.len() == 6 {
+                let mut mac = MacAddr::default();
+                mac.bytes.copy_from_slice(data);
+                Some(mac)
+            } else {
+                None
+            });
+            let mtu = message.attr(IFINFOMSG_LEN, IFLA_MTU).and_then(|data| if data.len() == 4 {
+                Some(read_u32(data))
+            } else {
+                None
+            });
+
+            interfaces.push((index, Interface {
+                name: name,
+                flags: parse_flags(read_u32(&header[8..])),
+                mac: mac,
+                mtu: mtu,
+                addrs: Vec::new(),
+            }));
+        }
+
+        for message in socket.dump(RTM_GETADDR, &ifaddrmsg(0, 0, 0))? {
+            let header = match message.header(IFADDRMSG_LEN) {
+                Some(header) => header,
+                None => continue
+            };
+            let prefix = header[1];
+            let index = read_u32(&header[4..]);
+            // On point to point links IFA_ADDRESS is the remote end, and IFA_LOCAL the address
+            let addr = match message.attr(IFADDRMSG_LEN, IFA_LOCAL).or_else(|| message.attr(IFADDRMSG_LEN, IFA_ADDRESS))
+                                    .and_then(parse_addr) {
+                Some(addr) => addr,
+                None => continue
+            };
+            if let Some(&mut (_, ref mut interface)) = interfaces.iter_mut().find(|entry| entry.0 == index) {
+                interface.addrs.push(Address {
+                    addr: addr,
+                    prefix: prefix,
+                });
+            }
+        }
+
+        Ok(interfaces.into_iter().map(|entry| entry.1).collect())
+    }
+
+    pub fn set_up(name: &str, up: bool) -> Result<()> {
+        let index = netlink::if_index(name)?;
+        let request = Request::new(RTM_NEWLINK, 0, &ifinfomsg(index, if up { IFF_UP } else { 0 }, IFF_UP));
+        Socket::open()?.execute(request)
+    }
+
+    fn addr_request(kind: u16, flags: u16, name: &str, addr: &Address) -> Result<Request> {
+        let index = netlink::if_index(name)?;
+        let (family, bytes) = match addr.addr {
+            IpAddr::V4(ip) => (libc::AF_INET as u8, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (libc::AF_INET6 as u8, ip.octets().to_vec()),
+        };
+        Ok(Request::new(kind, flags, &ifaddrmsg(family, addr.prefix, index))
+            .attr(IFA_LOCAL, &bytes)
+            .attr(IFA_ADDRESS, &bytes))
+    }
+
+    pub fn add_addr(name: &str, addr: &Address) -> Result<()> {
+        let request = addr_request(RTM_NEWADDR, NLM_F_CREATE | NLM_F_EXCL, name, addr)?;
+        Socket::open()?.execute(request)
+    }
+
+    pub fn del_addr(name: &str, addr: &Address) -> Result<()> {
+        let request = addr_request(RTM_DELADDR, 0, name, addr)?;
+        Socket::open()?.execute(request)
+    }
+}
+
+/// Interfaces are configured through the netcfg scheme of the network stack, which lists
+/// the interfaces that are up, and gives each of them one address
+#[cfg(target_os = "redox")]
+mod sys {
+    use netutils_core::MacAddr;
+    use std::fs::{self, File};
+    use std::io::{Error, ErrorKind, Read, Result, Write};
+
+    use super::{Address, Flags, Interface};
+
+    fn read_cfg(path: &str) -> Result<String> {
+        let mut value = String::new();
+        File::open(format!("netcfg:{}", path))?.read_to_string(&mut value)?;
+        Ok(value)
+    }
+
+    fn unsupported(what: &str) -> Error {
+        Error::new(ErrorKind::Other, format!("{} is not supported on Redox", what))
+    }
+
+    pub fn list() -> Result<Vec<Interface>> {
+        let mut interfaces = Vec::new();
+        for entry in fs::read_dir("netcfg:ifaces")? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            let mac = MacAddr::from_str(read_cfg(&format!("ifaces/{}/mac", name))?.trim());
+            let addrs = read_cfg(&format!("ifaces/{}/addr/list", name))?.lines()
+                .filter_map(|line| line.trim().parse::<Address>().ok())
+                .collect();
+            interfaces.push(Interface {
+                name: name,
+                flags: Flags {
+                    up: true,
+                    running: true,
+                    ..Flags::default()
+                },
+                mac: if mac == MacAddr::default() { None } else { Some(mac) },
+                mtu: None,
+                addrs: addrs,
+            });
+        }
+        Ok(interfaces)
+    }
+
+    pub fn set_up(_name: &str, _up: bool) -> Result<()> {
+        Err(unsupported("bringing interfaces up or down"))
+    }
+
+    /// Replaces the address of the interface
+    pub fn add_addr(name: &str, addr: &Address) -> Result<()> {
+        let mut file = File::create(format!("netcfg:ifaces/{}/addr/set", name))?;
+        file.write(addr.to_string().as_bytes())?;
+        file.sync_all()
+    }
+
+    pub fn del_addr(_name: &str, _addr: &Address) -> Result<()> {
+        Err(unsupported("removing addresses"))
+    }
+}
+
+pub fn format_mac(mac: &MacAddr) -> String {
+    let parts: Vec<String> = mac.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    parts.join(":")
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use super::Address;
+
+    #[test]
+    fn address_test() {
+        let addr = "192.168.1.2/24".parse::<Address>().unwrap();
+        assert_eq!(addr.addr, "192.168.1.2".parse::<IpAddr>().unwrap());
+        assert_eq!(addr.prefix, 24);
+        assert_eq!("fe80::1".parse::<Address>().unwrap().prefix, 128);
+        assert_eq!(addr.to_string(), "192.168.1.2/24");
+        assert!("192.168.1.2/33".parse::<Address>().is_err());
+        assert!("router/24".parse::<Address>().is_err());
+    }
+}
@@ -0,0 +1,127 @@
+extern crate netutils_core;
+#[cfg(not(target_os = "redox"))]
+extern crate libc;
+
+use netutils_core::args::Parser;
+use netutils_core::error::{NetError, Result};
+
+use iface::{format_mac, Address, Interface};
+
+mod iface;
+
+static IFCONFIG_MAN: &'static str = /* @MANSTART{ifconfig} */ r#"
+NAME
+    ifconfig - show and configure network interfaces
+
+SYNOPSIS
+    ifconfig [-a] [interface]
+    ifconfig interface up | down
+    ifconfig interface add address[/prefix]
+    ifconfig interface del address[/prefix]
+    ifconfig [-h | --help]
+
+DESCRIPTION
+    Without arguments, ifconfig shows the interfaces that are up with their
+    flags, MTU, hardware address and addresses.
+
+    An address without a prefix length is for a single host, /32 for IPv4
+    and /128 for IPv6.
+
+OPTIONS
+    -a
+        Show interfaces that are down as well.
+
+    up
+    down
+        Bring the interface up or down.
+
+    add address[/prefix]
+        Add an address to the interface.
+
+    del address[/prefix]
+        Remove an address from the interface.
+
+    -h
+    --help
+        Print this manual page.
+
+NOTES
+    Changing interfaces requires root. On Redox each interface has one
+    address, which add replaces, and interfaces can't be brought down.
+"#; /* @MANEND */
+
+fn show(interface: &Interface) {
+    print!("{}: flags=<{}>", interface.name, interface.flags.names().join(","));
+    if let Some(mtu) = interface.mtu {
+        print!("  mtu {}", mtu);
+    }
+    println!();
+    for addr in interface.addrs.iter() {
+        println!("        {} {}", if addr.addr.is_ipv4() { "inet" } else { "inet6" }, addr);
+    }
+    if let Some(ref mac) = interface.mac {
+        println!("        ether {}", format_mac(mac));
+    }
+    println!();
+}
+
+fn parse_address(value: &str) -> Result<Address> {
+    value.parse().map_err(NetError::Usage)
+}
+
+fn list() -> Result<Vec<Interface>> {
+    iface::list().map_err(|err| NetError::from(err).context("failed to list interfaces"))
+}
+
+fn run() -> Result<()> {
+    let parsed = Parser::new("ifconfig")
+        .synopsis("[-a] [interface]")
+        .synopsis("interface up | down")
+        .synopsis("interface add address[/prefix]")
+        .synopsis("interface del address[/prefix]")
+        .man(IFCONFIG_MAN)
+        .flag("a", "", "Show interfaces that are down too")
+        .parse_env();
+    let all = parsed.flag("a");
+    let args = parsed.free.clone();
+
+    let mut args = args.into_iter();
+    let name = match args.next() {
+        Some(name) => name,
+        None => {
+            for interface in list()?.iter().filter(|interface| all || interface.flags.up) {
+                show(interface);
+            }
+            return Ok(());
+        }
+    };
+
+    let command = match args.next() {
+        Some(command) => command,
+        None => {
+            return match list()?.iter().find(|interface| interface.name == name) {
+                Some(interface) => Ok(show(interface)),
+                None => Err(NetError::other(format!("no interface named {}", name)))
+            };
+        }
+    };
+    let value = args.next();
+    if args.next().is_some() {
+        parsed.usage();
+    }
+
+    let result = match (command.as_ref(), value) {
+        ("up", None) => iface::set_up(&name, true),
+        ("down", None) => iface::set_up(&name, false),
+        ("add", Some(value)) => iface::add_addr(&name, &parse_address(&value)?),
+        ("del", Some(value)) => iface::del_addr(&name, &parse_address(&value)?),
+        _ => parsed.usage()
+    };
+    result.map_err(|err| NetError::from(err).context(&format!("failed to configure {}", name)))
+}
+
+fn main() {
+    if let Err(err) = run() {
+        err.exit("ifconfig");
+    }
+}
@@ -0,0 +1,252 @@
+use termion::{color, style};
+
+/// Messages kept for each buffer, to show again when switching to it
+const SCROLLBACK: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Chat { user: String, message: String },
+    Action { user: String, message: String },
+    Info { message: String },
+    Joined { user: String, message: String },
+    Parted { user: String, message: String },
+    Quit { user: String, message: String },
+}
+
+impl Message {
+    /// The message as a line of the buffer `name`
+    pub fn format(&self, name: &str) -> String {
+        let reason = |message: &str| if message.is_empty() { String::new() } else { format!(" ({})", message) };
+        match *self {
+            Message::Chat { ref user, ref message } => format!("{}{}{}: {}{}", style::Bold, color::Fg(color::Green), user, style::Reset, message),
+            Message::Action { ref user, ref message } => format!("{}* {} {}{}", color::Fg(color::Magenta), user, message, style::Reset),
+            Message::Info { ref message } => format!("{}{}{}", style::Bold, message, style::Reset),
+            Message::Joined { ref user, ref message } => format!("{}{} joined {}{}{}", color::Fg(color::Blue), user, name, reason(message), style::Reset),
+            Message::Parted { ref user, ref message } => format!("{}{} parted {}{}{}", color::Fg(color::Blue), user, name, reason(message), style::Reset),
+            Message::Quit { ref user, ref message } => format!("{}{} quit{}{}", color::Fg(color::Blue), user, reason(message), style::Reset),
+        }
+    }
+
+    /// The message without colors, for logs
+    pub fn text(&self, name: &str) -> String {
+        let reason = |message: &str| if message.is_empty() { String::new() } else { format!(" ({})", message) };
+        match *self {
+            Message::Chat { ref user, ref message } => format!("<{}> {}", user, message),
+            Message::Action { ref user, ref message } => format!("* {} {}", user, message),
+            Message::Info { ref message } => message.clone(),
+            Message::Joined { ref user, ref message } => format!("{} joined {}{}", user, name, reason(message)),
+            Message::Parted { ref user, ref message } => format!("{} parted {}{}", user, name, reason(message)),
+            Message::Quit { ref user, ref message } => format!("{} quit{}", user, reason(message)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    /// Messages from the server that are not for a channel or user
+    Server,
+    Channel,
+    /// A conversation with one user
+    Private,
+}
+
+/// A channel, private conversation or the server messages, with the messages received while it
+/// wasn't focused on
+pub struct Buffer {
+    pub name: String,
+    pub kind: Kind,
+    pub messages: Vec<Message>,
+    pub unread: u32,
+    pub users: Vec<String>,
+    /// Has the nickname been mentioned since last look at the buffer?
+    pub mentioned: bool,
+}
+
+/// Nicknames in NAMES replies are prefixed with the user's channel modes
+fn strip_modes(user: &str) -> &str {
+    user.trim_left_matches(|c| "~&@%+".contains(c))
+}
+
+impl Buffer {
+    fn new(name: &str, kind: Kind) -> Buffer {
+        Buffer {
+            name: name.to_string(),
+            kind: kind,
+            messages: Vec::new(),
+            unread: 0,
+            users: Vec::new(),
+            mentioned: false,
+        }
+    }
+
+    /// The last `count` messages, formatted for display
+    pub fn lines(&self, count: usize) -> Vec<String> {
+        let start = self.messages.len().saturating_sub(count);
+        self.messages[start..].iter().map(|message| message.format(&self.name)).collect()
+    }
+
+    pub fn users(&self) -> String {
+        self.users.join(", ")
+    }
+
+    pub fn has_user(&self, username: &str) -> bool {
+        self.users.iter().any(|user| user.eq_ignore_ascii_case(username))
+    }
+
+    /// Pushes a new user to the channel users list, unless that user is already on the list.
+    pub fn push_user(&mut self, username: &str) {
+        let username = strip_modes(username);
+        if !username.is_empty() && !self.has_user(username) {
+            self.users.push(username.to_string());
+        }
+    }
+
+    /// Removes the user from the users list, do nothing if the user isn't on the list.
+    pub fn remove_user(&mut self, username: &str) {
+        self.users.retain(|user| !user.eq_ignore_ascii_case(username));
+    }
+}
+
+/// The open buffers, the first one is the server's and is never closed
+pub struct Buffers {
+    list: Vec<Buffer>,
+    current: usize,
+}
+
+impl Buffers {
+    pub fn new(server: &str) -> Buffers {
+        Buffers {
+            list: vec![Buffer::new(server, Kind::Server)],
+            current: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn iter(&self) -> ::std::slice::Iter<Buffer> {
+        self.list.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> ::std::slice::IterMut<Buffer> {
+        self.list.iter_mut()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Buffer> {
+        self.list.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Buffer> {
+        self.list.get_mut(index)
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Channel and nick names are case insensitive
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.list.iter().position(|buffer| buffer.kind != Kind::Server && buffer.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The buffer for `name`, which is added after the others if it is not open
+    pub fn open(&mut self, name: &str, kind: Kind) -> usize {
+        match self.find(name) {
+            Some(index) => index,
+            None => {
+                self.list.push(Buffer::new(name, kind));
+                self.list.len() - 1
+            }
+        }
+    }
+
+    /// Close a buffer, focusing the one before it if it was the current one
+    pub fn close(&mut self, index: usize) -> bool {
+        if index == 0 || index >= self.list.len() {
+            return false;
+        }
+        self.list.remove(index);
+        if self.current >= index {
+            self.current -= 1;
+        }
+        true
+    }
+
+    /// Focus a buffer, which marks its messages as read
+    pub fn switch(&mut self, index: usize) -> bool {
+        match self.list.get_mut(index) {
+            Some(buffer) => {
+                buffer.unread = 0;
+                buffer.mentioned = false;
+            },
+            None => return false
+        }
+        self.current = index;
+        true
+    }
+
+    /// Add a message to a buffer, returning true if it is the current one and should be shown
+    pub fn push(&mut self, index: usize, message: Message, mention: bool) -> bool {
+        let current = index == self.current;
+        if let Some(buffer) = self.list.get_mut(index) {
+            if buffer.messages.len() >= SCROLLBACK {
+                buffer.messages.remove(0);
+            }
+            buffer.messages.push(message);
+            if !current {
+                buffer.unread += 1;
+                buffer.mentioned |= mention;
+            }
+        }
+        current
+    }
+
+    /// The status line: the nick and the buffers by number, with the current one in brackets and
+    /// the unread count of the others, marked with ! if they mention the nick
+    pub fn status(&self, nick: &str) -> String {
+        let mut status = format!(" {} |", nick);
+        for (i, buffer) in self.list.iter().enumerate() {
+            if i == self.current {
+                status.push_str(&format!(" [{}:{}]", i + 1, buffer.name));
+            } else if buffer.unread > 0 {
+                status.push_str(&format!(" {}:{}({}{})", i + 1, buffer.name, buffer.unread, if buffer.mentioned { "!" } else { "" }));
+            } else {
+                status.push_str(&format!(" {}:{}", i + 1, buffer.name));
+            }
+        }
+        status
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Buffers, Kind, Message};
+
+    fn info(message: &str) -> Message {
+        Message::Info { message: message.to_string() }
+    }
+
+    #[test]
+    fn buffers_test() {
+        let mut buffers = Buffers::new("irc.example.org");
+        let redox = buffers.open("#redox", Kind::Channel);
+        let jackpot = buffers.open("jackpot51", Kind::Private);
+        assert_eq!(buffers.open("#Redox", Kind::Channel), redox);
+
+        assert!(buffers.push(0, info("motd"), false));
+        assert!(!buffers.push(redox, info("hello"), false));
+        assert!(!buffers.push(jackpot, info("hi"), true));
+        assert_eq!(buffers.status("redox"), " redox | [1:irc.example.org] 2:#redox(1) 3:jackpot51(1!)");
+
+        assert!(buffers.switch(jackpot));
+        assert!(!buffers.switch(3));
+        assert_eq!(buffers.status("redox"), " redox | 1:irc.example.org 2:#redox(1) [3:jackpot51]");
+
+        assert!(!buffers.close(0));
+        assert!(buffers.close(redox));
+        assert_eq!(buffers.current(), 1);
+        assert_eq!(buffers.find("jackpot51"), Some(1));
+        assert_eq!(buffers.find("irc.example.org"), None);
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Result};
+
+use sasl::Mechanism;
+
+/// Connection and account settings, from a configuration file and then the command line
+pub struct Config {
+    pub server: Option<String>,
+    pub port: Option<u16>,
+    pub tls: bool,
+    pub nick: Option<String>,
+    pub sasl: Option<Mechanism>,
+    /// Account to authenticate as, the nick if not set
+    pub account: Option<String>,
+    pub password: Option<String>,
+    /// Client certificate and key presented over TLS, for SASL EXTERNAL
+    pub cert: Option<String>,
+    pub key: Option<String>,
+    /// Identify with NickServ when SASL is not available
+    pub nickserv: bool,
+    /// Directory for logs of each channel, no logs are written if not set
+    pub log_dir: Option<String>,
+    /// Reconnect and join the channels again when the connection is lost
+    pub reconnect: bool,
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" => Some(false),
+        _ => None
+    }
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            server: None,
+            port: None,
+            tls: false,
+            nick: None,
+            sasl: None,
+            account: None,
+            password: None,
+            cert: None,
+            key: None,
+            nickserv: true,
+            log_dir: None,
+            reconnect: true,
+        }
+    }
+
+    pub fn load(&mut self, path: &str) -> Result<()> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        self.parse(&text).map_err(|err| Error::new(err.kind(), format!("{}:{}", path, err)))
+    }
+
+    /// Parse `key = value` lines:
+    ///
+    /// ```text
+    /// server = irc.libera.chat
+    /// tls = yes
+    /// nick = redox
+    /// sasl = plain
+    /// password = hunter2
+    /// log_dir = /home/user/irclogs
+    /// ```
+    pub fn parse(&mut self, text: &str) -> Result<()> {
+        for (i, line) in text.lines().enumerate() {
+            let invalid = |message: String| Error::new(ErrorKind::InvalidData, format!("{}: {}", i + 1, message));
+
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let value = match parts.next() {
+                Some(value) => value.trim().to_string(),
+                None => return Err(invalid(format!("expected '{} = value'", name)))
+            };
+
+            match name {
+                "server" => self.server = Some(value),
+                "port" => self.port = Some(value.parse().map_err(|_| invalid(format!("invalid port '{}'", value)))?),
+                "tls" | "ssl" => self.tls = parse_bool(&value).ok_or_else(|| invalid(format!("invalid boolean '{}'", value)))?,
+                "nick" => self.nick = Some(value),
+                "sasl" => self.sasl = match value.as_str() {
+                    "none" => None,
+                    _ => Some(Mechanism::parse(&value).ok_or_else(|| invalid(format!("unknown SASL mechanism '{}'", value)))?)
+                },
+                "account" => self.account = Some(value),
+                "password" => self.password = Some(value),
+                "cert" => self.cert = Some(value),
+                "key" => self.key = Some(value),
+                "nickserv" => self.nickserv = parse_bool(&value).ok_or_else(|| invalid(format!("invalid boolean '{}'", value)))?,
+                "log_dir" => self.log_dir = Some(value),
+                "reconnect" => self.reconnect = parse_bool(&value).ok_or_else(|| invalid(format!("invalid boolean '{}'", value)))?,
+                _ => return Err(invalid(format!("unknown setting '{}'", name)))
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Result, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// UTC date and time of a unix time, as "2000-10-10" and "13:55:36"
+fn format_time(secs: u64) -> (String, String) {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (format!("{:04}-{:02}-{:02}", year, month, day),
+     format!("{:02}:{:02}:{:02}", rem / 3600, rem / 60 % 60, rem % 60))
+}
+
+/// Channel names may contain characters that are not allowed in file names
+fn file_name(name: &str) -> String {
+    name.to_lowercase().chars().map(|c| if c == '/' || c == '\\' || c == ':' { '_' } else { c }).collect()
+}
+
+/// Logs of the messages of each buffer, in a file per buffer and day named
+/// `DIR/SERVER/NAME.YYYY-MM-DD.log`, with the UTC time of each line
+pub struct Logs {
+    dir: PathBuf,
+    /// Open files by buffer name, with the day they are for
+    files: Vec<(String, String, File)>,
+}
+
+impl Logs {
+    pub fn new(dir: &str, server: &str) -> Logs {
+        Logs {
+            dir: PathBuf::from(dir).join(file_name(server)),
+            files: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, name: &str, line: &str) -> Result<()> {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+        let (date, time) = format_time(secs);
+        let name = file_name(name);
+
+        // Files are reopened when the day changes
+        let index = match self.files.iter().position(|file| file.0 == name) {
+            Some(index) if self.files[index].1 == date => index,
+            other => {
+                if let Some(index) = other {
+                    self.files.remove(index);
+                }
+                fs::create_dir_all(&self.dir)?;
+                let path = self.dir.join(format!("{}.{}.log", name, date));
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                self.files.push((name, date, file));
+                self.files.len() - 1
+            }
+        };
+
+        writeln!(self.files[index].2, "[{}] {}", time, line)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{file_name, format_time};
+
+    #[test]
+    fn format_time_test() {
+        assert_eq!(format_time(0), ("1970-01-01".to_string(), "00:00:00".to_string()));
+        assert_eq!(format_time(971186136), ("2000-10-10".to_string(), "13:55:36".to_string()));
+        assert_eq!(format_time(951782400), ("2000-02-29".to_string(), "00:00:00".to_string()));
+        assert_eq!(file_name("#Redox/Dev"), "#redox_dev");
+    }
+}
@@ -1,561 +1,787 @@
+extern crate base64;
+extern crate netutils_core;
+extern crate rustls;
 extern crate termion;
 
-use termion::{color, style};
+use netutils_core::args::Parser;
+use netutils_core::error::NetError;
+use netutils_core::resolve;
+use netutils_core::tls;
+use termion::event::Key;
+use termion::input::TermRead;
 
-use std::env;
-use std::io::{stdin, Read, Write, Result};
-use std::net::{TcpStream, ToSocketAddrs};
-use std::str;
+use std::{cmp, mem, panic};
+use std::io::{self, BufRead};
 use std::sync::{Arc, Mutex};
 use std::thread;
-
-use std::cell::UnsafeCell;
-
-/// Redox domain socket
-pub struct Socket {
-    file: UnsafeCell<TcpStream>
+use std::time::{Duration, Instant};
+
+use buffer::{Buffers, Kind, Message};
+use config::Config;
+use log::Logs;
+use sasl::{Auth, Mechanism};
+use socket::Socket;
+use ui::Screen;
+
+mod buffer;
+mod config;
+mod log;
+mod sasl;
+mod socket;
+mod ui;
+
+static DEFAULT_SERVER: &'static str = "irc.mozilla.org";
+const DEFAULT_PORT: u16 = 6667;
+const DEFAULT_TLS_PORT: u16 = 6697;
+
+/// Seconds of silence from the server after which it is pinged, and after which the connection
+/// is lost
+const PING_INTERVAL: u64 = 120;
+const PING_TIMEOUT: u64 = 240;
+
+/// Seconds to wait before reconnecting, doubling after each failed attempt
+const MIN_BACKOFF: u64 = 5;
+const MAX_BACKOFF: u64 = 300;
+
+/// Client TLS settings, with the certificate presented to the server if there is one
+fn tls_config(config: &Config) -> io::Result<Arc<rustls::ClientConfig>> {
+    let mut options = tls::ClientOptions::new();
+    options.cert = config.cert.clone();
+    options.key = config.key.clone();
+    tls::client_config(&options)
 }
 
-unsafe impl Send for Socket {}
-unsafe impl Sync for Socket {}
-
-impl Socket {
-    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Socket> {
-        let file = try!(TcpStream::connect(addr));
-        Ok(Socket {
-            file: UnsafeCell::new(file)
-        })
-    }
-
-    pub fn receive(&self, buf: &mut [u8]) -> Result<usize> {
-        unsafe { (*self.file.get()).read(buf) }
+/// The parameters of a message, where the last one may contain spaces after a ':'
+fn params<'a, I: Iterator<Item=&'a str>>(args: I) -> Vec<String> {
+    let mut params = Vec::new();
+    let mut trailing: Option<Vec<&str>> = None;
+    for arg in args {
+        match trailing {
+            Some(ref mut trailing) => trailing.push(arg),
+            None if arg.starts_with(':') => trailing = Some(vec![&arg[1..]]),
+            None => if !arg.is_empty() {
+                params.push(arg.to_string());
+            }
+        }
     }
-
-    pub fn send(&self, buf: &[u8]) -> Result<usize> {
-        unsafe { (*self.file.get()).write(buf) }
+    if let Some(trailing) = trailing {
+        params.push(trailing.join(" "));
     }
+    params
 }
 
-#[derive(Debug, Clone)]
-pub enum Message {
-    Chat { user: String, message: String },
-    Info { message: String },
-    Joined { user: String, message: String },
-    Parted { user: String, message: String },
-    Quit { user: String, message: String },
-}
-
-/// Channel struct used to store currently open channels,
-/// and a buffer of messages received when the channel
-/// wasn't focused on
-#[derive(Clone)]
-pub struct Channel {
-    pub name: String,
-    pub buffer: Vec<Message>,
-    pub unread: u32,
-    pub users: Vec<String>,
-    /// Has the nickname been mentioned since last look at the channel?
-    pub mentioned: bool,
+/// The buffers and the screen, shared by the input thread and the thread reading from the server
+struct State {
+    nick: String,
+    buffers: Buffers,
+    screen: Screen,
+    /// The connection to the server, None while reconnecting
+    socket: Option<Arc<Socket>>,
+    /// Has the server accepted the nick of this connection?
+    registered: bool,
+    /// Has the user quit, so that the connection is not reopened?
+    quitting: bool,
+    logs: Option<Logs>,
 }
 
-impl Channel {
-    fn new(name: String) -> Self {
-        Channel {
-            name: name,
-            buffer: vec![],
-            unread: 0,
-            users: vec![],
-            mentioned: false,
+impl State {
+    /// Send a line to the server, returning false if there is no connection
+    fn send(&self, line: &str) -> bool {
+        match self.socket {
+            // A connection that fails is noticed, and reported, by the thread reading from it
+            Some(ref socket) => socket.send(format!("{}\r\n", line).as_bytes()).is_ok(),
+            None => false
         }
     }
 
-    fn get_name(&self) -> String {
-        self.name.clone()
+    fn draw(&mut self) {
+        let status = self.buffers.status(&self.nick);
+        self.screen.draw(&status);
     }
 
-    /*fn push(&mut self, arg: &str) {
-        self.buffer.push_str(arg);
-    }*/
-
-    /// Format the buffer into text, print it, clear the buffer, reset unread counter.
-    fn dump_buf(&mut self) {
-        for message in self.buffer.clone() {
-            match message {
-                Message::Chat{user, message} => println!("{}{}{}: {}{}", style::Bold, color::Fg(color::Green), user, message, style::Reset),
-                Message::Info{message} => println!("info: {}", message),
-                Message::Joined{user, message} => {
-                    //print!("\x1B[1m{} joined {}\x1B[21m", user, self.get_name());
-                    print!("{}{} joined {}{}", color::Fg(color::Blue), user, self.get_name(), style::Reset);
-                    if message == "".to_string() {
-                        print!("\n");
-                    } else {
-                        println!(" ({})", message);
-                    }
-                },
-                Message::Parted{user, message} => {
-                    print!("{}{} parted {}{}", color::Fg(color::Blue), user, self.get_name(), style::Reset);
-                    if message == "".to_string() {
-                        print!("\n");
-                    } else {
-                        println!(" ({})", message);
-                    }
-                },
-                Message::Quit{user, message} => {
-                    print!("{}{} Quit ({}){}\n", color::Fg(color::Blue), user, message, style::Reset);
-                },
-            }
+    /// Redraw the screen with the last messages of the current buffer
+    fn refresh(&mut self) {
+        if self.screen.interactive() {
+            let rows = self.screen.rows();
+            let lines = self.buffers.get(self.buffers.current()).map(|buffer| buffer.lines(rows)).unwrap_or_default();
+            self.screen.redraw(&lines);
+            self.draw();
         }
-        self.buffer = vec![];
-        self.unread = 0;
-        self.mentioned = false;
     }
 
-    fn users(&self) -> String {
-        let mut buffer = "".to_string();
-        for user in &self.users {
-            buffer.push_str(&format!("{}, ", user));
+    /// Add a message to a buffer, showing it if the buffer is focused
+    fn show(&mut self, index: usize, message: Message, mention: bool) {
+        let name = self.buffer_name(index);
+        let line = message.format(&name);
+        if let Some(err) = self.logs.as_mut().and_then(|logs| logs.write(&name, &message.text(&name)).err()) {
+            self.logs = None;
+            self.info(SERVER, format!("irc: Logging stopped, failed to write the log of {}: {}", name, err));
         }
 
-        buffer
+        let shown = self.buffers.push(index, message, mention);
+        if self.screen.resized() {
+            self.refresh();
+        } else {
+            if shown {
+                self.screen.print(&line);
+            }
+            self.draw();
+        }
     }
 
-    fn has_user(&self, username: &str) -> bool {
-        self.users.clone().into_iter().find(|list_user| {list_user == username}).is_some()
+    fn info(&mut self, index: usize, message: String) {
+        self.show(index, Message::Info { message: message }, false);
     }
 
-    /// Pushes a new user to the channel users list, unless that user is already on the list.
-    fn push_user(&mut self, username: &str) {
-        let on_list = self.users.clone().into_iter().find(|list_user| {list_user == username}).is_some();
-        if !on_list {
-            self.users.push(username.to_string());
+    /// Focus a buffer, without a terminal to redraw only the messages received meanwhile are printed
+    fn switch(&mut self, index: usize) -> bool {
+        let unread = self.buffers.get(index).map(|buffer| buffer.unread as usize).unwrap_or(0);
+        if !self.buffers.switch(index) {
+            return false;
         }
-    }
-
-    /// Removes the user from the users list, do nothing if the user isn't on the list.
-    fn remove_user(&mut self, username: &str) {
-        let on_list = self.users.clone().into_iter().position(|list_user| {list_user == username});
-        if on_list.is_some() {
-            self.users.remove(on_list.unwrap());
+        if self.screen.interactive() {
+            self.refresh();
+        } else {
+            for line in self.buffers.get(index).map(|buffer| buffer.lines(unread)).unwrap_or_default() {
+                self.screen.print(&line);
+            }
         }
+        true
     }
-}
 
+    fn buffer_name(&self, index: usize) -> String {
+        self.buffers.get(index).map(|buffer| buffer.name.clone()).unwrap_or_default()
+    }
 
-fn main() {
-    use std::num::Wrapping;
+    fn buffer_kind(&self, index: usize) -> Kind {
+        self.buffers.get(index).map(|buffer| buffer.kind).unwrap_or(Kind::Server)
+    }
+}
 
-    let mut args = env::args().skip(1);
+/// The buffer of messages from the server
+const SERVER: usize = 0;
 
-    let nick = args.next().expect("No nickname provided");
+fn is_channel(name: &str) -> bool {
+    name.starts_with(|c| "#&+!".contains(c))
+}
 
-    let socket_write = Arc::new(Socket::connect("irc.mozilla.org:6667").expect("Failed to connect to irc.mozilla.org"));
-    let socket_read = socket_write.clone();
+/// Send a message to the channel or user of a buffer
+fn say(state: &mut State, index: usize, text: &str) {
+    if state.buffer_kind(index) == Kind::Server {
+        state.info(index, "irc: You haven't joined a channel yet, use /join #chan_name".to_string());
+        return;
+    }
+    if !state.send(&format!("PRIVMSG {} :{}", state.buffer_name(index), text)) {
+        state.info(index, "irc: Not connected, the message was not sent".to_string());
+        return;
+    }
+    let nick = state.nick.clone();
+    state.show(index, Message::Chat { user: nick, message: text.to_string() }, false);
+}
 
-    let channels: Arc<Mutex<(Vec<Channel>, Wrapping<usize>)>> = Arc::new(Mutex::new((vec![], Wrapping(0))));
-    let channels_thread = channels.clone(); // Reference sent out to the thread
+fn help(state: &mut State, index: usize) {
+    for line in &[
+        "irc: Available commands:",
+        "     /join <channel_name> - Joins a channel",
+        "     /part [reason] or /leave - Leaves the current channel, or closes a private buffer",
+        "     /msg <target> <message> - Sends a message to a channel or user",
+        "     /query <user> [message] - Opens a private buffer with a user",
+        "     /me <action> - Sends an action to the current buffer",
+        "     /win <number> or /<number> - Goes to a buffer, Alt+1 to Alt+0 also switch",
+        "     /next and /back - Go to the next or earlier buffer",
+        "     /list - Lists the open buffers",
+        "     /users - Lists the users in the current channel",
+        "     /nick <nick> - Changes your nickname",
+        "     /topic [topic] - Shows or sets the topic of the current channel",
+        "     /quote <line> - Sends a raw line to the server",
+        "     /quit [message] or /exit - Exits this program",
+        "     /help or /commands - Shows this help message",
+    ] {
+        state.info(index, line.to_string());
+    }
+}
 
-    let register = format!("NICK {}\r\nUSER {} 0 * :{}\r\n", nick, nick, nick);
-    print!("{}", register);
-    socket_write.send(register.as_bytes()).unwrap();
+/// Handle a line typed by the user, returning false after quitting
+fn command(state: &mut State, line: &str) -> bool {
+    let current = state.buffers.current();
 
-    thread::spawn(move || {
-        let channels = channels_thread;
-        'stdin: loop {
+    // Lines starting with // send a message that starts with /
+    if !line.starts_with('/') || line.starts_with("//") {
+        let text = if line.starts_with("//") { &line[1..] } else { line };
+        say(state, current, text);
+        return true;
+    }
 
-            let mut line_original = String::new();
-            if stdin().read_line(&mut line_original).unwrap() == 0 {
-                println!("END OF INPUT");
-                break 'stdin;
+    let mut parts = line.splitn(2, ' ');
+    let cmd = parts.next().unwrap_or("");
+    let rest = parts.next().unwrap_or("").trim();
+    let name = state.buffer_name(current);
+
+    match cmd {
+        "/join" | "/j" => if rest.is_empty() {
+            state.info(current, "irc: JOIN: You must provide a channel to join, use /join #chan_name.".to_string());
+        } else {
+            // The buffer is opened when the server confirms the join
+            state.send(&format!("JOIN {}", rest));
+        },
+        "/part" | "/leave" | "/p" | "/close" => match state.buffer_kind(current) {
+            Kind::Server => state.info(current, "irc: LEAVE: The server buffer can't be closed.".to_string()),
+            Kind::Channel => if rest.is_empty() {
+                state.send(&format!("PART {}", name));
+            } else {
+                state.send(&format!("PART {} :{}", name, rest));
+            },
+            Kind::Private => {
+                state.buffers.close(current);
+                state.refresh();
             }
-
-            let line = line_original.trim();
-            if line.starts_with('/') {
-                let mut args = line.split(' ');
-                if let Some(cmd) = args.next() {
-                    match cmd {
-                        "/msg" => if let Some(target) = args.next() {
-                            let parts: Vec<&str> = args.collect();
-                            let message = parts.join(" ");
-                            socket_write.send(format!("PRIVMSG {} :{}\r\n", target, message).as_bytes()).unwrap();
-                        } else {
-                            println!("irc: MSG: No message target given, use /msg target_user message.");
-                        },
-                        "/join" | "/j" => {
-                            if let Some(chan) = args.next() {
-                                let channel = Channel::new(chan.to_string());
-                                let mut channels_lock = channels.lock().unwrap();
-
-                                channels_lock.0.push(channel);
-                                channels_lock.1 = Wrapping(channels_lock.0.len() - 1);
-                                socket_write.send(format!("JOIN {}\r\n", chan).as_bytes()).unwrap();
-                            } else {
-                                println!("irc: JOIN: You must provide a channel to join, use /join #chan_name.");
-                            }
-                        },
-                        "/users" => {
-                            let mut channels_lock = channels.lock().unwrap();
-
-                            if channels_lock.0.get((channels_lock.1).0).is_some() {
-                                let chan = channels_lock.0.get((channels_lock.1).0).unwrap().get_name();
-                                socket_write.send(format!("JOIN {}\r\n", chan).as_bytes()).unwrap();
-                                println!("irc: Users in this channel: \n{}", channels_lock.0.get((channels_lock.1).0).unwrap().users());
-                            } else {
-                                println!("irc: USERS: You aren't connected to any channels.")
-                            }
-                        },
-                        "/next" => {
-                            let mut channels_lock = channels.lock().unwrap();
-
-                            channels_lock.1 += Wrapping(1);
-                            channels_lock.1 %= Wrapping(channels_lock.0.len());
-                            println!("irc: Talking on {}", channels_lock.0.get((channels_lock.1).0).unwrap().name);
-                            let channel_number = (channels_lock.1).0;
-                            channels_lock.0.get_mut(channel_number).unwrap().dump_buf();
-                        },
-                        "/back" => {
-                            let mut channels_lock = channels.lock().unwrap();
-
-                            channels_lock.1 -= Wrapping(1);
-                            channels_lock.1 %= Wrapping(channels_lock.0.len());       
-                            println!("irc: Talking on {}", channels_lock.0.get((channels_lock.1).0).unwrap().name);  
-                            let channel_number = (channels_lock.1).0;     
-                            channels_lock.0.get_mut(channel_number).unwrap().dump_buf();             
-                        },
-                        "/goto" => {
-                            let mut channels_lock = channels.lock().unwrap();
-
-                            if let Some(n) = args.next() {
-                                let n = n.parse::<usize>();
-                                if n.is_err() {
-                                    println!("irc: GOTO: You must provide the channel's number. You can find the number by using /list");
-                                } else {
-                                    let n = n.unwrap();
-                                    if n < 1 || n > channels_lock.0.len() {
-                                        println!("irc: GOTO: This channel number is invalid. You can find the number by using /list");
-                                    } else {
-                                        channels_lock.1 = Wrapping(n - 1);
-                                        // Leaving this just in case, remove if you want to, this protects from accidentaly setting a wrong
-                                        // channel ID
-                                        channels_lock.1 %= Wrapping(channels_lock.0.len());       
-                                        println!("irc: Talking on {}", channels_lock.0.get((channels_lock.1).0).unwrap().name);
-
-                                        let channel_number = (channels_lock.1).0;
-                                        channels_lock.0.get_mut(channel_number).unwrap().dump_buf();
-                                    }   
-                                }
-                            } else {
-                                println!("irc: GOTO: You must provide the channel's number. You can find it by using /list");
-                            }
-                        },
-                        "/list" => {
-                            let mut channels_lock = channels.lock().unwrap();
-                            println!("irc: Currently connected to:");
-                            for (i, channel) in channels_lock.0.iter().enumerate() {
-                                if i == (channels_lock.1).0 {
-                                    println!("{}{}. > {}{}", color::Fg(color::Green), i + 1, channel.get_name(), style::Reset);
-                                } else if channel.mentioned == true {
-                                    println!("{}{}.     {}, {} unread, you were mentioned{}", color::Fg(color::Red), i + 1, channel.get_name(), channel.unread, style::Reset);
-                                } else if channel.unread > 0 { 
-                                    println!("{}.     {}, {}{}{} unread{}", i + 1, channel.get_name(), color::Fg(color::Yellow), style::Bold, channel.unread, style::Reset);
-                                } else {
-                                    println!("{}.     {}, {} unread", i + 1, channel.get_name(), channel.unread);
-                                }
-                            }
-                        },
-                        "/leave" | "/part" | "/p" => { 
-                            let mut channels_lock = channels.lock().unwrap();
-
-                            if channels_lock.0.get((channels_lock.1).0).is_some() {
-                                {
-                                    let chan = channels_lock.0.get((channels_lock.1).0).unwrap().get_name();
-                                    socket_write.send(format!("PART {}\r\n", chan).as_bytes()).unwrap();
-                                }
-                                let channel_number = (channels_lock.1).0;
-
-                                channels_lock.0.remove(channel_number);
-                                if channel_number != 0 {
-                                    (channels_lock.1).0 = channel_number - 1;
-                                }
-                            } else {
-                                println!("irc: LEAVE: You aren't connected to any channels.")
-                            }
-                        },
-                        "/help" | "/commands" => {
-                            println!("irc: Available commands:");
-                            println!("     /join <channel_name> - Joins a channel");
-                            println!("     /list - Lists channels you're connected to");
-                            println!("     /next - Goes to the next channel");
-                            println!("     /back - Goes to the earlier channel");
-                            println!("     /goto <channel_number> - Goes to a specified channel");
-                            println!("     /msg <user> <message> - Sends a private message");
-                            println!("     /leave or /part - Leaves a channel");
-                            println!("     /quit or /exit - Exits this program");
-                            println!("     /help or /commands - Shows this help message");
-                        }
-                        "/quit" | "/exit" => break 'stdin,
-                        // Next one also matches short form of goto, /<chan_number>
-                        _ => {
-                            let mut channels_lock = channels.lock().unwrap();
-
-                            // \/ this may PANIC!
-                            let mut cmd = cmd.to_string();
-                            cmd.remove(0);
-                            let n = cmd.parse::<usize>();
-                            if n.is_err() {
-                                println!("irc: {}: Unknown command. Try /help", cmd)
-                            } else {
-                                let n = n.unwrap();
-                                if n < 1 || n > channels_lock.0.len() {
-                                    println!("irc: GOTO: This channel number is invalid. You can find the number by using /list");
-                                } else {
-                                    channels_lock.1 = Wrapping(n - 1);
-                                    // Leaving this just in case, remove if you want to, this protects from accidentaly setting a wrong
-                                    // channel ID
-                                    channels_lock.1 %= Wrapping(channels_lock.0.len());       
-                                    println!("irc: Talking on {}", channels_lock.0.get((channels_lock.1).0).unwrap().name);
-
-                                    let channel_number = (channels_lock.1).0;
-                                    channels_lock.0.get_mut(channel_number).unwrap().dump_buf();
-                                }   
-                            }
-                        }
-                    }
+        },
+        "/msg" => {
+            let mut parts = rest.splitn(2, ' ');
+            let target = parts.next().unwrap_or("");
+            let text = parts.next().unwrap_or("").trim();
+            if target.is_empty() || text.is_empty() {
+                state.info(current, "irc: MSG: No message target given, use /msg target_user message.".to_string());
+            } else if is_channel(target) && state.buffers.find(target).is_none() {
+                state.send(&format!("PRIVMSG {} :{}", target, text));
+                state.info(current, format!("-> {}: {}", target, text));
+            } else {
+                let index = state.buffers.open(target, Kind::Private);
+                say(state, index, text);
+            }
+        },
+        "/query" | "/q" => {
+            let mut parts = rest.splitn(2, ' ');
+            let target = parts.next().unwrap_or("");
+            if target.is_empty() || is_channel(target) {
+                state.info(current, "irc: QUERY: You must provide a user, use /query user.".to_string());
+            } else {
+                let index = state.buffers.open(target, Kind::Private);
+                state.switch(index);
+                let text = parts.next().unwrap_or("").trim();
+                if !text.is_empty() {
+                    say(state, index, text);
                 }
-            } else if ! line.is_empty() {
-                let channels_lock = channels.lock().unwrap();
-
-                if let Some(ref chan) = channels_lock.0.get((channels_lock.1).0) {
-                    socket_write.send(format!("PRIVMSG {} :{}\r\n", chan.name, line).as_bytes()).unwrap();
+            }
+        },
+        "/me" => if state.buffer_kind(current) == Kind::Server {
+            state.info(current, "irc: You haven't joined a channel yet, use /join #chan_name".to_string());
+        } else {
+            state.send(&format!("PRIVMSG {} :\x01ACTION {}\x01", name, rest));
+            let nick = state.nick.clone();
+            state.show(current, Message::Action { user: nick, message: rest.to_string() }, false);
+        },
+        "/win" | "/goto" | "/buffer" => {
+            let switched = rest.parse::<usize>().ok().map_or(false, |n| n > 0 && state.switch(n - 1));
+            if !switched {
+                state.info(current, "irc: WIN: This buffer number is invalid. You can find the number by using /list".to_string());
+            }
+        },
+        "/next" => {
+            let len = state.buffers.len();
+            state.switch((current + 1) % len);
+        },
+        "/back" | "/prev" => {
+            let len = state.buffers.len();
+            state.switch((current + len - 1) % len);
+        },
+        "/list" => {
+            let list: Vec<String> = state.buffers.iter().enumerate().map(|(i, buffer)| {
+                if i == current {
+                    format!("{}. > {}", i + 1, buffer.name)
+                } else if buffer.mentioned {
+                    format!("{}.     {}, {} unread, you were mentioned", i + 1, buffer.name, buffer.unread)
                 } else {
-                    println!("irc: You haven't joined a channel yet, use /join #chan_name");
+                    format!("{}.     {}, {} unread", i + 1, buffer.name, buffer.unread)
                 }
+            }).collect();
+            state.info(current, "irc: Open buffers:".to_string());
+            for line in list {
+                state.info(current, line);
             }
+        },
+        "/users" | "/names" => if state.buffer_kind(current) == Kind::Channel {
+            let users = state.buffers.get(current).map(|buffer| buffer.users()).unwrap_or_default();
+            state.info(current, format!("irc: Users in this channel: {}", users));
+        } else {
+            state.info(current, "irc: USERS: This buffer isn't a channel.".to_string());
+        },
+        "/nick" => if rest.is_empty() {
+            state.info(current, "irc: NICK: You must provide a nickname.".to_string());
+        } else {
+            state.send(&format!("NICK {}", rest));
+        },
+        "/topic" => if state.buffer_kind(current) != Kind::Channel {
+            state.info(current, "irc: TOPIC: This buffer isn't a channel.".to_string());
+        } else if rest.is_empty() {
+            state.send(&format!("TOPIC {}", name));
+        } else {
+            state.send(&format!("TOPIC {} :{}", name, rest));
+        },
+        "/quote" | "/raw" => {
+            state.send(rest);
+        },
+        "/help" | "/commands" => help(state, current),
+        "/quit" | "/exit" => {
+            state.quitting = true;
+            if rest.is_empty() {
+                state.send("QUIT");
+            } else {
+                state.send(&format!("QUIT :{}", rest));
+            }
+            return false;
+        },
+        // Short form of /win, /<buffer_number>
+        _ => match cmd[1..].parse::<usize>() {
+            Ok(n) => if n == 0 || !state.switch(n - 1) {
+                state.info(current, "irc: WIN: This buffer number is invalid. You can find the number by using /list".to_string());
+            },
+            Err(_) => state.info(current, format!("irc: {}: Unknown command. Try /help", &cmd[1..]))
         }
+    }
 
-        socket_write.send(b"QUIT\r\n").unwrap();
-    });
+    true
+}
 
-    'stdout: loop {
-        let mut buffer = [0; 65536];
-        let count = socket_read.receive(&mut buffer).unwrap();
+/// Read keys from a terminal, editing the input line
+fn read_keys(state: &Mutex<State>) {
+    for key in io::stdin().keys() {
+        let key = match key {
+            Ok(key) => key,
+            Err(_) => break
+        };
+
+        let mut state = state.lock().unwrap();
+        match key {
+            Key::Char('\n') | Key::Char('\r') => {
+                let line = mem::replace(&mut state.screen.input, String::new());
+                let line = line.trim();
+                if !line.is_empty() && !command(&mut state, line) {
+                    return;
+                }
+            },
+            Key::Char(c) => state.screen.input.push(c),
+            Key::Backspace => {
+                state.screen.input.pop();
+            },
+            Key::Ctrl('u') => state.screen.input.clear(),
+            // Alt+1 to Alt+9 go to the first nine buffers, and Alt+0 to the tenth
+            Key::Alt(c) if c.is_digit(10) => {
+                let n = c.to_digit(10).unwrap_or(0) as usize;
+                state.switch(if n == 0 { 9 } else { n - 1 });
+            },
+            Key::Ctrl('c') | Key::Ctrl('d') => {
+                command(&mut state, "/quit");
+                return;
+            },
+            _ => ()
+        }
+        state.draw();
+    }
+    command(&mut state.lock().unwrap(), "/quit");
+}
 
-        if count == 0 {
-            println!("CONNECTION CLOSED");
-            break 'stdout;
+/// Read lines when stdin is not a terminal
+fn read_lines(state: &Mutex<State>) {
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break
+        };
+        let line = line.trim();
+        if !line.is_empty() && !command(&mut state.lock().unwrap(), line) {
+            return;
         }
+    }
+    command(&mut state.lock().unwrap(), "/quit");
+}
 
-        for line in unsafe { str::from_utf8_unchecked(&buffer[..count]) }.lines() {
-            let mut args = line.split(' ');
+/// Handle a line from the server
+fn receive(state: &mut State, auth: &mut Auth, line: &str) {
+    let mut words = line.split(' ');
+    let prefix = if line.starts_with(':') {
+        words.next().map(|prefix| &prefix[1..])
+    } else {
+        None
+    };
+    let source = prefix.unwrap_or("").split('!').next().unwrap_or("").to_string();
+    let cmd = match words.next() {
+        Some(cmd) => cmd,
+        None => return
+    };
+    let params = params(words);
+    let param = |i: usize| params.get(i).map(|param| param.as_str()).unwrap_or("");
+    let from_self = source.eq_ignore_ascii_case(&state.nick);
+
+    match cmd {
+        "PING" => {
+            state.send(&format!("PONG :{}", param(0)));
+        },
+        "CAP" | "AUTHENTICATE" | "900" | "902" | "903" | "904" | "905" | "906" | "907" | "908" => {
+            let (replies, message) = auth.handle(cmd, &params);
+            for reply in replies {
+                state.send(&reply);
+            }
+            if let Some(message) = message {
+                state.info(SERVER, message);
+            }
+        },
+        "001" => {
+            // The server may have shortened or changed the nick
+            if !param(0).is_empty() {
+                state.nick = param(0).to_string();
+            }
+            state.registered = true;
+            for reply in auth.registered(&state.nick) {
+                state.send(&reply);
+            }
+            state.info(SERVER, param(1).to_string());
+
+            // Channels still open are from before a reconnect
+            let channels: Vec<String> = state.buffers.iter()
+                .filter(|buffer| buffer.kind == Kind::Channel)
+                .map(|buffer| buffer.name.clone())
+                .collect();
+            for channel in channels {
+                state.send(&format!("JOIN {}", channel));
+            }
+        },
+        // ERR_NICKNAMEINUSE, which also happens when reconnecting before the old connection times out
+        "433" if !state.registered => {
+            let nick = format!("{}_", state.nick);
+            state.info(SERVER, format!("irc: {} is in use, trying {}", state.nick, nick));
+            state.send(&format!("NICK {}", nick));
+            state.nick = nick;
+        },
+        "ERROR" => state.info(SERVER, format!("ERROR: {}", param(0))),
+        "JOIN" => if from_self {
+            // Channels joined again after a reconnect keep their place
+            let rejoined = state.buffers.find(param(0)).is_some();
+            let index = state.buffers.open(param(0), Kind::Channel);
+            if !rejoined {
+                state.switch(index);
+            }
+            state.info(index, format!("irc: Talking on {}", param(0)));
+        } else if let Some(index) = state.buffers.find(param(0)) {
+            if let Some(buffer) = state.buffers.get_mut(index) {
+                buffer.push_user(&source);
+            }
+            state.show(index, Message::Joined { user: source.clone(), message: String::new() }, false);
+        },
+        "PART" => if let Some(index) = state.buffers.find(param(0)) {
+            if from_self {
+                state.buffers.close(index);
+                state.refresh();
+            } else {
+                if let Some(buffer) = state.buffers.get_mut(index) {
+                    buffer.remove_user(&source);
+                }
+                state.show(index, Message::Parted { user: source.clone(), message: param(1).to_string() }, false);
+            }
+        },
+        "KICK" => if let Some(index) = state.buffers.find(param(0)) {
+            if let Some(buffer) = state.buffers.get_mut(index) {
+                buffer.remove_user(param(1));
+            }
+            let mention = param(1).eq_ignore_ascii_case(&state.nick);
+            state.show(index, Message::Info { message: format!("{} was kicked by {} ({})", param(1), source, param(2)) }, mention);
+        },
+        "QUIT" => {
+            let indices: Vec<usize> = state.buffers.iter().enumerate()
+                .filter(|&(_, buffer)| buffer.has_user(&source) || (buffer.kind == Kind::Private && buffer.name.eq_ignore_ascii_case(&source)))
+                .map(|(i, _)| i)
+                .collect();
+            for index in indices {
+                if let Some(buffer) = state.buffers.get_mut(index) {
+                    buffer.remove_user(&source);
+                }
+                state.show(index, Message::Quit { user: source.clone(), message: param(0).to_string() }, false);
+            }
+        },
+        "NICK" => {
+            let new = param(0).to_string();
+            if from_self {
+                state.nick = new.clone();
+                state.info(SERVER, format!("You are now known as {}", new));
+            }
+            let mut indices = Vec::new();
+            for (i, buffer) in state.buffers.iter_mut().enumerate() {
+                if buffer.has_user(&source) {
+                    buffer.remove_user(&source);
+                    buffer.push_user(&new);
+                    indices.push(i);
+                } else if buffer.kind == Kind::Private && buffer.name.eq_ignore_ascii_case(&source) {
+                    buffer.name = new.clone();
+                    indices.push(i);
+                }
+            }
+            for index in indices {
+                state.info(index, format!("{} is now known as {}", source, new));
+            }
+        },
+        "353" => if let Some(buffer) = state.buffers.find(param(2)).and_then(|index| state.buffers.get_mut(index)) {
+            for user in param(3).split_whitespace() {
+                buffer.push_user(user);
+            }
+        },
+        "366" | "333" => (),
+        "332" => {
+            let index = state.buffers.find(param(1)).unwrap_or(SERVER);
+            state.info(index, format!("Topic: {}", param(2)));
+        },
+        "TOPIC" => {
+            let index = state.buffers.find(param(0)).unwrap_or(SERVER);
+            state.info(index, format!("{} changed the topic to: {}", source, param(1)));
+        },
+        "MODE" => {
+            let index = state.buffers.find(param(0)).unwrap_or(SERVER);
+            let modes = params.get(1..).map(|modes| modes.join(" ")).unwrap_or_default();
+            state.info(index, format!("{} set {} to mode {}", source, param(0), modes));
+        },
+        "PRIVMSG" | "NOTICE" => {
+            let target = param(0);
+            let text = param(1);
+            let index = if is_channel(target) {
+                state.buffers.find(target).unwrap_or(SERVER)
+            } else if cmd == "PRIVMSG" && !source.is_empty() && !source.contains('.') {
+                state.buffers.open(&source, Kind::Private)
+            } else {
+                // Notices from the server and services go to a private buffer only if one is open
+                state.buffers.find(&source).unwrap_or(SERVER)
+            };
+            let mention = state.buffer_kind(index) == Kind::Private || text.contains(&state.nick);
 
-            let prefix = if line.starts_with(':') {
-                args.next()
+            let message = if text.starts_with("\x01ACTION ") {
+                Message::Action { user: source.clone(), message: text[8..].trim_right_matches('\x01').to_string() }
             } else {
-                None
+                Message::Chat { user: source.clone(), message: text.to_string() }
             };
+            state.show(index, message, mention);
+        },
+        // Other numeric replies are for the user, after the nick they are sent to
+        _ if cmd.len() == 3 && cmd.chars().all(|c| c.is_digit(10)) => {
+            let message = params.get(1..).map(|params| params.join(" ")).unwrap_or_default();
+            state.info(SERVER, message);
+        },
+        _ => state.info(SERVER, line.to_string())
+    }
+}
+
+/// Read from the server until the connection is closed or lost, returning why it ended
+fn session(state: &Mutex<State>, socket: &Socket, auth: &mut Auth) -> String {
+    // Reads may end in the middle of a line, which is completed by the next one
+    let mut pending = Vec::new();
+    let mut buffer = [0; 65536];
+    let mut last_received = Instant::now();
+    let mut pinged = false;
+
+    loop {
+        let count = match socket.receive(&mut buffer, Duration::from_secs(1)) {
+            Ok(Some(0)) => return "Connection closed".to_string(),
+            Ok(Some(count)) => count,
+            Ok(None) => {
+                let state = state.lock().unwrap();
+                // The server closes the connection after QUIT, but may not
+                if state.quitting {
+                    return "Quit".to_string();
+                }
+
+                // A quiet server is asked for a reply, which shows if the connection still works
+                let idle = last_received.elapsed().as_secs();
+                if idle >= PING_TIMEOUT {
+                    return format!("Ping timeout: {} seconds", idle);
+                } else if idle >= PING_INTERVAL && !pinged {
+                    state.send(&format!("PING :{}", state.buffer_name(SERVER)));
+                    pinged = true;
+                }
+                continue;
+            },
+            Err(err) => return format!("Connection lost: {}", err)
+        };
+        last_received = Instant::now();
+        pinged = false;
+        pending.extend_from_slice(&buffer[..count]);
+
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..end + 1).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_right_matches(|c| c == '\r' || c == '\n');
+            if !line.is_empty() {
+                receive(&mut state.lock().unwrap(), auth, line);
+            }
+        }
+    }
+}
+
+fn main() {
+    let args = Parser::new("irc")
+        .synopsis("[-c file] [-4 | -6] [--ssl] [-s host[:port]] [--sasl plain|external] [--account name] [--password pass] \
+                   [--cert file --key file] [--no-nickserv] [--log-dir dir] [--no-reconnect] nick")
+        .opt("c", "config", "file", "Read settings from file, which the other options override")
+        .family()
+        .flag("", "ssl", "Connect with TLS")
+        .flag("", "tls", "The same as --ssl")
+        .opt("s", "server", "host[:port]", "Connect to host")
+        .opt("", "sasl", "plain|external", "Log in with SASL")
+        .opt("", "account", "name", "The account to log in to, the nick by default")
+        .opt("", "password", "pass", "The password of the account")
+        .opt("", "cert", "file", "Present the PEM certificate in file to the server")
+        .opt("", "key", "file", "The PEM private key of the certificate")
+        .flag("", "no-nickserv", "Don't identify to NickServ")
+        .opt("", "log-dir", "dir", "Log each buffer to a file in dir")
+        .flag("", "no-reconnect", "Exit when the connection is lost")
+        .parse_env();
+
+    // The configuration file is read first, so that flags override its settings
+    let mut config = Config::new();
+    if let Some(path) = args.value("config") {
+        // Like the settings file of every tool, a broken one is a usage error
+        if let Err(err) = config.load(path) {
+            NetError::Usage(format!("failed to load configuration: {}", err)).exit("irc");
+        }
+    }
+
+    if args.flag("ssl") || args.flag("tls") {
+        config.tls = true;
+    }
+    if let Some(value) = args.value("server") {
+        let (host, port) = resolve::split_port(value).unwrap_or_else(|err| args.invalid(&err.to_string()));
+        config.server = Some(host.to_string());
+        if port.is_some() {
+            config.port = port;
+        }
+    }
+    let family = args.family();
+    if let Some(value) = args.value("sasl") {
+        config.sasl = Some(Mechanism::parse(value).unwrap_or_else(|| args.invalid(&format!("unknown SASL mechanism '{}'", value))));
+    }
+    let string = |name| args.value(name).map(|value| value.to_string());
+    config.account = string("account").or(config.account);
+    config.password = string("password").or(config.password);
+    config.cert = string("cert").or(config.cert);
+    config.key = string("key").or(config.key);
+    config.log_dir = string("log-dir").or(config.log_dir);
+    if args.flag("no-nickserv") {
+        config.nickserv = false;
+    }
+    if args.flag("no-reconnect") {
+        config.reconnect = false;
+    }
+    match args.free.len() {
+        0 => (),
+        1 => config.nick = Some(args.free[0].clone()),
+        _ => args.usage()
+    }
+    let nick = config.nick.clone().unwrap_or_else(|| args.usage());
+    let server = config.server.clone().unwrap_or(DEFAULT_SERVER.to_string());
+    let port = config.port.unwrap_or(if config.tls { DEFAULT_TLS_PORT } else { DEFAULT_PORT });
+
+    if config.sasl == Some(Mechanism::External) && (!config.tls || config.cert.is_none() || config.key.is_none()) {
+        args.invalid("SASL EXTERNAL requires --ssl with a client certificate and key");
+    }
+    if config.sasl == Some(Mechanism::Plain) && config.password.is_none() {
+        args.invalid("SASL PLAIN requires a password");
+    }
+    let tls = if config.tls {
+        Some(tls_config(&config).unwrap_or_else(|err| {
+            NetError::from(err).context("failed to load TLS certificate").exit("irc")
+        }))
+    } else {
+        None
+    };
+    let connect = || match tls {
+        Some(ref tls) => Socket::connect_tls(&server, port, family, tls),
+        None => Socket::connect(&server, port, family)
+    };
+
+    // The first connection is made before taking over the terminal, so that errors are seen
+    let mut socket = Some(Arc::new(connect().unwrap_or_else(|err| err.exit("irc"))));
+
+    let state = Arc::new(Mutex::new(State {
+        nick: nick.clone(),
+        buffers: Buffers::new(&server),
+        screen: Screen::new(),
+        socket: None,
+        registered: false,
+        quitting: false,
+        logs: config.log_dir.as_ref().map(|dir| Logs::new(dir, &server)),
+    }));
+
+    // Restore the terminal before a panic message is printed
+    {
+        let state = state.clone();
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Ok(mut state) = state.try_lock() {
+                state.screen.restore();
+            }
+            default_hook(info);
+        }));
+    }
 
-            let source = prefix.unwrap_or("").split(':').nth(1).unwrap_or("").split("!").next().unwrap_or("");
-
-            if let Some(cmd) = args.next() {
-                match cmd {
-                    "ERROR" => {
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
-                        println!("\x1B[1mERROR: {}\x1B[21m", message);
-                    },
-                    "JOIN" => {
-                        let mut channels_lock = channels.lock().unwrap();
-
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
-                        let message_split: Vec<&str> = message.split(":").collect();
-                        let _target = message_split[0].to_string();
-                        let _target = _target.trim(); // without trimming I got issues in PART, put one here just in case
-                        let message = message_split.get(1).unwrap_or(&"").to_string();
-
-                        let channel: Option<&mut Channel>;
-                        channel = channels_lock.0.iter_mut().filter(|chan| {
-                            chan.get_name() == _target
-                        }).next(); 
-
-                        if channel.is_some(){
-                            let mut channel = channel.unwrap();
-                            //println!("Message hidden"); // this for testing
-                            channel.buffer.push(Message::Joined {user: source.to_string(), message: message});
-                            //format!("\x1B[7m{} {}: {}\x1B[27m\n", _target, source, message)
-                            channel.unread += 1;    
-                            channel.push_user(source);       
-                        } else {
-                            println!("\x1B[1m{} joined [{}]\x1B[21m", source, message);
-                        }
-                    },
-                    "353" => { // channel users list
-                        let mut channels_lock = channels.lock().unwrap();
-
-                        let mut parts: Vec<String> = args.map(|x| { x.to_string() }).collect();
-                        parts.reverse(); // there is a better way for this surely
-                        parts.pop(); parts.pop();
-                        let chan = parts.pop().unwrap();
-                        parts.reverse();
-                        parts.pop();
-                        if parts[0].starts_with(':') {
-                           //let clone = parts[0].clone().to_string();
-                            //clone.remove(0);
-                            parts[0].remove(0);
-                        }
-
-                        let channel: Option<&mut Channel>;
-                        channel = channels_lock.0.iter_mut().filter(|channel| {
-                            channel.get_name() == chan
-                        }).next();
-
-                        let channel = channel.unwrap(); 
-
-                        let users = parts;
-                        channel.users = vec![];
-                        for user in &users {
-                            channel.push_user(user);
-                        }
-                    }
-                    "MODE" => {
-                        let target = args.next().unwrap_or("");
-                        let mode = args.next().unwrap_or("");
-                        println!("\x1B[1m{} set to mode {}\x1B[21m", target, mode);
-                    },
-                    "NOTICE" => {
-                        let mut channels_lock = channels.lock().unwrap();
-
-                        let _target = args.next().unwrap_or("");
-
-                        let channel: Option<&mut Channel>;
-                        channel = channels_lock.0.iter_mut().filter(|chan| {
-                            chan.get_name() == _target
-                        }).next(); 
-
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
-
-                        if channel.is_some(){
-                            let mut channel = channel.unwrap();
-                            //println!("Message hidden"); // this for testing
-                            channel.buffer.push(Message::Chat {user: source.to_string(), message: message});
-                            //format!("\x1B[7m{} {}: {}\x1B[27m\n", _target, source, message)
-                            channel.unread += 1;             
-                        } else {
-                            println!("\x1B[7m{} {}: {}\x1B[27m", _target, source, message);
-                        }
-                    },
-                    "PART" => {
-                        let mut channels_lock = channels.lock().unwrap();
-
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
-                        let message_split: Vec<&str> = message.split(":").collect();
-                        let _target = message_split[0].to_string();
-                        let _target = _target.trim();
-                        let message = message_split.get(1).unwrap_or(&"").to_string();
-                        
-                        let channel: Option<&mut Channel>;
-                        channel = channels_lock.0.iter_mut().filter(|chan| {
-                            chan.get_name() == _target
-                        }).next(); 
-
-                        if channel.is_some(){
-                            let mut channel = channel.unwrap();
-                            //println!("Message hidden"); // this for testing
-                            channel.buffer.push(Message::Parted {user: source.to_string(), message: message});
-                            //format!("\x1B[7m{} {}: {}\x1B[27m\n", _target, source, message)
-                            channel.unread += 1;   
-                            channel.remove_user(source);          
-                        } else {
-                            println!("\x1B[1m{} parted {} ({})\x1B[21m", source, _target, message);
-                        }
-                    },
-                    "PING" => {
-                        socket_read.send(format!("PONG {}\r\n", nick).as_bytes()).unwrap();
-                    },
-                    "PRIVMSG" => {
-                        let mut channels_lock = channels.lock().unwrap();
-
-                        let _target = args.next().unwrap_or("");
-
-                        let channel: Option<&mut Channel>;
-                        channel = channels_lock.0.iter_mut().filter(|chan| {
-                            chan.get_name() == _target
-                        }).next(); 
-
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
-
-                        if channel.is_some(){
-
-                            let message = message.clone();
-                            let mut channel = channel.unwrap();
-                            //println!("Message hidden"); // this for testing
-                            channel.buffer.push(Message::Chat {user: source.to_string(), message: message.clone()});
-                            //format!("\x1B[7m{} {}: {}\x1B[27m\n", _target, source, message)
-                            channel.unread += 1;  
-
-                            if message.contains(&nick) {
-                                channel.mentioned = true;
-                            }           
-                        } else {
-                            println!("\x1B[7m{} {}: {}\x1B[27m", _target, source, message);
-                        }
-                    },
-                    "QUIT" => {
-                        let mut channels_lock = channels.lock().unwrap();
-
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
-
-                        for channel in &mut channels_lock.0 {
-                            if channel.has_user(source) {
-                                channel.buffer.push(Message::Quit { user: source.to_string(), message: message.clone()});
-                                channel.remove_user(source);
-                            }
-                        }
-                        //println!("\x1B[1m{} quit: {}\x1B[21m", source, message);
-                    },
-                    "372" => {
-                        let _target = args.next().unwrap_or("");
-                        let parts: Vec<&str> = args.collect();
-                        let mut message = parts.join(" ");
-                        if message.starts_with(':') {
-                            message.remove(0);
-                        }
-                        println!("\x1B[1m{}\x1B[21m", message);
-                    },
-                    _ => {
-                        println!("{}", line);
-                    }
+    let state_thread = state.clone();
+    thread::spawn(move || {
+        let interactive = state_thread.lock().unwrap().screen.interactive();
+        if interactive {
+            read_keys(&state_thread);
+        } else {
+            read_lines(&state_thread);
+        }
+    });
+
+    let mut backoff = MIN_BACKOFF;
+    loop {
+        if let Some(socket) = socket.take() {
+            let mut auth = Auth::new(config.sasl, config.account.as_ref().unwrap_or(&nick), config.password.clone(), config.nickserv);
+            {
+                let mut state = state.lock().unwrap();
+                state.info(SERVER, format!("irc: Connected to {}:{}, type /help for the commands", server, port));
+                state.socket = Some(socket.clone());
+                state.registered = false;
+                state.nick = nick.clone();
+
+                let mut register = auth.start();
+                register.push(format!("NICK {}", nick));
+                register.push(format!("USER {} 0 * :{}", nick, nick));
+                for line in register {
+                    state.send(&line);
                 }
             }
+
+            let reason = session(&state, &socket, &mut auth);
+
+            let mut state = state.lock().unwrap();
+            state.socket = None;
+            if state.quitting || !config.reconnect {
+                break;
+            }
+            // A connection that got as far as registering starts the backoff again
+            if state.registered {
+                backoff = MIN_BACKOFF;
+            }
+            for buffer in state.buffers.iter_mut() {
+                buffer.users.clear();
+            }
+            state.info(SERVER, format!("irc: {}, reconnecting in {} seconds", reason, backoff));
+        }
+
+        // Wait, unless the user quits meanwhile
+        let deadline = Instant::now() + Duration::from_secs(backoff);
+        while Instant::now() < deadline && !state.lock().unwrap().quitting {
+            thread::sleep(Duration::from_millis(100));
+        }
+        if state.lock().unwrap().quitting {
+            break;
         }
 
-        let mut channels_lock = channels.lock().unwrap();
-        let channel_number = (channels_lock.1).0;
-        let mut channel: Option<&mut Channel> = channels_lock.0.get_mut(channel_number);
-        if channel.is_some() {
-            let mut channel: &mut Channel = channel.unwrap();
-            channel.dump_buf();
+        backoff = cmp::min(backoff * 2, MAX_BACKOFF);
+        match connect() {
+            Ok(connected) => socket = Some(Arc::new(connected)),
+            Err(err) => state.lock().unwrap().info(SERVER, format!("irc: {}, retrying in {} seconds", err, backoff))
         }
     }
+
+    state.lock().unwrap().screen.restore();
+    println!("irc: connection closed");
 }
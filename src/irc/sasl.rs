@@ -0,0 +1,199 @@
+use base64;
+
+/// SASL mechanisms offered to the server
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mechanism {
+    /// Account name and password
+    Plain,
+    /// The TLS client certificate identifies the account
+    External,
+}
+
+impl Mechanism {
+    pub fn parse(name: &str) -> Option<Mechanism> {
+        match name.to_lowercase().as_str() {
+            "plain" => Some(Mechanism::Plain),
+            "external" => Some(Mechanism::External),
+            _ => None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Mechanism::Plain => "PLAIN",
+            Mechanism::External => "EXTERNAL",
+        }
+    }
+}
+
+/// AUTHENTICATE payloads are split into lines of this many characters
+const CHUNK_SIZE: usize = 400;
+
+/// Capability negotiation and SASL authentication during registration (IRCv3 `CAP` and `sasl`),
+/// with a NickServ IDENTIFY after registration when SASL is not available or fails
+pub struct Auth {
+    mechanism: Option<Mechanism>,
+    account: String,
+    password: Option<String>,
+    nickserv: bool,
+    /// Capabilities listed so far, `CAP LS` replies may span several lines
+    caps: Vec<String>,
+    negotiating: bool,
+    pub authenticated: bool,
+}
+
+impl Auth {
+    pub fn new(mechanism: Option<Mechanism>, account: &str, password: Option<String>, nickserv: bool) -> Auth {
+        Auth {
+            mechanism: mechanism,
+            account: account.to_string(),
+            password: password,
+            nickserv: nickserv,
+            caps: Vec::new(),
+            negotiating: false,
+            authenticated: false,
+        }
+    }
+
+    /// Lines sent before NICK and USER, listing capabilities holds off registration until `CAP END`
+    pub fn start(&mut self) -> Vec<String> {
+        if self.mechanism.is_some() {
+            self.negotiating = true;
+            vec!["CAP LS 302".to_string()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn end(&mut self) -> String {
+        self.negotiating = false;
+        "CAP END".to_string()
+    }
+
+    fn payload(&self) -> Vec<String> {
+        let encoded = match self.mechanism {
+            Some(Mechanism::Plain) => {
+                let password = self.password.as_ref().map(|password| password.as_str()).unwrap_or("");
+                base64::encode(format!("{}\0{}\0{}", self.account, self.account, password).as_bytes())
+            },
+            _ => String::new()
+        };
+
+        let mut lines: Vec<String> = encoded.as_bytes().chunks(CHUNK_SIZE)
+            .map(|chunk| format!("AUTHENTICATE {}", String::from_utf8_lossy(chunk)))
+            .collect();
+        // An empty line, or one after a full chunk, tells the server that the payload is complete
+        if encoded.len() % CHUNK_SIZE == 0 {
+            lines.push("AUTHENTICATE +".to_string());
+        }
+        lines
+    }
+
+    /// Handle a reply of the negotiation, returning the lines to send and a message for the user
+    pub fn handle(&mut self, command: &str, params: &[String]) -> (Vec<String>, Option<String>) {
+        if !self.negotiating {
+            return (Vec::new(), None);
+        }
+        let mechanism = match self.mechanism {
+            Some(mechanism) => mechanism,
+            None => return (Vec::new(), None)
+        };
+
+        match command {
+            "CAP" => {
+                let subcommand = params.get(1).map(|param| param.as_str()).unwrap_or("");
+                let caps = params.last().map(|param| param.as_str()).unwrap_or("");
+                match subcommand {
+                    "LS" => {
+                        // Values such as sasl=PLAIN,EXTERNAL list the supported mechanisms
+                        self.caps.extend(caps.split_whitespace().map(|cap| cap.to_string()));
+                        if params.len() > 3 && params[2] == "*" {
+                            return (Vec::new(), None);
+                        }
+
+                        let sasl = self.caps.iter().find(|cap| *cap == "sasl" || cap.starts_with("sasl=")).cloned();
+                        match sasl {
+                            Some(ref cap) if cap == "sasl" || cap[5..].split(',').any(|name| name == mechanism.name()) => {
+                                (vec!["CAP REQ :sasl".to_string()], None)
+                            },
+                            Some(_) => (vec![self.end()], Some(format!("server does not support SASL {}", mechanism.name()))),
+                            None => (vec![self.end()], Some("server does not support SASL".to_string()))
+                        }
+                    },
+                    "ACK" if caps.split_whitespace().any(|cap| cap == "sasl") => {
+                        (vec![format!("AUTHENTICATE {}", mechanism.name())], None)
+                    },
+                    "NAK" => (vec![self.end()], Some("server refused the sasl capability".to_string())),
+                    _ => (Vec::new(), None)
+                }
+            },
+            "AUTHENTICATE" if params.get(0).map(|param| param.as_str()) == Some("+") => (self.payload(), None),
+            // RPL_LOGGEDIN, the message names the account
+            "900" => (Vec::new(), params.last().cloned()),
+            // RPL_SASLSUCCESS
+            "903" => {
+                self.authenticated = true;
+                (vec![self.end()], Some("SASL authentication successful".to_string()))
+            },
+            // ERR_NICKLOCKED, ERR_SASLFAIL, ERR_SASLTOOLONG, ERR_SASLABORTED and ERR_SASLALREADY
+            "902" | "904" | "905" | "906" | "907" => {
+                let reason = params.last().cloned().unwrap_or_default();
+                (vec![self.end()], Some(format!("SASL authentication failed: {}", reason)))
+            },
+            // RPL_SASLMECHS, the server lists the mechanisms it does support
+            "908" => (vec![self.end()], Some(format!("server does not support SASL {}", mechanism.name()))),
+            _ => (Vec::new(), None)
+        }
+    }
+
+    /// Lines sent once registration completes, identifying with NickServ if SASL did not
+    pub fn registered(&mut self, nick: &str) -> Vec<String> {
+        match self.password {
+            Some(ref password) if self.nickserv && !self.authenticated => {
+                self.authenticated = true;
+                vec![format!("PRIVMSG NickServ :IDENTIFY {} {}", nick, password)]
+            },
+            _ => Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Auth, Mechanism};
+
+    fn params(params: &[&str]) -> Vec<String> {
+        params.iter().map(|param| param.to_string()).collect()
+    }
+
+    #[test]
+    fn plain_test() {
+        let mut auth = Auth::new(Some(Mechanism::Plain), "jilles", Some("sesame".to_string()), true);
+        assert_eq!(auth.start(), vec!["CAP LS 302"]);
+
+        assert_eq!(auth.handle("CAP", &params(&["*", "LS", "*", "multi-prefix"])).0, Vec::<String>::new());
+        assert_eq!(auth.handle("CAP", &params(&["*", "LS", "sasl=PLAIN,EXTERNAL"])).0, vec!["CAP REQ :sasl"]);
+        assert_eq!(auth.handle("CAP", &params(&["*", "ACK", "sasl"])).0, vec!["AUTHENTICATE PLAIN"]);
+        // Example from the IRCv3 SASL specification
+        assert_eq!(auth.handle("AUTHENTICATE", &params(&["+"])).0, vec!["AUTHENTICATE amlsbGVzAGppbGxlcwBzZXNhbWU="]);
+        assert_eq!(auth.handle("903", &params(&["jilles", "SASL authentication successful"])).0, vec!["CAP END"]);
+
+        assert!(auth.authenticated);
+        assert!(auth.registered("jilles").is_empty());
+    }
+
+    #[test]
+    fn fallback_test() {
+        let mut auth = Auth::new(Some(Mechanism::External), "", Some("sesame".to_string()), true);
+        auth.start();
+        assert_eq!(auth.handle("CAP", &params(&["*", "LS", "sasl=PLAIN"])).0, vec!["CAP END"]);
+        assert_eq!(auth.registered("jilles"), vec!["PRIVMSG NickServ :IDENTIFY jilles sesame"]);
+
+        let mut auth = Auth::new(Some(Mechanism::External), "", None, true);
+        auth.start();
+        auth.handle("CAP", &params(&["*", "ACK", "sasl"]));
+        assert_eq!(auth.handle("AUTHENTICATE", &params(&["+"])).0, vec!["AUTHENTICATE +"]);
+        assert_eq!(auth.handle("904", &params(&["*", "SASL authentication failed"])).0, vec!["CAP END"]);
+        assert!(!auth.authenticated);
+    }
+}
@@ -0,0 +1,81 @@
+use netutils_core::error;
+use netutils_core::proxy;
+use netutils_core::resolve::Family;
+use netutils_core::tls;
+use rustls;
+use std::io::{self, ErrorKind, Read, Result, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a read holds the connection before letting a writer in
+const POLL_INTERVAL_MS: u64 = 100;
+
+enum Stream {
+    Plain(TcpStream),
+    Tls(tls::ClientStream),
+}
+
+/// A connection to a server, shared by the threads that read and write it
+pub struct Socket {
+    stream: Mutex<Stream>,
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut
+}
+
+impl Socket {
+    fn connect_tcp(host: &str, port: u16, family: Family) -> error::Result<TcpStream> {
+        let tcp = proxy::connect("irc", host, port, family, None)?;
+        tcp.set_read_timeout(Some(Duration::from_millis(POLL_INTERVAL_MS)))?;
+        Ok(tcp)
+    }
+
+    pub fn connect(host: &str, port: u16, family: Family) -> error::Result<Socket> {
+        Ok(Socket {
+            stream: Mutex::new(Stream::Plain(Socket::connect_tcp(host, port, family)?))
+        })
+    }
+
+    /// Connect with TLS, verifying that the certificate is valid for `host`
+    pub fn connect_tls(host: &str, port: u16, family: Family, config: &Arc<rustls::ClientConfig>) -> error::Result<Socket> {
+        let tcp = Socket::connect_tcp(host, port, family)?;
+        Ok(Socket {
+            stream: Mutex::new(Stream::Tls(tls::connect(config, tcp, host)))
+        })
+    }
+
+    /// Read what the server sent, waiting up to `timeout` for something to arrive. Returns None
+    /// if nothing did and 0 once the server closes the connection.
+    pub fn receive(&self, buf: &mut [u8], timeout: Duration) -> Result<Option<usize>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+
+            let mut stream = self.stream.lock().unwrap();
+            let result = match *stream {
+                Stream::Plain(ref mut tcp) => tcp.read(buf),
+                Stream::Tls(ref mut tls) => tls.read(buf)
+            };
+            match result {
+                Err(ref err) if is_timeout(err) => (),
+                result => return result.map(Some)
+            }
+        }
+    }
+
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        let mut stream = self.stream.lock().unwrap();
+        match *stream {
+            Stream::Plain(ref mut tcp) => tcp.write_all(buf)?,
+            Stream::Tls(ref mut tls) => {
+                tls.write_all(buf)?;
+                tls.flush()?;
+            }
+        }
+        Ok(buf.len())
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::{self, Stdout, Write};
+use termion::{self, clear, cursor, style};
+use termion::raw::{IntoRawMode, RawTerminal};
+
+/// The terminal, with the messages of the current buffer above a status line and the input line.
+/// When stdin or stdout is not a terminal, messages are printed as lines and there is no status.
+/// The terminal is restored when this is dropped.
+pub struct Screen {
+    raw: Option<RawTerminal<Stdout>>,
+    /// Columns and rows
+    size: (u16, u16),
+    /// The line being typed
+    pub input: String,
+}
+
+impl Screen {
+    pub fn new() -> Screen {
+        let raw = if termion::is_tty(&io::stdin()) {
+            io::stdout().into_raw_mode().ok()
+        } else {
+            None
+        };
+        let mut screen = Screen {
+            raw: raw,
+            size: (0, 0),
+            input: String::new(),
+        };
+        screen.resized();
+        screen
+    }
+
+    pub fn interactive(&self) -> bool {
+        self.raw.is_some()
+    }
+
+    /// Rows for messages, the last two are the status and input lines
+    pub fn rows(&self) -> usize {
+        self.size.1.saturating_sub(2) as usize
+    }
+
+    /// Check the terminal size, which clears the screen if it changed so that the caller redraws
+    pub fn resized(&mut self) -> bool {
+        let size = termion::terminal_size().unwrap_or((80, 24));
+        if size == self.size {
+            return false;
+        }
+        self.size = size;
+
+        let rows = self.rows();
+        if let Some(ref mut raw) = self.raw {
+            // Messages scroll in a region that leaves out the status and input lines
+            let _ = write!(raw, "{}\x1B[1;{}r", clear::All, rows);
+        }
+        true
+    }
+
+    /// Show a message line
+    pub fn print(&mut self, line: &str) {
+        let rows = self.rows() as u16;
+        match self.raw {
+            Some(ref mut raw) => {
+                // A newline on the last row of the region scrolls it up, leaving an empty row
+                let _ = write!(raw, "{}\r\n{}", cursor::Goto(1, rows), line.replace('\n', "\r\n"));
+            },
+            None => println!("{}", line)
+        }
+    }
+
+    /// Replace the messages shown with `lines`
+    pub fn redraw(&mut self, lines: &[String]) {
+        if let Some(ref mut raw) = self.raw {
+            let _ = write!(raw, "{}", clear::All);
+        }
+        for line in lines {
+            self.print(line);
+        }
+    }
+
+    /// Draw the status and input lines, leaving the cursor at the end of the input
+    pub fn draw(&mut self, status: &str) {
+        let (width, height) = self.size;
+        if let Some(ref mut raw) = self.raw {
+            let width = width as usize;
+            let status: String = status.chars().take(width).collect();
+            let padding = width - status.chars().count();
+
+            // The end of the input is shown if it does not fit
+            let chars = self.input.chars().count();
+            let input: String = self.input.chars().skip((chars + 3).saturating_sub(width)).collect();
+
+            let _ = write!(raw, "{}{}{}{}{}{}{}{}> {}",
+                           cursor::Goto(1, height - 1), clear::CurrentLine, style::Invert, status,
+                           " ".repeat(padding), style::Reset,
+                           cursor::Goto(1, height), clear::CurrentLine, input);
+            let _ = raw.flush();
+        }
+    }
+
+    /// Leave raw mode and give the whole screen back, before exiting
+    pub fn restore(&mut self) {
+        let height = self.size.1;
+        if let Some(mut raw) = self.raw.take() {
+            let _ = write!(raw, "\x1B[r{}{}\r\n", cursor::Goto(1, height), clear::CurrentLine);
+            let _ = raw.flush();
+        }
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
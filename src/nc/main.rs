@@ -1,5 +1,8 @@
-use std::env;
-use std::io::{self, Write};
+extern crate netutils_core;
+
+use netutils_core::args::Parser;
+use netutils_core::ratelimit::Limit;
+use netutils_core::tls::ClientOptions;
 
 mod modes;
 use modes::*;
@@ -8,14 +11,22 @@ static MAN_PAGE: &'static str = /* @MANSTART{nc} */ r#"
 NAME
     nc - Concatenate and redirect sockets
 SYNOPSIS
-    nc [[-h | --help] | [-u | --udp] | [-l | --listen]] [hostname:port]
+    nc [[-h | --help] | [-4 | -6] | [-u | --udp] | [-l | --listen] | [--ssl]] [hostname:port]
 DESCRIPTION
-    Netcat (nc) is command line utility which can read and write data across network. Currently
-    it only works with IPv4 and does not support any encryption.
+    Netcat (nc) is command line utility which can read and write data across network. TCP
+    connections may be encrypted with TLS.
+
+    An IPv6 address is given in brackets, as in [2001:db8::1]:80, and a link-local one with its
+    zone, as in [fe80::1%eth0]:80. Listeners may leave out the host, as in :8080, to listen on
+    every interface.
 OPTIONS
     -h
     --help
         Print this manual page.
+    -4
+    -6
+        Use IPv4 only or IPv6 only. Listeners without a host listen on IPv4 by default, and on
+        IPv6 with -6.
     -u
     --udp
         Use UDP instead of default TCP.
@@ -23,6 +34,33 @@ OPTIONS
     -l
     --listen
         Listen for incoming connections.
+
+    --ssl
+        Connect with TLS, checking the certificate of the server against the Mozilla roots. A
+        listener needs --ssl-cert and --ssl-key.
+
+    --ssl-cert file
+    --ssl-key file
+        The PEM certificate and private key to present. Clients present them only if the
+        server asks for one.
+
+    --ssl-trustfile file
+        Trust the certificate authorities in the PEM file instead of the Mozilla roots.
+
+    --insecure
+        Don't check the certificate of the server.
+
+    --limit-rate rate
+        Send and receive no more than rate bytes a second each way. The rate may end in k, m
+        or g for KiB, MiB or GiB.
+
+    --limit-rate-in rate
+    --limit-rate-out rate
+        Receive, or send, no more than rate bytes a second, in place of --limit-rate for that
+        direction.
+
+    --limit-burst bytes
+        Let up to bytes through at once before the limits apply, a second's worth by default.
 AUTHOR
     Written by Sehny.
 "#; /* @MANEND */
@@ -38,54 +76,72 @@ enum NcMode {
 }
 
 fn main() {
+    let args = Parser::new("nc")
+        .synopsis("[-4 | -6] [-u | --udp] [-l | --listen] [--ssl [--ssl-cert file --ssl-key file] \
+                   [--ssl-trustfile file] [--insecure]] [--limit-rate rate] [--limit-rate-in rate] \
+                   [--limit-rate-out rate] [--limit-burst bytes] [hostname:port]")
+        .man(MAN_PAGE)
+        .family()
+        .flag("u", "udp", "Use UDP instead of default TCP")
+        .flag("l", "listen", "Listen for incoming connections")
+        .flag("", "ssl", "Connect with TLS")
+        .opt("", "ssl-cert", "file", "The PEM certificate to present")
+        .opt("", "ssl-key", "file", "The PEM private key of the certificate")
+        .opt("", "ssl-trustfile", "file", "Trust the certificate authorities in the PEM file")
+        .flag("", "insecure", "Don't check the certificate of the server")
+        .rate_limit()
+        .opt("", "limit-rate-in", "rate", "Receive no more than rate bytes a second")
+        .opt("", "limit-rate-out", "rate", "Send no more than rate bytes a second")
+        .parse_env();
 
-    let mut args = env::args().skip(1);
-    let mut hostname = "".to_string();
-    let mut proto = TransportProtocol::Tcp;
-    let mut mode = NcMode::Connect;
-    let mut stdout = io::stdout();
-
-    while let Some(arg) = args.next() {
-        if arg.starts_with('-') {
-            match arg.as_str() {
-                "-h" | "--help" => {
-                    stdout.write_all(MAN_PAGE.as_bytes()).unwrap();
-                    return;
-                }
-                "-u" | "--udp" => proto = TransportProtocol::Udp,
-                "-l" | "--listen" => {
-                    mode = NcMode::Listen;
-                }
-                _ => {
-                    println!("Invalid argument!");
-                    return;
-                }
+    let proto = if args.flag("udp") { TransportProtocol::Udp } else { TransportProtocol::Tcp };
+    let mode = if args.flag("listen") { NcMode::Listen } else { NcMode::Connect };
+    let family = args.family();
+    let hostname = args.free.last().cloned().unwrap_or_default();
+    // Each direction has a limit of its own, which --limit-rate sets for both
+    let burst = args.size("limit-burst");
+    let rate = args.size("limit-rate");
+    let limit = |name| args.size(name).or(rate).map(|rate| Limit::new(rate, burst));
+    let limits = Limits {
+        sent: limit("limit-rate-out"),
+        received: limit("limit-rate-in"),
+    };
+    if burst.is_some() && limits == Limits::default() {
+        args.invalid("--limit-burst needs --limit-rate, --limit-rate-in or --limit-rate-out");
+    }
+
+    if args.flag("ssl") {
+        let string = |name| args.value(name).map(|value| value.to_string());
+        match (mode, proto) {
+            (_, TransportProtocol::Udp) => args.invalid("--ssl only works over TCP"),
+            (NcMode::Connect, TransportProtocol::Tcp) => {
+                let mut options = ClientOptions::new();
+                options.ca_file = string("ssl-trustfile");
+                options.verify = !args.flag("insecure");
+                options.cert = string("ssl-cert");
+                options.key = string("ssl-key");
+                connect_tls(&hostname, family, &options, limits).unwrap_or_else(|err| err.exit("nc"));
+            }
+            (NcMode::Listen, TransportProtocol::Tcp) => match (args.value("ssl-cert"), args.value("ssl-key")) {
+                (Some(cert), Some(key)) => listen_tls(&hostname, family, cert, key, limits).unwrap_or_else(|err| err.exit("nc")),
+                _ => args.invalid("--ssl with --listen needs --ssl-cert and --ssl-key")
             }
-        } else {
-            hostname = arg;
         }
+        return;
     }
 
     match (mode, proto) {
         (NcMode::Connect, TransportProtocol::Tcp) => {
-            connect_tcp(&hostname).unwrap_or_else(|e| {
-                println!("nc error: {}", e);
-            });
+            connect_tcp(&hostname, family, limits).unwrap_or_else(|err| err.exit("nc"));
         }
         (NcMode::Listen, TransportProtocol::Tcp) => {
-            listen_tcp(&hostname).unwrap_or_else(|e| {
-                println!("nc error: {}", e);
-            });
+            listen_tcp(&hostname, family, limits).unwrap_or_else(|err| err.exit("nc"));
         }
         (NcMode::Connect, TransportProtocol::Udp) => {
-            connect_udp(&hostname).unwrap_or_else(|e| {
-                println!("nc error: {}", e);
-            });
+            connect_udp(&hostname, family, limits).unwrap_or_else(|err| err.exit("nc"));
         }
         (NcMode::Listen, TransportProtocol::Udp) => {
-            listen_udp(&hostname).unwrap_or_else(|e| {
-                println!("nc error: {}", e);
-            });
+            listen_udp(&hostname, family, limits).unwrap_or_else(|err| err.exit("nc"));
         }
     }
 
@@ -1,129 +1,228 @@
-use std::io::{stdin, Read, Write};
-use std::net::{TcpStream, TcpListener, UdpSocket};
-use std::process::exit;
-use std::str;
+use netutils_core::error::{NetError, Result};
+use netutils_core::ratelimit::{Limit, Limited, TokenBucket};
+use netutils_core::resolve::{self, Family};
+use netutils_core::tls::{self, ClientOptions};
+use std::io::{stderr, stdin, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, TcpListener, UdpSocket};
+use std::{cmp, process, str};
+use std::sync::{Arc, Mutex};
 use std::thread;
-
-macro_rules! print_err {
-    ($($arg:tt)*) => (
-        {
-            use std::io::prelude::*;
-            if let Err(e) = write!(&mut ::std::io::stderr(), "{}\n", format_args!($($arg)*)) {
-                panic!("Failed to write to stderr.\
-                    \nOriginal error output: {}\
-                    \nSecondary error writing to stderr: {}", format!($($arg)*), e);
-            }
-        }
-        )
-}
+use std::time::Duration;
 
 // TODO: variable buffer size?
 const BUFFER_SIZE: usize = 65636;
 
-/// Read from the input file into a buffer in an infinite loop.
+/// How long a read of a TLS connection holds it before letting the writer in
+const POLL_INTERVAL_MS: u64 = 100;
+
+/// The limits of what is sent and what is received, each held to a bucket of its own
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    pub sent: Option<Limit>,
+    pub received: Option<Limit>,
+}
+
+/// Print that the input ended, which ends nc successfully
+fn end_of_input() {
+    let _ = writeln!(stderr(), "End of input file/socket.");
+}
+
+/// Read from the input file into a buffer until it ends.
 /// Handle the buffer content with handler function.
-fn rw_loop<R, F>(input: &mut R, mut handler: F) -> ! 
-    where R: Read, F: FnMut(&[u8], usize) -> ()
+fn rw_loop<R, F>(input: &mut R, mut handler: F) -> Result<()>
+    where R: Read, F: FnMut(&[u8]) -> Result<()>
 {
+    let mut buffer = [0u8; BUFFER_SIZE];
     loop {
-        let mut buffer = [0u8; BUFFER_SIZE];
-        // TODO: improve error messages
-        let count  = match input.read(&mut buffer) {
+        let count = match input.read(&mut buffer) {
             Ok(0) => {
-                print_err!("End of input file/socket.");
-                exit(0);
+                end_of_input();
+                return Ok(());
             }
             Ok(c) => c,
-            Err(_) => {
-                print_err!("Error occurred while reading from file/socket.");
-                exit(1);
-            }
+            Err(e) => return Err(NetError::from(e).context("failed to read"))
         };
-        handler(&buffer, count);
+        handler(&buffer[..count])?;
+    }
+}
+
+/// Write what was read from the connection to standard output
+fn print_received(buffer: &[u8]) -> Result<()> {
+    print!("{}", unsafe { str::from_utf8_unchecked(buffer) });
+    Ok(())
+}
+
+/// End nc from the thread that reads the connection, since the other thread is waiting for
+/// standard input
+fn exit_reader(result: Result<()>) -> ! {
+    match result {
+        Ok(()) => process::exit(0),
+        Err(err) => err.exit("nc")
+    }
+}
+
+/// The host and the port of a `hostname:port` operand
+fn split(host: &str) -> Result<(&str, u16)> {
+    match resolve::split_port(host)? {
+        (name, Some(port)) => Ok((name, port)),
+        (_, None) => Err(NetError::Usage(format!("no port in '{}'", host)))
+    }
+}
+
+/// The addresses of a `hostname:port` operand. The host may be left out, as in `:8080`, for
+/// the unspecified address, which listens on every interface.
+fn addrs(host: &str, family: Family) -> Result<Vec<SocketAddr>> {
+    let (name, port) = split(host)?;
+    if name.is_empty() {
+        return Ok(vec![SocketAddr::new(family.unspecified(), port)]);
     }
+    resolve::addrs(name, port, family)
 }
 
-/// Use the rw_loop in both direction (TCP connection)
-fn both_dir_rw_loop(mut stream_read: TcpStream, mut stream_write: TcpStream) -> Result<(), String> {
+/// Use the rw_loop in both direction (TCP connection), each held to its own limit
+fn both_dir_rw_loop(stream_read: TcpStream, mut stream_write: TcpStream, limits: Limits) -> Result<()> {
     // Read loop
     thread::spawn(move || {
-        rw_loop(&mut stream_read, |buffer, count| {
-            print!("{}", unsafe { str::from_utf8_unchecked(&buffer[..count]) });
-        });
+        exit_reader(rw_loop(&mut Limited::new(stream_read, limits.received), print_received));
     });
 
     // Write loop
-    let mut stdin = stdin();
-    rw_loop(&mut stdin, |buffer, count| {
-        let _ = stream_write.write(&buffer[..count]).unwrap_or_else(|e| {
-            print_err!("Error occurred while writing into socket: {} ", e);
-            exit(1);
-        });
+    rw_loop(&mut Limited::new(stdin(), limits.sent), |buffer| {
+        stream_write.write_all(buffer).map_err(|e| NetError::from(e).context("failed to write into socket"))
+    })
+}
+
+/// Use the rw_loop in both directions over a TLS connection, which can't be cloned, so the
+/// directions take turns with it. Each direction is held to its own limit.
+fn shared_rw_loop<S: Read + Write + Send + 'static>(stream: S, limits: Limits) -> Result<()> {
+    let stream = Arc::new(Mutex::new(stream));
+
+    // Read loop, which waits for the bucket without holding the stream
+    let stream_read = stream.clone();
+    let received = limits.received.map(TokenBucket::new);
+    let len = limits.received.map_or(BUFFER_SIZE, |limit| cmp::min(limit.burst, BUFFER_SIZE as u64) as usize);
+    thread::spawn(move || {
+        let mut buffer = [0u8; BUFFER_SIZE];
+        loop {
+            let result = stream_read.lock().unwrap().read(&mut buffer[..len]);
+            match result {
+                Ok(0) => {
+                    end_of_input();
+                    exit_reader(Ok(()));
+                }
+                Ok(count) => {
+                    if let Some(ref received) = received {
+                        received.take(count);
+                    }
+                    let _ = print_received(&buffer[..count]);
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => (),
+                Err(e) => exit_reader(Err(NetError::from(e).context("failed to read from socket")))
+            }
+        }
     });
+
+    // Write loop
+    rw_loop(&mut Limited::new(stdin(), limits.sent), |buffer| {
+        let mut stream = stream.lock().unwrap();
+        stream.write_all(buffer).and_then(|_| stream.flush())
+            .map_err(|e| NetError::from(e).context("failed to write into socket"))
+    })
 }
 
 /// Connect to listening TCP socket
-pub fn connect_tcp(host: &str) -> Result<(), String> {
+pub fn connect_tcp(host: &str, family: Family, limits: Limits) -> Result<()> {
     // Open socket and create its clone
-    let stream_read = try!(TcpStream::connect(host)
-                           .map_err(|e| {format!("connect_tcp error: can not create socket ({})", e)}));
+    let (name, port) = split(host)?;
+    if name.is_empty() {
+        return Err(NetError::Usage(format!("no host in '{}'", host)));
+    }
+    let stream_read = try!(resolve::connect(name, port, family, None));
     let stream_write = try!(stream_read.try_clone()
-                            .map_err(|e| {format!("connect_tcp error: can not create socket clone ({})", e)}));
+                            .map_err(|e| NetError::from(e).context("can not create socket clone")));
 
-    print_err!("Remote host: {}", host);
+    let _ = writeln!(stderr(), "Remote host: {}", host);
 
-    both_dir_rw_loop(stream_read, stream_write)
+    both_dir_rw_loop(stream_read, stream_write, limits)
 
 }
 
 /// Listen on specified port and accept the first incoming connection
 /// NOTE: "-k Accept multiple connections in listen mode" is not implemented
-pub fn listen_tcp(host: &str) -> Result<(), String> {
-    let listener = try!(TcpListener::bind(host)
-                        .map_err(|e| {format!("connect_tcp error: can not bind to specified port ({})", e)}));
+pub fn listen_tcp(host: &str, family: Family, limits: Limits) -> Result<()> {
+    let listener = try!(TcpListener::bind(&addrs(host, family)?[..])
+                        .map_err(|e| NetError::from(e).context("can not bind to specified port")));
     let (stream_read, socketaddr) = try!(listener.accept()
-                                         .map_err(|e| {format!("connect_tcp error: can not establish connection ({})", e)}));
+                                         .map_err(|e| NetError::from(e).context("can not establish connection")));
     let stream_write = try!(stream_read.try_clone()
-                            .map_err(|e| {format!("connect_tcp error: can not create socket clone ({})", e)}));
-    print_err!("Incoming connection from: {}", socketaddr);
-    both_dir_rw_loop(stream_read, stream_write)
+                            .map_err(|e| NetError::from(e).context("can not create socket clone")));
+    let _ = writeln!(stderr(), "Incoming connection from: {}", socketaddr);
+    both_dir_rw_loop(stream_read, stream_write, limits)
+}
+
+/// Connect to a TLS server, checking its certificate as the options say
+pub fn connect_tls(host: &str, family: Family, options: &ClientOptions, limits: Limits) -> Result<()> {
+    let config = try!(tls::client_config(options).map_err(|e| NetError::Io(e).context("can not load TLS settings")));
+    let (name, port) = split(host)?;
+    if name.is_empty() {
+        return Err(NetError::Usage(format!("no host in '{}'", host)));
+    }
+    let tcp = try!(resolve::connect(name, port, family, None));
+    try!(tcp.set_read_timeout(Some(Duration::from_millis(POLL_INTERVAL_MS))));
+
+    let _ = writeln!(stderr(), "Remote host: {}", host);
+
+    shared_rw_loop(tls::connect(&config, tcp, name), limits)
+}
+
+/// Listen on specified port and accept the first incoming connection with TLS, presenting the
+/// certificate and key of PEM files
+pub fn listen_tls(host: &str, family: Family, cert: &str, key: &str, limits: Limits) -> Result<()> {
+    let config = try!(tls::server_config(cert, key).map_err(|e| NetError::Io(e).context("can not load TLS certificate")));
+    let listener = try!(TcpListener::bind(&addrs(host, family)?[..])
+                        .map_err(|e| NetError::from(e).context("can not bind to specified port")));
+    let (tcp, socketaddr) = try!(listener.accept()
+                                 .map_err(|e| NetError::from(e).context("can not establish connection")));
+    try!(tcp.set_read_timeout(Some(Duration::from_millis(POLL_INTERVAL_MS))));
+    let _ = writeln!(stderr(), "Incoming connection from: {}", socketaddr);
+    shared_rw_loop(tls::accept(&Arc::new(config), tcp), limits)
 }
 
 /// Send UDP datagrams to specified socket
-pub fn connect_udp(host: &str) -> Result<(), String> {
-    // TODO: Implement some port selection process (while loop?)
-    let socket = try!(UdpSocket::bind("localhost:30000")
-                      .map_err(|e| {format!("connect_udp error: could not bind to local socket ({})", e)}));
-    try!(socket.connect(host)
-         .map_err(|e| {format!("connect_udp error: could not set up remote socket ({})", e)}));
-
-    let mut stdin = stdin();
-    rw_loop(&mut stdin, |buffer, count| {
-        let _ = socket.send(&buffer[..count]).unwrap_or_else(|e| {
-            print_err!("Error occurred while writing into socket: {} ", e);
-            exit(1);
-        });
-    });
+pub fn connect_udp(host: &str, family: Family, limits: Limits) -> Result<()> {
+    let remote = addrs(host, family)?;
+    // The local socket has to be of the family of the remote one
+    let local = if remote[0].is_ipv4() { Family::V4 } else { Family::V6 }.unspecified();
+    let socket = try!(UdpSocket::bind((local, 0))
+                      .map_err(|e| NetError::from(e).context("could not bind to local socket")));
+    try!(socket.connect(&remote[..])
+         .map_err(|e| NetError::from(e).context("could not set up remote socket")));
+
+    rw_loop(&mut Limited::new(stdin(), limits.sent), |buffer| {
+        socket.send(buffer).map(|_| ()).map_err(|e| NetError::from(e).context("failed to write into socket"))
+    })
 }
 
-/// Listen for UDP datagrams on the specified socket
-pub fn listen_udp(host: &str) -> Result<(), String> {
-    let socket = try!(UdpSocket::bind(host)
-                      .map_err(|e| {format!("connect_udp error: could not bind to local socket ({})", e)}));
+/// Listen for UDP datagrams on the specified socket. Datagrams can't be split, so a limit on
+/// what is received holds back the next one until the last is paid for.
+pub fn listen_udp(host: &str, family: Family, limits: Limits) -> Result<()> {
+    let socket = try!(UdpSocket::bind(&addrs(host, family)?[..])
+                      .map_err(|e| NetError::from(e).context("could not bind to local socket")));
+    let received = limits.received.map(TokenBucket::new);
+    let mut buffer = [0u8; BUFFER_SIZE];
     loop {
-        let mut buffer = [0u8; BUFFER_SIZE];
-        let count  = match socket.recv_from(&mut buffer) {
+        let count = match socket.recv_from(&mut buffer) {
             Ok((0, _)) => {
-                print_err!("End of input file/socket.");
-                exit(0);
+                end_of_input();
+                return Ok(());
             }
             Ok((c, _)) => c,
-            Err(_) => {
-                print_err!("Error occurred while reading from file/socket.");
-                exit(1);
-            }
+            Err(e) => return Err(NetError::from(e).context("failed to read from socket"))
         };
-        print!("{}", unsafe { str::from_utf8_unchecked(&buffer[..count]) });
+        if let Some(ref received) = received {
+            received.take(count);
+        }
+        print_received(&buffer[..count])?;
     }
 }
 
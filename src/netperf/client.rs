@@ -0,0 +1,163 @@
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result, Write};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use proto::{Header, Protocol, StreamResult, Test};
+use stats::{as_secs, format_bytes, format_rate};
+
+/// Time for the last datagrams to arrive before the server is told that the test is done
+const UDP_GRACE_MS: u64 = 250;
+
+pub struct Options {
+    pub test: Test,
+    /// Bits per second of all UDP streams together
+    pub rate: u64,
+}
+
+fn read_line(reader: &mut BufRead) -> Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "the server closed the connection"));
+    }
+    Ok(line.trim_right().to_string())
+}
+
+fn send_tcp(addr: SocketAddr, token: &str, length: usize, deadline: Instant, sent: Arc<AtomicUsize>) -> Result<()> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.write_all(format!("STREAM {}\n", token).as_bytes())?;
+    let buffer = vec![0x5A; length];
+    while Instant::now() < deadline {
+        stream.write_all(&buffer)?;
+        sent.fetch_add(length, Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+/// Send datagrams at `rate` bits per second, returning how many were sent
+fn send_udp(addr: SocketAddr, id: u32, length: usize, rate: u64, deadline: Instant, sent: Arc<AtomicUsize>) -> Result<u64> {
+    let socket = UdpSocket::bind(if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+    socket.connect(addr)?;
+
+    let interval = length as f64 * 8.0 / rate as f64;
+    let start = Instant::now();
+    let mut buffer = vec![0x5A; length];
+    let mut seq = 0;
+    while Instant::now() < deadline {
+        Header {
+            stream: id,
+            seq: seq,
+            sent: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0)),
+        }.write(&mut buffer);
+        // A full buffer is retried, rather than counted as lost
+        match socket.send(&buffer) {
+            Ok(_) => (),
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => continue,
+            Err(err) => return Err(err)
+        }
+        seq += 1;
+        sent.fetch_add(length, Ordering::SeqCst);
+
+        // Datagrams are paced from the start, so that a late one is made up for
+        let next = interval * seq as f64;
+        let elapsed = as_secs(start.elapsed());
+        if next > elapsed {
+            let wait = next - elapsed;
+            thread::sleep(Duration::new(wait as u64, ((wait % 1.0) * 1e9) as u32));
+        }
+    }
+    Ok(seq)
+}
+
+pub fn run(addr: SocketAddr, options: &Options) -> Result<()> {
+    let test = options.test;
+    let mut control = TcpStream::connect(addr)?;
+    let mut reader = BufReader::new(control.try_clone()?);
+    control.write_all(test.to_line().as_bytes())?;
+    let line = read_line(&mut reader)?;
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() != 2 || parts[0] != "OK" {
+        return Err(Error::new(ErrorKind::InvalidData, format!("the server refused the test: {}", line)));
+    }
+    let token = parts[1].to_string();
+    println!("connected to {}, {} with {} streams for {} s", addr, test.protocol.name(), test.streams, test.duration);
+
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(test.duration);
+    let sent = Arc::new(AtomicUsize::new(0));
+    let mut handles = Vec::new();
+    for id in 0..test.streams {
+        let sent = sent.clone();
+        let token = token.clone();
+        // The streams share the rate, the first ones sending a bit a second more for what
+        // doesn't divide evenly
+        let streams = test.streams as u64;
+        let rate = options.rate / streams + if (id as u64) < options.rate % streams { 1 } else { 0 };
+        handles.push(thread::spawn(move || match test.protocol {
+            Protocol::Tcp => send_tcp(addr, &token, test.length, deadline, sent).map(|_| 0),
+            Protocol::Udp => send_udp(addr, id, test.length, rate, deadline, sent),
+        }));
+    }
+
+    // Report the throughput of each second while the streams run
+    let mut last = 0;
+    for second in 0..test.duration {
+        let next = start + Duration::from_secs(second + 1);
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        }
+        let total = sent.load(Ordering::SeqCst) as u64;
+        println!("[{:>4}-{:<4} s] {:>14} {:>16}", second, second + 1, format_bytes(total - last), format_rate(total - last, Duration::from_secs(1)));
+        last = total;
+    }
+
+    let mut datagrams = 0;
+    for handle in handles {
+        match handle.join() {
+            Ok(Ok(count)) => datagrams += count,
+            Ok(Err(err)) => return Err(err),
+            Err(_) => return Err(Error::new(ErrorKind::Other, "a stream panicked"))
+        }
+    }
+    let elapsed = start.elapsed();
+    if test.protocol == Protocol::Udp {
+        thread::sleep(Duration::from_millis(UDP_GRACE_MS));
+    }
+
+    control.write_all(b"DONE\n")?;
+    let mut results = Vec::new();
+    loop {
+        let line = read_line(&mut reader)?;
+        if line == "END" {
+            break;
+        }
+        results.push(StreamResult::parse(&line).ok_or(Error::new(ErrorKind::InvalidData, format!("invalid result: {}", line)))?);
+    }
+
+    let total = sent.load(Ordering::SeqCst) as u64;
+    let received: u64 = results.iter().map(|result| result.bytes).sum();
+    println!("sender:   {:>14} {:>16}", format_bytes(total), format_rate(total, elapsed));
+    println!("receiver: {:>14} {:>16}", format_bytes(received), format_rate(received, elapsed));
+
+    if test.protocol == Protocol::Udp {
+        for result in results.iter().filter(|_| results.len() > 1) {
+            println!("  stream {}: jitter {:.3} ms, lost {}/{}, {} out of order", result.id, as_secs(result.jitter) * 1000.0,
+                     result.lost, result.lost + result.packets, result.out_of_order);
+        }
+        // Datagrams lost at the end of a stream have no later sequence number to reveal them
+        let packets: u64 = results.iter().map(|result| result.packets).sum();
+        let lost = datagrams.saturating_sub(packets);
+        let jitter = if results.is_empty() {
+            0.0
+        } else {
+            results.iter().map(|result| as_secs(result.jitter)).sum::<f64>() / results.len() as f64
+        };
+        let out_of_order: u64 = results.iter().map(|result| result.out_of_order).sum();
+        println!("jitter {:.3} ms, lost {}/{} ({:.2}%), {} out of order", jitter * 1000.0, lost, datagrams,
+                 if datagrams > 0 { lost as f64 * 100.0 / datagrams as f64 } else { 0.0 }, out_of_order);
+    }
+    Ok(())
+}
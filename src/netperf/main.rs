@@ -0,0 +1,147 @@
+extern crate netutils_core;
+
+use netutils_core::args::Parser;
+use netutils_core::error::NetError;
+use netutils_core::resolve;
+
+use client::Options;
+use proto::{Protocol, Test, HEADER_LEN};
+
+mod client;
+mod proto;
+mod server;
+mod stats;
+
+static NETPERF_MAN: &'static str = /* @MANSTART{netperf} */ r#"
+NAME
+    netperf - measure network throughput
+
+SYNOPSIS
+    netperf -s [-4 | -6] [-p port]
+    netperf -c host [-4 | -6] [-p port] [-u] [-t seconds] [-P streams] [-l length] [-b rate]
+    netperf [-h | --help]
+
+DESCRIPTION
+    A netperf client sends data to a netperf server for a while, over TCP or
+    UDP, showing the throughput of each second and then of the whole test
+    as measured by both ends. For UDP the server also measures the jitter
+    of the datagrams, how many were lost and how many came out of order.
+
+    The server runs one test at a time, and keeps running until it is
+    interrupted.
+
+OPTIONS
+    -s
+        Run a server.
+
+    -c host
+        Run a client, testing against the server on host.
+
+    -4
+    -6
+        Use only IPv4 or only IPv6 addresses. The server listens on IPv4 by
+        default, and on IPv6 with -6.
+
+    -p port
+        The port of the server, 5201 by default. UDP tests use the UDP port
+        of the same number.
+
+    -u
+        Test with UDP instead of TCP.
+
+    -t seconds
+        Send for this many seconds, 10 by default.
+
+    -P streams
+        Send this many streams in parallel, 1 by default.
+
+    -l length
+        Bytes of each write, 128 KiB by default, or of each datagram, 1470
+        by default.
+
+    -b rate
+        The bits per second of UDP tests, which may end with K, M or G, 1M
+        by default, shared by the streams. TCP tests send as fast as the
+        connection allows.
+
+    -h
+    --help
+        Print this manual page.
+"#; /* @MANEND */
+
+const DEFAULT_PORT: u16 = 5201;
+const DEFAULT_DURATION: u64 = 10;
+const DEFAULT_TCP_LENGTH: usize = 128 * 1024;
+const DEFAULT_UDP_LENGTH: usize = 1470;
+const DEFAULT_UDP_RATE: u64 = 1000 * 1000;
+
+fn main() {
+    let args = Parser::new("netperf")
+        .synopsis("-s [-4 | -6] [-p port]")
+        .synopsis("-c host [-4 | -6] [-p port] [-u] [-t seconds] [-P streams] [-l length] [-b rate]")
+        .man(NETPERF_MAN)
+        .flag("s", "", "Run the server")
+        .opt("c", "", "host", "Run a test against the server on host")
+        .family()
+        .opt("p", "", "port", "The port of the server")
+        .flag("u", "", "Test UDP, rather than TCP")
+        .opt("t", "", "seconds", "How long the test runs for")
+        .opt("P", "", "streams", "Number of parallel streams")
+        .opt("l", "", "length", "Bytes in each write, or in each datagram")
+        .opt("b", "", "rate", "Bits per second to send over UDP, such as 10M")
+        .parse_env();
+    if !args.free.is_empty() {
+        args.usage();
+    }
+
+    let server = args.flag("s");
+    let host = args.value("c");
+    let family = args.family();
+    let port = args.parse("p").unwrap_or(DEFAULT_PORT);
+    let protocol = if args.flag("u") { Protocol::Udp } else { Protocol::Tcp };
+    let duration = args.parse("t").unwrap_or(DEFAULT_DURATION);
+    let streams = match args.parse::<u32>("P") {
+        Some(0) => args.invalid("there must be at least one stream"),
+        Some(streams) => streams,
+        None => 1
+    };
+    let length = args.parse::<usize>("l");
+    let rate = match args.value("b") {
+        Some(value) => stats::parse_rate(value).unwrap_or_else(|| args.invalid(&format!("invalid rate '{}'", value))),
+        None => DEFAULT_UDP_RATE
+    };
+    // Each stream sends its share of the rate, and one with none would never send
+    if protocol == Protocol::Udp && rate < streams as u64 {
+        args.invalid(&format!("a rate of {} bits per second can't be shared by {} streams", rate, streams));
+    }
+
+    if server {
+        if host.is_some() {
+            args.usage();
+        }
+        if let Err(err) = server::serve(family, port) {
+            NetError::from(err).context(&format!("failed to serve on port {}", port)).exit("netperf");
+        }
+        return;
+    }
+
+    let host = host.unwrap_or_else(|| args.usage());
+    let length = length.unwrap_or(if protocol == Protocol::Udp { DEFAULT_UDP_LENGTH } else { DEFAULT_TCP_LENGTH });
+    if length == 0 || (protocol == Protocol::Udp && length < HEADER_LEN) {
+        args.invalid(&format!("the length must be at least {} bytes for UDP, and more than 0 for TCP", HEADER_LEN));
+    }
+    let addr = resolve::resolve(host, port, family).unwrap_or_else(|err| err.exit("netperf"));
+
+    let options = Options {
+        test: Test {
+            protocol: protocol,
+            streams: streams,
+            duration: duration,
+            length: length,
+        },
+        rate: rate,
+    };
+    if let Err(err) = client::run(addr, &options) {
+        NetError::from(err).context("test failed").exit("netperf");
+    }
+}
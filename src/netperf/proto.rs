@@ -0,0 +1,165 @@
+//! The messages between the client and server. A test is set up over a control connection:
+//!
+//! ```text
+//! client: TEST tcp|udp STREAMS SECONDS LENGTH
+//! server: OK TOKEN
+//! ```
+//!
+//! For TCP the client then opens a connection for each stream, which starts with
+//! `STREAM TOKEN`. For UDP each stream sends datagrams to the same port as the control
+//! connection, starting with a header from `Header`. When the client is done sending it sends
+//! `DONE`, and the server answers with a `RESULT ID BYTES PACKETS LOST JITTER_US OUT_OF_ORDER`
+//! line for each stream that it received from, followed by `END`.
+
+use std::time::Duration;
+
+/// Size of the header of each UDP datagram
+pub const HEADER_LEN: usize = 24;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Test {
+    pub protocol: Protocol,
+    pub streams: u32,
+    pub duration: u64,
+    /// Bytes of each write, or of each datagram
+    pub length: usize,
+}
+
+impl Test {
+    pub fn to_line(&self) -> String {
+        format!("TEST {} {} {} {}\n", self.protocol.name(), self.streams, self.duration, self.length)
+    }
+
+    pub fn parse(line: &str) -> Option<Test> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 5 || parts[0] != "TEST" {
+            return None;
+        }
+        let protocol = match parts[1] {
+            "tcp" => Protocol::Tcp,
+            "udp" => Protocol::Udp,
+            _ => return None
+        };
+        let test = Test {
+            protocol: protocol,
+            streams: parts[2].parse().ok()?,
+            duration: parts[3].parse().ok()?,
+            length: parts[4].parse().ok()?,
+        };
+        if test.streams == 0 || (protocol == Protocol::Udp && test.length < HEADER_LEN) {
+            return None;
+        }
+        Some(test)
+    }
+}
+
+/// The result of one stream, as measured by the server
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StreamResult {
+    pub id: u32,
+    pub bytes: u64,
+    pub packets: u64,
+    pub lost: u64,
+    pub jitter: Duration,
+    pub out_of_order: u64,
+}
+
+impl StreamResult {
+    pub fn to_line(&self) -> String {
+        let jitter = self.jitter.as_secs() * 1_000_000 + self.jitter.subsec_nanos() as u64 / 1000;
+        format!("RESULT {} {} {} {} {} {}\n", self.id, self.bytes, self.packets, self.lost, jitter, self.out_of_order)
+    }
+
+    pub fn parse(line: &str) -> Option<StreamResult> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 7 || parts[0] != "RESULT" {
+            return None;
+        }
+        let jitter = parts[5].parse::<u64>().ok()?;
+        Some(StreamResult {
+            id: parts[1].parse().ok()?,
+            bytes: parts[2].parse().ok()?,
+            packets: parts[3].parse().ok()?,
+            lost: parts[4].parse().ok()?,
+            jitter: Duration::new(jitter / 1_000_000, (jitter % 1_000_000) as u32 * 1000),
+            out_of_order: parts[6].parse().ok()?,
+        })
+    }
+}
+
+/// The start of each UDP datagram: the stream, the sequence number in the stream and the time
+/// since the Unix epoch that it was sent at, in network byte order
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Header {
+    pub stream: u32,
+    pub seq: u64,
+    pub sent: Duration,
+}
+
+impl Header {
+    pub fn write(&self, buf: &mut [u8]) {
+        let secs = self.sent.as_secs();
+        let nanos = self.sent.subsec_nanos();
+        for i in 0..4 {
+            buf[i] = (self.stream >> (24 - i * 8)) as u8;
+            buf[20 + i] = (nanos >> (24 - i * 8)) as u8;
+        }
+        for i in 0..8 {
+            buf[4 + i] = (self.seq >> (56 - i * 8)) as u8;
+            buf[12 + i] = (secs >> (56 - i * 8)) as u8;
+        }
+    }
+
+    pub fn read(buf: &[u8]) -> Option<Header> {
+        if buf.len() < HEADER_LEN {
+            return None;
+        }
+        let read = |data: &[u8]| data.iter().fold(0u64, |value, &b| value << 8 | b as u64);
+        let nanos = read(&buf[20..24]) as u32;
+        if nanos >= 1_000_000_000 {
+            return None;
+        }
+        Some(Header {
+            stream: read(&buf[0..4]) as u32,
+            seq: read(&buf[4..12]),
+            sent: Duration::new(read(&buf[12..20]), nanos),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use super::{Header, Protocol, StreamResult, Test, HEADER_LEN};
+
+    #[test]
+    fn messages_test() {
+        let test = Test { protocol: Protocol::Udp, streams: 2, duration: 10, length: 1470 };
+        assert_eq!(test.to_line(), "TEST udp 2 10 1470\n");
+        assert_eq!(Test::parse(&test.to_line()), Some(test));
+        assert_eq!(Test::parse("TEST udp 2 10 8"), None);
+
+        let result = StreamResult { id: 1, bytes: 1000, packets: 10, lost: 1, jitter: Duration::new(0, 250_000), out_of_order: 2 };
+        assert_eq!(StreamResult::parse(&result.to_line()), Some(result));
+
+        let header = Header { stream: 3, seq: 1 << 40, sent: Duration::new(1_500_000_000, 999) };
+        let mut buf = [0; HEADER_LEN];
+        header.write(&mut buf);
+        assert_eq!(Header::read(&buf), Some(header));
+    }
+}
@@ -0,0 +1,192 @@
+use netutils_core::resolve::Family;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Read, Result, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use proto::{Header, Protocol, StreamResult, Test};
+use stats::{format_bytes, format_rate, UdpStats};
+
+/// Seconds to wait for the streams of a test to connect
+const STREAM_TIMEOUT: u64 = 10;
+
+/// Seconds past the duration of a test after which the client is given up on
+const GRACE: u64 = 10;
+
+fn read_line(reader: &mut BufRead) -> Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed"));
+    }
+    Ok(line.trim_right().to_string())
+}
+
+/// Read the first line of a stream a byte at a time, so that none of the data after it is taken
+fn read_stream_line(stream: &mut TcpStream) -> Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0; 1];
+    while line.len() < 64 {
+        if stream.read(&mut byte)? == 0 {
+            break;
+        }
+        if byte[0] == b'\n' {
+            return Ok(String::from_utf8_lossy(&line).into_owned());
+        }
+        line.push(byte[0]);
+    }
+    Err(Error::new(ErrorKind::InvalidData, "invalid stream"))
+}
+
+/// Accept the connections of the streams of a TCP test, which identify themselves by the token
+fn accept_streams(listener: &TcpListener, token: u64, count: u32) -> Result<Vec<TcpStream>> {
+    let deadline = Instant::now() + Duration::from_secs(STREAM_TIMEOUT);
+    listener.set_nonblocking(true)?;
+    let mut streams = Vec::new();
+    while streams.len() < count as usize {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(Duration::from_secs(STREAM_TIMEOUT)))?;
+                match read_stream_line(&mut stream) {
+                    Ok(ref line) if *line == format!("STREAM {}", token) => {
+                        stream.set_read_timeout(None)?;
+                        streams.push(stream);
+                    },
+                    // Clients of another test are turned away
+                    _ => continue
+                }
+            },
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    listener.set_nonblocking(false)?;
+                    return Err(Error::new(ErrorKind::TimedOut, "streams did not connect"));
+                }
+                thread::sleep(Duration::from_millis(10));
+            },
+            Err(err) => {
+                listener.set_nonblocking(false)?;
+                return Err(err);
+            }
+        }
+    }
+    listener.set_nonblocking(false)?;
+    Ok(streams)
+}
+
+/// Receive the TCP streams until the client closes them
+fn receive_tcp(streams: Vec<TcpStream>) -> Vec<thread::JoinHandle<StreamResult>> {
+    streams.into_iter().enumerate().map(|(id, mut stream)| thread::spawn(move || {
+        let mut buffer = vec![0; 128 * 1024];
+        let mut result = StreamResult {
+            id: id as u32,
+            ..StreamResult::default()
+        };
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(count) => result.bytes += count as u64
+            }
+        }
+        result
+    })).collect()
+}
+
+/// Receive datagrams until `done` is set, keeping the statistics of each stream
+fn receive_udp(socket: UdpSocket, done: Arc<AtomicBool>) -> thread::JoinHandle<Vec<StreamResult>> {
+    thread::spawn(move || {
+        let mut buffer = vec![0; 65536];
+        let mut streams: BTreeMap<u32, UdpStats> = BTreeMap::new();
+        while !done.load(Ordering::SeqCst) {
+            let count = match socket.recv_from(&mut buffer) {
+                Ok((count, _)) => count,
+                Err(_) => continue
+            };
+            let received = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+            if let Some(header) = Header::read(&buffer[..count]) {
+                streams.entry(header.stream).or_insert_with(UdpStats::default)
+                    .receive(header.seq, header.sent, received, count);
+            }
+        }
+        streams.into_iter().map(|(id, stats)| StreamResult {
+            id: id,
+            bytes: stats.bytes,
+            packets: stats.packets,
+            lost: stats.lost(),
+            jitter: stats.jitter(),
+            out_of_order: stats.out_of_order,
+        }).collect()
+    })
+}
+
+/// Run one test for the client of `control`
+fn run(listener: &TcpListener, mut control: TcpStream) -> Result<()> {
+    let peer = control.peer_addr()?;
+    control.set_read_timeout(Some(Duration::from_secs(STREAM_TIMEOUT)))?;
+    let mut reader = BufReader::new(control.try_clone()?);
+    let test = match Test::parse(&read_line(&mut reader)?) {
+        Some(test) => test,
+        None => {
+            control.write_all(b"ERROR invalid test\n")?;
+            return Err(Error::new(ErrorKind::InvalidData, "invalid test"));
+        }
+    };
+    println!("test from {}: {} with {} streams for {} s", peer, test.protocol.name(), test.streams, test.duration);
+
+    let token = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.subsec_nanos() as u64 ^ time.as_secs()).unwrap_or(0);
+    let start = Instant::now();
+    let results = match test.protocol {
+        Protocol::Tcp => {
+            control.write_all(format!("OK {}\n", token).as_bytes())?;
+            let handles = receive_tcp(accept_streams(listener, token, test.streams)?);
+
+            control.set_read_timeout(Some(Duration::from_secs(test.duration + GRACE)))?;
+            let done = read_line(&mut reader);
+            let results: Vec<StreamResult> = handles.into_iter().filter_map(|handle| handle.join().ok()).collect();
+            done?;
+            results
+        },
+        Protocol::Udp => {
+            // Datagrams are received on the port of the control connection
+            let socket = UdpSocket::bind(control.local_addr()?)?;
+            socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+            let done = Arc::new(AtomicBool::new(false));
+            let handle = receive_udp(socket, done.clone());
+            control.write_all(format!("OK {}\n", token).as_bytes())?;
+
+            control.set_read_timeout(Some(Duration::from_secs(test.duration + GRACE)))?;
+            let line = read_line(&mut reader);
+            done.store(true, Ordering::SeqCst);
+            let results = handle.join().unwrap_or(Vec::new());
+            line?;
+            results
+        }
+    };
+    let elapsed = start.elapsed();
+
+    let mut reply = String::new();
+    let mut bytes = 0;
+    for result in results.iter() {
+        reply.push_str(&result.to_line());
+        bytes += result.bytes;
+    }
+    reply.push_str("END\n");
+    control.write_all(reply.as_bytes())?;
+
+    println!("test from {}: received {} at {}", peer, format_bytes(bytes), format_rate(bytes, elapsed));
+    Ok(())
+}
+
+/// Run tests one at a time, forever
+pub fn serve(family: Family, port: u16) -> Result<()> {
+    let listener = TcpListener::bind((family.unspecified(), port))?;
+    println!("listening on port {}", port);
+    loop {
+        let (control, _) = listener.accept()?;
+        if let Err(err) = run(&listener, control) {
+            let _ = writeln!(io::stderr(), "netperf: test failed: {}", err);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+pub fn as_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
+/// What a receiver measured of one UDP stream
+#[derive(Clone, Debug, Default)]
+pub struct UdpStats {
+    pub bytes: u64,
+    pub packets: u64,
+    /// Datagrams that came after one with a higher sequence number
+    pub out_of_order: u64,
+    next_seq: u64,
+    /// Interarrival jitter in seconds, estimated as in RFC 3550 section 6.4.1
+    jitter: f64,
+    last_transit: Option<f64>,
+}
+
+impl UdpStats {
+    /// Count a datagram of `len` bytes, with the times it was sent and received. The clocks of
+    /// the hosts don't need to agree, since only differences of transit times are used.
+    pub fn receive(&mut self, seq: u64, sent: Duration, received: Duration, len: usize) {
+        self.bytes += len as u64;
+        self.packets += 1;
+        if seq < self.next_seq {
+            self.out_of_order += 1;
+        } else {
+            self.next_seq = seq + 1;
+        }
+
+        let transit = as_secs(received) - as_secs(sent);
+        if let Some(last) = self.last_transit {
+            self.jitter += ((transit - last).abs() - self.jitter) / 16.0;
+        }
+        self.last_transit = Some(transit);
+    }
+
+    /// Datagrams that were sent, judging by the sequence numbers, and never arrived
+    pub fn lost(&self) -> u64 {
+        self.next_seq.saturating_sub(self.packets)
+    }
+
+    pub fn jitter(&self) -> Duration {
+        let micros = (self.jitter * 1_000_000.0) as u64;
+        Duration::new(micros / 1_000_000, (micros % 1_000_000) as u32 * 1000)
+    }
+}
+
+/// Parse a rate in bits per second, such as 100M, with K, M and G being powers of 1000
+pub fn parse_rate(value: &str) -> Option<u64> {
+    let (number, multiplier) = match value.chars().last() {
+        Some('k') | Some('K') => (&value[..value.len() - 1], 1000),
+        Some('m') | Some('M') => (&value[..value.len() - 1], 1000 * 1000),
+        Some('g') | Some('G') => (&value[..value.len() - 1], 1000 * 1000 * 1000),
+        _ => (value, 1)
+    };
+    let number = number.parse::<f64>().ok()?;
+    if number > 0.0 {
+        Some((number * multiplier as f64) as u64)
+    } else {
+        None
+    }
+}
+
+pub fn format_rate(bytes: u64, elapsed: Duration) -> String {
+    let secs = as_secs(elapsed);
+    let bits = if secs > 0.0 { bytes as f64 * 8.0 / secs } else { 0.0 };
+    if bits >= 1e9 {
+        format!("{:.2} Gbit/s", bits / 1e9)
+    } else if bits >= 1e6 {
+        format!("{:.2} Mbit/s", bits / 1e6)
+    } else {
+        format!("{:.2} Kbit/s", bits / 1e3)
+    }
+}
+
+/// Sizes are in powers of 1024, as is usual for amounts of data
+pub fn format_bytes(bytes: u64) -> String {
+    let bytes = bytes as f64;
+    if bytes >= 1024.0 * 1024.0 * 1024.0 {
+        format!("{:.2} GBytes", bytes / (1024.0 * 1024.0 * 1024.0))
+    } else if bytes >= 1024.0 * 1024.0 {
+        format!("{:.2} MBytes", bytes / (1024.0 * 1024.0))
+    } else {
+        format!("{:.2} KBytes", bytes / 1024.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use super::{format_bytes, format_rate, parse_rate, UdpStats};
+
+    #[test]
+    fn udp_stats_test() {
+        let mut stats = UdpStats::default();
+        let ms = |ms: u64| Duration::from_millis(ms);
+        stats.receive(0, ms(0), ms(10), 100);
+        stats.receive(2, ms(20), ms(30), 100);
+        stats.receive(1, ms(10), ms(36), 100);
+        stats.receive(5, ms(50), ms(60), 100);
+        assert_eq!(stats.bytes, 400);
+        assert_eq!(stats.out_of_order, 1);
+        assert_eq!(stats.lost(), 2);
+        // Transit times of 10, 10, 26 and 10 ms
+        assert_eq!(stats.jitter().subsec_nanos() / 1000, 1937);
+    }
+
+    #[test]
+    fn format_test() {
+        assert_eq!(parse_rate("10M"), Some(10_000_000));
+        assert_eq!(parse_rate("1.5k"), Some(1500));
+        assert_eq!(parse_rate("fast"), None);
+        assert_eq!(format_rate(125_000_000, Duration::from_secs(1)), "1.00 Gbit/s");
+        assert_eq!(format_bytes(3 * 1024 * 1024), "3.00 MBytes");
+    }
+}
@@ -0,0 +1,208 @@
+extern crate netutils_core;
+
+use netutils_core::args::Parser;
+use netutils_core::error::NetError;
+use netutils_core::output::{Format, Object};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr};
+
+use socket::{Protocol, Socket};
+
+mod socket;
+
+static NETSTAT_MAN: &'static str = /* @MANSTART{netstat} */ r#"
+NAME
+    netstat - list TCP and UDP sockets
+
+SYNOPSIS
+    netstat [-h | --help] [-a | -l] [-t] [-u] [-n] [--output text|json]
+
+DESCRIPTION
+    netstat lists the connected TCP and UDP sockets with their local and
+    remote addresses, their state and the process that owns them, if it can
+    be found. Processes of other users are only shown to root.
+
+OPTIONS
+    -a
+        List listening sockets as well as connected ones.
+
+    -l
+        List only listening sockets, and UDP sockets without a remote
+        address.
+
+    -t
+        List TCP sockets. Both TCP and UDP are listed unless -t or -u is
+        given.
+
+    -u
+        List UDP sockets.
+
+    -n
+        Show numeric addresses and ports. Otherwise names are looked up in
+        /etc/hosts and /etc/services, without queries over the network.
+
+    --output text|json
+        Print a table, by default, or a JSON object for each socket followed
+        by one with the number of sockets, each on a line of its own.
+
+    -h
+    --help
+        Print this manual page.
+
+NOTES
+    The sockets are read from /proc/net on Linux. The network stack of
+    Redox has no table of its sockets to read, so netstat does not work
+    there.
+"#; /* @MANEND */
+
+fn read_file(path: &str) -> String {
+    let mut text = String::new();
+    if let Ok(mut file) = File::open(path) {
+        let _ = file.read_to_string(&mut text);
+    }
+    text
+}
+
+/// Host and service names from the local databases
+struct Names {
+    hosts: BTreeMap<IpAddr, String>,
+    services: BTreeMap<(u16, &'static str), String>,
+}
+
+impl Names {
+    fn new() -> Names {
+        let mut hosts = BTreeMap::new();
+        for line in read_file("/etc/hosts").lines() {
+            let mut fields = line.split('#').next().unwrap_or("").split_whitespace();
+            if let (Some(addr), Some(name)) = (fields.next(), fields.next()) {
+                if let Ok(addr) = addr.parse() {
+                    // The first name of an address is its canonical one
+                    hosts.entry(addr).or_insert(name.to_string());
+                }
+            }
+        }
+
+        let mut services = BTreeMap::new();
+        for line in read_file("/etc/services").lines() {
+            let mut fields = line.split('#').next().unwrap_or("").split_whitespace();
+            if let (Some(name), Some(port)) = (fields.next(), fields.next()) {
+                let mut parts = port.split('/');
+                let port = parts.next().and_then(|port| port.parse::<u16>().ok());
+                let protocol = match parts.next() {
+                    Some("tcp") => "tcp",
+                    Some("udp") => "udp",
+                    _ => continue
+                };
+                if let Some(port) = port {
+                    services.entry((port, protocol)).or_insert(name.to_string());
+                }
+            }
+        }
+
+        Names {
+            hosts: hosts,
+            services: services,
+        }
+    }
+
+    fn format(&self, addr: &SocketAddr, protocol: Protocol) -> String {
+        let host = if addr.ip().is_unspecified() {
+            "*".to_string()
+        } else {
+            match self.hosts.get(&addr.ip()) {
+                Some(name) => name.clone(),
+                None => format_ip(&addr.ip())
+            }
+        };
+        let key = (addr.port(), match protocol { Protocol::Tcp => "tcp", Protocol::Udp => "udp" });
+        let port = if addr.port() == 0 {
+            "*".to_string()
+        } else {
+            match self.services.get(&key) {
+                Some(name) => name.clone(),
+                None => addr.port().to_string()
+            }
+        };
+        format!("{}:{}", host, port)
+    }
+}
+
+fn format_ip(ip: &IpAddr) -> String {
+    match *ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("[{}]", ip),
+    }
+}
+
+fn format_numeric(addr: &SocketAddr) -> String {
+    format!("{}:{}", format_ip(&addr.ip()), addr.port())
+}
+
+fn main() {
+    let args = Parser::new("netstat")
+        .synopsis("[-a | -l] [-t] [-u] [-n] [--output text|json]")
+        .man(NETSTAT_MAN)
+        .flag("a", "", "Show listening and connected sockets")
+        .flag("l", "", "Show only listening sockets")
+        .flag("t", "", "Show TCP sockets")
+        .flag("u", "", "Show UDP sockets")
+        .flag("n", "", "Show addresses and ports as numbers")
+        // Processes are always shown when they are known
+        .flag("p", "", "Show the process of each socket")
+        .output()
+        .parse_env();
+    if !args.free.is_empty() {
+        args.usage();
+    }
+
+    let all = args.flag("a");
+    let listening = args.flag("l");
+    let numeric = args.flag("n");
+    let output = args.output();
+    let (tcp, udp) = match (args.flag("t"), args.flag("u")) {
+        (false, false) => (true, true),
+        protocols => protocols
+    };
+
+    let mut sockets: Vec<Socket> = socket::list(tcp, udp)
+        .unwrap_or_else(|err| NetError::from(err).context("failed to list sockets").exit("netstat"));
+    if !all {
+        sockets.retain(|socket| socket.state.listening() == listening);
+    }
+
+    let names = if numeric { None } else { Some(Names::new()) };
+    let format = |addr: &SocketAddr, protocol: Protocol| match names {
+        Some(ref names) => names.format(addr, protocol),
+        None => format_numeric(addr)
+    };
+
+    if output == Format::Json {
+        for socket in sockets.iter() {
+            println!("{}", Object::new()
+                     .string("proto", socket.protocol.name(socket.local.is_ipv6()))
+                     .string("local", &format(&socket.local, socket.protocol))
+                     .string("remote", &format(&socket.remote, socket.protocol))
+                     .string("state", socket.state.name())
+                     .number_option("pid", socket.owner.as_ref().map(|owner| owner.pid))
+                     .option("program", socket.owner.as_ref().map(|owner| owner.name.as_str())));
+        }
+        println!("{}", Object::new().number("sockets", sockets.len()));
+        return;
+    }
+
+    println!("{:<6} {:<30} {:<30} {:<12} {}", "Proto", "Local Address", "Foreign Address", "State", "PID/Program");
+    for socket in sockets {
+        let owner = match socket.owner {
+            Some(ref owner) => format!("{}/{}", owner.pid, owner.name),
+            None => "-".to_string()
+        };
+        println!("{:<6} {:<30} {:<30} {:<12} {}",
+                 socket.protocol.name(socket.local.is_ipv6()),
+                 format(&socket.local, socket.protocol),
+                 format(&socket.remote, socket.protocol),
+                 socket.state.name(),
+                 owner);
+    }
+}
@@ -0,0 +1,268 @@
+use std::net::SocketAddr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    pub fn name(&self, ipv6: bool) -> &'static str {
+        match (*self, ipv6) {
+            (Protocol::Tcp, false) => "tcp",
+            (Protocol::Tcp, true) => "tcp6",
+            (Protocol::Udp, false) => "udp",
+            (Protocol::Udp, true) => "udp6",
+        }
+    }
+}
+
+/// The TCP states of RFC 793, and the two states of UDP sockets
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Established,
+    SynSent,
+    SynReceived,
+    FinWait1,
+    FinWait2,
+    TimeWait,
+    Closed,
+    CloseWait,
+    LastAck,
+    Listen,
+    Closing,
+    /// A UDP socket without a remote address, which receives from anyone
+    Unconnected,
+    /// A UDP socket with a remote address
+    Connected,
+}
+
+impl State {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            State::Established => "ESTABLISHED",
+            State::SynSent => "SYN_SENT",
+            State::SynReceived => "SYN_RECV",
+            State::FinWait1 => "FIN_WAIT1",
+            State::FinWait2 => "FIN_WAIT2",
+            State::TimeWait => "TIME_WAIT",
+            State::Closed => "CLOSE",
+            State::CloseWait => "CLOSE_WAIT",
+            State::LastAck => "LAST_ACK",
+            State::Listen => "LISTEN",
+            State::Closing => "CLOSING",
+            State::Unconnected => "UNCONN",
+            State::Connected => "CONNECTED",
+        }
+    }
+
+    /// Does the socket accept connections or datagrams from any address?
+    pub fn listening(&self) -> bool {
+        *self == State::Listen || *self == State::Unconnected
+    }
+}
+
+/// A process with the socket open
+#[derive(Clone, Debug, PartialEq)]
+pub struct Owner {
+    pub pid: u32,
+    pub name: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Socket {
+    pub protocol: Protocol,
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    pub state: State,
+    /// None if the process is not known, such as when it belongs to another user
+    pub owner: Option<Owner>,
+}
+
+pub use self::sys::list;
+
+/// Sockets are read from the tables in /proc/net, and their owners found by the socket inodes
+/// that the file descriptors of each process link to
+#[cfg(not(target_os = "redox"))]
+mod sys {
+    use std::collections::BTreeMap;
+    use std::fs::{self, File};
+    use std::io::{ErrorKind, Read, Result};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use super::{Owner, Protocol, Socket, State};
+
+    fn tcp_state(state: u8) -> Option<State> {
+        Some(match state {
+            0x01 => State::Established,
+            0x02 => State::SynSent,
+            0x03 => State::SynReceived,
+            0x04 => State::FinWait1,
+            0x05 => State::FinWait2,
+            0x06 => State::TimeWait,
+            0x07 => State::Closed,
+            0x08 => State::CloseWait,
+            0x09 => State::LastAck,
+            0x0A => State::Listen,
+            0x0B => State::Closing,
+            _ => return None
+        })
+    }
+
+    /// An address is hex words of 32 bits in native byte order, and the port is hex
+    fn parse_addr(value: &str) -> Option<SocketAddr> {
+        let mut parts = value.split(':');
+        let addr = parts.next()?;
+        let port = u16::from_str_radix(parts.next()?, 16).ok()?;
+
+        let mut octets = Vec::with_capacity(16);
+        for i in 0..addr.len() / 8 {
+            let word = u32::from_str_radix(addr.get(i * 8..i * 8 + 8)?, 16).ok()?;
+            octets.extend_from_slice(&[word as u8, (word >> 8) as u8, (word >> 16) as u8, (word >> 24) as u8]);
+        }
+        let ip = match octets.len() {
+            4 => IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])),
+            16 => {
+                let mut bytes = [0; 16];
+                bytes.copy_from_slice(&octets);
+                IpAddr::V6(Ipv6Addr::from(bytes))
+            },
+            _ => return None
+        };
+        Some(SocketAddr::new(ip, port))
+    }
+
+    /// Parse a line of /proc/net/tcp or udp, returning the socket and its inode
+    pub fn parse_line(protocol: Protocol, line: &str) -> Option<(Socket, u64)> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 10 {
+            return None;
+        }
+        let local = parse_addr(fields[1])?;
+        let remote = parse_addr(fields[2])?;
+        let state = u8::from_str_radix(fields[3], 16).ok()?;
+        let state = match protocol {
+            Protocol::Tcp => tcp_state(state)?,
+            // UDP sockets are marked established once connected
+            Protocol::Udp => if state == 0x01 { State::Connected } else { State::Unconnected },
+        };
+        let inode = fields[9].parse().ok()?;
+        Some((Socket {
+            protocol: protocol,
+            local: local,
+            remote: remote,
+            state: state,
+            owner: None,
+        }, inode))
+    }
+
+    fn read_table(path: &str, protocol: Protocol, sockets: &mut Vec<(Socket, u64)>) -> Result<()> {
+        let mut text = String::new();
+        match File::open(path) {
+            Ok(mut file) => file.read_to_string(&mut text)?,
+            // Without IPv6 support there are no tables for it
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err)
+        };
+        sockets.extend(text.lines().skip(1).filter_map(|line| parse_line(protocol, line)));
+        Ok(())
+    }
+
+    /// The processes of each socket inode, skipping those that can't be read
+    fn owners() -> BTreeMap<u64, Owner> {
+        let mut owners = BTreeMap::new();
+        let entries = match fs::read_dir("/proc") {
+            Ok(entries) => entries,
+            Err(_) => return owners
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let pid = match entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) {
+                Some(pid) => pid,
+                None => continue
+            };
+            let fds = match fs::read_dir(entry.path().join("fd")) {
+                Ok(fds) => fds,
+                Err(_) => continue
+            };
+
+            let mut name = String::new();
+            if let Ok(mut file) = File::open(entry.path().join("comm")) {
+                let _ = file.read_to_string(&mut name);
+            }
+            let name = name.trim().to_string();
+
+            for fd in fds.filter_map(|fd| fd.ok()) {
+                let target = match fs::read_link(fd.path()) {
+                    Ok(target) => target,
+                    Err(_) => continue
+                };
+                let target = target.to_string_lossy();
+                if target.starts_with("socket:[") && target.ends_with(']') {
+                    if let Ok(inode) = target[8..target.len() - 1].parse() {
+                        owners.entry(inode).or_insert(Owner {
+                            pid: pid,
+                            name: name.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        owners
+    }
+
+    pub fn list(tcp: bool, udp: bool) -> Result<Vec<Socket>> {
+        let mut sockets = Vec::new();
+        if tcp {
+            read_table("/proc/net/tcp", Protocol::Tcp, &mut sockets)?;
+            read_table("/proc/net/tcp6", Protocol::Tcp, &mut sockets)?;
+        }
+        if udp {
+            read_table("/proc/net/udp", Protocol::Udp, &mut sockets)?;
+            read_table("/proc/net/udp6", Protocol::Udp, &mut sockets)?;
+        }
+
+        let owners = owners();
+        Ok(sockets.into_iter().map(|(mut socket, inode)| {
+            // Sockets in TIME_WAIT and the like have no inode
+            if inode != 0 {
+                socket.owner = owners.get(&inode).cloned();
+            }
+            socket
+        }).collect())
+    }
+}
+
+/// The network stack of Redox does not export a table of its sockets: its schemes only list
+/// interfaces, addresses and routes, which ifconfig and route read through netcfg
+#[cfg(target_os = "redox")]
+mod sys {
+    use std::io::{Error, ErrorKind, Result};
+
+    use super::Socket;
+
+    pub fn list(_tcp: bool, _udp: bool) -> Result<Vec<Socket>> {
+        Err(Error::new(ErrorKind::Other, "netstat does not work on Redox, whose network stack has no socket table"))
+    }
+}
+
+#[cfg(all(test, not(target_os = "redox")))]
+mod test {
+    use std::net::SocketAddr;
+    use super::{Protocol, State};
+    use super::sys::parse_line;
+
+    #[test]
+    fn parse_line_test() {
+        let line = "   0: 0100007F:0277 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 16411 1 0000000000000000 100 0 0 10 0";
+        let (socket, inode) = parse_line(Protocol::Tcp, line).unwrap();
+        assert_eq!(socket.local, "127.0.0.1:631".parse::<SocketAddr>().unwrap());
+        assert_eq!(socket.remote, "0.0.0.0:0".parse::<SocketAddr>().unwrap());
+        assert_eq!(socket.state, State::Listen);
+        assert_eq!(inode, 16411);
+
+        let line = "  1: 00000000000000000000000001000000:0035 00000000000000000000000000000000:0000 07 00000000:00000000 00:00000000 00000000   101        0 20960 2 0000000000000000 0";
+        let (socket, _) = parse_line(Protocol::Udp, line).unwrap();
+        assert_eq!(socket.local, "[::1]:53".parse::<SocketAddr>().unwrap());
+        assert_eq!(socket.state, State::Unconnected);
+    }
+}
@@ -0,0 +1,203 @@
+use ring::digest;
+use std::fs::File;
+use std::io::Read;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Digest {
+    Md5,
+    Sha1,
+}
+
+impl Digest {
+    fn len(&self) -> usize {
+        match *self {
+            Digest::Md5 => 16,
+            Digest::Sha1 => 20,
+        }
+    }
+}
+
+/// A symmetric key shared with a server (RFC 5905 section 7.3)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+    pub id: u32,
+    pub digest: Digest,
+    pub secret: Vec<u8>,
+}
+
+impl Key {
+    /// The digest of the key followed by the packet, as NTP defines it
+    fn digest(&self, packet: &[u8]) -> Vec<u8> {
+        let mut data = self.secret.clone();
+        data.extend_from_slice(packet);
+        match self.digest {
+            Digest::Md5 => md5(&data).to_vec(),
+            Digest::Sha1 => digest::digest(&digest::SHA1, &data).as_ref().to_vec(),
+        }
+    }
+
+    /// Append the message authentication code of a packet: the key identifier and the digest
+    pub fn sign(&self, packet: &mut Vec<u8>) {
+        let digest = self.digest(packet);
+        packet.extend_from_slice(&[(self.id >> 24) as u8, (self.id >> 16) as u8, (self.id >> 8) as u8, self.id as u8]);
+        packet.extend_from_slice(&digest);
+    }
+
+    /// Check the code after the 48 byte header of a reply, which must use this key
+    pub fn verify(&self, data: &[u8]) -> bool {
+        if data.len() != 48 + 4 + self.digest.len() {
+            return false;
+        }
+        let id = (data[48] as u32) << 24 | (data[49] as u32) << 16 | (data[50] as u32) << 8 | data[51] as u32;
+        if id != self.id {
+            return false;
+        }
+
+        // Compare every byte so the time taken does not reveal how much matched
+        let expected = self.digest(&data[..48]);
+        expected.iter().zip(data[52..].iter()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+/// Parse a keys file in the format ntpd uses, with a "id type key" line for each key. Keys of up
+/// to 20 characters are used as text, longer keys are hex.
+pub fn parse_keys(text: &str) -> Result<Vec<Key>, String> {
+    let mut keys = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let invalid = || format!("{}: invalid key '{}'", i + 1, line);
+        if words.len() != 3 {
+            return Err(invalid());
+        }
+
+        let id = words[0].parse::<u32>().map_err(|_| invalid())?;
+        let digest = match words[1].to_uppercase().as_ref() {
+            "M" | "MD5" => Digest::Md5,
+            "SHA1" | "SHA-1" => Digest::Sha1,
+            _ => return Err(format!("{}: unsupported key type '{}'", i + 1, words[1]))
+        };
+        let secret = if words[2].len() <= 20 {
+            words[2].as_bytes().to_vec()
+        } else {
+            hex(words[2]).ok_or_else(&invalid)?
+        };
+
+        keys.push(Key {
+            id: id,
+            digest: digest,
+            secret: secret,
+        });
+    }
+    Ok(keys)
+}
+
+pub fn load_keys(path: &str) -> Result<Vec<Key>, String> {
+    let mut text = String::new();
+    File::open(path).and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|err| format!("failed to read {}: {}", path, err))?;
+    parse_keys(&text).map_err(|err| format!("{}:{}", path, err))
+}
+
+fn hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len() / 2).map(|i| text.get(i * 2..i * 2 + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+/// MD5 (RFC 1321), which ring does not provide but NTP keys still commonly use
+fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+        5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+        4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+        6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+
+    // The integer part of abs(sin(i + 1)) * 2^32
+    let mut constants = [0u32; 64];
+    for (i, constant) in constants.iter_mut().enumerate() {
+        *constant = (((i + 1) as f64).sin().abs() * 4294967296.0) as u32;
+    }
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    let bits = (data.len() as u64).wrapping_mul(8);
+    for i in 0..8 {
+        message.push((bits >> (i * 8)) as u8);
+    }
+
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for chunk in message.chunks(64) {
+        let mut words = [0u32; 16];
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = chunk[i * 4] as u32 | (chunk[i * 4 + 1] as u32) << 8 |
+                    (chunk[i * 4 + 2] as u32) << 16 | (chunk[i * 4 + 3] as u32) << 24;
+        }
+
+        let (mut a, mut b, mut c, mut d) = (state[0], state[1], state[2], state[3]);
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a.wrapping_add(f).wrapping_add(constants[i]).wrapping_add(words[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated.rotate_left(SHIFTS[i]));
+        }
+
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut digest = [0; 16];
+    for (i, word) in state.iter().enumerate() {
+        for j in 0..4 {
+            digest[i * 4 + j] = (word >> (j * 8)) as u8;
+        }
+    }
+    digest
+}
+
+#[cfg(test)]
+mod test {
+    use super::{md5, parse_keys, Digest};
+
+    #[test]
+    fn md5_test() {
+        assert_eq!(md5(b"abc"), [0x90, 0x01, 0x50, 0x98, 0x3c, 0xd2, 0x4f, 0xb0,
+                                 0xd6, 0x96, 0x3f, 0x7d, 0x28, 0xe1, 0x7f, 0x72]);
+        assert_eq!(md5(&[b'a'; 64])[..4], [0x01, 0x48, 0x42, 0xd4]);
+    }
+
+    #[test]
+    fn keys_test() {
+        let keys = parse_keys("# comment\n1 M secret\n2 SHA1 00112233445566778899aabbccddeeff00112233\n").unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!((keys[0].id, keys[0].digest, &keys[0].secret[..]), (1, Digest::Md5, &b"secret"[..]));
+        assert_eq!(keys[1].secret.len(), 20);
+
+        let mut packet = vec![0x23; 48];
+        keys[0].sign(&mut packet);
+        assert_eq!(packet.len(), 68);
+        assert!(keys[0].verify(&packet));
+        packet[10] ^= 1;
+        assert!(!keys[0].verify(&packet));
+        assert!(parse_keys("1 AES128CMAC secret").is_err());
+    }
+}
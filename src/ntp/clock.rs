@@ -0,0 +1,84 @@
+use std::io::Error;
+
+/// Offsets below this are slewed rather than stepped, like ntpd's step threshold
+pub const SLEW_THRESHOLD: i64 = 128_000_000;
+
+/// Move the system clock forward by `offset` nanoseconds, in one step
+#[cfg(not(target_os = "redox"))]
+pub fn step(offset: i64) -> Result<(), String> {
+    use libc;
+    use packet::Timestamp;
+    use std::ptr;
+
+    let now = Timestamp::now().to_unix_nanos() + offset;
+    let time = libc::timeval {
+        tv_sec: (now / 1_000_000_000) as libc::time_t,
+        tv_usec: (now % 1_000_000_000 / 1000) as libc::suseconds_t,
+    };
+    if unsafe { libc::settimeofday(&time, ptr::null()) } < 0 {
+        return Err(format!("settimeofday: {}", Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "redox")]
+pub fn step(offset: i64) -> Result<(), String> {
+    use syscall;
+    use std::fs::OpenOptions;
+    use std::io::{Read, Write};
+
+    // Reading the realtime clock of the time scheme gets the time, writing it sets the time
+    let path = format!("time:{}", syscall::CLOCK_REALTIME);
+    let mut file = OpenOptions::new().read(true).write(true).open(&path)
+        .map_err(|err| format!("failed to open {}: {}", path, err))?;
+    let mut time = syscall::TimeSpec::default();
+    file.read(&mut time).map_err(|err| format!("failed to read {}: {}", path, err))?;
+
+    let now = time.tv_sec as i64 * 1_000_000_000 + time.tv_nsec as i64 + offset;
+    time.tv_sec = now / 1_000_000_000;
+    time.tv_nsec = (now % 1_000_000_000) as i32;
+    file.write(&time).map_err(|err| format!("failed to write {}: {}", path, err))?;
+    Ok(())
+}
+
+/// Gradually move the system clock by `offset` nanoseconds, so time never jumps or runs backwards
+#[cfg(not(target_os = "redox"))]
+pub fn slew(offset: i64) -> Result<(), String> {
+    use libc;
+    use std::ptr;
+
+    extern "C" {
+        fn adjtime(delta: *const libc::timeval, olddelta: *mut libc::timeval) -> libc::c_int;
+    }
+
+    // timeval needs a non-negative microsecond field
+    let micros = offset / 1000;
+    let mut delta = libc::timeval {
+        tv_sec: (micros / 1_000_000) as libc::time_t,
+        tv_usec: (micros % 1_000_000) as libc::suseconds_t,
+    };
+    if delta.tv_usec < 0 {
+        delta.tv_sec -= 1;
+        delta.tv_usec += 1_000_000;
+    }
+    if unsafe { adjtime(&delta, ptr::null_mut()) } < 0 {
+        return Err(format!("adjtime: {}", Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// Redox cannot slew its clock, so small offsets are stepped too
+#[cfg(target_os = "redox")]
+pub fn slew(offset: i64) -> Result<(), String> {
+    step(offset)
+}
+
+/// Correct the system clock by `offset`, slewing small corrections unless `step_only` is set,
+/// and returning whether the clock was stepped
+pub fn adjust(offset: i64, step_only: bool) -> Result<bool, String> {
+    if !step_only && offset.abs() < SLEW_THRESHOLD {
+        slew(offset).map(|_| false)
+    } else {
+        step(offset).map(|_| true)
+    }
+}
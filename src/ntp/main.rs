@@ -1,9 +1,35 @@
 #![deny(warnings)]
 
-extern crate ntpclient;
+#[cfg(not(target_os = "redox"))]
+extern crate libc;
+extern crate netutils_core;
+extern crate ring;
+#[cfg(target_os = "redox")]
+extern crate syscall;
 
-use ntpclient::retrieve_ntp_timestamp;
-use std::env;
+use auth::Key;
+use netutils_core::args::Parser;
+use netutils_core::error::{NetError, Result};
+use netutils_core::log::{Level, Logger};
+use netutils_core::resolve::{self, Family};
+use packet::{Packet, Sample, Timestamp};
+use std::{cmp, thread};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+mod auth;
+mod clock;
+mod packet;
+mod select;
+
+/// Polling intervals of the daemon, as powers of two seconds (RFC 5905 section 7.3)
+const MIN_POLL: u32 = 6;
+const MAX_POLL: u32 = 10;
+
+/// Addresses used of each server name, since pool names resolve to several servers
+const ADDRS_PER_NAME: usize = 4;
+
+static DEFAULT_KEYS: &'static str = "/etc/ntp.keys";
 
 fn format_time(mut ts: i64) -> String {
     let s = ts%86400;
@@ -27,8 +53,274 @@ fn format_time(mut ts: i64) -> String {
     format!("{:>04}-{:>02}-{:>02} {:>02}:{:>02}:{:>02}", c, e, f, h, m, s)
 }
 
+/// Send an SNTP request to `addr` and measure the offset of the local clock from its reply,
+/// authenticating both with `key` if one is given
+fn query(addr: SocketAddr, key: Option<&Key>) -> Result<(Packet, Sample)> {
+    let bind = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind).map_err(|err| NetError::from(err).context("failed to bind udp"))?;
+    socket.set_read_timeout(Some(Duration::new(5, 0)))
+        .map_err(|err| NetError::from(err).context("failed to set read timeout"))?;
+
+    let sent = Timestamp::now();
+    let mut request = Packet::request(sent).to_bytes();
+    if let Some(key) = key {
+        key.sign(&mut request);
+    }
+    socket.send_to(&request, addr)
+        .map_err(|err| NetError::from(err).context(&format!("failed to send to {}", addr)))?;
+
+    let mut data = [0; 1024];
+    loop {
+        let (count, from) = socket.recv_from(&mut data)
+            .map_err(|err| NetError::from(err).context(&format!("no reply from {}", addr)))?;
+        let received = Timestamp::now();
+        if from != addr {
+            continue;
+        }
+
+        let reply = match Packet::from_bytes(&data[..count]) {
+            Some(reply) => reply,
+            None => continue
+        };
+        // Replies must answer this request (RFC 4330 section 5)
+        if reply.mode != packet::MODE_SERVER || reply.originate != sent {
+            continue;
+        }
+        if let Some(key) = key {
+            // A zero key identifier with no digest is a crypto-NAK
+            if count == 52 {
+                return Err(NetError::Protocol(format!("{} does not accept key {}", addr, key.id)));
+            } else if !key.verify(&data[..count]) {
+                return Err(NetError::Protocol(format!("reply from {} failed authentication", addr)));
+            }
+        }
+        if reply.stratum == 0 {
+            return Err(NetError::Protocol(format!("{} refused the request: {}", addr,
+                                                  String::from_utf8_lossy(&reply.reference_id))));
+        }
+        if reply.leap == 3 || reply.transmit.is_zero() {
+            return Err(NetError::other(format!("{} is not synchronized", addr)));
+        }
+
+        let sample = Sample::new(&reply, sent, received);
+        return Ok((reply, sample));
+    }
+}
+
+/// A server that answered a poll
+struct Candidate {
+    name: String,
+    addr: SocketAddr,
+    stratum: u8,
+    sample: Sample,
+}
+
+/// The addresses of each server name
+fn resolve(servers: &[String], family: Family, log: &Logger) -> Result<Vec<(String, SocketAddr)>> {
+    let mut addrs = Vec::new();
+    for server in servers.iter() {
+        match resolve::addrs(server, 123, family) {
+            Ok(resolved) => for addr in resolved.into_iter().take(ADDRS_PER_NAME) {
+                if !addrs.iter().any(|&(_, known)| known == addr) {
+                    addrs.push((server.clone(), addr));
+                }
+            },
+            Err(err) => log.warn(&err.to_string())
+        }
+    }
+    if addrs.is_empty() {
+        return Err(NetError::Dns("no server could be resolved".to_string()));
+    }
+    Ok(addrs)
+}
+
+/// Query every server at once, returning those that answered
+fn poll(servers: &[String], family: Family, key: Option<&Key>, log: &Logger) -> Result<Vec<Candidate>> {
+    let threads: Vec<_> = resolve(servers, family, log)?.into_iter().map(|(name, addr)| {
+        let key = key.cloned();
+        thread::spawn(move || query(addr, key.as_ref()).map(|(reply, sample)| Candidate {
+            name: name,
+            addr: addr,
+            stratum: reply.stratum,
+            sample: sample,
+        }))
+    }).collect();
+
+    let mut candidates = Vec::new();
+    let mut last = None;
+    for thread in threads {
+        match thread.join() {
+            Ok(Ok(candidate)) => candidates.push(candidate),
+            Ok(Err(err)) => {
+                log.warn(&err.to_string());
+                last = Some(err);
+            },
+            Err(_) => ()
+        }
+    }
+    if candidates.is_empty() {
+        // The failures were printed; only their kind is kept, so that scripts can tell servers
+        // that didn't answer from servers that are unreachable
+        let message = "no server answered".to_string();
+        return Err(match last {
+            Some(NetError::Timeout(_)) => NetError::Timeout(message),
+            Some(NetError::Unreachable(_)) => NetError::Unreachable(message),
+            _ => NetError::other(message)
+        });
+    }
+    Ok(candidates)
+}
+
+/// Poll the servers, print what they answered and return the combined offset of those that agree
+fn measure(servers: &[String], family: Family, key: Option<&Key>, log: &Logger) -> Result<i64> {
+    let candidates = poll(servers, family, key, log)?;
+
+    let samples: Vec<(i64, i64)> = candidates.iter()
+        .map(|candidate| (candidate.sample.offset, candidate.sample.distance))
+        .collect();
+    let truechimers = select::truechimers(&samples);
+
+    for (i, candidate) in candidates.iter().enumerate() {
+        // Falsetickers are marked like ntpq marks them
+        let mark = if truechimers.contains(&i) { '*' } else { 'x' };
+        log.info(&format!("{} {} ({}): stratum {}, offset {} s, delay {} s", mark, candidate.name, candidate.addr,
+                          candidate.stratum, packet::format_seconds(candidate.sample.offset),
+                          packet::format_seconds(candidate.sample.delay)));
+    }
+
+    if truechimers.is_empty() {
+        return Err(NetError::other("servers do not agree on the time"));
+    }
+
+    let chosen: Vec<(i64, i64)> = truechimers.iter().map(|&i| samples[i]).collect();
+    let offset = select::combine(&chosen);
+    let time = (Timestamp::now().to_unix_nanos() + offset) / 1_000_000_000;
+    log.info(&format!("{}: offset {} s from {} of {} servers", format_time(time), packet::format_seconds(offset),
+                      chosen.len(), candidates.len()));
+    Ok(offset)
+}
+
+/// Apply an offset to the system clock, unless it is implausibly large, returning whether the
+/// clock was stepped
+fn set_clock(offset: i64, max_offset: i64, step_only: bool, log: &Logger) -> Result<bool> {
+    if offset.abs() > max_offset {
+        return Err(NetError::other(format!("offset {} s is larger than the maximum of {} s, not setting the clock",
+                                           packet::format_seconds(offset), packet::format_seconds(max_offset))));
+    }
+
+    let stepped = clock::adjust(offset, step_only).map_err(NetError::other)?;
+    if stepped {
+        log.info(&format!("stepped clock by {} s", packet::format_seconds(offset)));
+    } else {
+        log.info(&format!("slewing clock by {} s", packet::format_seconds(offset)));
+    }
+    Ok(stepped)
+}
+
+/// Keep the clock in time, polling more often while it needs large corrections and less often
+/// once it is stable
+fn daemon(servers: &[String], family: Family, key: Option<&Key>, set: bool, max_offset: i64,
+          step_only: bool, log: &Logger) -> Result<()> {
+    let mut poll = MIN_POLL;
+    loop {
+        let stable = match measure(servers, family, key, log) {
+            Ok(offset) => {
+                let stepped = if set {
+                    match set_clock(offset, max_offset, step_only, log) {
+                        Ok(stepped) => stepped,
+                        Err(err) => {
+                            log.error(&err.to_string());
+                            true
+                        }
+                    }
+                } else {
+                    false
+                };
+                !stepped && offset.abs() < clock::SLEW_THRESHOLD
+            },
+            Err(err) => {
+                log.error(&err.to_string());
+                false
+            }
+        };
+
+        poll = if stable { cmp::min(poll + 1, MAX_POLL) } else { cmp::max(poll - 1, MIN_POLL) };
+        thread::sleep(Duration::from_secs(1 << poll));
+    }
+}
+
+fn run(servers: &[String], family: Family, key: Option<&Key>, set: bool, max_offset: i64,
+       step_only: bool, log: &Logger) -> Result<()> {
+    let offset = measure(servers, family, key, log)?;
+    if set {
+        set_clock(offset, max_offset, step_only, log)?;
+    }
+    Ok(())
+}
+
+/// Servers to use when none are given: those configured by DHCP, or else the public pool
+fn default_servers() -> Vec<String> {
+    let configured: Vec<String> = netutils_core::getcfg("ntp").unwrap_or(String::new())
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    if configured.is_empty() {
+        vec!["pool.ntp.org".to_string()]
+    } else {
+        configured
+    }
+}
+
 fn main() {
-    let server = env::args().nth(1).unwrap_or("pool.ntp.org".to_string());
-    let ntp_time = retrieve_ntp_timestamp(&server).unwrap();
-    println!("{}: {}", server, format_time(ntp_time.sec));
+    let args = Parser::new("ntp")
+        .synopsis("[-d] [-4 | -6] [--set [--step]] [--keys file] [--key id] [--max-offset seconds] [server...]")
+        .flag("d", "", "Run as a daemon, keeping the clock in sync")
+        .family()
+        .flag("", "set", "Set the clock, rather than only printing the offset")
+        .flag("", "step", "Step the clock to the right time, rather than slewing it")
+        .opt("", "keys", "file", "Read symmetric keys from file, rather than /etc/ntp.keys")
+        .opt("", "key", "id", "Authenticate with the key with this identifier")
+        .opt("", "max-offset", "seconds", "Refuse offsets larger than this, 1000 by default")
+        .logging()
+        .parse_env();
+
+    let mut servers = args.free.clone();
+    let set = args.flag("set");
+    let step_only = args.flag("step");
+    let background = args.flag("d");
+    let family = args.family();
+    let log = args.logger(Level::Info);
+    let keys_path = args.value("keys").unwrap_or(DEFAULT_KEYS);
+    let key_id = args.parse::<u32>("key");
+    // Larger offsets most likely mean a bad server or reply, as ntpd's panic threshold assumes
+    let max_offset = match args.parse::<f64>("max-offset") {
+        Some(seconds) if seconds >= 0.0 => (seconds * 1e9) as i64,
+        Some(_) => args.invalid("--max-offset can't be negative"),
+        None => 1000 * 1_000_000_000
+    };
+
+    if servers.is_empty() {
+        servers = default_servers();
+    }
+
+    let key = match key_id {
+        Some(id) => match auth::load_keys(keys_path) {
+            Ok(keys) => match keys.into_iter().find(|key| key.id == id) {
+                Some(key) => Some(key),
+                None => args.invalid(&format!("no key {} in {}", id, keys_path))
+            },
+            Err(err) => NetError::Usage(err).exit("ntp")
+        },
+        None => None
+    };
+
+    let result = if background {
+        daemon(&servers, family, key.as_ref(), set, max_offset, step_only, &log)
+    } else {
+        run(&servers, family, key.as_ref(), set, max_offset, step_only, &log)
+    };
+    if let Err(err) = result {
+        err.exit("ntp");
+    }
 }
@@ -0,0 +1,224 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds from the NTP prime epoch, 1900-01-01, to the Unix epoch
+const UNIX_OFFSET: i64 = 2208988800;
+
+/// Seconds in an NTP era, after which the 32 bit seconds field wraps
+const ERA_SECONDS: i64 = 1 << 32;
+
+pub const MODE_CLIENT: u8 = 3;
+pub const MODE_SERVER: u8 = 4;
+
+pub const VERSION: u8 = 4;
+
+/// A 64 bit NTP timestamp, with the seconds since the start of the era and a binary fraction
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timestamp {
+    pub seconds: u32,
+    pub fraction: u32,
+}
+
+impl Timestamp {
+    pub fn now() -> Timestamp {
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => Timestamp::from_unix_nanos(duration.as_secs() as i64 * 1_000_000_000 +
+                                                       duration.subsec_nanos() as i64),
+            Err(_) => Timestamp::default()
+        }
+    }
+
+    pub fn from_unix_nanos(nanos: i64) -> Timestamp {
+        let mut seconds = nanos / 1_000_000_000;
+        let mut nanos = nanos % 1_000_000_000;
+        if nanos < 0 {
+            seconds -= 1;
+            nanos += 1_000_000_000;
+        }
+        Timestamp {
+            seconds: ((seconds + UNIX_OFFSET) % ERA_SECONDS + ERA_SECONDS) as u32,
+            fraction: ((nanos << 32) / 1_000_000_000) as u32,
+        }
+    }
+
+    /// Nanoseconds since the Unix epoch. Timestamps with the top bit clear are taken to be in
+    /// era 1, which starts in 2036, as RFC 4330 section 3 suggests.
+    pub fn to_unix_nanos(&self) -> i64 {
+        let mut seconds = self.seconds as i64;
+        if self.seconds & 0x80000000 == 0 {
+            seconds += ERA_SECONDS;
+        }
+        (seconds - UNIX_OFFSET) * 1_000_000_000 + ((self.fraction as i64 * 1_000_000_000) >> 32)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.seconds == 0 && self.fraction == 0
+    }
+
+    fn read(data: &[u8]) -> Timestamp {
+        Timestamp {
+            seconds: read_u32(&data[0..]),
+            fraction: read_u32(&data[4..]),
+        }
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        push_u32(data, self.seconds);
+        push_u32(data, self.fraction);
+    }
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    (data[0] as u32) << 24 | (data[1] as u32) << 16 | (data[2] as u32) << 8 | data[3] as u32
+}
+
+fn push_u32(data: &mut Vec<u8>, value: u32) {
+    data.push((value >> 24) as u8);
+    data.push((value >> 16) as u8);
+    data.push((value >> 8) as u8);
+    data.push(value as u8);
+}
+
+/// An NTP packet header (RFC 5905 section 7.3)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packet {
+    pub leap: u8,
+    pub version: u8,
+    pub mode: u8,
+    pub stratum: u8,
+    pub poll: i8,
+    pub precision: i8,
+    pub root_delay: u32,
+    pub root_dispersion: u32,
+    pub reference_id: [u8; 4],
+    pub reference: Timestamp,
+    pub originate: Timestamp,
+    pub receive: Timestamp,
+    pub transmit: Timestamp,
+}
+
+impl Packet {
+    /// A client request, which only needs the transmit timestamp (RFC 4330 section 5)
+    pub fn request(transmit: Timestamp) -> Packet {
+        Packet {
+            leap: 0,
+            version: VERSION,
+            mode: MODE_CLIENT,
+            stratum: 0,
+            poll: 0,
+            precision: 0,
+            root_delay: 0,
+            root_dispersion: 0,
+            reference_id: [0; 4],
+            reference: Timestamp::default(),
+            originate: Timestamp::default(),
+            receive: Timestamp::default(),
+            transmit: transmit,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Packet> {
+        if bytes.len() < 48 {
+            return None;
+        }
+
+        Some(Packet {
+            leap: bytes[0] >> 6,
+            version: (bytes[0] >> 3) & 7,
+            mode: bytes[0] & 7,
+            stratum: bytes[1],
+            poll: bytes[2] as i8,
+            precision: bytes[3] as i8,
+            root_delay: read_u32(&bytes[4..]),
+            root_dispersion: read_u32(&bytes[8..]),
+            reference_id: [bytes[12], bytes[13], bytes[14], bytes[15]],
+            reference: Timestamp::read(&bytes[16..]),
+            originate: Timestamp::read(&bytes[24..]),
+            receive: Timestamp::read(&bytes[32..]),
+            transmit: Timestamp::read(&bytes[40..]),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![self.leap << 6 | (self.version & 7) << 3 | self.mode & 7,
+                            self.stratum, self.poll as u8, self.precision as u8];
+        push_u32(&mut data, self.root_delay);
+        push_u32(&mut data, self.root_dispersion);
+        data.extend_from_slice(&self.reference_id);
+        self.reference.write(&mut data);
+        self.originate.write(&mut data);
+        self.receive.write(&mut data);
+        self.transmit.write(&mut data);
+        data
+    }
+}
+
+/// Nanoseconds in an NTP short format value, which has 16 bits of seconds and 16 of fraction
+pub fn short_nanos(value: u32) -> i64 {
+    (value as i64 * 1_000_000_000) >> 16
+}
+
+/// The clock offset and round-trip delay of one exchange, in nanoseconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    /// How far the server's clock is ahead of the local clock
+    pub offset: i64,
+    pub delay: i64,
+    /// Maximum error of the offset, including the server's own distance from its reference
+    pub distance: i64,
+}
+
+impl Sample {
+    /// Compute a sample from the reply to a request sent at `sent`, received at `received`
+    pub fn new(reply: &Packet, sent: Timestamp, received: Timestamp) -> Sample {
+        let t1 = sent.to_unix_nanos();
+        let t2 = reply.receive.to_unix_nanos();
+        let t3 = reply.transmit.to_unix_nanos();
+        let t4 = received.to_unix_nanos();
+        let delay = (t4 - t1) - (t3 - t2);
+        Sample {
+            offset: ((t2 - t1) + (t3 - t4)) / 2,
+            delay: delay,
+            // The root distance of RFC 5905 section 11.2, without the jitter of a single sample
+            distance: delay.abs() / 2 + short_nanos(reply.root_delay) / 2 + short_nanos(reply.root_dispersion),
+        }
+    }
+}
+
+/// Format nanoseconds as seconds with microsecond precision
+pub fn format_seconds(nanos: i64) -> String {
+    let sign = if nanos < 0 { "-" } else { "+" };
+    let micros = (nanos.abs() + 500) / 1000;
+    format!("{}{}.{:>06}", sign, micros / 1_000_000, micros % 1_000_000)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_seconds, Packet, Sample, Timestamp};
+
+    #[test]
+    fn timestamp_test() {
+        // 2017-01-01 00:00:00.5 UTC
+        let timestamp = Timestamp::from_unix_nanos(1483228800_500_000_000);
+        assert_eq!(timestamp, Timestamp { seconds: 3692217600, fraction: 0x80000000 });
+        assert_eq!(timestamp.to_unix_nanos(), 1483228800_500_000_000);
+
+        // 2040-01-01 is in era 1, after the seconds wrap in 2036
+        let timestamp = Timestamp::from_unix_nanos(2208988800_000_000_000);
+        assert_eq!(timestamp.seconds, 123010304);
+        assert_eq!(timestamp.to_unix_nanos(), 2208988800_000_000_000);
+    }
+
+    #[test]
+    fn sample_test() {
+        let at = |seconds: i64| Timestamp::from_unix_nanos(1483228800_000_000_000 + seconds * 1_000_000);
+        let mut reply = Packet::request(at(1150));
+        reply.receive = at(1100);
+        assert_eq!(Packet::from_bytes(&reply.to_bytes()), Some(reply.clone()));
+
+        // Sent at 0ms and received at 200ms, by a server 1000ms ahead that took 50ms to reply
+        let sample = Sample::new(&reply, at(0), at(200));
+        assert_eq!((sample.delay + 500_000) / 1_000_000, 150);
+        assert_eq!((sample.offset + 500_000) / 1_000_000, 1025);
+        assert_eq!(format_seconds(-1_500_000), "-0.001500");
+    }
+}
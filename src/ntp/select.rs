@@ -0,0 +1,64 @@
+/// Find the servers whose clocks agree, from each server's offset and distance, which bound the
+/// true time to within `offset - distance` and `offset + distance`. This is Marzullo's algorithm
+/// as simplified by RFC 5905 section 11.2.1: the truechimers are the intervals that contain the
+/// point covered by the most intervals, and there must be more of them than falsetickers.
+pub fn truechimers(samples: &[(i64, i64)]) -> Vec<usize> {
+    // Interval ends, with starts sorting before ends at the same point so touching intervals meet
+    let mut ends: Vec<(i64, i32)> = Vec::new();
+    for &(offset, distance) in samples.iter() {
+        ends.push((offset - distance, -1));
+        ends.push((offset + distance, 1));
+    }
+    ends.sort();
+
+    let mut best = 0;
+    let mut best_point = 0;
+    let mut count = 0;
+    for &(point, kind) in ends.iter() {
+        count -= kind;
+        if count > best {
+            best = count;
+            best_point = point;
+        }
+    }
+
+    if best as usize * 2 <= samples.len() {
+        return Vec::new();
+    }
+
+    (0..samples.len()).filter(|&i| {
+        let (offset, distance) = samples[i];
+        offset - distance <= best_point && best_point <= offset + distance
+    }).collect()
+}
+
+/// Combine the offsets of truechimers, weighting each by the inverse of its distance
+pub fn combine(samples: &[(i64, i64)]) -> i64 {
+    let mut total = 0.0;
+    let mut weights = 0.0;
+    for &(offset, distance) in samples.iter() {
+        // Avoid dividing by zero for servers on the same host
+        let weight = 1.0 / (distance as f64).max(1000.0);
+        total += offset as f64 * weight;
+        weights += weight;
+    }
+    if weights > 0.0 { (total / weights) as i64 } else { 0 }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{combine, truechimers};
+
+    #[test]
+    fn truechimers_test() {
+        // Three servers agree on an offset near 10, the fourth is far away
+        let samples = [(10, 5), (12, 4), (9, 2), (100, 5)];
+        assert_eq!(truechimers(&samples), vec![0, 1, 2]);
+
+        // Two disagreeing servers cannot outvote each other
+        assert!(truechimers(&[(0, 1), (10, 1)]).is_empty());
+        assert_eq!(truechimers(&[(5, 1)]), vec![0]);
+
+        assert_eq!(combine(&[(1000, 1000), (4000, 2000)]), 2000);
+    }
+}
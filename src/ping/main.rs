@@ -1,258 +1,202 @@
-#![feature(lookup_host)]
-
-extern crate syscall;
-extern crate event;
-
-use event::EventQueue;
-use std::cell::RefCell;
-use std::env::args;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::io::Error as IOError;
-use std::num::ParseIntError;
-use std::mem;
-use std::net::{Ipv4Addr, SocketAddr, lookup_host};
-use std::ops::{DerefMut, Deref};
-use std::os::unix::io::{RawFd, FromRawFd};
+extern crate netutils_core;
+
+use netutils_core::args::Parser;
+use netutils_core::error::{NetError, Result, EXIT_FAILURE};
+use netutils_core::output::{Format, Object};
+use netutils_core::rawsock::{self, Echo};
+use netutils_core::resolve::{self, Family};
+use netutils_core::targets;
+use std::cmp;
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr};
 use std::process;
-use std::rc::Rc;
-use std::slice;
 use std::str::FromStr;
-use syscall::data::TimeSpec;
-use std::fmt;
-use std::result;
-use std::convert;
+use std::time::{Duration, Instant};
+
+use sweep::Sweep;
+
+mod sweep;
 
 static PING_MAN: &'static str = /* @MANSTART{ping} */ r#"
 NAME
     ping - send ICMP ECHO_REQUEST to network hosts
 
 SYNOPSIS
-    ping [-h | --help] [-c count] [-i interval] destination
+    ping [-h | --help] [-c count] [-i interval] [--output text|json] destination
+    ping --sweep [-c count] [-i interval] [--window hosts] [--output text|json] target...
 
 DESCRIPTION
     ping sends ICMP ECHO_REQUEST packets to the specified destination host
     and reports on ECHO_RESPONSE packets it receives back.
 
+    With --sweep, ping probes many hosts at once, as fping does, and reports
+    each one as alive or unreachable, followed by how many of each there
+    were. A target is a host, a network such as 192.168.1.0/24, which
+    stands for each of its addresses, or a range such as 192.168.1.10-20.
+    Networks larger than a /16 are refused. Each host is sent one packet
+    unless -c says otherwise, and has a second to answer each one.
+
+    ping exits with 0 if any packet was answered, 1 if none was, and 3 if
+    the destination couldn't be resolved.
+
+    On Linux, ping needs root or CAP_NET_RAW unless the group of the user
+    is allowed ping sockets by net.ipv4.ping_group_range. Once the socket is
+    open, ping runs as the user who ran it, or as nobody instead of root.
+
 OPTIONS
     -c count
-        Number of packets to send. ping -c 0 will send packets until interrupted.
+    --count count
+        Number of packets to send. ping -c 0 will send packets until interrupted,
+        except with --sweep, which sends each host 1 by default.
 
     -h
     --help
         Print this manual page.
 
     -i interval
-        Wait interval seconds before sending next packet.
+    --interval interval
+        Wait interval seconds before sending next packet. With --sweep, this
+        is between the packets to each host, and a packet to some host is sent
+        at most every 10 milliseconds.
+
+    --output text|json
+        Print text, by default, or a JSON object for each reply or timeout
+        followed by one with the statistics, each on a line of its own. With
+        --sweep, there is one for each host rather than for each reply.
+
+    --sweep
+        Ping every target, as described above.
+
+    --window hosts
+        With --sweep, the most hosts to wait for answers from at once, 64 by
+        default.
 "#; /* @MANEND */
 
-const PING_INTERVAL_S: i64 = 1;
-const PING_TIMEOUT_S: i64 = 5;
+const PING_INTERVAL_S: u64 = 1;
+const PING_TIMEOUT_S: u64 = 5;
 const PING_PACKETS_TO_SEND: usize = 4;
-
-enum ErrorType {
-    IOError(IOError),
-    IntParseError(ParseIntError),
-    LocalError,
-}
-
-struct Error {
-    error_type: ErrorType,
-    descr: String,
-}
-
-impl Error {
-    pub fn new_local<S: Into<String>>(descr: S) -> Error {
-        Error {
-            error_type: ErrorType::LocalError,
-            descr: descr.into(),
-        }
-    }
-
-    pub fn from_int_parse_error<S: Into<String>>(int_parse_error: ParseIntError,
-                                                 descr: S)
-                                                 -> Error {
-        Error {
-            error_type: ErrorType::IntParseError(int_parse_error),
-            descr: descr.into(),
-        }
-    }
-
-    pub fn from_io_error<S: Into<String>>(io_error: IOError, descr: S) -> Error {
-        Error {
-            error_type: ErrorType::IOError(io_error),
-            descr: descr.into(),
-        }
-    }
-}
-
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
-        match self.error_type {
-            ErrorType::IntParseError(ref int_parse_error) => {
-                write!(f, "{} : int parse error: {}", self.descr, int_parse_error)
-            }
-            ErrorType::IOError(ref io_error) => {
-                write!(f, "{} : io error : {}", self.descr, io_error)
-            }
-            ErrorType::LocalError => write!(f, "{}", self.descr),
-        }
-    }
-}
-
-impl convert::From<IOError> for Error {
-    fn from(e: IOError) -> Self {
-        Error::from_io_error(e, "")
-    }
-}
-
-type Result<T> = result::Result<T, Error>;
-
-#[repr(C)]
-struct EchoPayload {
-    seq: u16,
-    timestamp: TimeSpec,
-    payload: [u8; 40],
-}
-
-impl Deref for EchoPayload {
-    type Target = [u8];
-    fn deref(&self) -> &[u8] {
-        unsafe {
-            slice::from_raw_parts(self as *const EchoPayload as *const u8,
-                                  mem::size_of::<EchoPayload>()) as &[u8]
-        }
-    }
-}
-
-impl DerefMut for EchoPayload {
-    fn deref_mut(&mut self) -> &mut [u8] {
-        unsafe {
-            slice::from_raw_parts_mut(self as *mut EchoPayload as *mut u8,
-                                      mem::size_of::<EchoPayload>()) as &mut [u8]
-        }
-    }
-}
+const PING_PAYLOAD: [u8; 40] = [1; 40];
+const PING_SWEEP_WINDOW: usize = 64;
 
 struct Ping {
     remote_host: Ipv4Addr,
-    time_file: File,
-    echo_file: File,
+    echo: Echo,
     seq: usize,
     recieved: usize,
-    //TODO: replace with BTreeMap once TimeSpec implements Ord
-    waiting_for: Vec<(TimeSpec, usize)>,
+    /// The requests without a reply, with the time they were sent
+    waiting_for: Vec<(Instant, usize)>,
     packets_to_send: usize,
-    interval: i64,
+    interval: Duration,
+    output: Format,
 }
 
-fn time_diff_ms(from: &TimeSpec, to: &TimeSpec) -> f32 {
-    ((to.tv_sec - from.tv_sec) * 1_000_000i64 +
-     ((to.tv_nsec - from.tv_nsec) as i64) / 1_000i64) as f32 / 1_000.0f32
+fn duration_ms(duration: Duration) -> f32 {
+    (duration.as_secs() * 1_000_000 + duration.subsec_nanos() as u64 / 1_000) as f32 / 1_000.0f32
+}
+
+/// Print the round trip time of a reply, or that there was none in time
+fn print_reply(output: Format, remote_host: Ipv4Addr, seq: usize, time_ms: Option<f32>) {
+    match (output, time_ms) {
+        (Format::Json, _) => println!("{}", Object::new()
+                                      .string("host", &remote_host.to_string())
+                                      .number("seq", seq)
+                                      .string("status", if time_ms.is_some() { "reply" } else { "timeout" })
+                                      .number_option("time_ms", time_ms)),
+        (_, Some(time_ms)) => println!("From {} icmp_seq={} time={}ms", remote_host, seq, time_ms),
+        (_, None) => println!("From {} icmp_seq={} timeout", remote_host, seq),
+    }
 }
 
 impl Ping {
     pub fn new(remote_host: Ipv4Addr,
                packets_to_send: usize,
-               interval: i64,
-               echo_file: File,
-               time_file: File)
+               interval: Duration,
+               output: Format,
+               echo: Echo)
                -> Ping {
         Ping {
             remote_host,
-            echo_file,
-            time_file,
+            echo,
             seq: 0,
             recieved: 0,
             waiting_for: vec![],
             packets_to_send,
             interval,
+            output,
         }
     }
 
-    pub fn on_echo_event(&mut self) -> Result<Option<()>> {
-        let mut payload = EchoPayload {
-            seq: 0,
-            timestamp: TimeSpec::default(),
-            payload: [0; 40],
-        };
-        let readed = self.echo_file.read(&mut payload)?;
-        if readed == 0 {
-            return Ok(None);
-        }
-        if readed < mem::size_of::<EchoPayload>() {
-            return Err(Error::new_local("Not enough data in the echo file"));
+    /// Send the requests, every interval, until they have all been answered or timed out
+    pub fn run(&mut self) -> Result<()> {
+        let mut next_send = Instant::now();
+        let mut payload = [0; 64];
+        loop {
+            let now = Instant::now();
+            if now >= next_send && !self.all_sent() {
+                self.send_ping(now)?;
+                next_send += self.interval;
+            }
+            self.check_timeouts(now);
+            if self.is_finished() {
+                return Ok(());
+            }
+
+            // Wait for a reply until the next request is due or the oldest one times out
+            let timeout = Duration::from_secs(PING_TIMEOUT_S);
+            let mut until = self.waiting_for.iter().map(|&(sent, _)| sent + timeout).min().unwrap_or(next_send);
+            if !self.all_sent() {
+                until = cmp::min(until, next_send);
+            }
+            let wait = if until > now { until - now } else { Duration::from_millis(0) };
+            if let Some((seq, _)) = self.echo.receive(&mut payload, wait)? {
+                self.on_reply(seq);
+            }
         }
-        let mut time = TimeSpec::default();
-        syscall::clock_gettime(syscall::CLOCK_MONOTONIC, &mut time)
-            .map_err(|_| Error::new_local("Failed to get the current time"))?;
+    }
+
+    fn on_reply(&mut self, reply_seq: u16) {
+        let now = Instant::now();
         let remote_host = self.remote_host;
+        let output = self.output;
         let mut recieved = 0;
         self.waiting_for
-            .retain(|&(_ts, seq)| if seq as u16 == payload.seq {
+            .retain(|&(sent, seq)| if seq as u16 == reply_seq {
                         recieved += 1;
-                        println!("From {} icmp_seq={} time={}ms",
-                                 remote_host,
-                                 seq,
-                                 time_diff_ms(&payload.timestamp, &time));
+                        print_reply(output, remote_host, seq, Some(duration_ms(now - sent)));
                         false
                     } else {
                         true
                     });
         self.recieved += recieved;
-        self.is_finished()
     }
 
-    pub fn on_time_event(&mut self) -> Result<Option<()>> {
-        let mut time = TimeSpec::default();
-        if self.time_file.read(&mut time)? < mem::size_of::<TimeSpec>() {
-            return Err(Error::new_local("Failed to read from time file"));
-        }
-        self.send_ping(&time)?;
-        self.check_timeouts(&time)?;
-        time.tv_sec += self.interval;
-        self.time_file
-            .write_all(&time)
-            .map_err(|e| Error::from_io_error(e, "Failed to write to time file"))?;
-        self.is_finished()
-    }
-
-    fn send_ping(&mut self, time: &TimeSpec) -> Result<Option<()>> {
-        if self.packets_to_send != 0 && self.seq >= self.packets_to_send {
-            return Ok(None);
-        }
-        let payload = EchoPayload {
-            seq: self.seq as u16,
-            timestamp: *time,
-            payload: [1; 40],
-        };
-        let _ = self.echo_file.write(&payload)?;
-        let mut timeout_time = *time;
-        timeout_time.tv_sec += PING_TIMEOUT_S;
-        self.waiting_for.push((timeout_time, self.seq));
+    fn send_ping(&mut self, now: Instant) -> Result<()> {
+        self.echo.send(self.seq as u16, &PING_PAYLOAD)
+            .map_err(|err| NetError::from(err).context("failed to send echo request"))?;
+        self.waiting_for.push((now, self.seq));
         self.seq += 1;
-        Ok(None)
+        Ok(())
     }
 
-    fn check_timeouts(&mut self, time: &TimeSpec) -> Result<Option<()>> {
+    fn check_timeouts(&mut self, now: Instant) {
         let remote_host = self.remote_host;
+        let output = self.output;
+        let timeout = Duration::from_secs(PING_TIMEOUT_S);
         self.waiting_for
-            .retain(|&(ts, seq)| if ts.tv_sec <= time.tv_sec {
-                        println!("From {} icmp_seq={} timeout", remote_host, seq);
+            .retain(|&(sent, seq)| if sent + timeout <= now {
+                        print_reply(output, remote_host, seq, None);
                         false
                     } else {
                         true
                     });
-        Ok(None)
     }
 
-    fn is_finished(&self) -> Result<Option<()>> {
-        if self.packets_to_send != 0 && self.seq == self.packets_to_send &&
-           self.waiting_for.is_empty() {
-            Ok(Some(()))
-        } else {
-            Ok(None)
-        }
+    fn all_sent(&self) -> bool {
+        self.packets_to_send != 0 && self.seq >= self.packets_to_send
+    }
+
+    fn is_finished(&self) -> bool {
+        self.all_sent() && self.waiting_for.is_empty()
     }
 
     fn get_transmitted(&self) -> usize {
@@ -266,106 +210,99 @@ impl Ping {
 
 fn resolve_host(host: &str) -> Result<Ipv4Addr> {
     Ipv4Addr::from_str(host)
-        .or_else(|_| if let Some(SocketAddr::V4(addr)) = lookup_host(host)?.next() {
-                     Ok(*addr.ip())
-                 } else {
-                     Err(Error::new_local("Failed to resolve remote host's IPv4 address"))
+        .or_else(|_| match resolve::lookup(host, Family::V4)?[0] {
+                     IpAddr::V4(addr) => Ok(addr),
+                     IpAddr::V6(_) => unreachable!()
                  })
 }
 
-fn run() -> Result<()> {
-    let mut args = args().skip(1);
-    let mut count = PING_PACKETS_TO_SEND;
-    let mut interval = PING_INTERVAL_S;
-    let mut remote_host = "".to_owned();
-
-    while let Some(arg) = args.next() {
-        match arg.as_str() {
-            "--help" | "-h" => {
-                println!("{}", PING_MAN);
-                return Ok(());
-            }
-            "-i" => {
-                interval = i64::from_str(&args.next()
-                                         .ok_or_else(|| {
-                                             Error::new_local("No argument to -i option")
-                                         })?)
-                    .map_err(|e| {
-                        Error::from_int_parse_error(e, "Invalid argument to -i option")
-                    })?;
-                if interval <= 0 {
-                    return Err(Error::new_local("Interval can't be less or equal to 0"));
-                }
-            }
-            "-c" => {
-                count = usize::from_str(&args.next()
-                                        .ok_or_else(|| {
-                                            Error::new_local("No argument to -c option")
-                                        })?)
-                    .map_err(|e| {
-                        Error::from_int_parse_error(e, "Invalid argument to -c option")
-                    })?;
-            }
-            host => {
-                if remote_host.is_empty() {
-                    remote_host = host.to_owned();
-                } else {
-                    return Err(Error::new_local("Too many hosts to ping"));
-                }
-            }
-        }
+/// Ping every host of the targets, returning whether any answered
+fn run_sweep(operands: &[String], count: usize, interval: Duration, window: usize, output: Format) -> Result<bool> {
+    // Replies are told apart by host, so a host in more than one target is only pinged once
+    let mut hosts = vec![];
+    let mut seen = HashSet::new();
+    for target in operands {
+        hosts.extend(targets::parse_target(target, Family::V4)?.into_iter().filter(|&addr| seen.insert(addr)));
     }
 
-    let remote_host = resolve_host(&remote_host)?;
-
-    let icmp_path = format!("icmp:echo/{}", remote_host);
-    let echo_fd = syscall::open(&icmp_path, syscall::O_RDWR | syscall::O_NONBLOCK)
-        .map_err(|_| Error::new_local(format!("Can't open path {}", icmp_path)))?;
-
-    let time_path = format!("time:{}", syscall::CLOCK_MONOTONIC);
-    let time_fd = syscall::open(&time_path, syscall::O_RDWR)
-        .map_err(|_| Error::new_local(format!("Can't open path {}", time_path)))?;
-
-
-    let ping = Rc::new(RefCell::new(Ping::new(remote_host,
-                                              count,
-                                              interval,
-                                              unsafe { File::from_raw_fd(echo_fd as RawFd) },
-                                              unsafe { File::from_raw_fd(time_fd as RawFd) })));
-
-    let mut event_queue =
-        EventQueue::<(), Error>::new()
-            .map_err(|e| Error::from_io_error(e, "Failed to create error queue"))?;
-
-    let ping_ = ping.clone();
-
-    event_queue
-        .add(echo_fd as RawFd,
-             move |_| ping_.borrow_mut().on_echo_event())?;
+    let mut echo = Echo::open_any(false).map_err(|err| NetError::from(err).context("failed to open ICMP socket"))?;
+    rawsock::drop_privileges().map_err(|err| NetError::from(err).context("failed to drop privileges"))?;
 
-    let ping_ = ping.clone();
-    event_queue
-        .add(time_fd as RawFd,
-             move |_| ping_.borrow_mut().on_time_event())?;
-
-    event_queue
-        .trigger_all(0)?;
-
-    event_queue.run()?;
+    let mut sweep = Sweep::new(hosts, count, interval, window, output);
+    let alive = sweep.run(&mut echo)?;
+    sweep.print_summary();
+    Ok(alive > 0)
+}
 
-    let transmited = ping.borrow().get_transmitted();
-    let recieved = ping.borrow().get_recieved();
-    println!("--- {} ping statistics ---", remote_host);
-    println!("{} packets transmitted, {} recieved, {}% packet loss",
-             transmited,
-             recieved,
-             100 * (transmited - recieved) / transmited);
-    Ok(())
+/// Ping the host, returning whether it answered
+fn run() -> Result<bool> {
+    let args = Parser::new("ping")
+        .synopsis("[-c count] [-i interval] [--sweep] [--window hosts] [--output text|json] destination...")
+        .man(PING_MAN)
+        .opt("c", "count", "count", "Number of packets to send")
+        .opt("i", "interval", "interval", "Seconds to wait before sending the next packet")
+        .flag("", "sweep", "Ping many hosts and networks at once")
+        .opt("", "window", "hosts", "The most hosts to wait for at once when sweeping")
+        .output()
+        .parse_env();
+    let sweeping = args.flag("sweep");
+    let count = args.parse("c").unwrap_or(if sweeping { 1 } else { PING_PACKETS_TO_SEND });
+    let interval = args.parse("i").unwrap_or(PING_INTERVAL_S);
+    let output = args.output();
+    if interval == 0 {
+        args.invalid("Interval can't be less or equal to 0");
+    }
+    if sweeping {
+        let window = args.parse("window").unwrap_or(PING_SWEEP_WINDOW);
+        if count == 0 {
+            args.invalid("A sweep can't send packets until interrupted");
+        }
+        if window == 0 {
+            args.invalid("Window can't be less or equal to 0");
+        }
+        if args.free.is_empty() {
+            args.invalid("No hosts to ping");
+        }
+        return run_sweep(&args.free, count, Duration::from_secs(interval), window, output);
+    }
+    if args.free.len() > 1 {
+        args.invalid("Too many hosts to ping, use --sweep to ping more than one");
+    }
+    let remote_host = args.free.get(0).map(|host| host.as_str()).unwrap_or("");
+
+    let remote_host = resolve_host(remote_host)?;
+
+    let echo = Echo::open(IpAddr::V4(remote_host))
+        .map_err(|err| NetError::from(err).context("failed to open ICMP socket"))?;
+    rawsock::drop_privileges().map_err(|err| NetError::from(err).context("failed to drop privileges"))?;
+
+    let mut ping = Ping::new(remote_host, count, Duration::from_secs(interval), output, echo);
+    ping.run()?;
+
+    let transmited = ping.get_transmitted();
+    let recieved = ping.get_recieved();
+    let loss = 100 * (transmited - recieved) / transmited;
+    match output {
+        Format::Text | Format::Tsv => {
+            println!("--- {} ping statistics ---", remote_host);
+            println!("{} packets transmitted, {} recieved, {}% packet loss",
+                     transmited,
+                     recieved,
+                     loss);
+        },
+        Format::Json => println!("{}", Object::new()
+                                 .string("host", &remote_host.to_string())
+                                 .number("transmitted", transmited)
+                                 .number("received", recieved)
+                                 .number("loss_percent", loss)),
+    }
+    Ok(recieved > 0)
 }
 
 fn main() {
-    if let Err(err) = run() {
-        println!("{}", err);
-        process::exit(1);
+    match run() {
+        Ok(true) => (),
+        Ok(false) => process::exit(EXIT_FAILURE),
+        Err(err) => err.exit("ping")
     }
 }
@@ -0,0 +1,285 @@
+//! Pinging many hosts at once, as fping does. Hosts take turns at one socket, and at most a
+//! window of them is waited on at a time. Each is reported alive or unreachable once its
+//! requests have all been answered or have timed out.
+
+use netutils_core::error::Result;
+use netutils_core::output::{Format, Object};
+use netutils_core::rawsock::Echo;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use {duration_ms, PING_PAYLOAD};
+
+/// How long each request has to be answered, which is shorter than for one host, since a
+/// window of unreachable hosts holds up the rest
+const SWEEP_TIMEOUT_MS: u64 = 1000;
+/// The least time between requests to any two hosts, so that a network isn't flooded
+const SWEEP_SEND_GAP_MS: u64 = 10;
+
+struct Host {
+    addr: IpAddr,
+    sent: usize,
+    /// The round trip times of the replies
+    times: Vec<f32>,
+    /// The requests without a reply, with the time they were sent
+    waiting_for: Vec<(Instant, u16)>,
+    next_send: Instant,
+}
+
+/// The state of a sweep, which `run` sends and receives for through a socket
+pub struct Sweep {
+    /// The hosts that haven't been sent anything yet
+    pending: VecDeque<IpAddr>,
+    active: Vec<Host>,
+    packets_to_send: usize,
+    interval: Duration,
+    window: usize,
+    output: Format,
+    seq: u16,
+    alive: usize,
+    unreachable: usize,
+}
+
+impl Sweep {
+    /// Ping each of `hosts`, which must all be different
+    pub fn new(hosts: Vec<IpAddr>,
+               packets_to_send: usize,
+               interval: Duration,
+               window: usize,
+               output: Format)
+               -> Sweep {
+        Sweep {
+            pending: hosts.into_iter().collect(),
+            active: vec![],
+            packets_to_send,
+            interval,
+            window,
+            output,
+            seq: 0,
+            alive: 0,
+            unreachable: 0,
+        }
+    }
+
+    /// Ping every host through `echo`, returning how many answered
+    pub fn run(&mut self, echo: &mut Echo) -> Result<usize> {
+        let timeout = Duration::from_millis(SWEEP_TIMEOUT_MS);
+        let gap = Duration::from_millis(SWEEP_SEND_GAP_MS);
+        let mut next_send = Instant::now();
+        let mut payload = [0; 64];
+        loop {
+            let now = Instant::now();
+            self.fill_window(now);
+
+            if now >= next_send {
+                if let Some((addr, seq)) = self.next_request(now) {
+                    // One that can't be sent, such as to a host with no route to it, counts as
+                    // unanswered, so that one host doesn't end the sweep
+                    if let Err(err) = echo.send_to(addr, seq, &PING_PAYLOAD) {
+                        let _ = writeln!(io::stderr(), "ping: failed to send echo request to {}: {}", addr, err);
+                        self.on_reply_lost(addr, seq);
+                    }
+                    next_send = now + gap;
+                }
+            }
+            self.check_timeouts(now, timeout);
+            self.finish_hosts();
+            if self.active.is_empty() && self.pending.is_empty() {
+                return Ok(self.alive);
+            }
+
+            // Wait for a reply until the next request is due or the oldest one times out
+            let until = self.active.iter()
+                .filter(|host| host.sent < self.packets_to_send)
+                .map(|host| if host.next_send > next_send { host.next_send } else { next_send })
+                .chain(self.active.iter().flat_map(|host| host.waiting_for.iter().map(|&(sent, _)| sent + timeout)))
+                .min()
+                .unwrap_or(now);
+            let wait = if until > now { until - now } else { Duration::from_millis(0) };
+            if let Some((addr, seq, _)) = echo.receive_from(&mut payload, wait)? {
+                self.on_reply(addr, seq, Instant::now());
+            }
+        }
+    }
+
+    /// Move hosts that haven't been sent anything into the window, while there is room
+    fn fill_window(&mut self, now: Instant) {
+        while self.active.len() < self.window {
+            match self.pending.pop_front() {
+                Some(addr) => self.active.push(Host {
+                    addr: addr,
+                    sent: 0,
+                    times: vec![],
+                    waiting_for: vec![],
+                    next_send: now,
+                }),
+                None => break
+            }
+        }
+    }
+
+    /// Record the next request of the first host in the window that is due one, returning its
+    /// address and sequence number to send it with
+    fn next_request(&mut self, now: Instant) -> Option<(IpAddr, u16)> {
+        let packets_to_send = self.packets_to_send;
+        let i = self.active.iter().position(|host| host.sent < packets_to_send && host.next_send <= now)?;
+        let seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+        let host = &mut self.active[i];
+        host.sent += 1;
+        host.next_send = now + self.interval;
+        host.waiting_for.push((now, seq));
+        Some((host.addr, seq))
+    }
+
+    /// Stop waiting for the reply to a request that couldn't be sent
+    fn on_reply_lost(&mut self, addr: IpAddr, seq: u16) {
+        if let Some(host) = self.active.iter_mut().find(|host| host.addr == addr) {
+            host.waiting_for.retain(|&(_, waiting)| waiting != seq);
+        }
+    }
+
+    fn on_reply(&mut self, addr: IpAddr, reply_seq: u16, now: Instant) {
+        if let Some(host) = self.active.iter_mut().find(|host| host.addr == addr) {
+            if let Some(i) = host.waiting_for.iter().position(|&(_, seq)| seq == reply_seq) {
+                let (sent, _) = host.waiting_for.remove(i);
+                host.times.push(duration_ms(now - sent));
+            }
+        }
+    }
+
+    fn check_timeouts(&mut self, now: Instant, timeout: Duration) {
+        for host in self.active.iter_mut() {
+            host.waiting_for.retain(|&(sent, _)| sent + timeout > now);
+        }
+    }
+
+    /// Report the hosts whose requests have all been answered or timed out, and make room in
+    /// the window for others
+    fn finish_hosts(&mut self) {
+        let packets_to_send = self.packets_to_send;
+        let (finished, active): (Vec<Host>, Vec<Host>) = self.active.drain(..)
+            .partition(|host| host.sent >= packets_to_send && host.waiting_for.is_empty());
+        self.active = active;
+        for host in finished {
+            if host.times.is_empty() {
+                self.unreachable += 1;
+            } else {
+                self.alive += 1;
+            }
+            self.print_host(&host);
+        }
+    }
+
+    fn print_host(&self, host: &Host) {
+        let time_ms = if host.times.is_empty() {
+            None
+        } else {
+            Some(host.times.iter().sum::<f32>() / host.times.len() as f32)
+        };
+        match (self.output, time_ms) {
+            (Format::Json, _) => println!("{}", Object::new()
+                                          .string("host", &host.addr.to_string())
+                                          .string("status", if time_ms.is_some() { "alive" } else { "unreachable" })
+                                          .number("transmitted", host.sent)
+                                          .number("received", host.times.len())
+                                          .number_option("time_ms", time_ms)),
+            (_, Some(time_ms)) => println!("{} is alive, {} of {} received, time={}ms",
+                                           host.addr, host.times.len(), host.sent, time_ms),
+            (_, None) => println!("{} is unreachable", host.addr),
+        }
+    }
+
+    /// Print the totals
+    pub fn print_summary(&self) {
+        match self.output {
+            Format::Text | Format::Tsv => {
+                println!("--- sweep statistics ---");
+                println!("{} hosts, {} alive, {} unreachable",
+                         self.alive + self.unreachable,
+                         self.alive,
+                         self.unreachable);
+            },
+            Format::Json => println!("{}", Object::new()
+                                     .number("hosts", self.alive + self.unreachable)
+                                     .number("alive", self.alive)
+                                     .number("unreachable", self.unreachable)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use netutils_core::output::Format;
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+    use super::Sweep;
+
+    fn sweep(hosts: &[&str], packets_to_send: usize, window: usize) -> Sweep {
+        let hosts = hosts.iter().map(|host| host.parse::<IpAddr>().unwrap()).collect();
+        Sweep::new(hosts, packets_to_send, Duration::from_secs(1), window, Format::Text)
+    }
+
+    #[test]
+    fn window_test() {
+        let mut sweep = sweep(&["10.0.0.1", "10.0.0.2", "10.0.0.3"], 1, 2);
+        let start = Instant::now();
+        sweep.fill_window(start);
+        assert_eq!(sweep.active.len(), 2);
+        assert_eq!(sweep.pending.len(), 1);
+
+        let (first, first_seq) = sweep.next_request(start).unwrap();
+        let (second, second_seq) = sweep.next_request(start).unwrap();
+        assert_eq!(first, "10.0.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!(second, "10.0.0.2".parse::<IpAddr>().unwrap());
+        assert!(sweep.next_request(start).is_none());
+
+        // A host is done once its requests are answered, which makes room for the next one
+        sweep.on_reply(first, first_seq, start + Duration::from_millis(5));
+        sweep.finish_hosts();
+        assert_eq!((sweep.alive, sweep.unreachable), (1, 0));
+        sweep.fill_window(start);
+        assert_eq!(sweep.active.len(), 2);
+        assert!(sweep.pending.is_empty());
+
+        // A reply with the sequence number of another host's request is ignored
+        sweep.on_reply(second, first_seq, start);
+        sweep.check_timeouts(start + Duration::from_millis(999), Duration::from_secs(1));
+        sweep.finish_hosts();
+        assert_eq!(sweep.active.len(), 2);
+
+        // The rest time out, and the window empties
+        sweep.check_timeouts(start + Duration::from_secs(1), Duration::from_secs(1));
+        sweep.finish_hosts();
+        assert_eq!((sweep.alive, sweep.unreachable), (1, 1));
+        assert_eq!(sweep.active.len(), 1);
+        let (third, _) = sweep.next_request(start).unwrap();
+        assert_eq!(third, "10.0.0.3".parse::<IpAddr>().unwrap());
+        sweep.check_timeouts(start + Duration::from_secs(2), Duration::from_secs(1));
+        sweep.finish_hosts();
+        assert_eq!((sweep.alive, sweep.unreachable), (1, 2));
+        assert!(sweep.active.is_empty());
+    }
+
+    #[test]
+    fn packets_test() {
+        let mut sweep = sweep(&["10.0.0.1"], 2, 64);
+        let start = Instant::now();
+        sweep.fill_window(start);
+        let (host, seq) = sweep.next_request(start).unwrap();
+        // The second request waits for the interval
+        assert!(sweep.next_request(start).is_none());
+        sweep.on_reply(host, seq, start);
+        sweep.finish_hosts();
+        assert_eq!(sweep.active.len(), 1);
+
+        // A request that couldn't be sent isn't waited for, and counts as unanswered
+        let (host, seq) = sweep.next_request(start + Duration::from_secs(1)).unwrap();
+        sweep.on_reply_lost(host, seq);
+        sweep.finish_hosts();
+        assert!(sweep.active.is_empty());
+        assert_eq!((sweep.alive, sweep.unreachable), (1, 0));
+    }
+}
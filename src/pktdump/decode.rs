@@ -0,0 +1,180 @@
+use netutils_core::{Arp, EthernetII, Ipv4, MacAddr};
+use netutils_core::icmp::{self, PROTO_ICMP, PROTO_ICMPV6};
+use netutils_core::tcp::{Tcp, TCP_ACK, TCP_FIN, TCP_PSH, TCP_RST, TCP_SYN};
+use netutils_core::udp::Udp;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+
+const PROTO_TCP: u8 = 6;
+const PROTO_UDP: u8 = 17;
+
+/// Size of the fixed IPv6 header
+const IPV6_HEADER_LEN: usize = 40;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    Arp,
+    Icmp,
+    Tcp,
+    Udp,
+    /// Other protocols over IP, or frames that could not be decoded
+    Other,
+}
+
+/// A decoded frame: the fields that can be filtered on, and the line that describes it
+#[derive(Clone, Debug)]
+pub struct Packet {
+    pub protocol: Protocol,
+    /// None for frames that are not IP or ARP
+    pub ipv6: Option<bool>,
+    pub src: Option<IpAddr>,
+    pub dst: Option<IpAddr>,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    pub summary: String,
+}
+
+fn to_ip(addr: ::netutils_core::Ipv4Addr) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(addr.bytes[0], addr.bytes[1], addr.bytes[2], addr.bytes[3]))
+}
+
+fn format_mac(mac: &MacAddr) -> String {
+    let parts: Vec<String> = mac.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    parts.join(":")
+}
+
+fn tcp_flags(flags: u16) -> String {
+    let mut names = String::new();
+    for &(flag, name) in [(TCP_SYN, 'S'), (TCP_FIN, 'F'), (TCP_RST, 'R'), (TCP_PSH, 'P'), (TCP_ACK, '.')].iter() {
+        if flags & flag != 0 {
+            names.push(name);
+        }
+    }
+    if names.is_empty() {
+        names.push_str("none");
+    }
+    names
+}
+
+/// Decode the transport header of an IP packet into `packet`
+fn decode_transport(packet: &mut Packet, proto: u8, data: &[u8]) {
+    let (src, dst) = match (packet.src, packet.dst) {
+        (Some(src), Some(dst)) => (src, dst),
+        _ => return
+    };
+    let endpoint = |addr: IpAddr, port: u16| format!("{}.{}", addr, port);
+
+    match proto {
+        PROTO_TCP => if let Some(tcp) = Tcp::from_bytes(data) {
+            let (src_port, dst_port) = (tcp.header.src.get(), tcp.header.dst.get());
+            packet.protocol = Protocol::Tcp;
+            packet.src_port = Some(src_port);
+            packet.dst_port = Some(dst_port);
+            packet.summary = format!("{} > {}: TCP [{}] seq {} ack {} win {} len {}",
+                                     endpoint(src, src_port), endpoint(dst, dst_port),
+                                     tcp_flags(tcp.header.flags.get()), tcp.header.sequence.get(),
+                                     tcp.header.ack_num.get(), tcp.header.window_size.get(), tcp.data.len());
+            return;
+        },
+        PROTO_UDP => if let Some(udp) = Udp::from_bytes(data) {
+            let (src_port, dst_port) = (udp.header.src.get(), udp.header.dst.get());
+            packet.protocol = Protocol::Udp;
+            packet.src_port = Some(src_port);
+            packet.dst_port = Some(dst_port);
+            packet.summary = format!("{} > {}: UDP len {}", endpoint(src, src_port), endpoint(dst, dst_port), udp.data.len());
+            return;
+        },
+        PROTO_ICMP | PROTO_ICMPV6 => if data.len() >= 4 {
+            packet.protocol = Protocol::Icmp;
+            let name = if proto == PROTO_ICMP { icmp::name(data[0], data[1]) } else { icmp::name_v6(data[0], data[1]) };
+            let mut summary = format!("{} > {}: {} {}", src, dst, if proto == PROTO_ICMP { "ICMP" } else { "ICMP6" }, name);
+            // Echo messages have an identifier and sequence number
+            if data.len() >= 8 && (data[0] == 0 || data[0] == 8 || data[0] == 128 || data[0] == 129) {
+                summary.push_str(&format!(", id {}, seq {}", (data[4] as u16) << 8 | data[5] as u16, (data[6] as u16) << 8 | data[7] as u16));
+            }
+            packet.summary = summary;
+            return;
+        },
+        _ => ()
+    }
+    packet.summary = format!("{} > {}: proto {} len {}", src, dst, proto, data.len());
+}
+
+pub fn decode(frame: &[u8]) -> Packet {
+    let mut packet = Packet {
+        protocol: Protocol::Other,
+        ipv6: None,
+        src: None,
+        dst: None,
+        src_port: None,
+        dst_port: None,
+        summary: format!("truncated frame, len {}", frame.len()),
+    };
+    let ethernet = match EthernetII::from_bytes(frame) {
+        Some(ethernet) => ethernet,
+        None => return packet
+    };
+
+    match ethernet.header.ethertype.get() {
+        ETHERTYPE_ARP => if let Some(arp) = Arp::from_bytes(&ethernet.data) {
+            packet.protocol = Protocol::Arp;
+            packet.ipv6 = Some(false);
+            packet.src = Some(to_ip(arp.header.src_ip));
+            packet.dst = Some(to_ip(arp.header.dst_ip));
+            packet.summary = match arp.header.oper.get() {
+                1 => format!("ARP who-has {} tell {}", to_ip(arp.header.dst_ip), to_ip(arp.header.src_ip)),
+                2 => format!("ARP reply {} is-at {}", to_ip(arp.header.src_ip), format_mac(&arp.header.src_mac)),
+                oper => format!("ARP operation {}", oper),
+            };
+        },
+        ETHERTYPE_IPV4 => if let Some(ip) = Ipv4::from_bytes(&ethernet.data) {
+            packet.ipv6 = Some(false);
+            packet.src = Some(to_ip(ip.header.src));
+            packet.dst = Some(to_ip(ip.header.dst));
+            decode_transport(&mut packet, ip.header.proto, &ip.data);
+        },
+        ETHERTYPE_IPV6 => if ethernet.data.len() >= IPV6_HEADER_LEN {
+            let data = &ethernet.data;
+            let mut src = [0; 16];
+            let mut dst = [0; 16];
+            src.copy_from_slice(&data[8..24]);
+            dst.copy_from_slice(&data[24..40]);
+            packet.ipv6 = Some(true);
+            packet.src = Some(IpAddr::V6(Ipv6Addr::from(src)));
+            packet.dst = Some(IpAddr::V6(Ipv6Addr::from(dst)));
+            // Extension headers are not followed, so their packets are shown by next header
+            let len = (data[4] as usize) << 8 | data[5] as usize;
+            let end = ::std::cmp::min(IPV6_HEADER_LEN + len, data.len());
+            decode_transport(&mut packet, data[6], &data[IPV6_HEADER_LEN..end]);
+        },
+        ethertype => {
+            packet.summary = format!("{} > {}, ethertype 0x{:04x}, len {}",
+                                     format_mac(&ethernet.header.src), format_mac(&ethernet.header.dst), ethertype, frame.len());
+        }
+    }
+    packet
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, Protocol};
+
+    #[test]
+    fn decode_test() {
+        // A SYN from 192.168.1.2:40000 to 192.168.1.1:80
+        let frame = [
+            0x02, 0, 0, 0, 0, 0x01, 0x02, 0, 0, 0, 0, 0x02, 0x08, 0x00,
+            0x45, 0, 0, 40, 0, 1, 0x40, 0, 64, 6, 0, 0, 192, 168, 1, 2, 192, 168, 1, 1,
+            0x9C, 0x40, 0, 80, 0, 0, 0, 100, 0, 0, 0, 0, 0x50, 0x02, 0x72, 0x10, 0, 0, 0, 0,
+        ];
+        let packet = decode(&frame);
+        assert_eq!(packet.protocol, Protocol::Tcp);
+        assert_eq!((packet.src_port, packet.dst_port), (Some(40000), Some(80)));
+        assert_eq!(packet.summary, "192.168.1.2.40000 > 192.168.1.1.80: TCP [S] seq 100 ack 0 win 29200 len 0");
+
+        assert_eq!(decode(&frame[..10]).protocol, Protocol::Other);
+    }
+}
@@ -0,0 +1,157 @@
+use std::net::IpAddr;
+
+use decode::{Packet, Protocol};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Term {
+    Host(IpAddr),
+    Src(IpAddr),
+    Dst(IpAddr),
+    Port(u16),
+    SrcPort(u16),
+    DstPort(u16),
+    Protocol(Protocol),
+    Ipv4,
+    Ipv6,
+}
+
+impl Term {
+    fn matches(&self, packet: &Packet) -> bool {
+        match *self {
+            Term::Host(addr) => packet.src == Some(addr) || packet.dst == Some(addr),
+            Term::Src(addr) => packet.src == Some(addr),
+            Term::Dst(addr) => packet.dst == Some(addr),
+            Term::Port(port) => packet.src_port == Some(port) || packet.dst_port == Some(port),
+            Term::SrcPort(port) => packet.src_port == Some(port),
+            Term::DstPort(port) => packet.dst_port == Some(port),
+            Term::Protocol(protocol) => packet.protocol == protocol,
+            Term::Ipv4 => packet.ipv6 == Some(false),
+            Term::Ipv6 => packet.ipv6 == Some(true),
+        }
+    }
+}
+
+/// A filter of alternatives joined by `or`, each of which is terms joined by `and`, where a term
+/// may be negated by `not`
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    alternatives: Vec<Vec<(bool, Term)>>,
+}
+
+fn parse_addr(value: Option<&str>) -> Result<IpAddr, String> {
+    let value = value.ok_or("expected an address".to_string())?;
+    value.parse().map_err(|_| format!("invalid address '{}'", value))
+}
+
+fn parse_port(value: Option<&str>) -> Result<u16, String> {
+    let value = value.ok_or("expected a port".to_string())?;
+    value.parse().map_err(|_| format!("invalid port '{}'", value))
+}
+
+impl Filter {
+    /// Parse a filter from words such as `tcp and port 80 or not host 10.0.0.1`
+    pub fn parse(words: &[String]) -> Result<Filter, String> {
+        let mut filter = Filter::default();
+        let mut terms = Vec::new();
+        let mut negate = false;
+        let mut expect_term = true;
+
+        let mut words = words.iter().map(|word| word.as_str());
+        while let Some(word) = words.next() {
+            let term = match word {
+                "and" | "&&" if !expect_term => {
+                    expect_term = true;
+                    continue;
+                },
+                "or" | "||" if !expect_term => {
+                    filter.alternatives.push(terms);
+                    terms = Vec::new();
+                    expect_term = true;
+                    continue;
+                },
+                "not" | "!" => {
+                    negate = !negate;
+                    continue;
+                },
+                "host" => Term::Host(parse_addr(words.next())?),
+                "src" => match words.next() {
+                    Some("port") => Term::SrcPort(parse_port(words.next())?),
+                    Some("host") => Term::Src(parse_addr(words.next())?),
+                    value => Term::Src(parse_addr(value)?),
+                },
+                "dst" => match words.next() {
+                    Some("port") => Term::DstPort(parse_port(words.next())?),
+                    Some("host") => Term::Dst(parse_addr(words.next())?),
+                    value => Term::Dst(parse_addr(value)?),
+                },
+                "port" => Term::Port(parse_port(words.next())?),
+                "tcp" => Term::Protocol(Protocol::Tcp),
+                "udp" => Term::Protocol(Protocol::Udp),
+                "icmp" | "icmp6" => Term::Protocol(Protocol::Icmp),
+                "arp" => Term::Protocol(Protocol::Arp),
+                "ip" => Term::Ipv4,
+                "ip6" => Term::Ipv6,
+                _ => return Err(format!("unexpected '{}' in filter", word))
+            };
+            // Terms next to each other are joined by and, as in pcap filters
+            terms.push((negate, term));
+            negate = false;
+            expect_term = false;
+        }
+        if expect_term && (!terms.is_empty() || !filter.alternatives.is_empty() || negate) {
+            return Err("the filter ends early".to_string());
+        }
+        if !terms.is_empty() {
+            filter.alternatives.push(terms);
+        }
+        Ok(filter)
+    }
+
+    /// An empty filter matches every packet
+    pub fn matches(&self, packet: &Packet) -> bool {
+        self.alternatives.is_empty() || self.alternatives.iter().any(|terms| {
+            terms.iter().all(|&(negate, ref term)| term.matches(packet) != negate)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use decode::{Packet, Protocol};
+    use super::Filter;
+
+    fn packet(protocol: Protocol, src: &str, dst_port: u16) -> Packet {
+        Packet {
+            protocol: protocol,
+            ipv6: Some(false),
+            src: Some(src.parse().unwrap()),
+            dst: Some("10.0.0.1".parse().unwrap()),
+            src_port: Some(40000),
+            dst_port: Some(dst_port),
+            summary: String::new(),
+        }
+    }
+
+    fn filter(text: &str) -> Filter {
+        let words: Vec<String> = text.split_whitespace().map(|word| word.to_string()).collect();
+        Filter::parse(&words).unwrap()
+    }
+
+    #[test]
+    fn filter_test() {
+        let web = packet(Protocol::Tcp, "10.0.0.2", 80);
+        let dns = packet(Protocol::Udp, "10.0.0.3", 53);
+
+        assert!(filter("").matches(&web));
+        assert!(filter("tcp port 80").matches(&web));
+        assert!(!filter("tcp and port 80").matches(&dns));
+        assert!(filter("tcp and port 80 or udp").matches(&dns));
+        assert!(filter("not src 10.0.0.2").matches(&dns));
+        assert!(!filter("not src host 10.0.0.2").matches(&web));
+        assert!(filter("dst port 53 and host 10.0.0.1").matches(&dns));
+
+        let words: Vec<String> = vec!["tcp".to_string(), "and".to_string()];
+        assert!(Filter::parse(&words).is_err());
+        assert!(Filter::parse(&["port".to_string(), "http".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+extern crate netutils_core;
+
+use netutils_core::args::Parser;
+use netutils_core::error::{NetError, Result};
+use netutils_core::rawsock::{self, Link};
+use std::cmp;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use filter::Filter;
+
+mod decode;
+mod filter;
+mod pcap;
+
+static PKTDUMP_MAN: &'static str = /* @MANSTART{pktdump} */ r#"
+NAME
+    pktdump - capture and show network traffic
+
+SYNOPSIS
+    pktdump [-h | --help] [-i iface] [-p] [-c count] [-s snaplen] [-w file] [filter]
+
+DESCRIPTION
+    pktdump captures the frames on an interface and shows a line for each of
+    them, decoding Ethernet, ARP, IPv4, IPv6, TCP, UDP and ICMP headers.
+
+    The filter is made of terms that are joined by and, which may be left
+    out, or by or, and negated by not:
+
+        host addr           either address is addr
+        src [host] addr     the source address is addr
+        dst [host] addr     the destination address is addr
+        port n              either port is n
+        src port n
+        dst port n
+        tcp, udp, icmp, arp
+        ip, ip6             IPv4 or IPv6, including ARP for ip
+
+    and binds tighter than or, so "tcp port 80 or arp" matches web traffic
+    and every ARP frame.
+
+OPTIONS
+    -i iface
+        Capture on iface. Every interface is captured otherwise.
+
+    -p
+        Don't put the interface in promiscuous mode, so only frames to and
+        from this host are captured.
+
+    -c count
+        Exit after count frames that match the filter.
+
+    -s snaplen
+        Keep the first snaplen bytes of each frame in the file given by -w,
+        65535 by default.
+
+    -w file
+        Write the frames to a pcap file, which Wireshark and tcpdump can
+        read, instead of showing them. - is the standard output.
+
+    -h
+    --help
+        Print this manual page.
+
+NOTES
+    Capturing requires root, which pktdump gives up once capturing has
+    started, after the file given with -w is open. On Redox every interface
+    is captured, without promiscuous mode.
+"#; /* @MANEND */
+
+const DEFAULT_SNAPLEN: u32 = 65535;
+
+/// The time of day in UTC, to the microsecond
+fn format_time(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+    let secs = since.as_secs() % 86400;
+    format!("{:02}:{:02}:{:02}.{:06}", secs / 3600, secs / 60 % 60, secs % 60, since.subsec_nanos() / 1000)
+}
+
+fn run() -> Result<()> {
+    let args = Parser::new("pktdump")
+        .synopsis("[-i iface] [-p] [-c count] [-s snaplen] [-w file] [filter]")
+        .man(PKTDUMP_MAN)
+        .opt("i", "", "iface", "Capture on this interface, rather than every one")
+        .flag("p", "", "Don't put the interface in promiscuous mode")
+        .opt("c", "", "count", "Exit after this many packets")
+        .opt("s", "", "snaplen", "Bytes of each packet to write to the file")
+        .opt("w", "", "file", "Write the packets to a pcap file, or - for standard output")
+        .parse_env();
+
+    let iface = args.value("i");
+    let promiscuous = !args.flag("p");
+    let count = args.parse::<u64>("c");
+    let snaplen = match args.parse::<u32>("s") {
+        Some(0) => args.invalid("the snaplen must be more than 0"),
+        Some(snaplen) => snaplen,
+        None => DEFAULT_SNAPLEN
+    };
+    let output = args.value("w");
+    let filter = Filter::parse(&args.free).unwrap_or_else(|err| args.invalid(&err));
+
+    let mut pcap = match output {
+        Some(path) => {
+            let writer: Box<Write> = if path == "-" {
+                Box::new(io::stdout())
+            } else {
+                Box::new(File::create(&path).map_err(|err| NetError::from(err).context(&format!("failed to create {}", path)))?)
+            };
+            Some(pcap::Writer::new(BufWriter::new(writer), snaplen).map_err(|err| NetError::from(err).context("failed to write"))?)
+        },
+        None => None
+    };
+
+    let mut link = Link::open(iface, None).map_err(|err| NetError::from(err).context("failed to open capture"))?;
+    if promiscuous && iface.is_some() {
+        // Frames to and from this host can still be captured
+        if let Err(err) = link.set_promiscuous() {
+            let _ = writeln!(io::stderr(), "pktdump: no promiscuous mode: {}", err);
+        }
+    }
+    rawsock::drop_privileges().map_err(|err| NetError::from(err).context("failed to drop privileges"))?;
+    let _ = writeln!(io::stderr(), "pktdump: capturing on {}", iface.unwrap_or("every interface"));
+
+    let mut buffer = vec![0; 65536];
+    let mut captured = 0;
+    while count.map_or(true, |count| captured < count) {
+        let len = match link.receive(&mut buffer, None) {
+            Ok(Some(len)) => len,
+            Ok(None) => continue,
+            Err(err) => return Err(NetError::from(err).context("failed to capture"))
+        };
+        let time = SystemTime::now();
+        let frame = &buffer[..len];
+
+        let packet = decode::decode(frame);
+        if !filter.matches(&packet) {
+            continue;
+        }
+        captured += 1;
+
+        match pcap {
+            // Each frame is flushed so that the file is whole when pktdump is interrupted
+            Some(ref mut pcap) => if let Err(err) = pcap.write(time, &frame[..cmp::min(len, snaplen as usize)], len)
+                                                     .and_then(|_| pcap.flush()) {
+                return Err(NetError::from(err).context("failed to write"));
+            },
+            None => println!("{} {}", format_time(time), packet.summary)
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run() {
+        err.exit("pktdump");
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::{Result, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAGIC: u32 = 0xA1B2_C3D4;
+const VERSION_MAJOR: u16 = 2;
+const VERSION_MINOR: u16 = 4;
+const LINKTYPE_ETHERNET: u32 = 1;
+
+/// Writes frames in the pcap format of libpcap, which Wireshark and tcpdump read
+pub struct Writer<W: Write> {
+    inner: W,
+}
+
+fn u16_bytes(value: u16) -> [u8; 2] {
+    [value as u8, (value >> 8) as u8]
+}
+
+fn u32_bytes(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+impl<W: Write> Writer<W> {
+    /// Write the file header, for frames of at most `snaplen` bytes
+    pub fn new(mut inner: W, snaplen: u32) -> Result<Writer<W>> {
+        // Fields are in the byte order of the writer, which readers detect from the magic number
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&u32_bytes(MAGIC));
+        header.extend_from_slice(&u16_bytes(VERSION_MAJOR));
+        header.extend_from_slice(&u16_bytes(VERSION_MINOR));
+        // Timestamps are in UTC, with no offset or accuracy given
+        header.extend_from_slice(&u32_bytes(0));
+        header.extend_from_slice(&u32_bytes(0));
+        header.extend_from_slice(&u32_bytes(snaplen));
+        header.extend_from_slice(&u32_bytes(LINKTYPE_ETHERNET));
+        inner.write_all(&header)?;
+        Ok(Writer { inner: inner })
+    }
+
+    /// Write a frame captured at `time`, of which `frame` may be the start of `len` bytes
+    pub fn write(&mut self, time: SystemTime, frame: &[u8], len: usize) -> Result<()> {
+        let since = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+        let mut record = Vec::with_capacity(16 + frame.len());
+        record.extend_from_slice(&u32_bytes(since.as_secs() as u32));
+        record.extend_from_slice(&u32_bytes(since.subsec_nanos() / 1000));
+        record.extend_from_slice(&u32_bytes(frame.len() as u32));
+        record.extend_from_slice(&u32_bytes(len as u32));
+        record.extend_from_slice(frame);
+        self.inner.write_all(&record)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+    use super::Writer;
+
+    #[test]
+    fn writer_test() {
+        let mut data = Vec::new();
+        {
+            let mut writer = Writer::new(&mut data, 65535).unwrap();
+            writer.write(UNIX_EPOCH + Duration::new(1, 2000), &[1, 2, 3], 60).unwrap();
+        }
+        assert_eq!(&data[..8], &[0xD4, 0xC3, 0xB2, 0xA1, 2, 0, 4, 0]);
+        assert_eq!(&data[16..24], &[0xFF, 0xFF, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(&data[24..], &[1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 60, 0, 0, 0, 1, 2, 3]);
+    }
+}
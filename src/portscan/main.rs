@@ -0,0 +1,204 @@
+extern crate netutils_core;
+
+use netutils_core::args::Parser;
+use netutils_core::error::NetError;
+use netutils_core::output::{Format, Object};
+use netutils_core::resolve::Family;
+use netutils_core::targets::parse_target;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::time::Duration;
+
+use scan::{connect_scan, PortResult, State};
+use syn::syn_scan;
+use targets::parse_ports;
+
+mod scan;
+mod syn;
+mod targets;
+
+static PORTSCAN_MAN: &'static str = /* @MANSTART{portscan} */ r#"
+NAME
+    portscan - find the open TCP ports of hosts
+
+SYNOPSIS
+    portscan [-p ports] [-j parallelism] [-w timeout] [-S | -b] [-a] [-o text|json] target...
+    portscan [-h | --help]
+
+DESCRIPTION
+    Probe each port of each target, and print the ports that are open. A
+    port is closed if the host refused the connection, and filtered if
+    nothing came back, which usually means that a firewall dropped the
+    probe.
+
+    A target is an address, a host name, an IPv4 network such as
+    192.0.2.0/24, or a range of the last byte of an IPv4 address, such as
+    192.0.2.10-20. The network and broadcast addresses of a network are not
+    scanned, and networks larger than /16 are refused.
+
+    By default each port is connected to, which needs no privileges, but
+    leaves a connection in the logs of each open service. A SYN scan (-S)
+    only sends the first packet of a connection, and is faster.
+
+    portscan exits with 0 once every port has been probed, whether or not
+    any is open, 2 if the arguments or a target are invalid, 3 if the name
+    of a target couldn't be resolved, and 1 on other errors.
+
+OPTIONS
+    -p ports
+        The ports to scan, as a list of ports and ranges such as
+        22,80,8000-8100, 1-1024 by default.
+
+    -j parallelism
+        Number of ports to probe at once, 100 by default.
+
+    -w timeout
+        Seconds to wait for each port to answer, which may be a fraction,
+        1 by default.
+
+    -S
+        Send SYN packets, rather than connecting. This requires root, and
+        only supports IPv4.
+
+    -b
+        Read the banner that each open service sends first, such as the
+        version line of an SSH server, waiting for at most the timeout.
+
+    -a
+        Show closed and filtered ports too.
+
+    -o text|json
+    --output text|json
+        Print a table, by default, or a JSON object for each port followed
+        by one with the totals, each on a line of its own.
+
+    -h
+    --help
+        Print this manual page.
+
+NOTES
+    Only scan hosts that you are allowed to. Scans are easily noticed, and
+    may be treated as an attack.
+"#; /* @MANEND */
+
+const DEFAULT_PORTS: &'static str = "1-1024";
+const DEFAULT_PARALLELISM: usize = 100;
+const DEFAULT_TIMEOUT: f64 = 1.0;
+
+/// Names of TCP ports from /etc/services
+fn services() -> BTreeMap<u16, String> {
+    let mut text = String::new();
+    if let Ok(mut file) = File::open("/etc/services") {
+        let _ = file.read_to_string(&mut text);
+    }
+    let mut services = BTreeMap::new();
+    for line in text.lines() {
+        let mut fields = line.split('#').next().unwrap_or("").split_whitespace();
+        if let (Some(name), Some(port)) = (fields.next(), fields.next()) {
+            if port.ends_with("/tcp") {
+                if let Ok(port) = port[..port.len() - 4].parse() {
+                    services.entry(port).or_insert(name.to_string());
+                }
+            }
+        }
+    }
+    services
+}
+
+fn print_text(results: &[PortResult], services: &BTreeMap<u16, String>, all: bool) {
+    let shown: Vec<&PortResult> = results.iter().filter(|result| all || result.state == State::Open).collect();
+    if !shown.is_empty() {
+        let width = shown.iter().map(|result| result.addr.to_string().len()).max().unwrap_or(0);
+        println!("{:<width$}  {:<5}  {:<8}  {:<12}  {}", "HOST", "PORT", "STATE", "SERVICE", "BANNER", width = width);
+        for result in shown {
+            let line = format!("{:<width$}  {:<5}  {:<8}  {:<12}  {}", result.addr, result.port, result.state.name(),
+                               services.get(&result.port).map(|name| name.as_str()).unwrap_or("-"),
+                               result.banner.as_ref().map(|banner| banner.as_str()).unwrap_or(""), width = width);
+            println!("{}", line.trim_right());
+        }
+    }
+}
+
+fn print_json(results: &[PortResult], services: &BTreeMap<u16, String>, all: bool) {
+    for result in results.iter().filter(|result| all || result.state == State::Open) {
+        println!("{}", Object::new()
+                 .string("host", &result.addr.to_string())
+                 .number("port", result.port)
+                 .string("state", result.state.name())
+                 .option("service", services.get(&result.port))
+                 .option("banner", result.banner.as_ref()));
+    }
+}
+
+fn main() {
+    let args = Parser::new("portscan")
+        .synopsis("[-p ports] [-j parallelism] [-w timeout] [-S | -b] [-a] [-o text|json] target...")
+        .man(PORTSCAN_MAN)
+        .opt("p", "", "ports", "The ports to scan, such as 22,80,8000-8100")
+        .opt("j", "", "parallelism", "Number of ports to probe at once")
+        .opt("w", "", "timeout", "Seconds to wait for each port to answer")
+        .flag("S", "", "Send SYN packets, rather than connecting")
+        .flag("b", "", "Read the banner of each open service")
+        .flag("a", "", "Show closed and filtered ports too")
+        .output_formats("o", "text|json", "The output format")
+        .parse_env();
+
+    let parallelism = match args.parse::<usize>("j") {
+        Some(0) => args.invalid("the parallelism must be at least 1"),
+        Some(parallelism) => parallelism,
+        None => DEFAULT_PARALLELISM
+    };
+    let timeout = match args.parse::<f64>("w") {
+        Some(timeout) if !(timeout > 0.0) => args.invalid("the timeout must be more than 0"),
+        Some(timeout) => timeout,
+        None => DEFAULT_TIMEOUT
+    };
+    let syn = args.flag("S");
+    let banner = args.flag("b");
+    let all = args.flag("a");
+    let output = args.output();
+    if syn && banner {
+        args.invalid("-S and -b can't be used together");
+    }
+    if args.free.is_empty() {
+        args.usage();
+    }
+
+    let ports = parse_ports(args.value("p").unwrap_or(DEFAULT_PORTS))
+        .unwrap_or_else(|err| args.invalid(&err));
+    let mut jobs = Vec::new();
+    for target in args.free.iter() {
+        for addr in parse_target(target, Family::Any).unwrap_or_else(|err| err.exit("portscan")) {
+            for &port in ports.iter() {
+                jobs.push((addr, port));
+            }
+        }
+    }
+    let timeout = Duration::from_millis((timeout * 1000.0) as u64);
+
+    let mut results = if syn {
+        syn_scan(jobs, parallelism, timeout).unwrap_or_else(|err| NetError::from(err).exit("portscan"))
+    } else {
+        connect_scan(jobs, parallelism, timeout, if banner { Some(timeout) } else { None })
+    };
+    results.sort_by_key(|result| (result.addr, result.port));
+
+    let services = services();
+    let count = |state| results.iter().filter(|result| result.state == state).count();
+    let (open, closed, filtered) = (count(State::Open), count(State::Closed), count(State::Filtered));
+    match output {
+        Format::Text | Format::Tsv => {
+            print_text(&results, &services, all);
+            println!("{} ports scanned: {} open, {} closed, {} filtered", results.len(), open, closed, filtered);
+        },
+        Format::Json => {
+            print_json(&results, &services, all);
+            println!("{}", Object::new()
+                     .number("scanned", results.len())
+                     .number("open", open)
+                     .number("closed", closed)
+                     .number("filtered", filtered));
+        }
+    }
+}
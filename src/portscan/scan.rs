@@ -0,0 +1,105 @@
+use std::cmp;
+use std::io::{ErrorKind, Read};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Bytes of a banner that are kept
+const BANNER_LEN: usize = 80;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Open,
+    Closed,
+    /// Nothing came back, so something dropped the probe
+    Filtered,
+}
+
+impl State {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            State::Open => "open",
+            State::Closed => "closed",
+            State::Filtered => "filtered",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PortResult {
+    pub addr: IpAddr,
+    pub port: u16,
+    pub state: State,
+    pub banner: Option<String>,
+}
+
+/// The first line that a service sent, with anything unprintable replaced
+fn clean_banner(data: &[u8]) -> Option<String> {
+    let line = String::from_utf8_lossy(data).lines().next().unwrap_or("").trim()
+        .chars().map(|c| if c.is_control() { '.' } else { c }).take(BANNER_LEN).collect::<String>();
+    if line.is_empty() {
+        None
+    } else {
+        Some(line)
+    }
+}
+
+/// Read what a service sends without being asked, as SSH, SMTP and FTP servers do
+fn read_banner(stream: &mut TcpStream, timeout: Duration) -> Option<String> {
+    stream.set_read_timeout(Some(timeout)).ok()?;
+    let mut buf = [0; 512];
+    match stream.read(&mut buf) {
+        Ok(count) => clean_banner(&buf[..count]),
+        Err(_) => None
+    }
+}
+
+fn connect(addr: IpAddr, port: u16, timeout: Duration, banner: Option<Duration>) -> PortResult {
+    let (state, banner) = match TcpStream::connect_timeout(&SocketAddr::new(addr, port), timeout) {
+        Ok(mut stream) => (State::Open, banner.and_then(|timeout| read_banner(&mut stream, timeout))),
+        Err(ref err) if err.kind() == ErrorKind::ConnectionRefused => (State::Closed, None),
+        Err(_) => (State::Filtered, None)
+    };
+    PortResult {
+        addr: addr,
+        port: port,
+        state: state,
+        banner: banner,
+    }
+}
+
+/// Connect to each port from `parallelism` threads, reading banners for `banner` if it is given.
+/// This needs no privileges, but servers see, and may log, each connection.
+pub fn connect_scan(jobs: Vec<(IpAddr, u16)>, parallelism: usize, timeout: Duration, banner: Option<Duration>) -> Vec<PortResult> {
+    let count = jobs.len();
+    let jobs = Arc::new(Mutex::new(jobs.into_iter()));
+    let (sender, receiver) = mpsc::channel();
+    for _ in 0..cmp::min(parallelism, count) {
+        let jobs = jobs.clone();
+        let sender = sender.clone();
+        thread::spawn(move || loop {
+            let job = jobs.lock().unwrap().next();
+            match job {
+                Some((addr, port)) => if sender.send(connect(addr, port, timeout, banner)).is_err() {
+                    break;
+                },
+                None => break
+            }
+        });
+    }
+    drop(sender);
+    receiver.iter().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::clean_banner;
+
+    #[test]
+    fn clean_banner_test() {
+        assert_eq!(clean_banner(b"SSH-2.0-OpenSSH_7.4\r\n"), Some("SSH-2.0-OpenSSH_7.4".to_string()));
+        assert_eq!(clean_banner(b"220 ready\x07\r\n220 more"), Some("220 ready.".to_string()));
+        assert_eq!(clean_banner(b"\r\n"), None);
+    }
+}
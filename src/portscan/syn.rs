@@ -0,0 +1,156 @@
+//! Half-open scanning: a SYN is sent to each port, and the reply tells whether the port is open
+//! (SYN ACK) or closed (RST). The kernel answers a SYN ACK with a RST itself, since it has no
+//! socket for the connection, so no connection is ever made.
+
+use netutils_core::{n16, n32, Checksum};
+use netutils_core::rawsock::{self, Ip};
+use netutils_core::tcp::{Tcp, TcpHeader, TCP_ACK, TCP_RST, TCP_SYN};
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use scan::{PortResult, State};
+
+const IPPROTO_TCP: u8 = 6;
+
+/// How long to wait for replies between sending probes
+const POLL_MS: u64 = 10;
+
+/// A socket that sends TCP segments, which the kernel puts in IP packets, and receives every
+/// TCP packet that arrives
+fn open() -> Result<Ip> {
+    Ip::open(IPPROTO_TCP).map_err(|err| if err.kind() == ErrorKind::PermissionDenied {
+        Error::new(err.kind(), "SYN scans require root or CAP_NET_RAW")
+    } else {
+        err
+    })
+}
+
+/// The address that packets to `addr` are sent from, which the checksum covers
+fn source(addr: Ipv4Addr) -> Result<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect((addr, 9))?;
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(addr) => Ok(addr),
+        IpAddr::V6(_) => Err(Error::new(ErrorKind::Other, "no IPv4 source address"))
+    }
+}
+
+fn to_lib(addr: Ipv4Addr) -> ::netutils_core::Ipv4Addr {
+    ::netutils_core::Ipv4Addr { bytes: addr.octets() }
+}
+
+fn send_syn(socket: &mut Ip, src: Ipv4Addr, dst: Ipv4Addr, src_port: u16, dst_port: u16, seq: u32) -> Result<()> {
+    let mut tcp = Tcp {
+        header: TcpHeader {
+            src: n16::new(src_port),
+            dst: n16::new(dst_port),
+            sequence: n32::new(seq),
+            ack_num: n32::new(0),
+            // A header of five words, with no options
+            flags: n16::new(5 << 12 | TCP_SYN),
+            window_size: n16::new(1024),
+            checksum: Checksum { data: 0 },
+            urgent_pointer: n16::new(0),
+        },
+        options: Vec::new(),
+        data: Vec::new(),
+    };
+    tcp.checksum(&to_lib(src), &to_lib(dst));
+    socket.send_to(dst, &tcp.to_bytes())
+}
+
+/// Read an IP packet if one arrives within `timeout`, returning its source address and TCP
+/// segment
+fn receive(socket: &mut Ip, buf: &mut [u8], timeout: Duration) -> Result<Option<(Ipv4Addr, Tcp)>> {
+    let count = match socket.receive(buf, timeout)? {
+        Some(count) => count,
+        None => return Ok(None)
+    };
+    let packet = &buf[..count];
+    if packet.len() < 20 || packet[0] >> 4 != 4 {
+        return Ok(None);
+    }
+    let header_len = (packet[0] & 0x0F) as usize * 4;
+    let src = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    Ok(packet.get(header_len..).and_then(Tcp::from_bytes).map(|tcp| (src, tcp)))
+}
+
+/// Send SYNs with at most `parallelism` waiting for a reply at once. Ports that don't reply
+/// within `timeout` are filtered.
+pub fn syn_scan(jobs: Vec<(IpAddr, u16)>, parallelism: usize, timeout: Duration) -> Result<Vec<PortResult>> {
+    let mut targets = Vec::with_capacity(jobs.len());
+    for (addr, port) in jobs {
+        match addr {
+            IpAddr::V4(addr) => targets.push((addr, port)),
+            IpAddr::V6(_) => return Err(Error::new(ErrorKind::InvalidInput, "SYN scans only support IPv4"))
+        }
+    }
+
+    let mut socket = open()?;
+    rawsock::drop_privileges()?;
+    let src_port = 40000 + (process::id() % 20000) as u16;
+    let seq = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.subsec_nanos()).unwrap_or(0);
+    let mut sources = BTreeMap::new();
+    let mut pending = BTreeMap::new();
+    let mut results = Vec::new();
+    let mut buf = [0; 65536];
+    let mut targets = targets.into_iter().peekable();
+
+    while targets.peek().is_some() || !pending.is_empty() {
+        while pending.len() < parallelism {
+            let (addr, port) = match targets.next() {
+                Some(target) => target,
+                None => break
+            };
+            if !sources.contains_key(&addr) {
+                sources.insert(addr, source(addr)?);
+            }
+            send_syn(&mut socket, sources[&addr], addr, src_port, port, seq)?;
+            pending.insert((addr, port), Instant::now());
+        }
+
+        // Replies are matched by their ports, and the sequence number that they acknowledge
+        let mut wait = Duration::from_millis(POLL_MS);
+        while let Some((addr, tcp)) = receive(&mut socket, &mut buf, wait)? {
+            wait = Duration::from_millis(0);
+            let flags = tcp.header.flags.get();
+            let key = (addr, tcp.header.src.get());
+            if tcp.header.dst.get() != src_port || !pending.contains_key(&key) {
+                continue;
+            }
+            let state = if flags & (TCP_SYN | TCP_ACK) == TCP_SYN | TCP_ACK && tcp.header.ack_num.get() == seq.wrapping_add(1) {
+                State::Open
+            } else if flags & TCP_RST == TCP_RST {
+                State::Closed
+            } else {
+                continue;
+            };
+            pending.remove(&key);
+            results.push(PortResult {
+                addr: IpAddr::V4(key.0),
+                port: key.1,
+                state: state,
+                banner: None,
+            });
+        }
+
+        let now = Instant::now();
+        let expired: Vec<(Ipv4Addr, u16)> = pending.iter()
+            .filter(|&(_, sent)| now.duration_since(*sent) >= timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            pending.remove(&key);
+            results.push(PortResult {
+                addr: IpAddr::V4(key.0),
+                port: key.1,
+                state: State::Filtered,
+                banner: None,
+            });
+        }
+    }
+    Ok(results)
+}
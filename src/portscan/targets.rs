@@ -0,0 +1,41 @@
+use std::collections::BTreeSet;
+
+/// Parse a list of ports and ranges of ports, such as `22,80,8000-8100`
+pub fn parse_ports(text: &str) -> Result<Vec<u16>, String> {
+    let mut ports = Vec::new();
+    let mut seen = BTreeSet::new();
+    for part in text.split(',') {
+        let (start, end) = match part.find('-') {
+            Some(dash) => (&part[..dash], &part[dash + 1..]),
+            None => (part, part)
+        };
+        let parse = |port: &str| match port.trim().parse::<u16>() {
+            Ok(port) if port > 0 => Ok(port),
+            _ => Err(format!("invalid port '{}'", port))
+        };
+        let (start, end) = (parse(start)?, parse(end)?);
+        if start > end {
+            return Err(format!("invalid port range '{}'", part));
+        }
+        for port in start as u32..end as u32 + 1 {
+            if seen.insert(port) {
+                ports.push(port as u16);
+            }
+        }
+    }
+    Ok(ports)
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_ports;
+
+    #[test]
+    fn parse_ports_test() {
+        assert_eq!(parse_ports("22,80,8000-8002,80"), Ok(vec![22, 80, 8000, 8001, 8002]));
+        assert_eq!(parse_ports("65535"), Ok(vec![65535]));
+        assert!(parse_ports("0").is_err());
+        assert!(parse_ports("90-80").is_err());
+        assert!(parse_ports("ssh").is_err());
+    }
+}
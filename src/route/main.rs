@@ -0,0 +1,186 @@
+extern crate netutils_core;
+#[cfg(not(target_os = "redox"))]
+extern crate libc;
+
+use netutils_core::args::Parser;
+use netutils_core::error::{NetError, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use table::Route;
+
+mod table;
+
+static ROUTE_MAN: &'static str = /* @MANSTART{route} */ r#"
+NAME
+    route - show and change the routing table
+
+SYNOPSIS
+    route [-4 | -6]
+    route [-6] add default | destination[/prefix] [via gateway] [dev iface] [metric n]
+    route [-6] del default | destination[/prefix] [via gateway] [dev iface] [metric n]
+    route [-h | --help]
+
+DESCRIPTION
+    Without a command, route shows the IPv4 and IPv6 routes of the main
+    routing table.
+
+    A route is either through a gateway, given with via, or to a network on
+    the link of an interface, given with dev. A destination without a
+    prefix length is a single host. The default route is IPv6 if its
+    gateway is, or with -6.
+
+    Routes are deleted if they match the destination and every other field
+    that is given.
+
+OPTIONS
+    -4
+    -6
+        Show only IPv4 or IPv6 routes.
+
+    -h
+    --help
+        Print this manual page.
+
+EXAMPLES
+    route add default via 192.168.1.1
+    route add 10.0.0.0/8 via 192.168.1.254 metric 100
+    route add 192.168.2.0/24 dev eth1
+    route -6 add default via fe80::1 dev eth0
+
+NOTES
+    Changing routes requires root. On Redox routes have no metric.
+"#; /* @MANEND */
+
+/// Parse the destination and fields of a route, where `ipv6` chooses the family of a default
+/// route without a gateway
+fn parse_route(args: &[String], ipv6: bool) -> ::std::result::Result<Route, String> {
+    let mut args = args.iter();
+    let dst = args.next().ok_or("missing destination".to_string())?;
+
+    let mut gateway = None;
+    let mut iface = None;
+    let mut metric = None;
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(format!("missing value for '{}'", arg))?;
+        match arg.as_ref() {
+            "via" | "gw" => gateway = Some(value.parse::<IpAddr>().map_err(|_| format!("invalid gateway '{}'", value))?),
+            "dev" => iface = Some(value.clone()),
+            "metric" => metric = Some(value.parse::<u32>().map_err(|_| format!("invalid metric '{}'", value))?),
+            _ => return Err(format!("unknown field '{}'", arg))
+        }
+    }
+
+    let (dst, prefix) = if dst == "default" {
+        match gateway {
+            Some(IpAddr::V6(_)) => (IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)), 0),
+            Some(IpAddr::V4(_)) => (IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+            None if ipv6 => (IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)), 0),
+            None => (IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+        }
+    } else {
+        let mut parts = dst.splitn(2, '/');
+        let addr = parts.next().unwrap_or("").parse::<IpAddr>().map_err(|_| format!("invalid destination '{}'", dst))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match parts.next() {
+            Some(prefix) => match prefix.parse::<u8>() {
+                Ok(prefix) if prefix <= max => prefix,
+                _ => return Err(format!("invalid prefix length '{}'", prefix))
+            },
+            None => max
+        };
+        (addr, prefix)
+    };
+    if let Some(ref gateway) = gateway {
+        if gateway.is_ipv4() != dst.is_ipv4() {
+            return Err("the gateway and destination are of different families".to_string());
+        }
+    }
+
+    Ok(Route {
+        dst: dst,
+        prefix: prefix,
+        gateway: gateway,
+        iface: iface,
+        metric: metric,
+    })
+}
+
+fn show(family: Option<bool>) -> Result<()> {
+    let routes = table::list().map_err(|err| NetError::from(err).context("failed to read the routing table"))?;
+    println!("{:<44} {:<40} {:<10} {}", "Destination", "Gateway", "Iface", "Metric");
+    for route in routes.iter().filter(|route| family.map_or(true, |ipv4| ipv4 == route.dst.is_ipv4())) {
+        println!("{:<44} {:<40} {:<10} {}",
+                 route.destination(),
+                 route.gateway.map(|gateway| gateway.to_string()).unwrap_or("*".to_string()),
+                 route.iface.as_ref().map(|iface| iface.as_str()).unwrap_or("*"),
+                 route.metric.map(|metric| metric.to_string()).unwrap_or("-".to_string()));
+    }
+    Ok(())
+}
+
+fn run() -> Result<()> {
+    let args = Parser::new("route")
+        .synopsis("[-4 | -6]")
+        .synopsis("[-6] add | del default | destination[/prefix] [via gateway] [dev iface] [metric n]")
+        .man(ROUTE_MAN)
+        .flag("4", "", "Only show IPv4 routes")
+        .flag("6", "", "Only show IPv6 routes")
+        .parse_env();
+    let family = match (args.flag("4"), args.flag("6")) {
+        (true, true) => args.invalid("-4 and -6 can't be used together"),
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        (false, false) => None
+    };
+    let words = &args.free;
+
+    if words.is_empty() {
+        return show(family);
+    }
+
+    let route = parse_route(&words[1..], family == Some(false)).unwrap_or_else(|err| args.invalid(&err));
+    if family.map_or(false, |ipv4| ipv4 != route.dst.is_ipv4()) {
+        args.invalid("the route is not of the family given");
+    }
+    let (result, action) = match words[0].as_ref() {
+        "add" => (table::add(&route), "add"),
+        "del" | "delete" => (table::delete(&route), "delete"),
+        _ => args.invalid(&format!("unknown command '{}'", words[0]))
+    };
+    result.map_err(|err| NetError::from(err).context(&format!("failed to {} route to {}", action, route.destination())))
+}
+
+fn main() {
+    if let Err(err) = run() {
+        err.exit("route");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+    use super::parse_route;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_route_test() {
+        let route = parse_route(&args("10.0.0.0/8 via 192.168.1.254 metric 100"), false).unwrap();
+        assert_eq!(route.dst, "10.0.0.0".parse::<IpAddr>().unwrap());
+        assert_eq!(route.prefix, 8);
+        assert_eq!(route.gateway, Some("192.168.1.254".parse().unwrap()));
+        assert_eq!(route.metric, Some(100));
+
+        let route = parse_route(&args("default via fe80::1 dev eth0"), false).unwrap();
+        assert!(route.dst.is_ipv6() && route.is_default());
+        assert_eq!(route.iface, Some("eth0".to_string()));
+        assert!(parse_route(&args("default dev eth0"), true).unwrap().dst.is_ipv6());
+
+        assert_eq!(parse_route(&args("192.168.2.1"), false).unwrap().prefix, 32);
+        assert!(parse_route(&args("10.0.0.0/8 via fe80::1"), false).is_err());
+        assert!(parse_route(&args("10.0.0.0/8 via"), false).is_err());
+        assert!(parse_route(&args("10.0.0.0/40"), false).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use std::net::IpAddr;
+
+/// A route to the network `dst`/`prefix`, through a gateway or directly on a link
+#[derive(Clone, Debug, PartialEq)]
+pub struct Route {
+    pub dst: IpAddr,
+    pub prefix: u8,
+    /// None for networks on the link
+    pub gateway: Option<IpAddr>,
+    pub iface: Option<String>,
+    pub metric: Option<u32>,
+}
+
+impl Route {
+    pub fn is_default(&self) -> bool {
+        self.prefix == 0
+    }
+
+    /// The destination as `addr/prefix`, or `default`
+    pub fn destination(&self) -> String {
+        if self.is_default() {
+            "default".to_string()
+        } else {
+            format!("{}/{}", self.dst, self.prefix)
+        }
+    }
+}
+
+pub use self::sys::{add, delete, list};
+
+/// The main routing table, read and changed over rtnetlink
+#[cfg(not(target_os = "redox"))]
+mod sys {
+    use libc;
+    use netutils_core::netlink::{self, read_u32, Request, Socket, NLM_F_CREATE, NLM_F_EXCL,
+                            RTM_DELROUTE, RTM_GETROUTE, RTM_NEWROUTE};
+    use std::io::{Error, ErrorKind, Result};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::Route;
+
+    /// Size of struct rtmsg
+    const RTMSG_LEN: usize = 12;
+
+    const RTA_DST: u16 = 1;
+    const RTA_OIF: u16 = 4;
+    const RTA_GATEWAY: u16 = 5;
+    const RTA_PRIORITY: u16 = 6;
+    const RTA_TABLE: u16 = 15;
+
+    const RT_TABLE_MAIN: u8 = 254;
+    const RTPROT_BOOT: u8 = 3;
+    const RT_SCOPE_UNIVERSE: u8 = 0;
+    const RT_SCOPE_LINK: u8 = 253;
+    /// Deleting with this scope matches routes of any scope
+    const RT_SCOPE_NOWHERE: u8 = 255;
+    const RTN_UNICAST: u8 = 1;
+
+    fn rtmsg(family: u8, prefix: u8, protocol: u8, scope: u8, kind: u8) -> [u8; RTMSG_LEN] {
+        [family, prefix, 0, 0, RT_TABLE_MAIN, protocol, scope, kind, 0, 0, 0, 0]
+    }
+
+    fn family(addr: &IpAddr) -> u8 {
+        match *addr {
+            IpAddr::V4(_) => libc::AF_INET as u8,
+            IpAddr::V6(_) => libc::AF_INET6 as u8,
+        }
+    }
+
+    fn addr_bytes(addr: &IpAddr) -> Vec<u8> {
+        match *addr {
+            IpAddr::V4(addr) => addr.octets().to_vec(),
+            IpAddr::V6(addr) => addr.octets().to_vec(),
+        }
+    }
+
+    fn parse_addr(data: &[u8]) -> Option<IpAddr> {
+        match data.len() {
+            4 => Some(IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3]))),
+            16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                Some(IpAddr::V6(Ipv6Addr::from(octets)))
+            },
+            _ => None
+        }
+    }
+
+    fn read_attr_u32(data: &[u8]) -> Option<u32> {
+        if data.len() == 4 { Some(read_u32(data)) } else { None }
+    }
+
+    pub fn list() -> Result<Vec<Route>> {
+        let mut routes = Vec::new();
+        for message in Socket::open()?.dump(RTM_GETROUTE, &rtmsg(0, 0, 0, 0, 0))? {
+            let header = match message.header(RTMSG_LEN) {
+                Some(header) => header,
+                None => continue
+            };
+            // Tables above 255 are only given as an attribute
+            let table = message.attr(RTMSG_LEN, RTA_TABLE).and_then(read_attr_u32).unwrap_or(header[4] as u32);
+            if table != RT_TABLE_MAIN as u32 || header[7] != RTN_UNICAST {
+                continue;
+            }
+
+            let dst = match message.attr(RTMSG_LEN, RTA_DST).and_then(parse_addr) {
+                Some(dst) => dst,
+                None => if header[0] == libc::AF_INET6 as u8 {
+                    IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0))
+                } else {
+                    IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))
+                }
+            };
+            routes.push(Route {
+                dst: dst,
+                prefix: header[1],
+                gateway: message.attr(RTMSG_LEN, RTA_GATEWAY).and_then(parse_addr),
+                iface: message.attr(RTMSG_LEN, RTA_OIF).and_then(read_attr_u32).and_then(netlink::if_name),
+                metric: message.attr(RTMSG_LEN, RTA_PRIORITY).and_then(read_attr_u32),
+            });
+        }
+        Ok(routes)
+    }
+
+    /// A request for the route, with the interface and metric only if they are given, so that
+    /// deleting matches any of them
+    fn request(kind: u16, flags: u16, header: &[u8], route: &Route) -> Result<Request> {
+        let mut request = Request::new(kind, flags, header);
+        if !route.is_default() {
+            request = request.attr(RTA_DST, &addr_bytes(&route.dst));
+        }
+        if let Some(ref gateway) = route.gateway {
+            request = request.attr(RTA_GATEWAY, &addr_bytes(gateway));
+        }
+        if let Some(ref iface) = route.iface {
+            let index = netlink::if_index(iface)?;
+            request = request.attr(RTA_OIF, &[index as u8, (index >> 8) as u8, (index >> 16) as u8, (index >> 24) as u8]);
+        }
+        if let Some(metric) = route.metric {
+            request = request.attr(RTA_PRIORITY, &[metric as u8, (metric >> 8) as u8, (metric >> 16) as u8, (metric >> 24) as u8]);
+        }
+        Ok(request)
+    }
+
+    pub fn add(route: &Route) -> Result<()> {
+        // Routes without a gateway are to hosts on the link
+        let scope = if route.gateway.is_some() { RT_SCOPE_UNIVERSE } else { RT_SCOPE_LINK };
+        let header = rtmsg(family(&route.dst), route.prefix, RTPROT_BOOT, scope, RTN_UNICAST);
+        let request = request(RTM_NEWROUTE, NLM_F_CREATE | NLM_F_EXCL, &header, route)?;
+        Socket::open()?.execute(request)
+    }
+
+    pub fn delete(route: &Route) -> Result<()> {
+        let header = rtmsg(family(&route.dst), route.prefix, 0, RT_SCOPE_NOWHERE, 0);
+        let request = request(RTM_DELROUTE, 0, &header, route)?;
+        // The kernel reports a missing route as ESRCH
+        Socket::open()?.execute(request).map_err(|err| if err.raw_os_error() == Some(libc::ESRCH) {
+            Error::new(ErrorKind::NotFound, "no such route")
+        } else {
+            err
+        })
+    }
+}
+
+/// The routing table of the network stack is in its netcfg scheme, as lines of
+/// `destination [via gateway] [dev iface]`, and is changed by writing such a line to
+/// `route/add` or the destination to `route/rm`
+#[cfg(target_os = "redox")]
+mod sys {
+    use std::fs::File;
+    use std::io::{Read, Result, Write};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::Route;
+
+    fn parse_line(line: &str) -> Option<Route> {
+        let mut fields = line.split_whitespace();
+        let (dst, prefix) = match fields.next()? {
+            "default" => (IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+            dst => {
+                let mut parts = dst.splitn(2, '/');
+                let addr: IpAddr = parts.next()?.parse().ok()?;
+                let max = if addr.is_ipv4() { 32 } else { 128 };
+                (addr, parts.next().map_or(Some(max), |prefix| prefix.parse().ok())?)
+            }
+        };
+        let mut route = Route {
+            dst: dst,
+            prefix: prefix,
+            gateway: None,
+            iface: None,
+            metric: None,
+        };
+        while let Some(field) = fields.next() {
+            match field {
+                "via" => route.gateway = fields.next().and_then(|gateway| gateway.parse().ok()),
+                "dev" => route.iface = fields.next().map(|iface| iface.to_string()),
+                _ => ()
+            }
+        }
+        if route.is_default() {
+            if let Some(IpAddr::V6(_)) = route.gateway {
+                route.dst = IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0));
+            }
+        }
+        Some(route)
+    }
+
+    pub fn list() -> Result<Vec<Route>> {
+        let mut text = String::new();
+        File::open("netcfg:route/list")?.read_to_string(&mut text)?;
+        Ok(text.lines().filter_map(parse_line).collect())
+    }
+
+    fn write_cfg(path: &str, value: &str) -> Result<()> {
+        let mut file = File::create(format!("netcfg:{}", path))?;
+        file.write(value.as_bytes())?;
+        file.sync_all()
+    }
+
+    pub fn add(route: &Route) -> Result<()> {
+        let mut line = route.destination();
+        if let Some(ref gateway) = route.gateway {
+            line.push_str(&format!(" via {}", gateway));
+        }
+        if let Some(ref iface) = route.iface {
+            line.push_str(&format!(" dev {}", iface));
+        }
+        write_cfg("route/add", &line)
+    }
+
+    pub fn delete(route: &Route) -> Result<()> {
+        write_cfg("route/rm", &route.destination())
+    }
+}
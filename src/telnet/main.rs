@@ -0,0 +1,294 @@
+#![deny(warnings)]
+
+extern crate netutils_core;
+extern crate termion;
+
+use netutils_core::args::Parser;
+use netutils_core::error::{NetError, Result};
+use netutils_core::proxy;
+use netutils_core::resolve::Family;
+use protocol::Telnet;
+use std::{env, panic, process, thread};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+use terminal::Terminal;
+
+mod protocol;
+mod terminal;
+
+/// Ctrl-], which leaves the session for the command prompt
+const DEFAULT_ESCAPE: u8 = 0x1D;
+
+static MAN_PAGE: &'static str = /* @MANSTART{telnet} */ r#"
+NAME
+    telnet - user interface to the TELNET protocol
+SYNOPSIS
+    telnet [-h | --help] [-4 | -6] [-e escape] host [port]
+DESCRIPTION
+    Connect to a telnet server, negotiating echo, suppressing go-ahead, the terminal type and the
+    window size with it. The port defaults to 23. An IPv6 host may be given in brackets, and a
+    link-local one with its zone, as in fe80::1%eth0.
+
+    Once the server echoes, the terminal is put in raw mode and characters are sent as they are
+    typed. The escape character, Ctrl-] by default, opens a prompt for these commands:
+
+    close, quit     Close the connection and exit
+    send CHAR       Send brk, ip (interrupt process), ao (abort output), ayt (are you there)
+                    or escape (the escape character itself)
+    set escape C    Change the escape character, given as a character or as ^C
+    status          Show the connection and mode
+    help, ?         Show the commands
+
+    An empty line returns to the session.
+OPTIONS
+    -h
+    --help
+        Print this manual page.
+    -4
+    -6
+        Connect over IPv4 only or IPv6 only.
+    -e escape
+        Use another escape character.
+ENVIRONMENT
+    all_proxy
+        A proxy to connect through, an HTTP one that allows CONNECT to the port or a socks5://
+        or socks5h:// one. Hosts and domains in the comma-separated no_proxy are reached
+        directly.
+"#; /* @MANEND */
+
+/// Send bytes to the server, which must not be written to by two threads at once
+fn send(stream: &Mutex<TcpStream>, data: &[u8]) -> io::Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    let mut stream = stream.lock().unwrap();
+    stream.write_all(data)?;
+    stream.flush()
+}
+
+/// Leave raw mode and exit
+fn exit(terminal: &Mutex<Terminal>, status: i32) -> ! {
+    match terminal.lock() {
+        Ok(mut terminal) => terminal.restore(),
+        Err(poisoned) => poisoned.into_inner().restore()
+    }
+    process::exit(status);
+}
+
+/// Show what the server sends, answering its negotiation and switching between line and
+/// character mode as it asks
+fn receive(mut reader: TcpStream, writer: Arc<Mutex<TcpStream>>, telnet: Arc<Mutex<Telnet>>,
+           terminal: Arc<Mutex<Terminal>>) {
+    let mut stdout = io::stdout();
+    let mut buffer = [0; 4096];
+    loop {
+        let count = match reader.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(count) => count
+        };
+
+        let mut data = Vec::new();
+        let mut reply = Vec::new();
+        let character_mode = {
+            let mut telnet = telnet.lock().unwrap();
+            telnet.receive(&buffer[..count], &mut data, &mut reply);
+            telnet.character_mode()
+        };
+
+        // The terminal is locked while the command prompt is open, which holds output back
+        terminal.lock().unwrap().set_character_mode(character_mode);
+        if stdout.write_all(&data).and_then(|_| stdout.flush()).is_err() || send(&writer, &reply).is_err() {
+            break;
+        }
+    }
+
+    if let Ok(mut terminal) = terminal.lock() {
+        terminal.restore();
+    }
+    println!("\nConnection closed by foreign host.");
+    process::exit(0);
+}
+
+/// Parse an escape character given as itself or as ^X
+fn parse_escape(value: &str) -> Option<u8> {
+    let bytes = value.as_bytes();
+    match bytes.len() {
+        1 => Some(bytes[0]),
+        2 if bytes[0] == b'^' => Some(bytes[1].to_ascii_uppercase() ^ 0x40),
+        _ => None
+    }
+}
+
+fn escape_name(escape: u8) -> String {
+    if escape < 0x20 || escape == 0x7F {
+        format!("^{}", (escape ^ 0x40) as char)
+    } else {
+        format!("{}", escape as char)
+    }
+}
+
+/// What to do after a command
+enum Command {
+    Resume,
+    Quit,
+}
+
+/// Run commands from the prompt until an empty line returns to the session
+fn command_mode(host: &str, writer: &Mutex<TcpStream>, telnet: &Mutex<Telnet>, terminal: &mut Terminal,
+                escape: &mut u8) -> Command {
+    let character_mode = terminal.character_mode();
+    terminal.set_character_mode(false);
+
+    let stdin = io::stdin();
+    let mut line = String::new();
+    let command = loop {
+        print!("\ntelnet> ");
+        let _ = io::stdout().flush();
+
+        line.clear();
+        match stdin.read_line(&mut line) {
+            Ok(0) | Err(_) => break Command::Quit,
+            Ok(_) => ()
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.get(0).map(|word| *word) {
+            None => break Command::Resume,
+            Some("close") | Some("quit") => break Command::Quit,
+            Some("send") => {
+                let data = match words.get(1).map(|word| *word) {
+                    Some("brk") => Ok(vec![protocol::IAC, protocol::BRK]),
+                    Some("ip") => Ok(vec![protocol::IAC, protocol::IP]),
+                    Some("ao") => Ok(vec![protocol::IAC, protocol::AO]),
+                    Some("ayt") => Ok(vec![protocol::IAC, protocol::AYT]),
+                    Some("escape") => Ok(vec![*escape]),
+                    _ => Err("send brk, ip, ao, ayt or escape".to_string())
+                };
+                data.and_then(|data| send(writer, &data).map_err(|err| format!("failed to write: {}", err)))
+                    .map(|_| String::new())
+            },
+            Some("set") => match (words.get(1).map(|word| *word), words.get(2).and_then(|value| parse_escape(value))) {
+                (Some("escape"), Some(value)) => {
+                    *escape = value;
+                    Ok(format!("escape character is '{}'", escape_name(value)))
+                },
+                _ => Err("set escape CHARACTER".to_string())
+            },
+            Some("status") => Ok(format!("Connected to {}.\nOperating in {} mode.\nEscape character is '{}'.", host,
+                                         if telnet.lock().unwrap().character_mode() { "character" } else { "line" },
+                                         escape_name(*escape))),
+            Some("help") | Some("?") => Ok("close, quit, send CHAR, set escape CHAR, status, help".to_string()),
+            Some(other) => Err(format!("unknown command '{}', try help", other))
+        };
+
+        match result {
+            Ok(ref message) if message.is_empty() => (),
+            Ok(message) => println!("{}", message),
+            Err(err) => println!("?{}", err)
+        }
+    };
+
+    terminal.set_character_mode(character_mode);
+    command
+}
+
+fn connect(host: &str, port: u16, family: Family, mut escape: u8) -> Result<()> {
+    println!("Trying {}...", host);
+    let stream = proxy::connect("telnet", host, port, family, None)?;
+    println!("Connected to {}.", host);
+    println!("Escape character is '{}'.", escape_name(escape));
+
+    let reader = stream.try_clone().map_err(|err| NetError::from(err).context("failed to clone stream"))?;
+    let writer = Arc::new(Mutex::new(stream));
+
+    let term = env::var("TERM").unwrap_or("unknown".to_string());
+    let size = termion::terminal_size().unwrap_or((80, 24));
+    let telnet = Arc::new(Mutex::new(Telnet::new(&term, size)));
+    let terminal = Arc::new(Mutex::new(Terminal::new()));
+
+    // Restore the terminal before a panic message is printed
+    {
+        let terminal = terminal.clone();
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Ok(mut terminal) = terminal.try_lock() {
+                terminal.restore();
+            }
+            default_hook(info);
+        }));
+    }
+
+    let start = telnet.lock().unwrap().start();
+    send(&writer, &start).map_err(|err| NetError::from(err).context("failed to write"))?;
+
+    {
+        let writer = writer.clone();
+        let telnet = telnet.clone();
+        let terminal = terminal.clone();
+        thread::spawn(move || receive(reader, writer, telnet, terminal));
+    }
+
+    let mut stdin = io::stdin();
+    let mut buffer = [0; 1024];
+    loop {
+        let count = match stdin.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(err) => return Err(NetError::from(err).context("failed to read stdin"))
+        };
+
+        let mut data = Vec::new();
+        for &byte in buffer[..count].iter() {
+            if byte == escape {
+                send(&writer, &protocol::escape(&data)).map_err(|err| NetError::from(err).context("failed to write"))?;
+                data.clear();
+
+                let command = {
+                    let mut terminal = terminal.lock().unwrap();
+                    command_mode(host, &writer, &telnet, &mut terminal, &mut escape)
+                };
+                if let Command::Quit = command {
+                    let _ = writer.lock().unwrap().shutdown(Shutdown::Both);
+                    println!("Connection closed.");
+                    exit(&terminal, 0);
+                }
+                continue;
+            }
+
+            // The network virtual terminal ends lines with CR LF, and a lone CR with CR NUL
+            match byte {
+                b'\n' => data.extend_from_slice(b"\r\n"),
+                b'\r' => data.extend_from_slice(b"\r\0"),
+                _ => data.push(byte)
+            }
+        }
+        send(&writer, &protocol::escape(&data)).map_err(|err| NetError::from(err).context("failed to write"))?;
+    }
+
+    exit(&terminal, 0);
+}
+
+fn main() {
+    let args = Parser::new("telnet")
+        .synopsis("[-4 | -6] [-e escape] host [port]")
+        .man(MAN_PAGE)
+        .family()
+        .opt("e", "", "escape", "The escape character, such as ^]")
+        .parse_env();
+
+    let escape = match args.value("e") {
+        Some(value) => parse_escape(value).unwrap_or_else(|| args.invalid("-e requires a character or ^X")),
+        None => DEFAULT_ESCAPE
+    };
+    let (host, port) = match args.free.len() {
+        1 => (&args.free[0], 23),
+        2 => match args.free[1].parse() {
+            Ok(port) => (&args.free[0], port),
+            Err(_) => args.invalid(&format!("invalid port '{}'", args.free[1]))
+        },
+        _ => args.usage()
+    };
+
+    connect(host, port, args.family(), escape).unwrap_or_else(|err| err.exit("telnet"));
+}
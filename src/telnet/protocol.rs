@@ -0,0 +1,248 @@
+//! Telnet option negotiation (RFC 854 and RFC 855)
+
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+pub const SB: u8 = 250;
+pub const AYT: u8 = 246;
+pub const AO: u8 = 245;
+pub const IP: u8 = 244;
+pub const BRK: u8 = 243;
+pub const SE: u8 = 240;
+
+pub const OPT_ECHO: u8 = 1;
+pub const OPT_SGA: u8 = 3;
+pub const OPT_TTYPE: u8 = 24;
+pub const OPT_NAWS: u8 = 31;
+
+const TTYPE_IS: u8 = 0;
+const TTYPE_SEND: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Data,
+    Iac,
+    /// After a DO, DONT, WILL or WONT, waiting for its option
+    Negotiate(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+/// The negotiation state of a connection. Options are only answered when their state changes,
+/// so the two ends never loop acknowledging each other (RFC 854 page 3).
+pub struct Telnet {
+    state: State,
+    subnegotiation: Vec<u8>,
+    /// Options this end performs
+    local: [bool; 256],
+    /// Options the other end performs
+    remote: [bool; 256],
+    /// Options this end offered with WILL, or asked for with DO, and is waiting to hear about
+    offered_local: [bool; 256],
+    offered_remote: [bool; 256],
+    pub terminal: String,
+    pub size: (u16, u16),
+}
+
+impl Telnet {
+    pub fn new(terminal: &str, size: (u16, u16)) -> Telnet {
+        Telnet {
+            state: State::Data,
+            subnegotiation: Vec::new(),
+            local: [false; 256],
+            remote: [false; 256],
+            offered_local: [false; 256],
+            offered_remote: [false; 256],
+            terminal: terminal.to_string(),
+            size: size,
+        }
+    }
+
+    /// Options to offer when connecting: the terminal type and size, and suppressing go-ahead
+    pub fn start(&mut self) -> Vec<u8> {
+        self.offered_local[OPT_TTYPE as usize] = true;
+        self.offered_local[OPT_NAWS as usize] = true;
+        self.offered_remote[OPT_SGA as usize] = true;
+        vec![IAC, WILL, OPT_TTYPE, IAC, WILL, OPT_NAWS, IAC, DO, OPT_SGA]
+    }
+
+    /// Whether the other end echoes what is typed and suppresses go-ahead, so the session can
+    /// send a character at a time
+    pub fn character_mode(&self) -> bool {
+        self.remote[OPT_ECHO as usize] && self.remote[OPT_SGA as usize]
+    }
+
+    /// The window size subnegotiation, if the other end accepted it
+    fn window_size(&self) -> Vec<u8> {
+        if !self.local[OPT_NAWS as usize] {
+            return Vec::new();
+        }
+        let mut reply = vec![IAC, SB, OPT_NAWS];
+        for &value in [self.size.0, self.size.1].iter() {
+            for &byte in [(value >> 8) as u8, value as u8].iter() {
+                reply.push(byte);
+                // Data bytes of 255 are doubled, including in subnegotiations
+                if byte == IAC {
+                    reply.push(IAC);
+                }
+            }
+        }
+        reply.extend_from_slice(&[IAC, SE]);
+        reply
+    }
+
+    fn negotiate(&mut self, command: u8, option: u8, reply: &mut Vec<u8>) {
+        let index = option as usize;
+        // Answers to our own offers are acknowledgements, which are not answered again
+        match command {
+            DO => {
+                let supported = option == OPT_TTYPE || option == OPT_NAWS || option == OPT_SGA;
+                if supported && !self.local[index] {
+                    self.local[index] = true;
+                    if !self.offered_local[index] {
+                        reply.extend_from_slice(&[IAC, WILL, option]);
+                    }
+                    if option == OPT_NAWS {
+                        reply.extend_from_slice(&self.window_size());
+                    }
+                } else if !supported {
+                    reply.extend_from_slice(&[IAC, WONT, option]);
+                }
+                self.offered_local[index] = false;
+            },
+            DONT => {
+                if self.local[index] {
+                    self.local[index] = false;
+                    reply.extend_from_slice(&[IAC, WONT, option]);
+                }
+                self.offered_local[index] = false;
+            },
+            WILL => {
+                let supported = option == OPT_ECHO || option == OPT_SGA;
+                if supported && !self.remote[index] {
+                    self.remote[index] = true;
+                    if !self.offered_remote[index] {
+                        reply.extend_from_slice(&[IAC, DO, option]);
+                    }
+                } else if !supported {
+                    reply.extend_from_slice(&[IAC, DONT, option]);
+                }
+                self.offered_remote[index] = false;
+            },
+            WONT => {
+                if self.remote[index] {
+                    self.remote[index] = false;
+                    reply.extend_from_slice(&[IAC, DONT, option]);
+                }
+                self.offered_remote[index] = false;
+            },
+            _ => ()
+        }
+    }
+
+    fn subnegotiate(&mut self, reply: &mut Vec<u8>) {
+        if self.subnegotiation.get(0) == Some(&OPT_TTYPE) && self.subnegotiation.get(1) == Some(&TTYPE_SEND) {
+            reply.extend_from_slice(&[IAC, SB, OPT_TTYPE, TTYPE_IS]);
+            reply.extend_from_slice(self.terminal.to_uppercase().as_bytes());
+            reply.extend_from_slice(&[IAC, SE]);
+        }
+        self.subnegotiation.clear();
+    }
+
+    /// Separate received bytes into data and commands, adding data to `data` and answers to
+    /// commands to `reply`. Commands may be split across calls.
+    pub fn receive(&mut self, input: &[u8], data: &mut Vec<u8>, reply: &mut Vec<u8>) {
+        for &byte in input.iter() {
+            self.state = match self.state {
+                State::Data => if byte == IAC {
+                    State::Iac
+                } else {
+                    data.push(byte);
+                    State::Data
+                },
+                State::Iac => match byte {
+                    IAC => {
+                        data.push(IAC);
+                        State::Data
+                    },
+                    DO | DONT | WILL | WONT => State::Negotiate(byte),
+                    SB => State::Subnegotiation,
+                    // Other commands, such as go-ahead and no-op, need no answer
+                    _ => State::Data
+                },
+                State::Negotiate(command) => {
+                    self.negotiate(command, byte, reply);
+                    State::Data
+                },
+                State::Subnegotiation => if byte == IAC {
+                    State::SubnegotiationIac
+                } else {
+                    self.subnegotiation.push(byte);
+                    State::Subnegotiation
+                },
+                State::SubnegotiationIac => match byte {
+                    SE => {
+                        self.subnegotiate(reply);
+                        State::Data
+                    },
+                    _ => {
+                        self.subnegotiation.push(byte);
+                        State::Subnegotiation
+                    }
+                },
+            };
+        }
+    }
+}
+
+/// Double the IAC bytes of data to send
+pub fn escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &byte in data.iter() {
+        escaped.push(byte);
+        if byte == IAC {
+            escaped.push(IAC);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn negotiation_test() {
+        let mut telnet = Telnet::new("xterm", (80, 24));
+        telnet.start();
+
+        // Accepting an offer is not acknowledged, but the window size follows
+        let mut data = Vec::new();
+        let mut reply = Vec::new();
+        telnet.receive(&[IAC, DO, OPT_NAWS, IAC, WILL, OPT_SGA], &mut data, &mut reply);
+        assert_eq!(reply, vec![IAC, SB, OPT_NAWS, 0, 80, 0, 24, IAC, SE]);
+        assert!(telnet.remote[OPT_SGA as usize]);
+
+        reply.clear();
+        // Split in the middle of a command, with an escaped data byte and an unknown option
+        telnet.receive(&[b'h', IAC, WILL], &mut data, &mut reply);
+        telnet.receive(&[OPT_ECHO, IAC, IAC, IAC, DO, 99, b'i'], &mut data, &mut reply);
+        assert_eq!(data, vec![b'h', IAC, b'i']);
+        assert_eq!(reply, vec![IAC, DO, OPT_ECHO, IAC, WONT, 99]);
+        assert!(telnet.character_mode());
+
+        // Repeated requests are not answered again
+        reply.clear();
+        telnet.receive(&[IAC, WILL, OPT_ECHO, IAC, DO, OPT_TTYPE], &mut data, &mut reply);
+        assert!(reply.is_empty());
+
+        telnet.receive(&[IAC, SB, OPT_TTYPE, TTYPE_SEND, IAC, SE], &mut data, &mut reply);
+        assert_eq!(reply, [&[IAC, SB, OPT_TTYPE, TTYPE_IS][..], b"XTERM", &[IAC, SE]].concat());
+
+        telnet.size = (255, 24);
+        assert_eq!(telnet.window_size(), vec![IAC, SB, OPT_NAWS, 0, 255, 255, 0, 24, IAC, SE]);
+        assert_eq!(escape(&[1, IAC]), vec![1, IAC, IAC]);
+    }
+}
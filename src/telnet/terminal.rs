@@ -0,0 +1,46 @@
+use std::io::{self, Stdout};
+use termion::raw::{IntoRawMode, RawTerminal};
+
+/// The local terminal, in raw mode for character at a time sessions. The terminal is restored
+/// when this is dropped.
+pub struct Terminal {
+    raw: Option<RawTerminal<Stdout>>,
+    character_mode: bool,
+}
+
+impl Terminal {
+    /// Start in line mode, the server decides when to switch with its negotiation
+    pub fn new() -> Terminal {
+        // Output that is not a terminal stays in line mode
+        let raw = io::stdout().into_raw_mode().ok();
+        if let Some(ref raw) = raw {
+            let _ = raw.suspend_raw_mode();
+        }
+        Terminal {
+            raw: raw,
+            character_mode: false,
+        }
+    }
+
+    pub fn character_mode(&self) -> bool {
+        self.character_mode
+    }
+
+    pub fn set_character_mode(&mut self, character_mode: bool) {
+        if character_mode == self.character_mode {
+            return;
+        }
+        if let Some(ref raw) = self.raw {
+            let result = if character_mode { raw.activate_raw_mode() } else { raw.suspend_raw_mode() };
+            if result.is_ok() {
+                self.character_mode = character_mode;
+            }
+        }
+    }
+
+    /// Leave raw mode for good, before exiting
+    pub fn restore(&mut self) {
+        self.set_character_mode(false);
+        self.raw = None;
+    }
+}
@@ -1,13 +1,13 @@
 #![deny(warnings)]
-#![feature(asm)]
-#![feature(const_fn)]
 
 #[cfg(not(target_os = "redox"))]
 extern crate libc;
+extern crate netutils_core;
 
 #[cfg(target_os = "redox")]
 extern crate syscall;
 
+use netutils_core::args::Parser;
 use std::env;
 use std::error::Error;
 use std::fs::{File, OpenOptions};
@@ -210,13 +210,14 @@ fn fork()  -> usize {
 }
 
 fn main() {
-    let mut background = false;
-    for arg in env::args().skip(1) {
-        match arg.as_ref() {
-            "-b" => background = true,
-            _ => ()
-        }
+    let args = Parser::new("telnetd")
+        .synopsis("[-b]")
+        .flag("b", "", "Fork into the background")
+        .parse_env();
+    if !args.free.is_empty() {
+        args.usage();
     }
+    let background = args.flag("b");
 
     println!("Telnet");
     if background {
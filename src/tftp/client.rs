@@ -0,0 +1,114 @@
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use packet::{Mode, Options, Packet, BLOCK_SIZE, ERROR_OPTIONS, MIN_BLOCK_SIZE};
+use transfer::{peer_error, Connection, DEFAULT_TIMEOUT, RETRIES};
+
+/// How to ask for a transfer
+pub struct Settings {
+    pub mode: Mode,
+    pub blksize: Option<usize>,
+    pub timeout: Option<u64>,
+}
+
+impl Settings {
+    fn timeout(&self) -> u64 {
+        self.timeout.unwrap_or(DEFAULT_TIMEOUT)
+    }
+}
+
+/// Send `request` to the server until it replies. The reply comes from the port that the server
+/// chose for the transfer, which is returned with it.
+fn request(server: SocketAddr, request: &Packet, timeout: u64) -> Result<(UdpSocket, Packet, SocketAddr)> {
+    let socket = UdpSocket::bind(if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+    socket.set_read_timeout(Some(Duration::from_secs(timeout)))?;
+    let request = request.to_bytes();
+    let mut buf = vec![0; 65536];
+    for _ in 0..RETRIES {
+        socket.send_to(&request, server)?;
+        loop {
+            match socket.recv_from(&mut buf) {
+                Ok((count, from)) => if from.ip() == server.ip() {
+                    if let Some(packet) = Packet::from_bytes(&buf[..count]) {
+                        return Ok((socket, packet, from));
+                    }
+                },
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => break,
+                Err(err) => return Err(err)
+            }
+        }
+    }
+    Err(Error::new(ErrorKind::TimedOut, "the server did not respond"))
+}
+
+/// Check the options that the server acknowledged, which may only lower the block size and
+/// must keep the timeout, returning the block size of the transfer
+fn negotiate(settings: &Settings, oack: &Options) -> Option<usize> {
+    if oack.timeout.is_some() && oack.timeout != settings.timeout {
+        return None;
+    }
+    match (oack.blksize, settings.blksize) {
+        (Some(blksize), Some(requested)) if blksize >= MIN_BLOCK_SIZE && blksize <= requested => Some(blksize),
+        (Some(_), _) => None,
+        (None, _) => Some(BLOCK_SIZE)
+    }
+}
+
+/// Open the connection of a transfer from the first reply of the server, returning the data
+/// of block 1 if that was the reply to a read request
+fn connect(settings: &Settings, socket: UdpSocket, reply: Packet, peer: SocketAddr, write: bool) -> Result<(Connection, Option<Vec<u8>>)> {
+    match reply {
+        Packet::OptionAck(ref oack) => {
+            let mut connection = Connection::new(socket, peer, BLOCK_SIZE, settings.timeout())?;
+            match negotiate(settings, oack) {
+                Some(blksize) => {
+                    connection.blksize = blksize;
+                    Ok((connection, None))
+                },
+                None => {
+                    connection.abort(ERROR_OPTIONS, "Option negotiation failed");
+                    Err(Error::new(ErrorKind::InvalidData, "the server acknowledged options that were not asked for"))
+                }
+            }
+        },
+        // A server that doesn't know options replies as in RFC 1350
+        Packet::Ack(0) if write => Ok((Connection::new(socket, peer, BLOCK_SIZE, settings.timeout())?, None)),
+        Packet::Data { block: 1, data } if !write => Ok((Connection::new(socket, peer, BLOCK_SIZE, settings.timeout())?, Some(data))),
+        Packet::Error { code, message } => Err(peer_error(code, &message)),
+        _ => Err(Error::new(ErrorKind::InvalidData, "unexpected reply from the server"))
+    }
+}
+
+fn requested(settings: &Settings, tsize: Option<u64>) -> Options {
+    Options {
+        blksize: settings.blksize,
+        timeout: settings.timeout,
+        tsize: tsize,
+    }
+}
+
+/// Read `filename` from the server into `writer`, returning the number of bytes received
+pub fn get(server: SocketAddr, filename: &str, settings: &Settings, writer: &mut Write) -> Result<u64> {
+    let packet = Packet::Read {
+        filename: filename.to_string(),
+        mode: settings.mode,
+        options: requested(settings, None),
+    };
+    let (socket, reply, peer) = request(server, &packet, settings.timeout())?;
+    let (connection, first) = connect(settings, socket, reply, peer, false)?;
+    connection.receive_data(writer, first)
+}
+
+/// Write the data of `reader` to `filename` on the server, telling the server its `size` if it
+/// is known, and returning the number of bytes sent
+pub fn put(server: SocketAddr, filename: &str, settings: &Settings, reader: &mut Read, size: Option<u64>) -> Result<u64> {
+    let packet = Packet::Write {
+        filename: filename.to_string(),
+        mode: settings.mode,
+        options: requested(settings, size),
+    };
+    let (socket, reply, peer) = request(server, &packet, settings.timeout())?;
+    let (connection, _) = connect(settings, socket, reply, peer, true)?;
+    connection.send_data(reader, None)
+}
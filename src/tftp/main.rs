@@ -0,0 +1,206 @@
+extern crate netutils_core;
+
+use netutils_core::args::Parser;
+use netutils_core::error::NetError;
+use netutils_core::resolve;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use std::env;
+
+use client::Settings;
+use netascii::{Decoder, Encoder};
+use packet::{Mode, MAX_BLOCK_SIZE, MAX_TIMEOUT, MIN_BLOCK_SIZE, MIN_TIMEOUT};
+
+mod client;
+mod netascii;
+mod packet;
+mod server;
+mod transfer;
+
+static TFTP_MAN: &'static str = /* @MANSTART{tftp} */ r#"
+NAME
+    tftp - trivial file transfer protocol client and server
+
+SYNOPSIS
+    tftp [-4 | -6] [-p port] [-m mode] [-b blksize] [-t timeout] host get remote [local]
+    tftp [-4 | -6] [-p port] [-m mode] [-b blksize] [-t timeout] host put local [remote]
+    tftp -s [-4 | -6] [-p port] [-d directory]
+    tftp [-h | --help]
+
+DESCRIPTION
+    Transfer files with TFTP (RFC 1350), which is how embedded devices and
+    netboot environments usually fetch them.
+
+    get reads the remote file from the server on host into local, which is
+    the file name of remote by default. put writes the local file to remote
+    on the server, which is the file name of local by default.
+
+    With -s, tftp is a server that sends the files under a directory to
+    anyone who asks. It refuses to write files, and to send anything
+    outside of the directory.
+
+OPTIONS
+    -4
+    -6
+        Use only IPv4 or only IPv6 addresses. The server listens on IPv4 by
+        default, and on IPv6 with -6.
+
+    -p port
+        The port of the server, 69 by default.
+
+    -m mode
+        Transfer in octet mode, the default, or netascii mode, which
+        converts line endings.
+
+    -b blksize
+        Ask for blocks of this many bytes, from 8 to 65464, rather than 512
+        (RFC 2348). Larger blocks are much faster, but a block that doesn't
+        fit in a packet of the network is fragmented.
+
+    -t timeout
+        Ask the server to wait this many seconds, from 1 to 255, before
+        sending a packet again (RFC 2349). The client waits as long.
+
+    -s
+        Run a server.
+
+    -d directory
+        The directory of the files of the server, the current directory by
+        default.
+
+    -h
+    --help
+        Print this manual page.
+
+NOTES
+    Each block is sent and acknowledged before the next, so transfers over
+    links with a long round trip time are slow. The server answers the
+    tsize option of octet transfers with the size of the file.
+"#; /* @MANEND */
+
+const DEFAULT_PORT: u16 = 69;
+
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name().map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| NetError::Usage(format!("'{}' has no file name", path)).exit("tftp"))
+}
+
+fn report(action: &str, bytes: u64, start: Instant) {
+    let elapsed = start.elapsed();
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+    println!("{} {} bytes in {:.2} s", action, bytes, secs);
+}
+
+fn get(addr: SocketAddr, remote: &str, local: &str, settings: &Settings) {
+    let file = File::create(local)
+        .unwrap_or_else(|err| NetError::from(err).context(&format!("failed to create {}", local)).exit("tftp"));
+    let start = Instant::now();
+    let result = match settings.mode {
+        Mode::Octet => client::get(addr, remote, settings, &mut &file),
+        Mode::Netascii => {
+            let mut decoder = Decoder::new(&file);
+            client::get(addr, remote, settings, &mut decoder).and_then(|bytes| decoder.finish().map(|_| bytes))
+        }
+    };
+    match result {
+        Ok(bytes) => report("received", bytes, start),
+        Err(err) => {
+            // Don't leave a partial file behind
+            let _ = fs::remove_file(local);
+            NetError::from(err).context(&format!("failed to get {}", remote)).exit("tftp");
+        }
+    }
+}
+
+fn put(addr: SocketAddr, local: &str, remote: &str, settings: &Settings) {
+    let mut file = File::open(local)
+        .unwrap_or_else(|err| NetError::from(err).context(&format!("failed to open {}", local)).exit("tftp"));
+    let start = Instant::now();
+    let result = match settings.mode {
+        Mode::Octet => {
+            let size = file.metadata().map(|metadata| metadata.len()).ok();
+            client::put(addr, remote, settings, &mut file, size)
+        },
+        Mode::Netascii => {
+            let mut encoder = Encoder::new(BufReader::new(file));
+            client::put(addr, remote, settings, &mut encoder as &mut Read, None)
+        }
+    };
+    match result {
+        Ok(bytes) => report("sent", bytes, start),
+        Err(err) => NetError::from(err).context(&format!("failed to put {}", remote)).exit("tftp")
+    }
+}
+
+fn main() {
+    let args = Parser::new("tftp")
+        .synopsis("[-4 | -6] [-p port] [-m mode] [-b blksize] [-t timeout] host get remote [local]")
+        .synopsis("[-4 | -6] [-p port] [-m mode] [-b blksize] [-t timeout] host put local [remote]")
+        .synopsis("-s [-4 | -6] [-p port] [-d directory]")
+        .man(TFTP_MAN)
+        .flag("s", "", "Serve files, rather than transferring one")
+        .family()
+        .opt("p", "", "port", "The port of the server")
+        .opt("d", "", "directory", "The directory to serve")
+        .opt("m", "", "mode", "The transfer mode, octet or netascii")
+        .opt("b", "", "blksize", "The block size to ask the server for")
+        .opt("t", "", "timeout", "Seconds to wait for each packet")
+        .parse_env();
+
+    let server = args.flag("s");
+    let family = args.family();
+    let port = args.parse("p").unwrap_or(DEFAULT_PORT);
+    let directory = args.value("d").map(PathBuf::from);
+    let mode = match args.value("m") {
+        Some(value) => Mode::parse(value).unwrap_or_else(|| args.invalid(&format!("unknown mode '{}'", value))),
+        None => Mode::Octet
+    };
+    let blksize = match args.parse("b") {
+        Some(blksize) if blksize < MIN_BLOCK_SIZE || blksize > MAX_BLOCK_SIZE =>
+            args.invalid(&format!("the block size must be from {} to {}", MIN_BLOCK_SIZE, MAX_BLOCK_SIZE)),
+        blksize => blksize
+    };
+    let timeout = match args.parse("t") {
+        Some(timeout) if timeout < MIN_TIMEOUT || timeout > MAX_TIMEOUT =>
+            args.invalid(&format!("the timeout must be from {} to {} seconds", MIN_TIMEOUT, MAX_TIMEOUT)),
+        timeout => timeout
+    };
+    let settings = Settings {
+        mode: mode,
+        blksize: blksize,
+        timeout: timeout,
+    };
+    let operands = &args.free;
+
+    if server {
+        if !operands.is_empty() {
+            args.usage();
+        }
+        let root = match directory {
+            Some(directory) => directory,
+            None => env::current_dir().unwrap_or_else(|err| {
+                NetError::from(err).context("failed to get the current directory").exit("tftp")
+            })
+        };
+        if let Err(err) = server::serve(family, port, root) {
+            NetError::from(err).context(&format!("failed to serve on port {}", port)).exit("tftp");
+        }
+        return;
+    }
+
+    if directory.is_some() || operands.len() < 3 || operands.len() > 4 {
+        args.usage();
+    }
+    let host = &operands[0];
+    let addr = resolve::resolve(host, port, family).unwrap_or_else(|err| err.exit("tftp"));
+    let source = &operands[2];
+    let target = operands.get(3).cloned().unwrap_or_else(|| file_name(source));
+    match operands[1].as_ref() {
+        "get" => get(addr, source, &target, &settings),
+        "put" => put(addr, source, &target, &settings),
+        _ => args.invalid(&format!("unknown command '{}'", operands[1]))
+    }
+}
@@ -0,0 +1,150 @@
+//! Netascii (RFC 764) ends lines with CR LF, and sends a bare CR as CR NUL
+
+use std::io::{BufRead, Read, Result, Write};
+
+/// Encodes the data of a reader as netascii
+pub struct Encoder<R: BufRead> {
+    inner: R,
+    pending: Option<u8>,
+}
+
+impl<R: BufRead> Encoder<R> {
+    pub fn new(inner: R) -> Encoder<R> {
+        Encoder {
+            inner: inner,
+            pending: None,
+        }
+    }
+}
+
+impl<R: BufRead> Read for Encoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let mut count = 0;
+        while count < buf.len() {
+            if let Some(b) = self.pending.take() {
+                buf[count] = b;
+                count += 1;
+                continue;
+            }
+
+            let consumed = {
+                let data = self.inner.fill_buf()?;
+                if data.is_empty() {
+                    break;
+                }
+                let mut consumed = 0;
+                for &b in data.iter() {
+                    if count >= buf.len() {
+                        break;
+                    }
+                    consumed += 1;
+                    match b {
+                        b'\n' => {
+                            buf[count] = b'\r';
+                            self.pending = Some(b'\n');
+                        },
+                        b'\r' => {
+                            buf[count] = b'\r';
+                            self.pending = Some(0);
+                        },
+                        _ => buf[count] = b
+                    }
+                    count += 1;
+                    if self.pending.is_some() {
+                        break;
+                    }
+                }
+                consumed
+            };
+            self.inner.consume(consumed);
+        }
+        Ok(count)
+    }
+}
+
+/// Decodes netascii written to it, into local lines
+pub struct Decoder<W: Write> {
+    inner: W,
+    cr: bool,
+}
+
+impl<W: Write> Decoder<W> {
+    pub fn new(inner: W) -> Decoder<W> {
+        Decoder {
+            inner: inner,
+            cr: false,
+        }
+    }
+
+    /// Write a CR that ended the data, which had nothing after it to say what it was
+    pub fn finish(mut self) -> Result<W> {
+        if self.cr {
+            self.inner.write_all(b"\r")?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Decoder<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut data = Vec::with_capacity(buf.len() + 1);
+        for &b in buf.iter() {
+            if self.cr {
+                self.cr = false;
+                match b {
+                    b'\n' => {
+                        data.push(b'\n');
+                        continue;
+                    },
+                    0 => {
+                        data.push(b'\r');
+                        continue;
+                    },
+                    _ => data.push(b'\r')
+                }
+            }
+            if b == b'\r' {
+                self.cr = true;
+            } else {
+                data.push(b);
+            }
+        }
+        self.inner.write_all(&data)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use super::{Decoder, Encoder};
+
+    #[test]
+    fn netascii_test() {
+        let text = b"one\ntwo\rthree\r\n";
+        let mut encoded = Vec::new();
+        // A small buffer splits the pairs of bytes across reads
+        let mut encoder = Encoder::new(&text[..]);
+        let mut buf = [0; 3];
+        loop {
+            let count = encoder.read(&mut buf).unwrap();
+            if count == 0 {
+                break;
+            }
+            encoded.extend_from_slice(&buf[..count]);
+        }
+        assert_eq!(encoded, b"one\r\ntwo\r\0three\r\0\r\n".to_vec());
+
+        let mut decoder = Decoder::new(Vec::new());
+        for chunk in encoded.chunks(4) {
+            decoder.write_all(chunk).unwrap();
+        }
+        decoder.write_all(b"\r").unwrap();
+        assert_eq!(decoder.finish().unwrap(), b"one\ntwo\rthree\r\n\r".to_vec());
+    }
+}
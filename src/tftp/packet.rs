@@ -0,0 +1,230 @@
+const RRQ: u16 = 1;
+const WRQ: u16 = 2;
+const DATA: u16 = 3;
+const ACK: u16 = 4;
+const ERROR: u16 = 5;
+const OACK: u16 = 6;
+
+/// Block size of a transfer that didn't negotiate one (RFC 1350)
+pub const BLOCK_SIZE: usize = 512;
+
+/// Limits of the block size option (RFC 2348)
+pub const MIN_BLOCK_SIZE: usize = 8;
+pub const MAX_BLOCK_SIZE: usize = 65464;
+
+/// Limits of the timeout option in seconds (RFC 2349)
+pub const MIN_TIMEOUT: u64 = 1;
+pub const MAX_TIMEOUT: u64 = 255;
+
+/// Error codes (RFC 1350 appendix, RFC 2347)
+pub const ERROR_UNDEFINED: u16 = 0;
+pub const ERROR_NOT_FOUND: u16 = 1;
+pub const ERROR_ACCESS: u16 = 2;
+pub const ERROR_DISK_FULL: u16 = 3;
+pub const ERROR_ILLEGAL: u16 = 4;
+pub const ERROR_UNKNOWN_TID: u16 = 5;
+pub const ERROR_OPTIONS: u16 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Netascii,
+    Octet,
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Mode::Netascii => "netascii",
+            Mode::Octet => "octet",
+        }
+    }
+
+    /// Mail mode is obsolete, and not supported
+    pub fn parse(name: &str) -> Option<Mode> {
+        if name.eq_ignore_ascii_case("netascii") {
+            Some(Mode::Netascii)
+        } else if name.eq_ignore_ascii_case("octet") {
+            Some(Mode::Octet)
+        } else {
+            None
+        }
+    }
+}
+
+/// The options of a request or option acknowledgement (RFC 2347). Unknown options, and options
+/// with values that don't parse, are ignored.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Options {
+    pub blksize: Option<usize>,
+    pub timeout: Option<u64>,
+    pub tsize: Option<u64>,
+}
+
+impl Options {
+    pub fn is_empty(&self) -> bool {
+        self.blksize.is_none() && self.timeout.is_none() && self.tsize.is_none()
+    }
+
+    fn read(&mut self, name: &str, value: &str) {
+        if name.eq_ignore_ascii_case("blksize") {
+            self.blksize = value.parse().ok();
+        } else if name.eq_ignore_ascii_case("timeout") {
+            self.timeout = value.parse().ok();
+        } else if name.eq_ignore_ascii_case("tsize") {
+            self.tsize = value.parse().ok();
+        }
+    }
+
+    fn write(&self, data: &mut Vec<u8>) {
+        if let Some(blksize) = self.blksize {
+            push_string(data, "blksize");
+            push_string(data, &blksize.to_string());
+        }
+        if let Some(timeout) = self.timeout {
+            push_string(data, "timeout");
+            push_string(data, &timeout.to_string());
+        }
+        if let Some(tsize) = self.tsize {
+            push_string(data, "tsize");
+            push_string(data, &tsize.to_string());
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Packet {
+    Read { filename: String, mode: Mode, options: Options },
+    Write { filename: String, mode: Mode, options: Options },
+    Data { block: u16, data: Vec<u8> },
+    Ack(u16),
+    Error { code: u16, message: String },
+    OptionAck(Options),
+}
+
+fn push_u16(data: &mut Vec<u8>, value: u16) {
+    data.push((value >> 8) as u8);
+    data.push(value as u8);
+}
+
+fn push_string(data: &mut Vec<u8>, value: &str) {
+    data.extend_from_slice(value.as_bytes());
+    data.push(0);
+}
+
+fn read_u16(data: &[u8]) -> u16 {
+    (data[0] as u16) << 8 | data[1] as u16
+}
+
+/// Split the NUL terminated strings that follow the opcode of a packet
+fn read_strings(data: &[u8]) -> Option<Vec<String>> {
+    if data.last() != Some(&0) {
+        return None;
+    }
+    Some(data[..data.len() - 1].split(|&b| b == 0).map(|s| String::from_utf8_lossy(s).into_owned()).collect())
+}
+
+fn read_options(strings: &[String]) -> Option<Options> {
+    if strings.len() % 2 != 0 {
+        return None;
+    }
+    let mut options = Options::default();
+    for pair in strings.chunks(2) {
+        options.read(&pair[0], &pair[1]);
+    }
+    Some(options)
+}
+
+fn read_request(data: &[u8]) -> Option<(String, Mode, Options)> {
+    let strings = read_strings(data)?;
+    if strings.len() < 2 || strings[0].is_empty() {
+        return None;
+    }
+    Some((strings[0].clone(), Mode::parse(&strings[1])?, read_options(&strings[2..])?))
+}
+
+impl Packet {
+    pub fn from_bytes(data: &[u8]) -> Option<Packet> {
+        if data.len() < 2 {
+            return None;
+        }
+        let body = &data[2..];
+        match read_u16(data) {
+            RRQ => read_request(body).map(|(filename, mode, options)| Packet::Read {
+                filename: filename,
+                mode: mode,
+                options: options,
+            }),
+            WRQ => read_request(body).map(|(filename, mode, options)| Packet::Write {
+                filename: filename,
+                mode: mode,
+                options: options,
+            }),
+            DATA if body.len() >= 2 => Some(Packet::Data {
+                block: read_u16(body),
+                data: body[2..].to_vec(),
+            }),
+            ACK if body.len() >= 2 => Some(Packet::Ack(read_u16(body))),
+            ERROR if body.len() >= 2 => Some(Packet::Error {
+                code: read_u16(body),
+                // Some implementations leave out the terminating NUL
+                message: String::from_utf8_lossy(&body[2..]).trim_right_matches('\0').to_string(),
+            }),
+            OACK => read_strings(body).and_then(|strings| read_options(&strings)).map(Packet::OptionAck),
+            _ => None
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        match *self {
+            Packet::Read { ref filename, mode, ref options } | Packet::Write { ref filename, mode, ref options } => {
+                push_u16(&mut data, if let Packet::Read { .. } = *self { RRQ } else { WRQ });
+                push_string(&mut data, filename);
+                push_string(&mut data, mode.name());
+                options.write(&mut data);
+            },
+            Packet::Data { block, data: ref block_data } => {
+                push_u16(&mut data, DATA);
+                push_u16(&mut data, block);
+                data.extend_from_slice(block_data);
+            },
+            Packet::Ack(block) => {
+                push_u16(&mut data, ACK);
+                push_u16(&mut data, block);
+            },
+            Packet::Error { code, ref message } => {
+                push_u16(&mut data, ERROR);
+                push_u16(&mut data, code);
+                push_string(&mut data, message);
+            },
+            Packet::OptionAck(ref options) => {
+                push_u16(&mut data, OACK);
+                options.write(&mut data);
+            }
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Mode, Options, Packet};
+
+    #[test]
+    fn packet_test() {
+        let request = b"\x00\x01pxelinux.0\x00OCTET\x00BLKSIZE\x001468\x00tsize\x000\x00windowsize\x004\x00";
+        let options = Options { blksize: Some(1468), timeout: None, tsize: Some(0) };
+        let packet = Packet::Read { filename: "pxelinux.0".to_string(), mode: Mode::Octet, options: options };
+        assert_eq!(Packet::from_bytes(request), Some(packet.clone()));
+        assert_eq!(Packet::from_bytes(&packet.to_bytes()), Some(packet));
+
+        assert_eq!(Packet::from_bytes(b"\x00\x02file\x00mail\x00"), None);
+        assert_eq!(Packet::from_bytes(b"\x00\x01file\x00octet"), None);
+        assert_eq!(Packet::from_bytes(b"\x00\x03\x00\x07abc"), Some(Packet::Data { block: 7, data: b"abc".to_vec() }));
+        assert_eq!(Packet::Ack(258).to_bytes(), b"\x00\x04\x01\x02");
+        assert_eq!(Packet::from_bytes(b"\x00\x05\x00\x01File not found"),
+                   Some(Packet::Error { code: 1, message: "File not found".to_string() }));
+        assert_eq!(Packet::from_bytes(b"\x00\x06timeout\x003\x00"),
+                   Some(Packet::OptionAck(Options { blksize: None, timeout: Some(3), tsize: None })));
+    }
+}
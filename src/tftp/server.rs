@@ -0,0 +1,130 @@
+use netutils_core::resolve::Family;
+use std::cmp;
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Result, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+
+use netascii::Encoder;
+use packet::{Mode, Options, Packet, BLOCK_SIZE, ERROR_ACCESS, ERROR_ILLEGAL, ERROR_NOT_FOUND, ERROR_UNDEFINED,
+             MAX_BLOCK_SIZE, MAX_TIMEOUT, MIN_BLOCK_SIZE, MIN_TIMEOUT};
+use transfer::{Connection, DEFAULT_TIMEOUT};
+
+/// The path of `filename` under `root`. Names are taken as relative to the root even if they
+/// start with a slash, as netboot clients often ask for them, and may not leave it.
+fn resolve(root: &Path, filename: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for component in Path::new(filename).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::RootDir | Component::CurDir => (),
+            _ => return None
+        }
+    }
+    Some(path)
+}
+
+fn refuse(socket: &UdpSocket, peer: SocketAddr, code: u16, message: &str) {
+    let _ = socket.send_to(&Packet::Error { code: code, message: message.to_string() }.to_bytes(), peer);
+}
+
+/// The options of a request that the server accepts, with `tsize` set to the size of the file
+fn negotiate(options: &Options, mode: Mode, size: u64) -> Options {
+    Options {
+        blksize: match options.blksize {
+            Some(blksize) if blksize >= MIN_BLOCK_SIZE => Some(cmp::min(blksize, MAX_BLOCK_SIZE)),
+            _ => None
+        },
+        timeout: match options.timeout {
+            Some(timeout) if timeout >= MIN_TIMEOUT && timeout <= MAX_TIMEOUT => Some(timeout),
+            _ => None
+        },
+        // The size of a file in netascii isn't known until it has been converted
+        tsize: match options.tsize {
+            Some(_) if mode == Mode::Octet => Some(size),
+            _ => None
+        },
+    }
+}
+
+fn open(path: &Path) -> Result<(File, u64)> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(ErrorKind::NotFound, "not a file"));
+    }
+    Ok((file, metadata.len()))
+}
+
+/// Send a file to `peer` from a port of its own, returning the number of bytes sent
+fn read(root: &Path, peer: SocketAddr, filename: &str, mode: Mode, options: &Options) -> Result<u64> {
+    let socket = UdpSocket::bind(if peer.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+    let path = match resolve(root, filename) {
+        Some(path) => path,
+        None => {
+            refuse(&socket, peer, ERROR_ACCESS, "Access violation");
+            return Err(io::Error::new(ErrorKind::PermissionDenied, "outside of the root"));
+        }
+    };
+    let (mut file, size) = match open(&path) {
+        Ok(file) => file,
+        Err(err) => {
+            match err.kind() {
+                ErrorKind::NotFound => refuse(&socket, peer, ERROR_NOT_FOUND, "File not found"),
+                ErrorKind::PermissionDenied => refuse(&socket, peer, ERROR_ACCESS, "Access violation"),
+                _ => refuse(&socket, peer, ERROR_UNDEFINED, &err.to_string())
+            }
+            return Err(err);
+        }
+    };
+
+    let accepted = negotiate(options, mode, size);
+    let connection = Connection::new(socket, peer, accepted.blksize.unwrap_or(BLOCK_SIZE), accepted.timeout.unwrap_or(DEFAULT_TIMEOUT))?;
+    let oack = if accepted.is_empty() { None } else { Some(Packet::OptionAck(accepted)) };
+    match mode {
+        Mode::Octet => connection.send_data(&mut file, oack.as_ref()),
+        Mode::Netascii => connection.send_data(&mut Encoder::new(BufReader::new(file)), oack.as_ref())
+    }
+}
+
+/// Serve the files under `root` to read requests, each from a thread of its own. Write requests
+/// are refused.
+pub fn serve(family: Family, port: u16, root: PathBuf) -> Result<()> {
+    let socket = UdpSocket::bind((family.unspecified(), port))?;
+    println!("serving {} on port {}", root.display(), port);
+    let mut buf = [0; 65536];
+    loop {
+        let (count, peer) = socket.recv_from(&mut buf)?;
+        match Packet::from_bytes(&buf[..count]) {
+            Some(Packet::Read { filename, mode, options }) => {
+                let root = root.clone();
+                thread::spawn(move || match read(&root, peer, &filename, mode, &options) {
+                    Ok(bytes) => println!("{}: sent {} ({} bytes)", peer, filename, bytes),
+                    Err(err) => {
+                        let _ = writeln!(io::stderr(), "tftp: {}: failed to send {}: {}", peer, filename, err);
+                    }
+                });
+            },
+            Some(Packet::Write { .. }) => refuse(&socket, peer, ERROR_ACCESS, "Server is read-only"),
+            // Errors are never answered, so that two hosts can't keep answering each other
+            Some(Packet::Error { .. }) => (),
+            _ => refuse(&socket, peer, ERROR_ILLEGAL, "Illegal TFTP operation")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+    use super::resolve;
+
+    #[test]
+    fn resolve_test() {
+        let root = Path::new("/srv/tftp");
+        assert_eq!(resolve(root, "/pxelinux.cfg/default"), Some(PathBuf::from("/srv/tftp/pxelinux.cfg/default")));
+        assert_eq!(resolve(root, "./boot.img"), Some(PathBuf::from("/srv/tftp/boot.img")));
+        assert_eq!(resolve(root, "../etc/passwd"), None);
+        assert_eq!(resolve(root, "boot/../../etc/passwd"), None);
+    }
+}
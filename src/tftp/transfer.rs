@@ -0,0 +1,188 @@
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use packet::{Packet, ERROR_DISK_FULL, ERROR_UNDEFINED, ERROR_UNKNOWN_TID};
+
+/// Times a packet is sent before the peer is given up on
+pub const RETRIES: usize = 5;
+
+/// Seconds to wait for a reply when no timeout was negotiated
+pub const DEFAULT_TIMEOUT: u64 = 1;
+
+/// The error of an ERROR packet from the peer
+pub fn peer_error(code: u16, message: &str) -> Error {
+    Error::new(ErrorKind::Other, format!("{} (error {})", message, code))
+}
+
+/// The socket of a transfer, and what was negotiated for it. Each data packet is acknowledged
+/// before the next is sent, as in RFC 1350.
+pub struct Connection {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    pub blksize: usize,
+}
+
+impl Connection {
+    pub fn new(socket: UdpSocket, peer: SocketAddr, blksize: usize, timeout: u64) -> Result<Connection> {
+        socket.set_read_timeout(Some(Duration::from_secs(timeout)))?;
+        Ok(Connection {
+            socket: socket,
+            peer: peer,
+            blksize: blksize,
+        })
+    }
+
+    pub fn send(&self, packet: &[u8]) -> Result<()> {
+        self.socket.send_to(packet, self.peer).map(|_| ())
+    }
+
+    /// Tell the peer why the transfer is ending
+    pub fn abort(&self, code: u16, message: &str) {
+        let _ = self.send(&Packet::Error { code: code, message: message.to_string() }.to_bytes());
+    }
+
+    /// Receive the next packet from the peer, or `None` if the timeout passes first. Packets
+    /// from other ports belong to no transfer of this socket, and are answered with an error.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<Option<Packet>> {
+        loop {
+            match self.socket.recv_from(buf) {
+                Ok((count, from)) => {
+                    if from != self.peer {
+                        let error = Packet::Error { code: ERROR_UNKNOWN_TID, message: "Unknown transfer ID".to_string() };
+                        let _ = self.socket.send_to(&error.to_bytes(), from);
+                        continue;
+                    }
+                    if let Some(packet) = Packet::from_bytes(&buf[..count]) {
+                        return Ok(Some(packet));
+                    }
+                },
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => return Ok(None),
+                Err(err) => return Err(err)
+            }
+        }
+    }
+
+    /// Send `packet` until the peer acknowledges it as `block`. Acknowledgements of earlier
+    /// blocks are ignored rather than answered, so that a delayed one doesn't cause every
+    /// later block to be sent twice (the Sorcerer's Apprentice bug of RFC 1123 section 4.2.3.1).
+    fn exchange(&self, packet: &[u8], block: u16) -> Result<()> {
+        let mut buf = [0; 516];
+        for _ in 0..RETRIES {
+            self.send(packet)?;
+            while let Some(reply) = self.recv(&mut buf)? {
+                match reply {
+                    Packet::Ack(acked) if acked == block => return Ok(()),
+                    Packet::Ack(_) => (),
+                    Packet::Error { code, message } => return Err(peer_error(code, &message)),
+                    _ => {
+                        self.abort(ERROR_UNDEFINED, "Unexpected packet");
+                        return Err(Error::new(ErrorKind::InvalidData, "unexpected packet from the peer"));
+                    }
+                }
+            }
+        }
+        Err(Error::new(ErrorKind::TimedOut, "the peer stopped responding"))
+    }
+
+    /// Send the data of `reader`, after an option acknowledgement if one is given, which the
+    /// peer acknowledges as block 0, returning the number of bytes sent
+    pub fn send_data(&self, reader: &mut Read, oack: Option<&Packet>) -> Result<u64> {
+        if let Some(oack) = oack {
+            self.exchange(&oack.to_bytes(), 0)?;
+        }
+
+        let mut buffer = vec![0; self.blksize];
+        let mut block: u16 = 0;
+        let mut total = 0;
+        loop {
+            let count = match read_block(reader, &mut buffer) {
+                Ok(count) => count,
+                Err(err) => {
+                    self.abort(ERROR_UNDEFINED, "Read error");
+                    return Err(err);
+                }
+            };
+            // Block numbers wrap around, so that there's no limit on the size of a file
+            block = block.wrapping_add(1);
+            self.exchange(&Packet::Data { block: block, data: buffer[..count].to_vec() }.to_bytes(), block)?;
+            total += count as u64;
+            if count < self.blksize {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Receive data into `writer`, starting with `first` if block 1 has already arrived, and
+    /// otherwise acknowledging block 0 until it does. Returns the number of bytes received.
+    pub fn receive_data(&self, writer: &mut Write, first: Option<Vec<u8>>) -> Result<u64> {
+        let mut buf = vec![0; self.blksize + 4];
+        let mut first = first;
+        let mut ack = Packet::Ack(0).to_bytes();
+        let mut block: u16 = 1;
+        let mut total = 0;
+        if first.is_none() {
+            self.send(&ack)?;
+        }
+        loop {
+            let data = match first.take() {
+                Some(data) => data,
+                None => self.wait_data(&mut buf, &ack, block)?
+            };
+            if let Err(err) = writer.write_all(&data) {
+                self.abort(ERROR_DISK_FULL, "Write error");
+                return Err(err);
+            }
+            total += data.len() as u64;
+
+            ack = Packet::Ack(block).to_bytes();
+            self.send(&ack)?;
+            if data.len() < self.blksize {
+                return Ok(total);
+            }
+            block = block.wrapping_add(1);
+        }
+    }
+
+    /// Wait for block `block`, sending `ack` again whenever the peer seems to have missed it
+    fn wait_data(&self, buf: &mut [u8], ack: &[u8], block: u16) -> Result<Vec<u8>> {
+        for _ in 0..RETRIES {
+            while let Some(packet) = self.recv(buf)? {
+                match packet {
+                    Packet::Data { block: received, data } => {
+                        if received == block {
+                            if data.len() > self.blksize {
+                                self.abort(ERROR_UNDEFINED, "Block too large");
+                                return Err(Error::new(ErrorKind::InvalidData, "a block was larger than the block size"));
+                            }
+                            return Ok(data);
+                        }
+                        // The peer sent the previous block again, so it missed the acknowledgement
+                        self.send(ack)?;
+                    },
+                    Packet::Error { code, message } => return Err(peer_error(code, &message)),
+                    _ => {
+                        self.abort(ERROR_UNDEFINED, "Unexpected packet");
+                        return Err(Error::new(ErrorKind::InvalidData, "unexpected packet from the peer"));
+                    }
+                }
+            }
+            self.send(ack)?;
+        }
+        Err(Error::new(ErrorKind::TimedOut, "the peer stopped responding"))
+    }
+}
+
+/// Fill `buffer` as far as possible, since only the last block may be short
+fn read_block(reader: &mut Read, buffer: &mut [u8]) -> Result<usize> {
+    let mut count = 0;
+    while count < buffer.len() {
+        match reader.read(&mut buffer[count..]) {
+            Ok(0) => break,
+            Ok(read) => count += read,
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => (),
+            Err(err) => return Err(err)
+        }
+    }
+    Ok(count)
+}
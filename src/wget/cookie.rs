@@ -0,0 +1,227 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Result, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use hyper::Url;
+use hyper::header::HttpDate;
+
+/// A single cookie, using the fields of the Netscape cookie file format
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cookie {
+    /// Domain without a leading dot
+    pub domain: String,
+    /// Whether the cookie also applies to subdomains of `domain`
+    pub include_subdomains: bool,
+    pub path: String,
+    pub secure: bool,
+    /// Expiry as seconds since the UNIX epoch, 0 for session cookies
+    pub expires: u64,
+    pub name: String,
+    pub value: String,
+}
+
+impl Cookie {
+    fn matches(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or("").to_lowercase();
+        let domain_match = host == self.domain
+            || (self.include_subdomains && host.ends_with(&format!(".{}", self.domain)));
+
+        let path = url.path();
+        let path_match = path == self.path || (path.starts_with(&self.path)
+            && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+
+        domain_match && path_match && (!self.secure || url.scheme() == "https")
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires != 0 && self.expires <= now
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// The directory part of a request path, used when Set-Cookie has no Path attribute
+fn default_path(url: &Url) -> String {
+    let path = url.path();
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(i) => path[..i].to_string(),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    pub cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    pub fn new() -> CookieJar {
+        CookieJar::default()
+    }
+
+    /// Load cookies from a Netscape format cookie file, as written by wget, curl and browsers.
+    pub fn load(path: &str) -> Result<CookieJar> {
+        let mut jar = CookieJar::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            // curl marks HttpOnly cookies by prefixing the domain, treat them as normal cookies
+            let line = line.trim_left_matches("#HttpOnly_");
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 7 {
+                continue;
+            }
+
+            jar.insert(Cookie {
+                domain: fields[0].trim_left_matches('.').to_lowercase(),
+                include_subdomains: fields[1] == "TRUE",
+                path: fields[2].to_string(),
+                secure: fields[3] == "TRUE",
+                expires: fields[4].parse().unwrap_or(0),
+                name: fields[5].to_string(),
+                value: fields[6].to_string(),
+            });
+        }
+        Ok(jar)
+    }
+
+    /// Save all persistent, unexpired cookies to `path` in Netscape format.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let now = now();
+        let mut file = File::create(path)?;
+        writeln!(file, "# Netscape HTTP Cookie File")?;
+        for cookie in self.cookies.iter() {
+            if cookie.expires == 0 || cookie.is_expired(now) {
+                continue;
+            }
+            writeln!(file, "{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                     if cookie.include_subdomains { "." } else { "" },
+                     cookie.domain,
+                     if cookie.include_subdomains { "TRUE" } else { "FALSE" },
+                     cookie.path,
+                     if cookie.secure { "TRUE" } else { "FALSE" },
+                     cookie.expires,
+                     cookie.name,
+                     cookie.value)?;
+        }
+        file.sync_all()
+    }
+
+    /// Add a cookie, replacing any cookie with the same name, domain and path.
+    pub fn insert(&mut self, cookie: Cookie) {
+        self.cookies.retain(|c| !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path));
+        if !cookie.is_expired(now()) {
+            self.cookies.push(cookie);
+        }
+    }
+
+    /// Parse a Set-Cookie header received in response to a request for `url`.
+    pub fn set_cookie(&mut self, url: &Url, header: &str) {
+        let mut parts = header.split(';');
+        let (name, value) = match parts.next().and_then(|pair| {
+            let mut pair = pair.splitn(2, '=');
+            match (pair.next(), pair.next()) {
+                (Some(name), Some(value)) if !name.trim().is_empty() => Some((name.trim(), value.trim())),
+                _ => None,
+            }
+        }) {
+            Some(pair) => pair,
+            None => return,
+        };
+
+        let host = url.host_str().unwrap_or("").to_lowercase();
+        let mut cookie = Cookie {
+            domain: host.clone(),
+            include_subdomains: false,
+            path: default_path(url),
+            secure: false,
+            expires: 0,
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+
+        let mut max_age = None;
+        for attr in parts {
+            let mut attr = attr.splitn(2, '=');
+            let key = attr.next().unwrap_or("").trim().to_lowercase();
+            let value = attr.next().unwrap_or("").trim();
+            match key.as_str() {
+                "domain" => {
+                    let domain = value.trim_left_matches('.').to_lowercase();
+                    // Refuse cookies for domains the server does not belong to
+                    if domain != host && !host.ends_with(&format!(".{}", domain)) {
+                        return;
+                    }
+                    cookie.domain = domain;
+                    cookie.include_subdomains = true;
+                },
+                "path" => if value.starts_with('/') {
+                    cookie.path = value.to_string();
+                },
+                "expires" => if let Ok(date) = value.parse::<HttpDate>() {
+                    cookie.expires = SystemTime::from(date).duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(1);
+                },
+                "max-age" => if let Ok(seconds) = value.parse::<i64>() {
+                    max_age = Some(seconds);
+                },
+                "secure" => cookie.secure = true,
+                _ => (),
+            }
+        }
+
+        // Max-Age takes priority over Expires; a non-positive value deletes the cookie
+        if let Some(seconds) = max_age {
+            cookie.expires = if seconds > 0 { now() + seconds as u64 } else { 1 };
+        }
+
+        self.insert(cookie);
+    }
+
+    /// The value of the Cookie header to send with a request for `url`, if any cookies apply.
+    pub fn cookie_header(&self, url: &Url) -> Option<String> {
+        let now = now();
+        let pairs: Vec<String> = self.cookies.iter()
+            .filter(|cookie| !cookie.is_expired(now) && cookie.matches(url))
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+
+        if pairs.is_empty() {
+            None
+        } else {
+            Some(pairs.join("; "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::Url;
+    use super::CookieJar;
+
+    #[test]
+    fn set_cookie_test() {
+        let url = Url::parse("http://www.example.com/downloads/file.tar.gz").unwrap();
+        let mut jar = CookieJar::new();
+
+        jar.set_cookie(&url, "session=abc123; Path=/");
+        jar.set_cookie(&url, "token=xyz; Domain=example.com; Path=/; Max-Age=3600");
+        jar.set_cookie(&url, "secret=1; Secure");
+        jar.set_cookie(&url, "evil=1; Domain=example.org");
+
+        assert_eq!(jar.cookies.len(), 3);
+        assert_eq!(jar.cookie_header(&url), Some("session=abc123; token=xyz".to_string()));
+
+        let other = Url::parse("https://cdn.example.com/").unwrap();
+        assert_eq!(jar.cookie_header(&other), Some("token=xyz".to_string()));
+
+        let secure = Url::parse("https://www.example.com/downloads/other").unwrap();
+        assert_eq!(jar.cookie_header(&secure), Some("session=abc123; token=xyz; secret=1".to_string()));
+
+        jar.set_cookie(&url, "token=gone; Domain=example.com; Path=/; Max-Age=0");
+        assert_eq!(jar.cookie_header(&other), None);
+    }
+}
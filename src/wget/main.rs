@@ -1,97 +1,353 @@
 #![deny(warnings)]
 
-extern crate arg_parser;
 extern crate hyper;
 extern crate hyper_rustls;
+extern crate netutils_core;
 extern crate pbr;
 
-use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, Read, Write};
-use std::process;
-use std::time::Duration;
-use hyper::Client;
-use hyper::net::HttpsConnector;
-use hyper::header::ContentLength;
+use std::{cmp, thread};
+use std::str;
+use std::time::{Duration, SystemTime};
+use hyper::{Client, Url};
+use hyper::client::{RedirectPolicy, Response};
+use hyper::net::{HttpStream, HttpsStream, NetworkConnector, SslClient};
+use hyper::header::{ContentLength, Headers, HttpDate, IfModifiedSince, LastModified, Location};
 use hyper::status::StatusCode;
-use arg_parser::ArgParser;
+use netutils_core::args::Parser;
+use netutils_core::error::{self, NetError};
+use netutils_core::ftp::{FtpStream, TransferType};
+use netutils_core::proxy;
+use netutils_core::ratelimit::{Limit, Limited};
+use netutils_core::resolve::{self, Family};
+use netutils_core::tls;
 use pbr::{ProgressBar, Units};
 
-fn wget<W: Write>(url: &str, mut output: W) {
-    let mut stderr = io::stderr();
+use cookie::CookieJar;
+
+mod cookie;
+
+const MAX_REDIRECTS: usize = 20;
+/// The longest wait between tries, in seconds, which grows by a second after each
+const MAX_RETRY_WAIT: u64 = 10;
+
+/// Connects through the proxy that the environment sets for the scheme, if any, and with Happy
+/// Eyeballs, to the addresses of one family if -4 or -6 was given, none of which hyper's own
+/// connector does. `HttpsConnector` only asks its connector for http streams, which would hide
+/// the scheme, so https streams are wrapped in TLS here instead.
+struct Connector {
+    family: Family,
+    tls: hyper_rustls::TlsClient,
+}
+
+impl NetworkConnector for Connector {
+    type Stream = HttpsStream<<hyper_rustls::TlsClient as SslClient>::Stream>;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> hyper::Result<Self::Stream> {
+        let stream = proxy::connect(scheme, host, port, self.family, None)
+            .map(HttpStream)
+            .map_err(|err| hyper::Error::Io(err.into()))?;
+        if scheme == "https" {
+            self.tls.wrap_client(stream, host).map(HttpsStream::Https)
+        } else {
+            Ok(HttpsStream::Http(stream))
+        }
+    }
+}
+
+/// Copy `input` to `output`, no faster than `limit` allows, with a progress bar unless `quiet`
+fn download<R: Read, W: Write + ?Sized>(input: &mut R, length: u64, output: &mut W, quiet: bool,
+                                       limit: Option<Limit>) -> error::Result<()> {
+    let mut input = Limited::new(input, limit);
+
+    let mut pb = if quiet {
+        None
+    } else {
+        let mut pb = ProgressBar::on(io::stderr(), length);
+        pb.set_units(Units::Bytes);
+        Some(pb)
+    };
+
+    let mut count = 0;
+    loop {
+        let mut buf = [0; 8192];
+        let res = input.read(&mut buf).map_err(|err| NetError::from(err).context("failed to read data"))?;
+        if res == 0 {
+            return Ok(());
+        }
+        count += output.write(&buf[.. res]).map_err(|err| NetError::Io(err).context("failed to write data"))?;
+        if let Some(ref mut pb) = pb {
+            pb.set(count as u64);
+        }
+    }
+}
+
+/// Run `f` with the file at `path` as output, or stdout if there is no path.
+fn write_output<F: FnOnce(&mut Write) -> error::Result<()>>(path: Option<&str>, f: F) -> error::Result<()> {
+    match path {
+        Some(path) => {
+            let mut file = File::create(path)
+                .map_err(|err| NetError::Io(err).context(&format!("failed to create '{}'", path)))?;
+            f(&mut file)?;
+            file.sync_all().map_err(|err| NetError::Io(err).context("failed to sync data"))
+        },
+        None => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            f(&mut stdout)?;
+            stdout.flush().map_err(|err| NetError::Io(err).context("failed to flush data"))
+        }
+    }
+}
 
-    let mut client = Client::with_connector(HttpsConnector::new(hyper_rustls::TlsClient::new()));
+fn local_modified(path: Option<&str>, timestamping: bool) -> Option<SystemTime> {
+    if timestamping {
+        path.and_then(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+    } else {
+        None
+    }
+}
+
+/// Decode %XX escapes in a URL component
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(byte) = str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Download a file, or the listing of a directory if the path ends in '/', from an ftp:// url
+fn wget_ftp(url: &str, path: Option<&str>, family: Family, quiet: bool, timestamping: bool,
+            limit: Option<Limit>) -> error::Result<()> {
+    let url = Url::parse(url).map_err(|err| NetError::Usage(format!("invalid url: {}", err)))?;
+    let host = url.host_str().ok_or_else(|| NetError::Usage("url has no host".to_string()))?;
+
+    let mut ftp = FtpStream::connect(resolve::resolve(host, url.port().unwrap_or(21), family)?)?;
+    ftp.login(&percent_decode(url.username()), &percent_decode(url.password().unwrap_or("")))?;
+
+    let remote = percent_decode(url.path().trim_left_matches('/'));
+    if remote.is_empty() || remote.ends_with('/') {
+        let listing = ftp.list_string(if remote.is_empty() { None } else { Some(remote.as_str()) }, false)?;
+        write_output(path, |output| {
+            output.write_all(listing.as_bytes()).map_err(|err| NetError::Io(err).context("failed to write data"))
+        })?;
+    } else {
+        ftp.transfer_type(TransferType::Binary)?;
+
+        if let Some(local_modified) = local_modified(path, timestamping) {
+            if let Some(remote_modified) = ftp.modified(&remote)? {
+                if remote_modified <= local_modified {
+                    if !quiet {
+                        let _ = writeln!(io::stderr(), "wget: '{}' is up to date, not retrieving", path.unwrap_or(""));
+                    }
+                    return Ok(ftp.quit()?);
+                }
+            }
+        }
+
+        let length = ftp.size(&remote)?.unwrap_or(0);
+        let mut data = ftp.retr(&remote)?;
+        write_output(path, |output| download(&mut data, length, output, quiet, limit))?;
+        drop(data);
+        ftp.finish()?;
+    }
+
+    Ok(ftp.quit()?)
+}
+
+/// Send a GET request for `url`, following redirects and recording any cookies set along the
+/// way in `jar`.
+fn get(client: &Client, url: &str, jar: &mut CookieJar, local_modified: Option<SystemTime>) -> hyper::Result<Response> {
+    let mut url = Url::parse(url)?;
+
+    for _ in 0..MAX_REDIRECTS {
+        let mut headers = Headers::new();
+        if let Some(local_modified) = local_modified {
+            headers.set(IfModifiedSince(HttpDate::from(local_modified)));
+        }
+        if let Some(cookie) = jar.cookie_header(&url) {
+            headers.set_raw("Cookie", vec![cookie.into_bytes()]);
+        }
+
+        let response = client.get(url.clone()).headers(headers).send()?;
+
+        if let Some(values) = response.headers.get_raw("Set-Cookie") {
+            for value in values.iter() {
+                if let Ok(value) = str::from_utf8(value) {
+                    jar.set_cookie(&url, value);
+                }
+            }
+        }
+
+        if response.status.is_redirection() {
+            if let Some(location) = response.headers.get::<Location>() {
+                url = url.join(location)?;
+                continue;
+            }
+        }
+
+        return Ok(response);
+    }
+
+    Err(hyper::Error::Io(io::Error::new(io::ErrorKind::Other, "too many redirects")))
+}
+
+/// Connection failures keep the kind of their I/O error, and anything else that hyper rejects
+/// is a protocol error
+fn request_error(err: hyper::Error) -> NetError {
+    match err {
+        hyper::Error::Io(err) => NetError::from(err),
+        hyper::Error::Uri(err) => NetError::Usage(format!("invalid url: {}", err)),
+        err => NetError::Protocol(err.to_string()),
+    }
+}
+
+/// The HTTP client, which connects to the addresses of `family` and checks certificates with
+/// `tls`
+fn client(family: Family, tls: hyper_rustls::TlsClient) -> Client {
+    let mut client = Client::with_connector(Connector {
+        family: family,
+        tls: tls,
+    });
     client.set_read_timeout(Some(Duration::new(5, 0)));
     client.set_write_timeout(Some(Duration::new(5, 0)));
-    match client.get(url).send() {
+    // Redirects are followed by `get` so that cookies set by intermediate responses are kept
+    client.set_redirect_policy(RedirectPolicy::FollowNone);
+    client
+}
+
+/// Download `url` and write the body to the file at `path`, or to stdout if there is no path.
+/// Requests that fail to connect or time out are tried again, up to `tries` times in all, and
+/// the body is read no faster than `limit` allows.
+///
+/// With `timestamping`, an existing file at `path` is only replaced if the server reports a
+/// newer `Last-Modified` time than the file's modification time.
+fn wget(client: &Client, url: &str, path: Option<&str>, jar: &mut CookieJar, quiet: bool, timestamping: bool, tries: u64,
+        limit: Option<Limit>) -> error::Result<()> {
+    let mut stderr = io::stderr();
+
+    let local_modified = local_modified(path, timestamping);
+
+    let mut tried = 1;
+    let result = loop {
+        match get(client, url, jar, local_modified) {
+            Err(hyper::Error::Io(ref err)) if tried < tries => {
+                if !quiet {
+                    let _ = writeln!(stderr, "wget: {}, retrying", err);
+                }
+                thread::sleep(Duration::new(cmp::min(tried, MAX_RETRY_WAIT), 0));
+                tried += 1;
+            },
+            result => break result
+        }
+    };
+
+    match result {
         Ok(mut response) => match response.status {
             StatusCode::Ok => {
-                let mut count = 0;
-                let length = response.headers.get::<ContentLength>().map_or(0, |h| h.0 as usize);
-
-                let mut pb = ProgressBar::on(io::stderr(), length as u64);
-                pb.set_units(Units::Bytes);
-                loop {
-                    let mut buf = [0; 8192];
-                    let res = match response.read(&mut buf) {
-                        Ok(res) => res,
-                        Err(err) => {
-                            writeln!(stderr, "wget: failed to read data: {}", err).unwrap();
-                            process::exit(1);
+                let remote_modified = response.headers.get::<LastModified>().map(|h| SystemTime::from(h.0));
+                if let (Some(local_modified), Some(remote_modified)) = (local_modified, remote_modified) {
+                    if remote_modified <= local_modified {
+                        if !quiet {
+                            let _ = writeln!(stderr, "wget: '{}' is up to date, not retrieving", path.unwrap_or(""));
                         }
-                    };
-                    if res == 0 {
-                        break;
+                        return Ok(());
                     }
-                    count += match output.write(&buf[.. res]) {
-                        Ok(res) => res,
-                        Err(err) => {
-                            writeln!(stderr, "wget: failed to write data: {}", err).unwrap();
-                            process::exit(1);
-                        }
-                    };
-                    pb.set(count as u64);
                 }
+
+                let length = response.headers.get::<ContentLength>().map_or(0, |h| h.0);
+                write_output(path, |output| download(&mut response, length, output, quiet, limit))
             },
-            _ => {
-                let _ = writeln!(stderr, "wget: failed to receive request: {}", response.status);
-                process::exit(1);
-            }
+            StatusCode::NotModified if local_modified.is_some() => {
+                if !quiet {
+                    let _ = writeln!(stderr, "wget: '{}' is up to date, not retrieving", path.unwrap_or(""));
+                }
+                Ok(())
+            },
+            _ => Err(NetError::other(format!("failed to receive request: {}", response.status)))
         },
-        Err(err) => {
-            let _ = writeln!(stderr, "wget: failed to send request: {}", err);
-            process::exit(1);
-        }
+        Err(err) => Err(request_error(err).context("failed to send request"))
     }
 }
 
 fn main() {
-    let mut parser = ArgParser::new(1)
-        .add_opt("O", "output-document");
-    parser.parse(env::args());
-
-    match parser.args.get(0) {
-        Some(url) => match parser.get_opt("output-document") {
-            Some(path) => match File::create(&path) {
-                Ok(mut file) => {
-                    wget(&url, &mut file);
-                    if let Err(err) = file.sync_all() {
-                        let _ = writeln!(io::stderr(), "wget: failed to sync data: {}", err);
-                        process::exit(1);
-                    }
-                },
-                Err(err) => {
-                    writeln!(io::stderr(), "wget: failed to create '{}': {}", path, err).unwrap();
-                    process::exit(1);
-                }
-            },
-            None => {
-                wget(&url, io::stdout());
-            }
+    let args = Parser::new("wget")
+        .synopsis("[-q] [-N] [-4 | -6] [-O output] [--load-cookies file] [--save-cookies file] [-t tries] [--limit-rate rate] [--ca-certificate file] \
+                   [--no-check-certificate] (http|https|ftp)://host:port/path")
+        .flag("q", "quiet", "Don't show a progress bar")
+        .family()
+        .flag("N", "timestamping", "Only download the document if it is newer than the output file")
+        .opt("O", "output-document", "output", "Write the document to this file, or - for standard output")
+        .opt("", "load-cookies", "file", "Send the cookies in this file")
+        .opt("", "save-cookies", "file", "Save the cookies to this file")
+        .opt("t", "tries", "tries", "Try HTTP requests that fail to connect this many times, once by default")
+        .rate_limit()
+        .opt("", "ca-certificate", "file", "Trust the certificate authorities in the PEM file")
+        .flag("", "no-check-certificate", "Don't check the certificate of the server")
+        .parse_env();
+    if args.free.len() != 1 {
+        args.usage();
+    }
+    let url = &args.free[0];
+
+    let quiet = args.flag("quiet");
+    let family = args.family();
+    let timestamping = args.flag("timestamping");
+    let tries = match args.parse("tries") {
+        Some(0) => args.invalid("the number of tries must be at least 1"),
+        Some(tries) => tries,
+        None => 1
+    };
+    let limit = args.rate_limit();
+
+    // `-O -` writes the document to stdout, same as not passing `-O` at all
+    let path = args.value("output-document").and_then(|path| {
+        if path == "-" { None } else { Some(path) }
+    });
+
+    if timestamping && path.is_none() {
+        args.invalid("-N requires an output file");
+    }
+
+    let mut jar = match args.value("load-cookies") {
+        Some(cookies) => match CookieJar::load(cookies) {
+            Ok(jar) => jar,
+            Err(err) => NetError::Io(err).context(&format!("failed to load cookies from '{}'", cookies)).exit("wget")
         },
-        None => {
-            writeln!(io::stderr(), "wget http://host:port/path [-O output]").unwrap();
-            process::exit(1);
+        None => CookieJar::new(),
+    };
+
+    let result = if url.starts_with("ftp://") {
+        wget_ftp(url, path, family, quiet, timestamping, limit).map_err(|err| err.context("ftp"))
+    } else {
+        let mut options = tls::ClientOptions::new();
+        options.ca_file = args.value("ca-certificate").map(|file| file.to_string());
+        options.verify = !args.flag("no-check-certificate");
+        let cfg = tls::client_config(&options)
+            .unwrap_or_else(|err| NetError::Io(err).context("failed to load TLS settings").exit("wget"));
+        let client = client(family, hyper_rustls::TlsClient { cfg: cfg });
+        wget(&client, url, path, &mut jar, quiet, timestamping, tries, limit)
+    };
+
+    // The cookies set before a failure, such as by a redirect to a missing page, are still saved
+    if let Some(cookies) = args.value("save-cookies") {
+        if let Err(err) = jar.save(cookies) {
+            NetError::Io(err).context(&format!("failed to save cookies to '{}'", cookies)).exit("wget");
         }
     }
+    if let Err(err) = result {
+        err.exit("wget");
+    }
 }
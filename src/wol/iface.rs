@@ -0,0 +1,79 @@
+use std::net::Ipv4Addr;
+
+pub use self::sys::broadcast;
+
+/// The broadcast address of the subnet of `addr`
+fn subnet_broadcast(addr: Ipv4Addr, prefix: u8) -> Ipv4Addr {
+    let mask = if prefix >= 32 { !0 } else { !(!0u32 >> prefix) };
+    Ipv4Addr::from(u32::from(addr) | !mask)
+}
+
+#[cfg(not(target_os = "redox"))]
+mod sys {
+    use libc;
+    use netutils_core::netlink::{self, Socket, RTM_GETADDR};
+    use std::io::{Error, ErrorKind, Result};
+    use std::net::Ipv4Addr;
+
+    use super::subnet_broadcast;
+
+    const IFA_ADDRESS: u16 = 1;
+    const IFA_LOCAL: u16 = 2;
+    const IFA_BROADCAST: u16 = 4;
+
+    fn to_addr(data: &[u8]) -> Option<Ipv4Addr> {
+        if data.len() == 4 {
+            Some(Ipv4Addr::new(data[0], data[1], data[2], data[3]))
+        } else {
+            None
+        }
+    }
+
+    /// The broadcast address of the first IPv4 subnet of an interface
+    pub fn broadcast(iface: &str) -> Result<Ipv4Addr> {
+        let index = netlink::if_index(iface)?;
+        // struct ifaddrmsg for AF_INET
+        for message in Socket::open()?.dump(RTM_GETADDR, &[libc::AF_INET as u8, 0, 0, 0, 0, 0, 0, 0])? {
+            let header = match message.header(8) {
+                Some(header) => header,
+                None => continue
+            };
+            if netlink::read_u32(&header[4..8]) != index {
+                continue;
+            }
+            if let Some(addr) = message.attr(8, IFA_BROADCAST).and_then(to_addr) {
+                return Ok(addr);
+            }
+            if let Some(addr) = message.attr(8, IFA_LOCAL).or_else(|| message.attr(8, IFA_ADDRESS)).and_then(to_addr) {
+                return Ok(subnet_broadcast(addr, header[1]));
+            }
+        }
+        Err(Error::new(ErrorKind::NotFound, format!("{} has no IPv4 address", iface)))
+    }
+}
+
+#[cfg(target_os = "redox")]
+mod sys {
+    use std::fs::File;
+    use std::io::{Error, ErrorKind, Read, Result};
+    use std::net::Ipv4Addr;
+    use std::str::FromStr;
+
+    use super::subnet_broadcast;
+
+    /// The broadcast address of the first IPv4 subnet of an interface
+    pub fn broadcast(iface: &str) -> Result<Ipv4Addr> {
+        let mut list = String::new();
+        File::open(format!("netcfg:ifaces/{}/addr/list", iface))?.read_to_string(&mut list)?;
+        // Addresses are listed as address/prefix length, one per line
+        for line in list.lines() {
+            let mut parts = line.trim().splitn(2, '/');
+            let addr = parts.next().and_then(|addr| Ipv4Addr::from_str(addr).ok());
+            let prefix = parts.next().and_then(|prefix| prefix.parse().ok());
+            if let (Some(addr), Some(prefix)) = (addr, prefix) {
+                return Ok(subnet_broadcast(addr, prefix));
+            }
+        }
+        Err(Error::new(ErrorKind::NotFound, format!("{} has no IPv4 address", iface)))
+    }
+}
@@ -0,0 +1,131 @@
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+use netutils_core::MacAddr;
+
+/// Parse a MAC address with its bytes separated by colons or dashes, or as twelve hex digits,
+/// which may be grouped by dots
+pub fn parse_mac(text: &str) -> Option<MacAddr> {
+    let mut mac = MacAddr::default();
+    if text.contains(':') || text.contains('-') {
+        let parts: Vec<&str> = text.split(|c| c == ':' || c == '-').collect();
+        if parts.len() != 6 {
+            return None;
+        }
+        for (byte, part) in mac.bytes.iter_mut().zip(parts) {
+            if part.is_empty() || part.len() > 2 {
+                return None;
+            }
+            *byte = u8::from_str_radix(part, 16).ok()?;
+        }
+    } else {
+        let digits: String = text.chars().filter(|&c| c != '.').collect();
+        if digits.len() != 12 || !digits.chars().all(|c| c.is_digit(16)) {
+            return None;
+        }
+        for (i, byte) in mac.bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).ok()?;
+        }
+    }
+    Some(mac)
+}
+
+/// Parse a SecureOn password, which is six bytes written as a MAC address, or four written as
+/// an IPv4 address
+pub fn parse_password(text: &str) -> Option<Vec<u8>> {
+    match Ipv4Addr::from_str(text) {
+        Ok(addr) => Some(addr.octets().to_vec()),
+        Err(_) => parse_mac(text).map(|mac| mac.bytes.to_vec())
+    }
+}
+
+/// Six bytes of 0xFF, then the MAC address sixteen times, then the password if there is one
+pub fn magic_packet(mac: &MacAddr, password: Option<&[u8]>) -> Vec<u8> {
+    let mut packet = vec![0xFF; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac.bytes);
+    }
+    if let Some(password) = password {
+        packet.extend_from_slice(password);
+    }
+    packet
+}
+
+/// A machine to wake, from a hosts file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Host {
+    pub mac: MacAddr,
+    pub broadcast: Option<Ipv4Addr>,
+    pub password: Option<Vec<u8>>,
+}
+
+/// Parse a hosts file, of lines of a MAC address followed by the broadcast address to use, or
+/// `-` for the default, and a password. Everything after a `#` is a comment.
+pub fn parse_hosts(text: &str) -> Result<Vec<Host>, String> {
+    let mut hosts = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        if fields.len() > 3 {
+            return Err(format!("line {}: too many fields", number + 1));
+        }
+        let mac = parse_mac(fields[0]).ok_or(format!("line {}: invalid MAC address {}", number + 1, fields[0]))?;
+        let broadcast = match fields.get(1) {
+            Some(&"-") | None => None,
+            Some(addr) => Some(Ipv4Addr::from_str(addr).map_err(|_| format!("line {}: invalid address {}", number + 1, addr))?)
+        };
+        let password = match fields.get(2) {
+            Some(password) => Some(parse_password(password).ok_or(format!("line {}: invalid password {}", number + 1, password))?),
+            None => None
+        };
+        hosts.push(Host {
+            mac: mac,
+            broadcast: broadcast,
+            password: password,
+        });
+    }
+    Ok(hosts)
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+    use netutils_core::MacAddr;
+    use super::{magic_packet, parse_hosts, parse_mac, parse_password, Host};
+
+    #[test]
+    fn magic_packet_test() {
+        let mac = MacAddr { bytes: [0x00, 0x11, 0x22, 0x33, 0x44, 0x55] };
+        assert_eq!(parse_mac("00:11:22:33:44:55"), Some(mac));
+        assert_eq!(parse_mac("0-11-22-33-44-55"), Some(mac));
+        assert_eq!(parse_mac("0011.2233.4455"), Some(mac));
+        assert_eq!(parse_mac("00:11:22:33:44"), None);
+        assert_eq!(parse_mac("001122334455aa"), None);
+
+        let packet = magic_packet(&mac, None);
+        assert_eq!(packet.len(), 102);
+        assert_eq!(&packet[..6], &[0xFF; 6]);
+        assert_eq!(&packet[96..], &mac.bytes);
+        assert_eq!(magic_packet(&mac, Some(&[1, 2, 3, 4])).len(), 106);
+
+        assert_eq!(parse_password("192.168.1.1"), Some(vec![192, 168, 1, 1]));
+        assert_eq!(parse_password("01:02:03:04:05:06"), Some(vec![1, 2, 3, 4, 5, 6]));
+        assert_eq!(parse_password("secret"), None);
+    }
+
+    #[test]
+    fn parse_hosts_test() {
+        let hosts = parse_hosts("# lab\n00:11:22:33:44:55\n\n00:11:22:33:44:66 192.0.2.255 # nas\n00:11:22:33:44:77 - 1.2.3.4\n").unwrap();
+        assert_eq!(hosts.len(), 3);
+        assert_eq!(hosts[1], Host {
+            mac: MacAddr { bytes: [0x00, 0x11, 0x22, 0x33, 0x44, 0x66] },
+            broadcast: Some(Ipv4Addr::new(192, 0, 2, 255)),
+            password: None,
+        });
+        assert_eq!(hosts[2].broadcast, None);
+        assert_eq!(hosts[2].password, Some(vec![1, 2, 3, 4]));
+        assert_eq!(parse_hosts("00:11:22:33:44:55\nnonsense\n"), Err("line 2: invalid MAC address nonsense".to_string()));
+    }
+}
@@ -0,0 +1,141 @@
+#[cfg(not(target_os = "redox"))]
+extern crate libc;
+extern crate netutils_core;
+
+use netutils_core::args::Parser;
+use netutils_core::error::{NetError, Result, EXIT_FAILURE};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::process;
+
+use magic::{magic_packet, parse_hosts, parse_mac, parse_password, Host};
+
+mod iface;
+mod magic;
+
+static WOL_MAN: &'static str = /* @MANSTART{wol} */ r#"
+NAME
+    wol - wake machines with a wake-on-lan magic packet
+
+SYNOPSIS
+    wol [-i iface | -b broadcast] [-p port] [-s password] mac...
+    wol [-i iface | -b broadcast] [-p port] -f hosts-file
+    wol [-h | --help]
+
+DESCRIPTION
+    Broadcast a magic packet for each MAC address, which wakes the machine
+    with that address if its network card is set to wake on LAN and it is
+    on the subnet that the packet is broadcast to.
+
+    MAC addresses may be written with colons or dashes, as in
+    00:11:22:33:44:55, or as twelve hex digits.
+
+OPTIONS
+    -i iface
+        Broadcast on the subnet of this interface.
+
+    -b broadcast
+        Broadcast to this address, 255.255.255.255 by default. A directed
+        broadcast address, such as 192.168.1.255, reaches a subnet that
+        routers forward it to.
+
+    -p port
+        Send to this UDP port, 9 (discard) by default.
+
+    -s password
+        Add a SecureOn password, which is six bytes written as a MAC
+        address, or four written as an IPv4 address.
+
+    -f hosts-file
+        Wake the machines listed in the file, each on a line of its MAC
+        address, followed by its broadcast address, or - for the default,
+        and its password. Everything after a # is a comment.
+
+    -h
+    --help
+        Print this manual page.
+
+NOTES
+    Machines that are off only listen for the packet, so there's no reply
+    to tell whether one woke up.
+"#; /* @MANEND */
+
+const DEFAULT_PORT: u16 = 9;
+
+/// Send the magic packets, returning false if any couldn't be sent
+fn run() -> Result<bool> {
+    let args = Parser::new("wol")
+        .synopsis("[-i iface | -b broadcast] [-p port] [-s password] mac...")
+        .synopsis("[-i iface | -b broadcast] [-p port] -f hosts-file")
+        .man(WOL_MAN)
+        .opt("i", "", "iface", "Send to the broadcast address of this interface")
+        .opt("b", "", "broadcast", "Send to this broadcast address")
+        .opt("p", "", "port", "The UDP port to send to")
+        .opt("s", "", "password", "The SecureOn password, as a MAC or IPv4 address")
+        .opt("f", "", "hosts-file", "Wake every host in this file")
+        .parse_env();
+
+    let iface = args.value("i");
+    let broadcast = args.parse::<Ipv4Addr>("b");
+    let port = args.parse("p").unwrap_or(DEFAULT_PORT);
+    let password = args.value("s").map(|value| parse_password(value).unwrap_or_else(|| args.invalid(&format!("invalid password {}", value))));
+    let hosts_file = args.value("f");
+    let macs: Vec<_> = args.free.iter()
+        .map(|arg| parse_mac(arg).unwrap_or_else(|| args.invalid(&format!("invalid MAC address {}", arg))))
+        .collect();
+    if iface.is_some() && broadcast.is_some() {
+        args.invalid("-i and -b can't be used together");
+    }
+
+    let hosts = match hosts_file {
+        Some(path) => {
+            if !macs.is_empty() || password.is_some() {
+                args.usage();
+            }
+            let mut text = String::new();
+            File::open(&path).and_then(|mut file| file.read_to_string(&mut text))
+                .map_err(|err| NetError::from(err).context(&format!("failed to read {}", path)))?;
+            parse_hosts(&text).map_err(|err| NetError::Usage(format!("{}: {}", path, err)))?
+        },
+        None => macs.into_iter().map(|mac| Host {
+            mac: mac,
+            broadcast: None,
+            password: password.clone(),
+        }).collect()
+    };
+    if hosts.is_empty() {
+        args.usage();
+    }
+
+    let default = match iface {
+        Some(iface) => iface::broadcast(iface)
+            .map_err(|err| NetError::from(err).context(&format!("failed to find the broadcast address of {}", iface)))?,
+        None => broadcast.unwrap_or(Ipv4Addr::new(255, 255, 255, 255))
+    };
+
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| socket.set_broadcast(true).map(|_| socket))
+        .map_err(|err| NetError::from(err).context("failed to open a socket"))?;
+    let mut failed = false;
+    for host in hosts.iter() {
+        let addr = SocketAddrV4::new(host.broadcast.unwrap_or(default), port);
+        let packet = magic_packet(&host.mac, host.password.as_ref().map(|password| password.as_slice()));
+        match socket.send_to(&packet, addr) {
+            Ok(_) => println!("sent magic packet for {} to {}", host.mac.to_string(), addr),
+            Err(err) => {
+                let _ = writeln!(io::stderr(), "wol: failed to send to {}: {}", addr, err);
+                failed = true;
+            }
+        }
+    }
+    Ok(!failed)
+}
+
+fn main() {
+    match run() {
+        Ok(true) => (),
+        Ok(false) => process::exit(EXIT_FAILURE),
+        Err(err) => err.exit("wol")
+    }
+}
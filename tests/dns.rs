@@ -0,0 +1,53 @@
+mod support;
+
+use support::{closed_udp_port, run, stderr, stdout, MockDns};
+
+#[test]
+fn lookup_test() {
+    let server = MockDns::start(&[("example.com", "93.184.216.34"), ("example.com", "2606:2800:220:1::1")], false);
+    let output = run("dns", &["--server", &server.addr(), "example.com"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "93.184.216.34\n2606:2800:220:1::1\n");
+    assert_eq!(server.questions(), vec!["udp example.com 1".to_string(), "udp example.com 28".to_string()]);
+}
+
+#[test]
+fn family_test() {
+    let server = MockDns::start(&[("example.com", "93.184.216.34"), ("example.com", "2606:2800:220:1::1")], false);
+    let output = run("dns", &["-6", "--server", &server.addr(), "--output", "json", "example.com"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "{\"name\":\"example.com\",\"address\":\"2606:2800:220:1::1\",\"family\":\"ipv6\"}\n\
+                                 {\"name\":\"example.com\",\"addresses\":1}\n");
+    assert_eq!(server.questions(), vec!["udp example.com 28".to_string()]);
+
+    // A name with addresses of only the other family
+    let server = MockDns::start(&[("example.com", "93.184.216.34")], false);
+    let output = run("dns", &["-6", "--server", &server.addr(), "example.com"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("no IPv6 address found"), "{}", stderr(&output));
+}
+
+#[test]
+fn no_such_name_test() {
+    let server = MockDns::start(&[("example.com", "93.184.216.34")], false);
+    let output = run("dns", &["--server", &server.addr(), "example.org"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("no such name"), "{}", stderr(&output));
+    assert_eq!(server.questions(), vec!["udp example.org 1".to_string()]);
+}
+
+#[test]
+fn truncated_test() {
+    // A truncated reply is asked for again over TCP
+    let server = MockDns::start(&[("example.com", "93.184.216.34")], true);
+    let output = run("dns", &["-4", "--server", &server.addr(), "example.com"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "93.184.216.34\n");
+    assert_eq!(server.questions(), vec!["udp example.com 1".to_string(), "tcp example.com 1".to_string()]);
+}
+
+#[test]
+fn refused_test() {
+    let output = run("dns", &["--server", &format!("127.0.0.1:{}", closed_udp_port()), "example.com"]);
+    assert_eq!(output.status.code(), Some(4));
+}
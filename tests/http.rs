@@ -0,0 +1,68 @@
+mod support;
+
+use support::{run, stderr, stdout, MockServer};
+
+static HELLO: &'static str = "HTTP/1.1 200 OK\r\nContent-Length: 6\r\nConnection: close\r\n\r\nhello\n";
+
+fn redirect(location: &str) -> String {
+    format!("HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", location)
+}
+
+#[test]
+fn curl_test() {
+    let server = MockServer::http(&[HELLO]);
+    let output = run("curl", &[&server.url("/hello")]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "hello\n");
+    assert!(server.requests()[0].starts_with("GET /hello HTTP/1.1\r\n"));
+}
+
+#[test]
+fn curl_redirect_test() {
+    let server = MockServer::http(&[&redirect("/final"), HELLO]);
+    let output = run("curl", &["-L", &server.url("/start")]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "hello\n");
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].starts_with("GET /final HTTP/1.1\r\n"));
+
+    // Without -L, the redirect is the response
+    let server = MockServer::http(&[&redirect("/final")]);
+    let output = run("curl", &["-i", &server.url("/start")]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with("HTTP/1.1 302 Found\r\n"));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn curl_truncated_test() {
+    let server = MockServer::http(&["HTTP/1.1 200 OK\r\nContent-Length: 100\r\nConnection: close\r\n\r\nhello\n"]);
+    let output = run("curl", &[&server.url("/hello")]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("failed to transfer data"), "{}", stderr(&output));
+}
+
+#[test]
+fn wget_redirect_test() {
+    // Cookies set by a redirect are sent to where it leads
+    let server = MockServer::http(&[
+        "HTTP/1.1 302 Found\r\nLocation: /final\r\nSet-Cookie: session=1; Path=/\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        HELLO,
+    ]);
+    let output = run("wget", &["-q", "-O", "-", &server.url("/start")]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "hello\n");
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].starts_with("GET /final HTTP/1.1\r\n"));
+    assert!(requests[1].contains("Cookie: session=1\r\n"), "{}", requests[1]);
+}
+
+#[test]
+fn wget_not_found_test() {
+    let server = MockServer::http(&["HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"]);
+    let output = run("wget", &["-q", "-O", "-", &server.url("/missing")]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("404"), "{}", stderr(&output));
+}
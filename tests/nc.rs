@@ -0,0 +1,91 @@
+mod support;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use support::{closed_port, run, stderr, stdout, tool, MockServer};
+
+#[test]
+fn connect_test() {
+    let server = MockServer::echo();
+    let mut child = tool("nc").arg(server.addr())
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+        .spawn().unwrap();
+    // Standard input is left open, since nc exits at its end, and the server closes the
+    // connection once it has answered
+    child.stdin.as_mut().unwrap().write_all(b"hello\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "hello\n");
+    assert_eq!(server.requests(), vec!["hello\n".to_string()]);
+}
+
+#[test]
+fn refused_test() {
+    let output = run("nc", &[&format!("127.0.0.1:{}", closed_port())]);
+    assert_eq!(output.status.code(), Some(4));
+}
+
+/// The bytes that each side sends in the rate limit tests, which would take two seconds at 1k
+/// a second after the first burst of 1k
+const LIMITED_LEN: usize = 3 * 1024;
+
+/// Run nc with rate limits against a server that sends `LIMITED_LEN` bytes as soon as it
+/// accepts the connection, while nc sends as many, returning how long the server took to
+/// receive them and how long nc took to print what it received
+fn limited(args: &[&str]) -> (Duration, Duration) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let start = Instant::now();
+        let mut writer = stream.try_clone().unwrap();
+        let written = thread::spawn(move || writer.write_all(&[b'a'; LIMITED_LEN]));
+        let mut data = vec![0; LIMITED_LEN];
+        stream.read_exact(&mut data).unwrap();
+        sender.send(start.elapsed()).unwrap();
+        let _ = written.join();
+    });
+
+    let start = Instant::now();
+    let mut child = tool("nc").args(args).arg(&addr)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null())
+        .spawn().unwrap();
+    // Standard input is kept open until nc has printed everything, since nc exits at its end
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(&[b'b'; LIMITED_LEN]).unwrap();
+    let mut printed = vec![0; LIMITED_LEN];
+    child.stdout.take().unwrap().read_exact(&mut printed).unwrap();
+    let received = start.elapsed();
+    let sent = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    drop(stdin);
+    let _ = child.wait();
+    (sent, received)
+}
+
+#[test]
+fn limit_in_test() {
+    let (sent, received) = limited(&["--limit-rate-in", "1k", "--limit-burst", "1k"]);
+    assert!(received >= Duration::from_millis(1500), "{:?}", received);
+    assert!(sent < Duration::from_secs(1), "{:?}", sent);
+}
+
+#[test]
+fn limit_out_test() {
+    let (sent, received) = limited(&["--limit-rate-out", "1k", "--limit-burst", "1k"]);
+    assert!(sent >= Duration::from_millis(1500), "{:?}", sent);
+    assert!(received < Duration::from_secs(1), "{:?}", received);
+}
+
+#[test]
+fn limit_both_test() {
+    // --limit-rate sets both directions, and -out overrides it for sending
+    let (sent, received) = limited(&["--limit-rate", "1k", "--limit-rate-out", "1m", "--limit-burst", "1k"]);
+    assert!(received >= Duration::from_millis(1500), "{:?}", received);
+    assert!(sent < Duration::from_secs(1), "{:?}", sent);
+}
@@ -0,0 +1,302 @@
+//! Loopback servers that answer with canned responses, and the tools to run against them. Each
+//! test file includes this module, and uses only some of it.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How long a server waits for a request, so that a tool that hangs fails its test rather than
+/// the whole run
+const TIMEOUT_SECS: u64 = 10;
+
+/// A server on 127.0.0.1 that answers a number of connections, one after another, and keeps
+/// the requests that it received
+pub struct MockServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+/// Read up to and including `terminator`, or until the peer stops sending
+fn read_request(stream: &mut TcpStream, terminator: &[u8]) -> Vec<u8> {
+    let mut request = Vec::new();
+    let mut byte = [0; 1];
+    while !request.ends_with(terminator) {
+        match stream.read(&mut byte) {
+            Ok(1) => request.push(byte[0]),
+            _ => break
+        }
+    }
+    request
+}
+
+impl MockServer {
+    /// Accept `connections` connections, one after another, and pass each to `handle` with its
+    /// number and the requests received so far
+    fn serve<F>(connections: usize, handle: F) -> MockServer
+        where F: Fn(usize, TcpStream, &Mutex<Vec<String>>) + Send + 'static
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let received = requests.clone();
+        thread::spawn(move || for i in 0..connections {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(_) => return
+            };
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SECS)));
+            handle(i, stream, &received);
+        });
+
+        MockServer {
+            addr: addr,
+            requests: requests,
+        }
+    }
+
+    /// Answer `connections` connections, each with what `respond` returns for its number and
+    /// the request, which ends with `terminator`. The connection is closed after the response.
+    pub fn start<F>(terminator: &'static [u8], connections: usize, respond: F) -> MockServer
+        where F: Fn(usize, &[u8]) -> Vec<u8> + Send + 'static
+    {
+        MockServer::serve(connections, move |i, mut stream, received| {
+            let request = read_request(&mut stream, terminator);
+            received.lock().unwrap().push(String::from_utf8_lossy(&request).into_owned());
+            let _ = stream.write_all(&respond(i, &request));
+        })
+    }
+
+    /// A WHOIS server, which answers each query with the next response
+    pub fn whois(responses: &[&str]) -> MockServer {
+        let responses: Vec<Vec<u8>> = responses.iter().map(|response| response.as_bytes().to_vec()).collect();
+        MockServer::start(b"\r\n", responses.len(), move |i, _| responses[i].clone())
+    }
+
+    /// An HTTP server, which answers each request with the next response, headers and all.
+    /// Responses should have `Connection: close`, since every connection is closed after one.
+    pub fn http(responses: &[&str]) -> MockServer {
+        let responses: Vec<Vec<u8>> = responses.iter().map(|response| response.as_bytes().to_vec()).collect();
+        MockServer::start(b"\r\n\r\n", responses.len(), move |i, _| responses[i].clone())
+    }
+
+    /// A server that sends back the first line that it receives
+    pub fn echo() -> MockServer {
+        MockServer::start(b"\n", 1, |_, request| request.to_vec())
+    }
+
+    /// An HTTP proxy for one connection, which answers a CONNECT request and then relays the
+    /// tunnel to `upstream`, whatever host it was asked for, so that the host needn't resolve
+    pub fn proxy(upstream: &MockServer) -> MockServer {
+        let upstream = upstream.addr;
+        MockServer::serve(1, move |_, mut stream, received| {
+            let request = read_request(&mut stream, b"\r\n\r\n");
+            received.lock().unwrap().push(String::from_utf8_lossy(&request).into_owned());
+            let mut server = match TcpStream::connect(upstream) {
+                Ok(server) => server,
+                Err(_) => return
+            };
+            let _ = stream.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n");
+
+            let (mut client_reader, mut server_writer) = (stream.try_clone().unwrap(), server.try_clone().unwrap());
+            thread::spawn(move || io::copy(&mut client_reader, &mut server_writer));
+            let _ = io::copy(&mut server, &mut stream);
+            let _ = stream.shutdown(Shutdown::Write);
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// The address as a `host:port` operand
+    pub fn addr(&self) -> String {
+        self.addr.to_string()
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// The requests received so far
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// A DNS server on 127.0.0.1, which answers queries over UDP and TCP on the same port with the
+/// addresses of names, and keeps the questions that it received, as in `udp example.com 1`
+pub struct MockDns {
+    addr: SocketAddr,
+    questions: Arc<Mutex<Vec<String>>>,
+}
+
+/// The name and type of the question of a query, and where the question ends
+fn dns_question(query: &[u8]) -> Option<(String, u16, usize)> {
+    let mut labels = Vec::new();
+    let mut at = 12;
+    loop {
+        let len = *query.get(at)? as usize;
+        at += 1;
+        if len == 0 {
+            break;
+        }
+        labels.push(String::from_utf8_lossy(query.get(at..at + len)?).into_owned());
+        at += len;
+    }
+    let qtype = (*query.get(at)? as u16) << 8 | *query.get(at + 1)? as u16;
+    Some((labels.join("."), qtype, at + 4))
+}
+
+/// The reply to a query, with the A or AAAA records of `records` for the name, or NXDOMAIN if
+/// it has none of either. A truncated reply has no records.
+fn dns_reply(query: &[u8], records: &[(String, IpAddr)], truncate: bool) -> Option<Vec<u8>> {
+    let (name, qtype, end) = dns_question(query)?;
+    let known = records.iter().any(|record| record.0 == name);
+    let answers: Vec<&IpAddr> = records.iter()
+        .filter(|record| record.0 == name && !truncate)
+        .map(|record| &record.1)
+        .filter(|addr| if qtype == 1 { addr.is_ipv4() } else { qtype == 28 && addr.is_ipv6() })
+        .collect();
+
+    // A reply to the query, which is recursive and is truncated or has no such name as told
+    let flags = 0x8180 | (if truncate { 0x0200 } else { 0 }) | (if known { 0 } else { 3 });
+    let mut reply = vec![query[0], query[1], (flags >> 8) as u8, flags as u8, 0, 1, 0, answers.len() as u8, 0, 0, 0, 0];
+    reply.extend_from_slice(&query[12..end]);
+    for addr in answers {
+        let data = match *addr {
+            IpAddr::V4(addr) => addr.octets().to_vec(),
+            IpAddr::V6(addr) => addr.octets().to_vec(),
+        };
+        // The name is a pointer to the one in the question
+        reply.extend_from_slice(&[0xc0, 12, 0, qtype as u8, 0, 1, 0, 0, 1, 44, 0, data.len() as u8]);
+        reply.extend_from_slice(&data);
+    }
+    Some(reply)
+}
+
+impl MockDns {
+    /// Answer with the addresses of `records`, pairs of a name and an address. With `truncate`,
+    /// replies over UDP are truncated, so that queries have to be sent again over TCP.
+    pub fn start(records: &[(&str, &str)], truncate: bool) -> MockDns {
+        let records: Arc<Vec<(String, IpAddr)>> = Arc::new(records.iter()
+            .map(|&(name, addr)| (name.to_string(), addr.parse().unwrap()))
+            .collect());
+        let questions = Arc::new(Mutex::new(Vec::new()));
+
+        // The TCP port of the UDP one may be taken, so try a few
+        let (udp, tcp) = (0..10).filter_map(|_| {
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            TcpListener::bind(udp.local_addr().unwrap()).ok().map(|tcp| (udp, tcp))
+        }).next().unwrap();
+        let addr = udp.local_addr().unwrap();
+
+        let (udp_records, received) = (records.clone(), questions.clone());
+        thread::spawn(move || {
+            let mut query = [0; 512];
+            while let Ok((count, from)) = udp.recv_from(&mut query) {
+                let query = &query[..count];
+                if let Some((name, qtype, _)) = dns_question(query) {
+                    received.lock().unwrap().push(format!("udp {} {}", name, qtype));
+                }
+                if let Some(reply) = dns_reply(query, &udp_records, truncate) {
+                    let _ = udp.send_to(&reply, from);
+                }
+            }
+        });
+
+        let received = questions.clone();
+        thread::spawn(move || for stream in tcp.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => return
+            };
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SECS)));
+            let mut len = [0; 2];
+            if stream.read_exact(&mut len).is_err() {
+                continue;
+            }
+            let mut query = vec![0; (len[0] as usize) << 8 | len[1] as usize];
+            if stream.read_exact(&mut query).is_err() {
+                continue;
+            }
+            if let Some((name, qtype, _)) = dns_question(&query) {
+                received.lock().unwrap().push(format!("tcp {} {}", name, qtype));
+            }
+            if let Some(reply) = dns_reply(&query, &records, false) {
+                let _ = stream.write_all(&[(reply.len() >> 8) as u8, reply.len() as u8]);
+                let _ = stream.write_all(&reply);
+            }
+        });
+
+        MockDns {
+            addr: addr,
+            questions: questions,
+        }
+    }
+
+    /// The address as a `host:port` operand
+    pub fn addr(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// The questions received so far, with the protocol, name and type of each
+    pub fn questions(&self) -> Vec<String> {
+        self.questions.lock().unwrap().clone()
+    }
+}
+
+/// A port on 127.0.0.1 that nothing listens on
+pub fn closed_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// A UDP port on 127.0.0.1 that nothing listens on
+pub fn closed_udp_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// The directory of the tools, which is above the `deps` directory of the tests
+fn bin_dir() -> PathBuf {
+    let mut dir = env::current_exe().unwrap();
+    dir.pop();
+    if dir.ends_with("deps") {
+        dir.pop();
+    }
+    dir
+}
+
+/// The variables that set proxies
+const PROXY_VARS: [&'static str; 4] = ["http_proxy", "https_proxy", "all_proxy", "no_proxy"];
+
+/// A command that runs a tool, without the configuration file or the proxies of the user
+/// running the tests
+pub fn tool(name: &str) -> Command {
+    let mut command = Command::new(bin_dir().join(name));
+    command.env("XDG_CONFIG_HOME", env::temp_dir().join("netutils-tests-no-config"))
+        .stdin(Stdio::null());
+    for var in PROXY_VARS.iter() {
+        command.env_remove(var).env_remove(var.to_uppercase());
+    }
+    command
+}
+
+/// Run a tool to completion
+pub fn run(name: &str, args: &[&str]) -> Output {
+    tool(name).args(args).output().unwrap()
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
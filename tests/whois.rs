@@ -0,0 +1,77 @@
+mod support;
+
+use support::{closed_port, run, stderr, stdout, tool, MockServer};
+
+#[test]
+fn query_test() {
+    let server = MockServer::whois(&["domain: example.com\r\n"]);
+    let output = run("whois", &["-h", "127.0.0.1", "-p", &server.port().to_string(), "example.com"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "domain: example.com\r\n");
+    assert_eq!(server.requests(), vec!["example.com\r\n".to_string()]);
+}
+
+#[test]
+fn referral_test() {
+    // Referrals keep the port, so the second server is the same one under another name
+    let server = MockServer::whois(&["% IANA WHOIS server\r\nwhois:        localhost\r\n",
+                                     "Domain Name: EXAMPLE.COM\r\n"]);
+    let output = run("whois", &["-4", "-h", "127.0.0.1", "-p", &server.port().to_string(), "example.com"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "% IANA WHOIS server\r\nwhois:        localhost\r\nDomain Name: EXAMPLE.COM\r\n");
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn self_referral_test() {
+    let server = MockServer::whois(&["Registrar WHOIS Server: 127.0.0.1\r\n"]);
+    let output = run("whois", &["-h", "127.0.0.1", "-p", &server.port().to_string(), "example.com"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn referral_loop_test() {
+    let server = MockServer::whois(&["whois: localhost\r\n", "Whois Server: 127.0.0.1\r\n"]);
+    let output = run("whois", &["-4", "-h", "127.0.0.1", "-p", &server.port().to_string(), "example.com"]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stderr(&output).contains("referral loop"), "{}", stderr(&output));
+}
+
+#[test]
+fn truncated_test() {
+    // A response cut off in the middle of a line is still printed
+    let server = MockServer::whois(&["domain: exa"]);
+    let output = run("whois", &["-h", "127.0.0.1", "-p", &server.port().to_string(), "example.com"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "domain: exa");
+}
+
+#[test]
+fn refused_test() {
+    let output = run("whois", &["-h", "127.0.0.1", "-p", &closed_port().to_string(), "example.com"]);
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn proxy_test() {
+    // Only the proxy knows where whois.example.invalid is
+    let server = MockServer::whois(&["domain: example.com\r\n"]);
+    let proxy = MockServer::proxy(&server);
+    let output = tool("whois").args(&["-h", "whois.example.invalid", "example.com"])
+        .env("all_proxy", format!("http://{}", proxy.addr()))
+        .output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "domain: example.com\r\n");
+    assert!(proxy.requests()[0].starts_with("CONNECT whois.example.invalid:43 HTTP/1.1\r\n"), "{}", proxy.requests()[0]);
+    assert_eq!(server.requests(), vec!["example.com\r\n".to_string()]);
+
+    // Unless it's bypassed
+    let proxy = MockServer::proxy(&server);
+    let output = tool("whois").args(&["-h", "whois.example.invalid", "example.com"])
+        .env("all_proxy", format!("http://{}", proxy.addr()))
+        .env("no_proxy", "example.invalid")
+        .output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert!(proxy.requests().is_empty());
+}